@@ -0,0 +1,127 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::index::SearchIndex;
+use crate::postgres::index::open_search_index;
+use crate::postgres::visibility_checker::{invisible_ctids, invisible_ctids_among};
+use crate::query::SearchQueryInput;
+use crate::schema::SearchFieldType;
+use anyhow::{anyhow, bail, Context, Result};
+use pgrx::{default, iter::TableIterator, name, pg_extern, pg_sys, JsonB, PgRelation};
+use tantivy::aggregation::agg_req::Aggregations;
+use tantivy::schema::Facet;
+
+/// Run the aggregations described by `aggs`, in Elasticsearch's aggregation JSON format, over the
+/// documents in `index` that match `query` and are visible to the current transaction.
+///
+/// Aggregations can only read fast fields.  The result is the Elasticsearch-style response JSON.
+#[pg_extern]
+pub fn aggregate(index: PgRelation, query: SearchQueryInput, aggs: JsonB) -> Result<JsonB> {
+    // # Safety
+    //
+    // Lock the index relation until the end of this function so it is not dropped or
+    // altered while we are reading it.
+    //
+    // Because we accept a PgRelation above, we have confidence that Postgres has already
+    // validated the existence of the relation. We are safe calling the function below as
+    // long we do not pass pg_sys::NoLock without any other locking mechanism of our own.
+    let index = unsafe { PgRelation::with_lock(index.oid(), pg_sys::AccessShareLock as _) };
+    let heap_oid = index
+        .heap_relation()
+        .expect("index should belong to a table")
+        .oid();
+    let heap = unsafe { PgRelation::with_lock(heap_oid, pg_sys::AccessShareLock as _) };
+
+    let aggregations: Aggregations =
+        serde_json::from_value(aggs.0).context("invalid aggregation request")?;
+
+    let search_index = open_search_index(&index)?;
+    let search_reader = search_index.get_reader()?;
+    let query = search_index.query(&index, &query, &search_reader);
+
+    let invisible = invisible_ctids(
+        heap.as_ptr(),
+        unsafe { pg_sys::GetActiveSnapshot() },
+        &search_reader,
+        &query,
+    );
+    let results = search_reader
+        .aggregate(SearchIndex::executor(), &query, aggregations, invisible)
+        .context("aggregation failed")?;
+
+    Ok(JsonB(serde_json::to_value(results)?))
+}
+
+/// Count the documents in `index` that match `query`, and are visible to the current transaction,
+/// under each of the immediate children of `facet` in the facet field `field`.
+///
+/// Asking for `/electronics` returns a row for `/electronics/phones`, `/electronics/laptops`, and
+/// so on, each counting every document filed anywhere underneath it.
+#[pg_extern]
+pub fn facet_counts(
+    index: PgRelation,
+    query: SearchQueryInput,
+    field: &str,
+    facet: default!(&str, "'/'"),
+) -> Result<TableIterator<'static, (name!(facet, String), name!(count, i64))>> {
+    // # Safety
+    //
+    // Lock the index relation until the end of this function so it is not dropped or
+    // altered while we are reading it.
+    //
+    // Because we accept a PgRelation above, we have confidence that Postgres has already
+    // validated the existence of the relation. We are safe calling the function below as
+    // long we do not pass pg_sys::NoLock without any other locking mechanism of our own.
+    let index = unsafe { PgRelation::with_lock(index.oid(), pg_sys::AccessShareLock as _) };
+    let heap_oid = index
+        .heap_relation()
+        .expect("index should belong to a table")
+        .oid();
+    let heap = unsafe { PgRelation::with_lock(heap_oid, pg_sys::AccessShareLock as _) };
+
+    let facet = Facet::from_text(facet)
+        .map_err(|_| anyhow!("'{facet}' is not a valid facet path, which must start with '/'"))?;
+
+    let search_index = open_search_index(&index)?;
+    match search_index.schema.get_search_field(&field.into()) {
+        Some(search_field) if search_field.type_ == SearchFieldType::Facet => {}
+        Some(_) => bail!("'{field}' is not a facet field"),
+        None => bail!("'{field}' is not part of the pg_search index"),
+    }
+    let search_reader = search_index.get_reader()?;
+    let query = search_index.query(&index, &query, &search_reader);
+
+    // the matching documents are collected alongside their counts, so it's only those that
+    // need checking for visibility
+    let matches = search_reader
+        .facet_matches(SearchIndex::executor(), &query, &[(field.into(), facet)])
+        .context("facet counting failed")?;
+    let invisible = invisible_ctids_among(
+        heap.as_ptr(),
+        unsafe { pg_sys::GetActiveSnapshot() },
+        matches.ctids(),
+    );
+    let counts = search_reader
+        .visible_facet_counts(matches, &invisible)
+        .context("facet counting failed")?
+        .pop()
+        .unwrap_or_default();
+
+    Ok(TableIterator::new(counts.into_iter().map(
+        |(facet, count)| (facet.to_path_string(), count as i64),
+    )))
+}
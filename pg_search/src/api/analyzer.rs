@@ -0,0 +1,102 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use pgrx::*;
+use tokenizers::analyzer::SearchAnalyzer;
+use tokenizers::SearchTokenizer;
+
+// Every change to an analyzer bumps its version.  Indexes keep the version they were built with,
+// so they only pick up a change when they're reindexed.
+extension_sql!(
+    r#"
+    CREATE TABLE paradedb.analyzers (
+        name TEXT PRIMARY KEY,
+        config JSONB NOT NULL,
+        version BIGINT NOT NULL DEFAULT 1
+    );
+    GRANT SELECT ON paradedb.analyzers TO PUBLIC;
+    SELECT pg_catalog.pg_extension_config_dump('paradedb.analyzers', '');
+    "#,
+    name = "create_analyzers_table"
+);
+
+/// Create the analyzer `name`, or replace it if it already exists
+#[pg_extern(volatile)]
+pub fn create_analyzer(name: &str, config: JsonB) {
+    if let Err(err) = SearchAnalyzer::from_json_value(0, &config.0) {
+        panic!("invalid configuration for analyzer '{name}': {err}");
+    }
+
+    let version = Spi::connect(|mut client| {
+        client
+            .update(
+                "INSERT INTO paradedb.analyzers (name, config) VALUES ($1, $2)
+                 ON CONFLICT (name) DO UPDATE
+                 SET config = EXCLUDED.config, version = analyzers.version + 1
+                 RETURNING version",
+                None,
+                Some(vec![
+                    (PgBuiltInOids::TEXTOID.oid(), name.into_datum()),
+                    (PgBuiltInOids::JSONBOID.oid(), config.into_datum()),
+                ]),
+            )?
+            .first()
+            .get_one::<i64>()
+    })
+    .unwrap_or_else(|err| panic!("could not save analyzer '{name}': {err}"))
+    .expect("saving an analyzer should return its version");
+
+    if version > 1 {
+        warning!(
+            "analyzer '{name}' is now at version {version}, and bm25 indexes that use an earlier version \
+             keep using it until they are reindexed"
+        );
+    }
+}
+
+/// Look up the definition of a named analyzer, so that it can be kept in an index's schema
+pub fn resolve_analyzer(tokenizer: &mut SearchTokenizer) {
+    if let SearchTokenizer::Analyzer {
+        name,
+        analyzer: analyzer @ None,
+        ..
+    } = tokenizer
+    {
+        *analyzer = Some(Box::new(load_analyzer(name)));
+    }
+}
+
+fn load_analyzer(name: &str) -> SearchAnalyzer {
+    let row = Spi::connect(|client| {
+        let table = client.select(
+            "SELECT config, version FROM paradedb.analyzers WHERE name = $1",
+            Some(1),
+            Some(vec![(PgBuiltInOids::TEXTOID.oid(), name.into_datum())]),
+        )?;
+        if table.is_empty() {
+            return Ok(None);
+        }
+        table.first().get_two::<JsonB, i64>().map(Some)
+    })
+    .unwrap_or_else(|err| panic!("could not read analyzer '{name}': {err}"));
+
+    let Some((Some(config), Some(version))) = row else {
+        panic!("analyzer '{name}' does not exist");
+    };
+    SearchAnalyzer::from_json_value(version, &config.0)
+        .unwrap_or_else(|err| panic!("invalid configuration for analyzer '{name}': {err}"))
+}
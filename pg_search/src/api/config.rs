@@ -30,6 +30,8 @@ pub fn field(
     expand_dots: default!(Option<bool>, "NULL"),
     tokenizer: default!(Option<JsonB>, "NULL"),
     normalizer: default!(Option<String>, "NULL"),
+    nested: default!(Option<bool>, "NULL"),
+    similarity: default!(Option<JsonB>, "NULL"),
 ) -> JsonB {
     let mut config = Map::new();
 
@@ -41,6 +43,8 @@ pub fn field(
     expand_dots.map(|v| config.insert("expand_dots".to_string(), Value::Bool(v)));
     tokenizer.map(|v| config.insert("tokenizer".to_string(), v.0));
     normalizer.map(|v| config.insert("normalizer".to_string(), Value::String(v)));
+    nested.map(|v| config.insert("nested".to_string(), Value::Bool(v)));
+    similarity.map(|v| config.insert("similarity".to_string(), v.0));
 
     JsonB(json!({ name: config }))
 }
@@ -57,6 +61,15 @@ pub fn tokenizer(
     language: default!(Option<String>, "NULL"),
     pattern: default!(Option<String>, "NULL"),
     stemmer: default!(Option<String>, "NULL"),
+    stopwords_language: default!(Option<String>, "NULL"),
+    stopwords: default!(Option<Vec<String>>, "NULL"),
+    synonyms: default!(Option<String>, "NULL"),
+    ascii_folding: default!(Option<bool>, "NULL"),
+    shingle: default!(Option<JsonB>, "NULL"),
+    edge_ngram: default!(Option<JsonB>, "NULL"),
+    length: default!(Option<JsonB>, "NULL"),
+    pattern_replace: default!(Option<JsonB>, "NULL"),
+    analyzer: default!(Option<String>, "NULL"),
 ) -> JsonB {
     let mut config = Map::new();
 
@@ -66,6 +79,18 @@ pub fn tokenizer(
     remove_long.map(|v| config.insert("remove_long".to_string(), Value::Number(v.into())));
     lowercase.map(|v| config.insert("lowercase".to_string(), Value::Bool(v)));
     stemmer.map(|v| config.insert("stemmer".to_string(), Value::String(v)));
+    match (stopwords_language, stopwords) {
+        (Some(_), Some(_)) => panic!("only one of stopwords_language and stopwords can be set"),
+        (Some(v), None) => config.insert("stopwords".to_string(), Value::String(v)),
+        (None, Some(v)) => config.insert("stopwords".to_string(), json!(v)),
+        (None, None) => None,
+    };
+    synonyms.map(|v| config.insert("synonyms".to_string(), Value::String(v)));
+    ascii_folding.map(|v| config.insert("ascii_folding".to_string(), Value::Bool(v)));
+    shingle.map(|v| config.insert("shingle".to_string(), v.0));
+    edge_ngram.map(|v| config.insert("edge_ngram".to_string(), v.0));
+    length.map(|v| config.insert("length".to_string(), v.0));
+    pattern_replace.map(|v| config.insert("pattern_replace".to_string(), v.0));
     // Options for type = ngram
     min_gram.map(|v| config.insert("min_gram".to_string(), Value::Number(v.into())));
     max_gram.map(|v| config.insert("max_gram".to_string(), Value::Number(v.into())));
@@ -74,6 +99,23 @@ pub fn tokenizer(
     language.map(|v| config.insert("language".to_string(), Value::String(v)));
     // Options for type = regex
     pattern.map(|v| config.insert("pattern".to_string(), Value::String(v)));
+    // Options for type = analyzer
+    analyzer.map(|v| config.insert("name".to_string(), Value::String(v)));
 
     JsonB(json!(config))
 }
+
+// Each row is a group of terms or phrases that mean the same thing.  Queries read the table when
+// they are built, so changes to it apply to existing indexes right away.
+extension_sql!(
+    r#"
+    CREATE TABLE paradedb.synonyms (
+        set_name TEXT NOT NULL,
+        terms TEXT[] NOT NULL
+    );
+    CREATE INDEX synonyms_set_name_idx ON paradedb.synonyms (set_name);
+    GRANT SELECT ON paradedb.synonyms TO PUBLIC;
+    SELECT pg_catalog.pg_extension_config_dump('paradedb.synonyms', '');
+    "#,
+    name = "create_synonyms_table"
+);
@@ -18,9 +18,11 @@
 use pgrx::datum::RangeBound;
 use pgrx::{iter::TableIterator, *};
 
+use crate::index::reader::SortKey;
 use crate::postgres::index::open_search_index;
 use crate::postgres::types::TantivyValue;
-use crate::query::{SearchQueryInput, TermInput};
+use crate::postgres::utils::item_pointer_to_u64;
+use crate::query::{DecayFunction, MinimumShouldMatch, ScoreFunction, SearchQueryInput, TermInput};
 use crate::schema::AnyEnum;
 use crate::schema::IndexRecordOption;
 use serde::{Deserialize, Serialize};
@@ -133,16 +135,20 @@ pub fn all() -> SearchQueryInput {
     SearchQueryInput::All
 }
 
+/// `minimum_should_match` is how many of the `should` clauses have to match, as a count like
+/// `'3'` or a percentage like `'75%'`.  Negative values say how many don't have to match.
 #[pg_extern(name = "boolean", immutable, parallel_safe)]
 pub fn boolean_arrays(
     must: default!(Vec<SearchQueryInput>, "ARRAY[]::searchqueryinput[]"),
     should: default!(Vec<SearchQueryInput>, "ARRAY[]::searchqueryinput[]"),
     must_not: default!(Vec<SearchQueryInput>, "ARRAY[]::searchqueryinput[]"),
+    minimum_should_match: default!(Option<String>, "NULL"),
 ) -> SearchQueryInput {
     SearchQueryInput::Boolean {
         must,
         should,
         must_not,
+        minimum_should_match: parse_minimum_should_match(minimum_should_match),
     }
 }
 
@@ -156,6 +162,7 @@ pub fn boolean_singles(
         must.map_or(vec![], |v| vec![v]),
         should.map_or(vec![], |v| vec![v]),
         must_not.map_or(vec![], |v| vec![v]),
+        None,
     )
 }
 
@@ -198,6 +205,14 @@ pub fn exists(field: FieldName) -> SearchQueryInput {
     }
 }
 
+#[pg_extern(immutable, parallel_safe)]
+pub fn facet(field: FieldName, path: String) -> SearchQueryInput {
+    SearchQueryInput::Facet {
+        field: field.into_inner(),
+        path,
+    }
+}
+
 // Not clear on whether this query makes sense to support, as only our "key_field" is a fast
 // field... and the user can just use SQL to select a range. We'll keep the implementation here
 // for now, but we should remove when we decide definitively that we don't need this.
@@ -228,6 +243,119 @@ pub fn fast_field_range_weight(
     }
 }
 
+/// Scores docs by `modifier(factor * value)` of a numeric or date fast field, for use in
+/// `paradedb.function_score`.  Docs without a value use `missing`, or score 1 if it's NULL.
+#[pg_extern(immutable, parallel_safe)]
+pub fn field_value_factor(
+    field: FieldName,
+    factor: default!(f64, 1.0),
+    modifier: default!(String, "'none'"),
+    missing: default!(Option<f64>, "NULL"),
+) -> JsonB {
+    score_function_json(ScoreFunction::FieldValueFactor {
+        field: field.into_inner(),
+        factor,
+        modifier: parse_score_option("modifier", modifier),
+        missing,
+    })
+}
+
+/// Blend the scores of `query` with `functions` of each doc's fast field values, which are built
+/// with `paradedb.field_value_factor` and the `paradedb.*_decay` functions
+#[pg_extern(immutable, parallel_safe)]
+pub fn function_score(
+    query: SearchQueryInput,
+    functions: Vec<JsonB>,
+    score_mode: default!(String, "'multiply'"),
+    boost_mode: default!(String, "'multiply'"),
+) -> SearchQueryInput {
+    SearchQueryInput::FunctionScore {
+        query: Box::new(query),
+        functions: functions
+            .into_iter()
+            .map(|function| {
+                serde_json::from_value(function.0)
+                    .unwrap_or_else(|err| panic!("invalid score function: {err}"))
+            })
+            .collect(),
+        score_mode: parse_score_option("score_mode", score_mode),
+        boost_mode: parse_score_option("boost_mode", boost_mode),
+    }
+}
+
+/// Scores docs on a bell curve by how far their value of a numeric or date fast field is from
+/// `origin`, for use in `paradedb.function_score`
+#[pg_extern(immutable, parallel_safe)]
+pub fn gauss_decay(
+    field: FieldName,
+    scale: String,
+    origin: default!(Option<String>, "NULL"),
+    offset: default!(Option<String>, "NULL"),
+    decay: default!(f64, 0.5),
+) -> JsonB {
+    score_function_json(ScoreFunction::Gauss(decay_function(
+        field, scale, origin, offset, decay,
+    )))
+}
+
+/// Like `paradedb.gauss_decay`, but decays exponentially
+#[pg_extern(immutable, parallel_safe)]
+pub fn exp_decay(
+    field: FieldName,
+    scale: String,
+    origin: default!(Option<String>, "NULL"),
+    offset: default!(Option<String>, "NULL"),
+    decay: default!(f64, 0.5),
+) -> JsonB {
+    score_function_json(ScoreFunction::Exp(decay_function(
+        field, scale, origin, offset, decay,
+    )))
+}
+
+/// Like `paradedb.gauss_decay`, but decays linearly down to zero
+#[pg_extern(immutable, parallel_safe)]
+pub fn linear_decay(
+    field: FieldName,
+    scale: String,
+    origin: default!(Option<String>, "NULL"),
+    offset: default!(Option<String>, "NULL"),
+    decay: default!(f64, 0.5),
+) -> JsonB {
+    score_function_json(ScoreFunction::Linear(decay_function(
+        field, scale, origin, offset, decay,
+    )))
+}
+
+fn decay_function(
+    field: FieldName,
+    scale: String,
+    origin: Option<String>,
+    offset: Option<String>,
+    decay: f64,
+) -> DecayFunction {
+    // numbers are for numeric fields, and dates and durations like '7d' are for date fields
+    let value = |value: String| match value.trim().parse::<f64>() {
+        Ok(number) => OwnedValue::F64(number),
+        Err(_) => OwnedValue::Str(value),
+    };
+    DecayFunction {
+        field: field.into_inner(),
+        origin: origin.map(value),
+        scale: value(scale),
+        offset: offset.map(value),
+        decay,
+    }
+}
+
+fn score_function_json(function: ScoreFunction) -> JsonB {
+    JsonB(serde_json::to_value(function).expect("score function should serialize"))
+}
+
+fn parse_score_option<T: serde::de::DeserializeOwned>(name: &str, value: String) -> T {
+    serde_json::from_value(serde_json::Value::String(value.to_lowercase()))
+        .unwrap_or_else(|_| panic!("invalid {name} '{value}'"))
+}
+
 #[pg_extern(immutable, parallel_safe)]
 pub fn fuzzy_term(
     field: FieldName,
@@ -335,16 +463,61 @@ pub fn more_like_this_id(
     }
 }
 
+/// Match docs where `first` and `second` appear with at most `distance` other tokens between
+/// them, in that order if `ordered` is true.  The values are matched against the indexed tokens
+/// as they are, without being tokenized.
+#[pg_extern(immutable, parallel_safe)]
+pub fn near(
+    field: FieldName,
+    first: String,
+    second: String,
+    distance: default!(i32, 0),
+    ordered: default!(bool, false),
+) -> SearchQueryInput {
+    let field = field.into_inner();
+    span_near(
+        vec![
+            SearchQueryInput::SpanTerm {
+                field: field.clone(),
+                value: first,
+            },
+            SearchQueryInput::SpanTerm {
+                field,
+                value: second,
+            },
+        ],
+        distance,
+        ordered,
+    )
+}
+
+/// Match the rows where one object in the array at `path` of a nested JSON field matches all of
+/// `query`, scoring them by `score_mode` of the matching objects' scores
+#[pg_extern(immutable, parallel_safe)]
+pub fn nested(
+    path: FieldName,
+    query: SearchQueryInput,
+    score_mode: default!(String, "'avg'"),
+) -> SearchQueryInput {
+    SearchQueryInput::Nested {
+        path: path.into_inner(),
+        query: Box::new(query),
+        score_mode: parse_score_option("score_mode", score_mode),
+    }
+}
+
 #[pg_extern(immutable, parallel_safe)]
 pub fn parse(
     query_string: String,
     lenient: default!(Option<bool>, "NULL"),
     conjunction_mode: default!(Option<bool>, "NULL"),
+    minimum_should_match: default!(Option<String>, "NULL"),
 ) -> SearchQueryInput {
     SearchQueryInput::Parse {
         query_string,
         lenient,
         conjunction_mode,
+        minimum_should_match: parse_minimum_should_match(minimum_should_match),
     }
 }
 
@@ -354,12 +527,14 @@ pub fn parse_with_field(
     query_string: String,
     lenient: default!(Option<bool>, "NULL"),
     conjunction_mode: default!(Option<bool>, "NULL"),
+    minimum_should_match: default!(Option<String>, "NULL"),
 ) -> SearchQueryInput {
     SearchQueryInput::ParseWithField {
         field: field.into_inner(),
         query_string,
         lenient,
         conjunction_mode,
+        minimum_should_match: parse_minimum_should_match(minimum_should_match),
     }
 }
 
@@ -564,6 +739,118 @@ pub fn regex_phrase(
     }
 }
 
+/// Re-score the top `window_size` matches of `query` with `rescore_query`, blending the two
+/// scores by `weights`, which are the weights of `query` and `rescore_query` in that order
+#[pg_extern(immutable, parallel_safe)]
+pub fn rescore(
+    query: SearchQueryInput,
+    rescore_query: SearchQueryInput,
+    window_size: default!(i32, 10),
+    weights: default!(Vec<f32>, "ARRAY[1.0, 1.0]"),
+) -> SearchQueryInput {
+    let [query_weight, rescore_query_weight] = weights[..] else {
+        panic!("rescore weights must be an array of two weights, for the query and rescore_query")
+    };
+
+    SearchQueryInput::Rescore {
+        query: Box::new(query),
+        rescore_query: Box::new(rescore_query),
+        window_size: u32::try_from(window_size)
+            .unwrap_or_else(|_| panic!("window_size must not be negative")),
+        query_weight,
+        rescore_query_weight,
+    }
+}
+
+/// Resume a sorted search after the last row of the previous page.  `sort_keys` are written
+/// like `'rating desc'` or `'paradedb.score() desc'`, `after` is a JSON array of the previous
+/// page's last values for them, and `ctid` is that row's ctid, which orders any ties.
+#[pg_extern(immutable, parallel_safe)]
+pub fn search_after(
+    query: SearchQueryInput,
+    sort_keys: Vec<String>,
+    after: JsonB,
+    ctid: pg_sys::ItemPointerData,
+) -> SearchQueryInput {
+    let sort_keys = sort_keys
+        .iter()
+        .map(|key| {
+            key.parse::<SortKey>()
+                .unwrap_or_else(|err| panic!("invalid search_after sort key: {err}"))
+        })
+        .collect::<Vec<_>>();
+    let values = match after.0 {
+        serde_json::Value::Array(values) => values
+            .into_iter()
+            .map(|value| {
+                serde_json::from_value::<OwnedValue>(value)
+                    .unwrap_or_else(|err| panic!("invalid search_after value: {err}"))
+            })
+            .collect::<Vec<_>>(),
+        _ => panic!("search_after values must be a JSON array"),
+    };
+    if sort_keys.len() != values.len() {
+        panic!(
+            "search_after has {} sort keys but {} values",
+            sort_keys.len(),
+            values.len()
+        );
+    }
+
+    SearchQueryInput::SearchAfter {
+        query: Box::new(query),
+        sort_keys,
+        values,
+        ctid: item_pointer_to_u64(ctid),
+    }
+}
+
+/// Match spans of the `clauses` with at most `slop` positions between them in total, in the
+/// order they are given if `in_order` is true.
+#[pg_extern(immutable, parallel_safe)]
+pub fn span_near(
+    clauses: Vec<SearchQueryInput>,
+    slop: default!(i32, 0),
+    in_order: default!(bool, false),
+) -> SearchQueryInput {
+    SearchQueryInput::SpanNear {
+        clauses,
+        slop: u32::try_from(slop).unwrap_or_else(|_| panic!("slop must not be negative")),
+        in_order,
+    }
+}
+
+/// Match the spans of `include` that don't overlap a span of `exclude`, and don't have one
+/// within `pre` positions before them or `post` positions after them.
+#[pg_extern(immutable, parallel_safe)]
+pub fn span_not(
+    include: SearchQueryInput,
+    exclude: SearchQueryInput,
+    pre: default!(i32, 0),
+    post: default!(i32, 0),
+) -> SearchQueryInput {
+    SearchQueryInput::SpanNot {
+        include: Box::new(include),
+        exclude: Box::new(exclude),
+        pre: u32::try_from(pre).unwrap_or_else(|_| panic!("pre must not be negative")),
+        post: u32::try_from(post).unwrap_or_else(|_| panic!("post must not be negative")),
+    }
+}
+
+#[pg_extern(immutable, parallel_safe)]
+pub fn span_or(clauses: Vec<SearchQueryInput>) -> SearchQueryInput {
+    SearchQueryInput::SpanOr { clauses }
+}
+
+/// The positions of an indexed token, for use in the other `span_*` queries
+#[pg_extern(immutable, parallel_safe)]
+pub fn span_term(field: FieldName, value: String) -> SearchQueryInput {
+    SearchQueryInput::SpanTerm {
+        field: field.into_inner(),
+        value,
+    }
+}
+
 macro_rules! term_fn {
     ($func_name:ident, $value_type:ty) => {
         #[pg_extern(name = "term", immutable, parallel_safe)]
@@ -837,6 +1124,21 @@ pub fn term_set(
     SearchQueryInput::TermSet { terms }
 }
 
+/// Match the terms of `field` that fit `pattern`, where `?` matches any single character and `*`
+/// matches any run of characters.  Use `\` to match either of them literally.
+#[pg_extern(immutable, parallel_safe)]
+pub fn wildcard(
+    field: FieldName,
+    pattern: String,
+    case_insensitive: default!(bool, false),
+) -> SearchQueryInput {
+    SearchQueryInput::Wildcard {
+        field: field.into_inner(),
+        pattern,
+        case_insensitive,
+    }
+}
+
 /// A type used whenever our builder functions require a fieldname.
 #[derive(
     Debug, Clone, Ord, Eq, PartialOrd, PartialEq, Hash, Serialize, Deserialize, PostgresType,
@@ -887,6 +1189,14 @@ fn text_to_fieldname(field: String) -> FieldName {
     FieldName(field)
 }
 
+fn parse_minimum_should_match(value: Option<String>) -> Option<MinimumShouldMatch> {
+    value.map(|value| {
+        value
+            .parse()
+            .unwrap_or_else(|err: anyhow::Error| panic!("{err}"))
+    })
+}
+
 #[pg_cast(implicit)]
 fn jsonb_to_searchqueryinput(query: JsonB) -> SearchQueryInput {
     serde_path_to_error::deserialize(query.0).unwrap_or_else(|err| {
@@ -15,6 +15,8 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+pub mod aggregate;
+pub mod analyzer;
 pub mod config;
 pub mod index;
 pub mod operator;
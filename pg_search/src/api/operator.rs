@@ -21,11 +21,12 @@ mod text;
 use crate::api::index::{fieldname_typoid, FieldName};
 use crate::nodecast;
 use crate::postgres::index::open_search_index;
-use crate::postgres::utils::locate_bm25_index;
+use crate::postgres::utils::{bm25_index_partitions, is_partitioned_index, locate_bm25_index};
 use crate::query::SearchQueryInput;
 use pgrx::callconv::{BoxRet, FcInfo};
 use pgrx::datum::Datum;
 use pgrx::pg_sys::expression_tree_walker;
+use pgrx::pg_sys::panic::ErrorReport;
 use pgrx::pgrx_sql_entity_graph::metadata::{
     ArgumentError, Returns, ReturnsError, SqlMapping, SqlTranslatable,
 };
@@ -123,31 +124,103 @@ pub fn searchqueryinput_typoid() -> pg_sys::Oid {
     }
 }
 
+/// Estimate the selectivity of `search_query_input` against `indexrel`.
+///
+/// A partitioned index has no documents of its own.  Without the planner to say which of its
+/// partitions are left after pruning, it's estimated from its largest partition alone, rather
+/// than opening every one of them.
 pub(crate) fn estimate_selectivity(
     indexrel: &PgRelation,
     search_query_input: &SearchQueryInput,
 ) -> Option<f64> {
-    let reltuples = indexrel
-        .heap_relation()
-        .expect("indexrel should be an index")
-        .reltuples()
-        .unwrap_or(1.0) as f64;
-    if !reltuples.is_normal() || reltuples.is_sign_negative() {
-        // we can't estimate against a non-normal or negative estimate of heap tuples
-        return None;
+    if !is_partitioned_index(indexrel) {
+        return estimate_selectivity_over(&[indexrel.oid()], search_query_input);
     }
 
-    let search_index = open_search_index(indexrel).expect("should be able to open search index");
-    let search_reader = search_index
-        .get_reader()
-        .expect("search reader creation should not fail");
-    let estimate = search_reader
-        .estimate_docs(
-            indexrel,
-            search_index.query_parser(),
-            search_query_input.clone(),
-        )
-        .unwrap_or(1) as f64;
+    let largest = bm25_index_partitions(indexrel)
+        .into_iter()
+        .map(|indexrelid| (indexrelid, partition_reltuples(indexrelid)))
+        .filter(|(_, reltuples)| reltuples.is_normal() && reltuples.is_sign_positive())
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    estimate_selectivity_over(&[largest.0], search_query_input)
+}
+
+/// [`estimate_selectivity`] for a `@@@` qual on `var`, a column of a relation `root` is planning.
+/// A partitioned table is estimated over the partitions that are left after pruning, once the
+/// planner has expanded them.
+pub(crate) unsafe fn estimate_var_selectivity(
+    root: *mut pg_sys::PlannerInfo,
+    var: *mut pg_sys::Var,
+    indexrel: &PgRelation,
+    search_query_input: &SearchQueryInput,
+) -> Option<f64> {
+    if is_partitioned_index(indexrel) && var_is_of_table(root, var, indexrel) {
+        let indexrelids = unpruned_partition_indexes(root, (*var).varno as pg_sys::Index);
+        if !indexrelids.is_empty() {
+            return estimate_selectivity_over(&indexrelids, search_query_input);
+        }
+    }
+
+    estimate_selectivity(indexrel, search_query_input)
+}
+
+/// Is `var` directly a column of the table `indexrel` is on, rather than of a subquery or of an
+/// outer query, so that its partitions are the children of its range table entry in `root`?
+unsafe fn var_is_of_table(
+    root: *mut pg_sys::PlannerInfo,
+    var: *mut pg_sys::Var,
+    indexrel: &PgRelation,
+) -> bool {
+    let varno = (*var).varno as i32;
+    if (*var).varlevelsup != 0
+        || (*root).simple_rte_array.is_null()
+        || varno <= 0
+        || varno >= (*root).simple_rel_array_size
+    {
+        return false;
+    }
+
+    let rte = *(*root).simple_rte_array.add(varno as usize);
+    !rte.is_null()
+        && (*rte).rtekind == pg_sys::RTEKind::RTE_RELATION
+        && indexrel
+            .heap_relation()
+            .is_some_and(|heaprel| heaprel.oid() == (*rte).relid)
+}
+
+/// Add up the estimates of the indexes `indexrelids`, which each hold different documents.
+fn estimate_selectivity_over(
+    indexrelids: &[pg_sys::Oid],
+    search_query_input: &SearchQueryInput,
+) -> Option<f64> {
+    let mut reltuples = 0.0;
+    let mut estimate = 0.0;
+    for indexrelid in indexrelids {
+        let partition_reltuples = partition_reltuples(*indexrelid);
+        if !partition_reltuples.is_normal() || partition_reltuples.is_sign_negative() {
+            // we can't estimate against a non-normal or negative estimate of heap tuples
+            continue;
+        }
+
+        let indexrel = unsafe { PgRelation::with_lock(*indexrelid, pg_sys::AccessShareLock as _) };
+        let search_index =
+            open_search_index(&indexrel).expect("should be able to open search index");
+        let search_reader = search_index
+            .get_reader()
+            .expect("search reader creation should not fail");
+        reltuples += partition_reltuples;
+        estimate += search_reader
+            .estimate_docs(
+                &indexrel,
+                search_index.query_parser(),
+                search_query_input.clone(),
+            )
+            .unwrap_or(1) as f64;
+    }
+    if reltuples == 0.0 {
+        // none of the indexes had an estimate we could use
+        return None;
+    }
 
     let mut selectivity = estimate / reltuples;
     if selectivity > 1.0 {
@@ -157,6 +230,53 @@ pub(crate) fn estimate_selectivity(
     Some(selectivity)
 }
 
+/// The estimated number of rows in the table the index `indexrelid` is on
+fn partition_reltuples(indexrelid: pg_sys::Oid) -> f64 {
+    let indexrel = unsafe { PgRelation::with_lock(indexrelid, pg_sys::AccessShareLock as _) };
+    indexrel
+        .heap_relation()
+        .expect("indexrel should be an index")
+        .reltuples()
+        .unwrap_or(1.0) as f64
+}
+
+/// The `USING bm25` indexes of the partitions of the relation `relid` that `root` has kept after
+/// pruning, including those of sub-partitions.  Pruned partitions are never expanded into the
+/// query's `append_rel_list`, so it's the partitions found there.
+unsafe fn unpruned_partition_indexes(
+    root: *mut pg_sys::PlannerInfo,
+    relid: pg_sys::Index,
+) -> Vec<pg_sys::Oid> {
+    let append_rel_infos = PgList::<pg_sys::AppendRelInfo>::from_pg((*root).append_rel_list);
+
+    let mut indexrelids = vec![];
+    let mut pending = append_rel_infos
+        .iter_ptr()
+        .filter(|info| (**info).parent_relid == relid)
+        .map(|info| (*info).child_relid)
+        .collect::<Vec<_>>();
+    while let Some(relid) = pending.pop() {
+        let children = append_rel_infos
+            .iter_ptr()
+            .filter(|info| (**info).parent_relid == relid)
+            .map(|info| (*info).child_relid)
+            .collect::<Vec<_>>();
+        if !children.is_empty() {
+            pending.extend(children);
+            continue;
+        }
+
+        let rte = *(*root).simple_rte_array.add(relid as usize);
+        if rte.is_null() || (*rte).rtekind != pg_sys::RTEKind::RTE_RELATION {
+            continue;
+        }
+        if let Some(indexrel) = locate_bm25_index((*rte).relid) {
+            indexrelids.push(indexrel.oid());
+        }
+    }
+    indexrelids
+}
+
 unsafe fn make_search_query_input_opexpr_node(
     srs: *mut pg_sys::SupportRequestSimplify,
     input_args: &mut PgList<pg_sys::Node>,
@@ -319,6 +439,144 @@ unsafe fn make_search_query_input_opexpr_node(
     ReturnedNodePointer(NonNull::new(newopexpr.cast()))
 }
 
+/// The quals of a partition are copies of those written against its partitioned table, so their
+/// `@@@` operators are wrapped in a [`SearchQueryInput::WithIndex`] of the partitioned table's
+/// index.  Point them at the partition's own index instead, so that when they're evaluated row by
+/// row they only match the documents of the partition the row is from -- key values are only
+/// unique within a partition.
+pub unsafe fn use_partition_bm25_index(
+    rel: *mut pg_sys::RelOptInfo,
+    rte: *mut pg_sys::RangeTblEntry,
+) {
+    #[pg_guard]
+    unsafe extern "C" fn walker(node: *mut pg_sys::Node, data: *mut core::ffi::c_void) -> bool {
+        if node.is_null() {
+            return false;
+        }
+
+        if let Some(opexpr) = nodecast!(OpExpr, T_OpExpr, node) {
+            let data = &*data.cast::<Data>();
+            let args = PgList::<pg_sys::Node>::from_pg((*opexpr).args);
+            if (*opexpr).opno == data.opoid {
+                if let Some(rhs) = args
+                    .get_ptr(1)
+                    .and_then(|rhs| nodecast!(Const, T_Const, rhs))
+                {
+                    if let Some(SearchQueryInput::WithIndex { oid, query }) =
+                        SearchQueryInput::from_datum((*rhs).constvalue, (*rhs).constisnull)
+                    {
+                        if pg_sys::get_rel_relkind(oid) as u8 == pg_sys::RELKIND_PARTITIONED_INDEX {
+                            (*rhs).constvalue = SearchQueryInput::WithIndex {
+                                oid: data.indexrelid,
+                                query,
+                            }
+                            .into_datum()
+                            .unwrap();
+                        }
+                    }
+                }
+            }
+        }
+
+        expression_tree_walker(node, Some(walker), data)
+    }
+
+    struct Data {
+        opoid: pg_sys::Oid,
+        indexrelid: pg_sys::Oid,
+    }
+
+    if (*rel).reloptkind != pg_sys::RelOptKind::RELOPT_OTHER_MEMBER_REL
+        || (*rte).rtekind != pg_sys::RTEKind::RTE_RELATION
+    {
+        return;
+    }
+    let Some(indexrel) = locate_bm25_index((*rte).relid) else {
+        return;
+    };
+
+    if is_partitioned_index(&indexrel) {
+        // the quals of its own partitions will be pointed at their indexes
+        return;
+    }
+
+    // any partitioned index the quals name belongs to a partitioned table above this partition
+    let mut data = Data {
+        opoid: anyelement_query_input_opoid(),
+        indexrelid: indexrel.oid(),
+    };
+
+    for ri in PgList::<pg_sys::RestrictInfo>::from_pg((*rel).baserestrictinfo).iter_ptr() {
+        walker((*ri).clause.cast(), addr_of_mut!(data).cast());
+    }
+}
+
+/// A partial `USING bm25` index only has the rows that satisfy its predicate, so the `@@@` quals
+/// of `rel` can only find every row they should when the query's other quals imply it.  Postgres
+/// has already worked that out for the indexes of `rel`, and if they don't, we raise an error
+/// rather than silently leave out the rows that aren't in the index.
+pub unsafe fn require_bm25_index_predicate(
+    rel: *mut pg_sys::RelOptInfo,
+    rte: *mut pg_sys::RangeTblEntry,
+) {
+    #[pg_guard]
+    unsafe extern "C" fn walker(node: *mut pg_sys::Node, data: *mut core::ffi::c_void) -> bool {
+        if node.is_null() {
+            return false;
+        }
+
+        if let Some(opexpr) = nodecast!(OpExpr, T_OpExpr, node) {
+            if (*opexpr).opno == *data.cast::<pg_sys::Oid>() {
+                return true;
+            }
+        }
+
+        expression_tree_walker(node, Some(walker), data)
+    }
+
+    if (*rte).rtekind != pg_sys::RTEKind::RTE_RELATION {
+        return;
+    }
+    let Some(indexrel) = locate_bm25_index((*rte).relid) else {
+        return;
+    };
+    if pg_sys::RelationGetIndexPredicate(indexrel.as_ptr()).is_null() {
+        return;
+    }
+
+    let indexlist = PgList::<pg_sys::IndexOptInfo>::from_pg((*rel).indexlist);
+    if indexlist
+        .iter_ptr()
+        .any(|index| (*index).indexoid == indexrel.oid() && (*index).predOK)
+    {
+        return;
+    }
+
+    // `predOK` is only ever proven from the relation's own restrictions.  The join clauses are
+    // looked at as well because `@@@` is answered by the index wherever it's evaluated, so a join
+    // clause can't match the rows the index leaves out any more than a restriction can.  Those
+    // rows would be missing from the results either way
+    let mut opoid = anyelement_query_input_opoid();
+    let uses_search_operator = PgList::<pg_sys::RestrictInfo>::from_pg((*rel).baserestrictinfo)
+        .iter_ptr()
+        .chain(PgList::<pg_sys::RestrictInfo>::from_pg((*rel).joininfo).iter_ptr())
+        .any(|ri| walker((*ri).clause.cast(), addr_of_mut!(opoid).cast()));
+    if uses_search_operator {
+        let heaprel = PgRelation::open((*rte).relid);
+        ErrorReport::new(
+            PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED,
+            format!(
+                "the query on `{}.{}` must imply the predicate of its partial `USING bm25` index `{}`",
+                heaprel.namespace(),
+                heaprel.name(),
+                indexrel.name()
+            ),
+            function_name!(),
+        )
+        .report(PgLogLevel::ERROR);
+    }
+}
+
 /// Given a [`pg_sys::Var`] and a [`pg_sys::PlannerInfo`], attempt to find the relation Oid that
 /// contains the var.
 ///
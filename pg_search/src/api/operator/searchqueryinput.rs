@@ -18,13 +18,13 @@ use super::{
     anyelement_query_input_opoid, anyelement_query_input_procoid,
     make_search_query_input_opexpr_node,
 };
-use crate::api::operator::{estimate_selectivity, find_var_relation, ReturnedNodePointer};
+use crate::api::operator::{estimate_var_selectivity, find_var_relation, ReturnedNodePointer};
 use crate::gucs::per_tuple_cost;
 use crate::index::fast_fields_helper::FFHelper;
 use crate::index::SearchIndex;
 use crate::postgres::index::open_search_index;
 use crate::postgres::types::TantivyValue;
-use crate::postgres::utils::locate_bm25_index;
+use crate::postgres::utils::{is_partitioned_index, locate_bm25_index};
 use crate::query::SearchQueryInput;
 use crate::{nodecast, UNKNOWN_SELECTIVITY};
 use pgrx::{
@@ -53,9 +53,24 @@ pub fn search_with_query_input(
         let indexrel = unsafe {
             &PgRelation::with_lock(index_oid, pg_sys::AccessShareLock as pg_sys::LOCKMODE)
         };
+
+        // key values are only unique within a partition, so the index of a partitioned table
+        // can't tell which of its partitions' documents the element is from.  the quals of a
+        // partition scan are pointed at the partition's own index when they're planned, so we
+        // only get here if the operator is evaluated somewhere other than a scan of the table
+        if is_partitioned_index(indexrel) {
+            let heaprel = indexrel
+                .heap_relation()
+                .expect("indexrel should be an index");
+            panic!(
+                "the `@@@` operator on partitioned table `{}.{}` can only be evaluated while scanning one of its partitions",
+                heaprel.namespace(),
+                heaprel.name()
+            );
+        }
+
         let search_index =
             open_search_index(indexrel).expect("should be able to open search index");
-
         let key_field = search_index.key_field_name();
         let key_field_type = search_index.key_field().type_.into();
         let search_reader = search_index.get_reader().unwrap();
@@ -188,7 +203,7 @@ pub fn query_input_restrict(
                 )?),
             };
 
-            estimate_selectivity(&indexrel, &search_query_input)
+            estimate_var_selectivity(info, var, &indexrel, &search_query_input)
         }
     }
 
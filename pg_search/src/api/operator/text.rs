@@ -16,7 +16,7 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 use crate::api::operator::{
-    anyelement_text_opoid, anyelement_text_procoid, attname_from_var, estimate_selectivity,
+    anyelement_text_opoid, anyelement_text_procoid, attname_from_var, estimate_var_selectivity,
     make_search_query_input_opexpr_node, ReturnedNodePointer,
 };
 use crate::postgres::utils::locate_bm25_index;
@@ -116,7 +116,7 @@ pub fn text_restrict(
             let (heaprelid, search_query_input) = make_query_from_var_and_const(info, var, const_);
             let indexrel = locate_bm25_index(heaprelid)?;
 
-            estimate_selectivity(&indexrel, &search_query_input)
+            estimate_var_selectivity(info, var, &indexrel, &search_query_input)
         }
     }
 
@@ -148,6 +148,7 @@ unsafe fn make_query_from_var_and_const(
             query_string,
             lenient: None,
             conjunction_mode: None,
+            minimum_should_match: None,
         },
 
         // the Var represents a table reference, and that means the Const value is to be used
@@ -156,6 +157,7 @@ unsafe fn make_query_from_var_and_const(
             query_string,
             lenient: None,
             conjunction_mode: None,
+            minimum_should_match: None,
         },
     };
     (heaprelid, query)
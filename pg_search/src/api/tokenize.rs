@@ -15,20 +15,22 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use crate::api::analyzer::resolve_analyzer;
 use pgrx::{iter::TableIterator, name, pg_extern, JsonB};
 use strum::VariantNames;
 use tokenizers::SearchTokenizer;
 
 /// Tokenize text with a given tokenizer setting
-#[pg_extern(immutable, parallel_safe)]
+#[pg_extern(stable, parallel_safe)]
 pub fn tokenize(
     tokenizer_setting: JsonB,
     input_text: &str,
 ) -> TableIterator<(name!(token, String), name!(position, i32))> {
     let tokenizer_setting = serde_json::to_value(tokenizer_setting)
         .expect("invalid tokenizer setting, expected paradedb.tokenizer()");
-    let tokenizer = SearchTokenizer::from_json_value(&tokenizer_setting)
+    let mut tokenizer = SearchTokenizer::from_json_value(&tokenizer_setting)
         .expect("invalid tokenizer setting, expected paradedb.tokenizer()");
+    resolve_analyzer(&mut tokenizer);
 
     let mut analyzer = tokenizer
         .to_tantivy_tokenizer()
@@ -21,6 +21,7 @@ use anyhow::bail;
 use anyhow::Result;
 use pgrx::prelude::*;
 use pgrx::JsonB;
+use pgrx::PgList;
 use pgrx::PgRelation;
 use serde_json::Map;
 use serde_json::Value;
@@ -28,13 +29,16 @@ use tokenizers::manager::SearchTokenizerFilters;
 use tokenizers::SearchNormalizer;
 use tokenizers::SearchTokenizer;
 
-use crate::index::{SearchFs, SearchIndex, WriterDirectory};
-use crate::postgres::index::{open_search_index, relfilenode_from_pg_relation};
+use crate::index::{SearchIndexWriter, WriterDirectory};
+use crate::nodecast;
+use crate::postgres::index::open_search_index;
 use crate::postgres::options::SearchIndexCreateOptions;
+use crate::postgres::utils::locate_bm25_index;
 use crate::schema::IndexRecordOption;
 use crate::schema::SearchFieldConfig;
 use crate::schema::SearchFieldName;
 use crate::schema::SearchFieldType;
+use crate::schema::SearchSimilarity;
 
 #[allow(clippy::too_many_arguments)]
 #[pg_extern]
@@ -119,23 +123,12 @@ unsafe fn delete_bm25_index_by_oid(index_oid: pg_sys::Oid) -> Result<()> {
         .expect("could not look up pg_search relfilenode directory");
 
     for directory in relfile_paths {
-        // Drop the Tantivy data directory.
-        // It's expected that this will be queued to actually perform the delete upon
-        // transaction commit.
-        match SearchIndex::from_disk(&directory) {
-            Ok(mut search_index) => {
-                search_index.drop_index().unwrap_or_else(|err| {
-                    panic!("error dropping index with OID {index_oid:?}: {err:?}")
-                });
-            }
-            Err(e) => {
-                pgrx::warning!(
-                    "error dropping index with OID {index_oid:?} at path {}: {e:?}",
-                    directory.search_index_dir_path(false).unwrap().0.display()
-                );
-            }
-        }
+        // The index itself lives in the (now dropped) index relation, and Postgres takes care of
+        // its storage.  All that's left to clean up is any directory created by an earlier
+        // version of pg_search, which is queued to be removed upon transaction commit.
+        SearchIndexWriter::mark_pending_drop(&directory);
     }
+    crate::postgres::transaction::register_callback();
     Ok(())
 }
 
@@ -231,6 +224,13 @@ pub unsafe fn index_fields(index: PgRelation) -> JsonB {
             _ => panic!("'{name}' cannot be indexed as a datetime field"),
         });
 
+    let facet_fields = rdopts.get_facet_fields().into_iter().map(|(name, config)| {
+        match name_type_map.get(&name) {
+            Some(SearchFieldType::Text) => (name, config, SearchFieldType::Facet),
+            _ => panic!("'{name}' cannot be indexed as a facet field"),
+        }
+    });
+
     let key_field = rdopts.get_key_field().expect("must specify key field");
     let key_field_type = match name_type_map.get(&key_field) {
         Some(field_type) => field_type,
@@ -252,18 +252,22 @@ pub unsafe fn index_fields(index: PgRelation) -> JsonB {
             tokenizer: SearchTokenizer::Raw(SearchTokenizerFilters::default()),
             record: IndexRecordOption::Basic,
             normalizer: SearchNormalizer::Raw,
+            similarity: SearchSimilarity::default(),
         },
         SearchFieldType::Json => SearchFieldConfig::Json {
             indexed: true,
             fast: true,
             stored: true,
             expand_dots: false,
+            nested: false,
             tokenizer: SearchTokenizer::Raw(SearchTokenizerFilters::default()),
             record: IndexRecordOption::Basic,
             normalizer: SearchNormalizer::Raw,
+            similarity: SearchSimilarity::default(),
             fieldnorms: true,
         },
         SearchFieldType::Range => SearchFieldConfig::Range { stored: true },
+        SearchFieldType::Facet => unreachable!("column types are never inferred to be facets"),
         SearchFieldType::Bool => SearchFieldConfig::Boolean {
             indexed: true,
             fast: true,
@@ -283,6 +287,7 @@ pub unsafe fn index_fields(index: PgRelation) -> JsonB {
         .chain(json_fields)
         .chain(range_fields)
         .chain(datetime_fields)
+        .chain(facet_fields)
         .chain(std::iter::once((
             key_field.clone(),
             key_config,
@@ -318,17 +323,12 @@ fn index_size(index: PgRelation) -> Result<i64> {
     // validated the existence of the relation. We are safe calling the function below as
     // long we do not pass pg_sys::NoLock without any other locking mechanism of our own.
     let index = unsafe { PgRelation::with_lock(index.oid(), pg_sys::AccessShareLock as _) };
-    let index_oid = index.oid();
 
-    let database_oid = crate::MyDatabaseId();
-    let relfilenode = relfilenode_from_pg_relation(&index);
-
-    // Create a WriterDirectory with the obtained index_oid
-    let writer_directory =
-        WriterDirectory::from_oids(database_oid, index_oid.as_u32(), relfilenode.as_u32());
-
-    // Call the total_size method to get the size in bytes
-    let total_size = writer_directory.total_size()?;
+    // The index is stored entirely in the blocks of the index relation
+    let nblocks = unsafe {
+        pg_sys::RelationGetNumberOfBlocksInFork(index.as_ptr(), pg_sys::ForkNumber::MAIN_FORKNUM)
+    };
+    let total_size = nblocks as u64 * pg_sys::BLCKSZ as u64;
 
     Ok(total_size as i64)
 }
@@ -359,7 +359,7 @@ fn index_info(
 
     // open the specified index
     let index = open_search_index(&index).expect("should be able to open search index");
-    let directory = index.directory.clone();
+    let directory = index.block_directory.clone();
     let data = index
         .underlying_index
         .searchable_segment_metas()?
@@ -369,19 +369,7 @@ fn index_info(
             let byte_size = meta
                 .list_files()
                 .into_iter()
-                .map(|file| {
-                    let mut full_path = directory.tantivy_dir_path(false).unwrap().0;
-                    full_path.push(file);
-
-                    if full_path.exists() {
-                        full_path
-                            .metadata()
-                            .map(|metadata| metadata.len())
-                            .unwrap_or(0)
-                    } else {
-                        0
-                    }
-                })
+                .map(|file| directory.file_len(&file).unwrap_or(0))
                 .sum::<u64>() as i64;
             let num_docs = meta.num_docs() as i64;
             let num_deleted = meta.num_deleted_docs() as i64;
@@ -418,3 +406,74 @@ extension_sql!(
     name = "create_drop_bm25_event_trigger",
     requires = [ delete_bm25_index_by_oid ]
 );
+
+#[pg_extern(sql = "
+CREATE OR REPLACE FUNCTION paradedb.detach_bm25_event_trigger()
+RETURNS event_trigger
+LANGUAGE c AS 'MODULE_PATHNAME', '@FUNCTION_NAME@';
+")]
+unsafe fn detach_bm25_event_trigger(fcinfo: pg_sys::FunctionCallInfo) -> Result<()> {
+    let trigger_data = (*fcinfo).context as *mut pg_sys::EventTriggerData;
+    if trigger_data.is_null() {
+        return Ok(());
+    }
+    let Some(stmt) = nodecast!(AlterTableStmt, T_AlterTableStmt, (*trigger_data).parsetree) else {
+        return Ok(());
+    };
+    let parent_relid = pg_sys::RangeVarGetRelidExtended(
+        (*stmt).relation,
+        pg_sys::NoLock as _,
+        pg_sys::RVR_MISSING_OK,
+        None,
+        std::ptr::null_mut(),
+    );
+    if parent_relid == pg_sys::InvalidOid || locate_bm25_index(parent_relid).is_none() {
+        return Ok(());
+    }
+
+    // Postgres keeps the index of a detached partition as an index of its own, but it only ever
+    // was a part of the partitioned table's bm25 index, so it goes with the partition
+    let cmds = PgList::<pg_sys::AlterTableCmd>::from_pg((*stmt).cmds);
+    for cmd in cmds.iter_ptr() {
+        if (*cmd).subtype != pg_sys::AlterTableType::AT_DetachPartition {
+            continue;
+        }
+        let Some(partition_cmd) = nodecast!(PartitionCmd, T_PartitionCmd, (*cmd).def) else {
+            continue;
+        };
+        let relid = pg_sys::RangeVarGetRelidExtended(
+            (*partition_cmd).name,
+            pg_sys::NoLock as _,
+            pg_sys::RVR_MISSING_OK,
+            None,
+            std::ptr::null_mut(),
+        );
+        if relid == pg_sys::InvalidOid {
+            continue;
+        }
+        let Some(index_oid) = locate_bm25_index(relid).map(|indexrel| indexrel.oid()) else {
+            continue;
+        };
+
+        let object = pg_sys::ObjectAddress {
+            classId: pg_sys::RelationRelationId,
+            objectId: index_oid,
+            objectSubId: 0,
+        };
+        pg_sys::performDeletion(&object, pg_sys::DropBehavior::DROP_RESTRICT, 0);
+        // the sql_drop event has already fired for this command, so clean up after it ourselves
+        delete_bm25_index_by_oid(index_oid)?;
+    }
+    Ok(())
+}
+
+extension_sql!(
+    r#"
+    CREATE EVENT TRIGGER trigger_on_bm25_partition_detach
+    ON ddl_command_end
+    WHEN TAG IN ('ALTER TABLE')
+    EXECUTE FUNCTION paradedb.detach_bm25_event_trigger();
+    "#
+    name = "create_detach_bm25_event_trigger",
+    requires = [ detach_bm25_event_trigger ]
+);
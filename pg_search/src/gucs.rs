@@ -25,6 +25,14 @@ static TELEMETRY: GucSetting<bool> = GucSetting::<bool>::new(true);
 /// Allows the user to toggle the use of our "ParadeDB Custom Scan".  The default is `true`.
 static ENABLE_CUSTOM_SCAN: GucSetting<bool> = GucSetting::<bool>::new(true);
 
+/// Should the custom scan compute the aggregates and `GROUP BY` of a query over a search from the
+/// index's fast fields, rather than returning the rows for Postgres to aggregate?  Default is `true`.
+static ENABLE_AGGREGATE_CUSTOM_SCAN: GucSetting<bool> = GucSetting::<bool>::new(true);
+
+/// Should the scans of the tables under the same Append, such as the partitions of a partitioned
+/// table, score their matches with term statistics from all of their indexes?  Default is `false`.
+static ENABLE_GLOBAL_STATISTICS: GucSetting<bool> = GucSetting::<bool>::new(false);
+
 /// The `PER_TUPLE_COST` is an arbitrary value that needs to be really high.  In fact, we default
 /// to one hundred million.
 ///
@@ -57,6 +65,10 @@ static STATEMENT_PARALLELISM: GucSetting<i32> = GucSetting::<i32>::new(8);
 /// thread.  So if there's 10 threads and this value is 100MB, then a total of 1GB will be allocated.
 static STATEMENT_MEMORY_BUDGET: GucSetting<i32> = GucSetting::<i32>::new(0);
 
+/// How much memory can tantivy use to compute the buckets and metrics of a single aggregation?
+/// Default is `work_mem`.
+static AGGREGATE_MEMORY_LIMIT: GucSetting<i32> = GucSetting::<i32>::new(0);
+
 pub fn init() {
     // Note that Postgres is very specific about the naming convention of variables.
     // They must be namespaced... we use 'paradedb.<variable>' below.
@@ -80,6 +92,24 @@ pub fn init() {
         GucFlags::default(),
     );
 
+    GucRegistry::define_bool_guc(
+        "paradedb.enable_aggregate_custom_scan",
+        "Enable ParadeDB's custom scan for aggregates",
+        "When enabled, COUNT/SUM/AVG/MIN/MAX and GROUP BY over fast fields are computed by the index",
+        &ENABLE_AGGREGATE_CUSTOM_SCAN,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_bool_guc(
+        "paradedb.enable_global_statistics",
+        "Score the tables under the same Append with statistics from all of their indexes",
+        "When enabled, the partitions of a partitioned table are scored as if they were one index, so that their scores can be compared",
+        &ENABLE_GLOBAL_STATISTICS,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+
     GucRegistry::define_float_guc(
         "paradedb.per_tuple_cost",
         "Arbitrary multiplier for the cost of retrieving a tuple from a USING bm25 index outside of an IndexScan",
@@ -143,6 +173,17 @@ pub fn init() {
         GucContext::Suset,
         GucFlags::UNIT_MB,
     );
+
+    GucRegistry::define_int_guc(
+        "paradedb.aggregate_memory_limit",
+        "The amount of memory an aggregation can use before it fails",
+        "Default is `work_mem`",
+        &AGGREGATE_MEMORY_LIMIT,
+        0,
+        i32::MAX,
+        GucContext::Userset,
+        GucFlags::UNIT_MB,
+    );
 }
 
 pub fn telemetry_enabled() -> bool {
@@ -155,6 +196,14 @@ pub fn enable_custom_scan() -> bool {
     ENABLE_CUSTOM_SCAN.get()
 }
 
+pub fn enable_aggregate_custom_scan() -> bool {
+    ENABLE_AGGREGATE_CUSTOM_SCAN.get()
+}
+
+pub fn enable_global_statistics() -> bool {
+    ENABLE_GLOBAL_STATISTICS.get()
+}
+
 pub fn per_tuple_cost() -> f64 {
     PER_TUPLE_COST.get()
 }
@@ -179,6 +228,16 @@ pub fn statement_memory_budget() -> usize {
     adjust_budget(STATEMENT_MEMORY_BUDGET.get(), statement_parallelism())
 }
 
+pub fn aggregate_memory_limit() -> u64 {
+    match AGGREGATE_MEMORY_LIMIT.get() {
+        limit if limit <= 0 => unsafe {
+            // SAFETY:  Postgres sets work_mem when it starts up
+            pg_sys::work_mem as u64 * 1024 // convert from kilobytes to bytes
+        },
+        limit => limit as u64 * 1024 * 1024, // convert from megabytes to bytes
+    }
+}
+
 fn adjust_nthreads(nthreads: i32) -> NonZeroUsize {
     let nthreads = if nthreads <= 0 {
         std::thread::available_parallelism()
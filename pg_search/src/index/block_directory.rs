@@ -0,0 +1,1163 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! A [`tantivy::Directory`] that stores every index file in the blocks of the `bm25` index
+//! relation itself.
+//!
+//! All reads and writes go through the Postgres buffer manager and every page modification is
+//! logged with a generic WAL record, which makes the index crash-safe and lets it be shipped to
+//! physical replicas just like a btree.
+//!
+//! The relation is laid out as follows:
+//!
+//! - block 0 is the metapage.  It points to the first block of the current [`Catalog`]
+//! - the [`Catalog`] is a serialized map of file paths to the first block of each file,
+//!   along with a list of blocks that have been freed and can be recycled
+//! - every file, including the catalog itself, is a singly-linked chain of blocks whose
+//!   special area holds the number of the next block in the chain.  The catalog also records
+//!   the runs of blocks each file is made of, so that any byte range of a file can be read
+//!   without reading the blocks before it
+//!
+//! Readers only read the byte ranges tantivy asks for, one block at a time through the buffer
+//! manager, so shared buffers rather than each backend's memory hold the index.  The buffer
+//! manager can only be used from the backend's main thread though, and a tantivy `IndexWriter`
+//! opens segments from its own merge and commit threads.  Those threads forward their reads to
+//! the main thread, which does them while it waits on the writer in
+//! [`BlockDirectory::serve_reads_while`].  Files written by tantivy are kept in memory until
+//! [`BlockDirectory::flush`] is called, again from the main thread, which writes them out to new
+//! blocks and atomically swaps the metapage over to a new catalog.
+
+use anyhow::Result;
+use crossbeam::channel::{Receiver, Sender};
+use pgrx::pg_sys;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread::ThreadId;
+use tantivy::directory::error::{DeleteError, LockError, OpenReadError, OpenWriteError};
+use tantivy::directory::{
+    AntiCallToken, DirectoryLock, FileHandle, FileSlice, Lock, OwnedBytes, TerminatingWrite,
+    WatchCallback, WatchHandle, WritePtr, INDEX_WRITER_LOCK,
+};
+use tantivy::{Directory, HasLen};
+use thiserror::Error;
+use tracing::warn;
+
+/// The block number of the metapage
+pub const METAPAGE_BLOCKNO: pg_sys::BlockNumber = 0;
+
+/// The page lock we take, via `LockPage()`, to serialize tantivy writers across backends
+const WRITER_LOCK_BLOCKNO: pg_sys::BlockNumber = METAPAGE_BLOCKNO;
+
+const BLOCK_DIRECTORY_MAGIC: u32 = 0x424D3235; // "BM25"
+const BLOCK_DIRECTORY_VERSION: u32 = 1;
+
+/// Postgres' `SizeOfPageHeaderData`
+const PAGE_HEADER_SIZE: usize =
+    std::mem::size_of::<pg_sys::PageHeaderData>() - std::mem::size_of::<pg_sys::ItemIdData>();
+
+#[inline(always)]
+const fn maxalign(len: usize) -> usize {
+    (len + 7) & !7
+}
+
+/// The number of file bytes we can fit on a single block
+const BLOCK_DATA_SIZE: usize = pg_sys::BLCKSZ as usize
+    - maxalign(PAGE_HEADER_SIZE)
+    - maxalign(std::mem::size_of::<BlockSpecial>());
+
+/// Stored in the special area of every file block
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct BlockSpecial {
+    next_blockno: pg_sys::BlockNumber,
+}
+
+/// Stored in the contents area of the metapage
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct BlockDirectoryMetaData {
+    magic: u32,
+    version: u32,
+    catalog_start: pg_sys::BlockNumber,
+    catalog_len: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub start: pg_sys::BlockNumber,
+    pub len: u64,
+    /// The blocks of the file, in order, as runs of consecutive block numbers.  Catalogs written
+    /// before this was recorded leave it empty, and the chain is followed instead
+    #[serde(default)]
+    pub extents: Vec<Extent>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Extent {
+    pub start: pg_sys::BlockNumber,
+    pub nblocks: u32,
+}
+
+impl FileEntry {
+    /// Every block of the file, in order
+    unsafe fn blocks(&self, rel: pg_sys::Relation) -> Vec<pg_sys::BlockNumber> {
+        if self.extents.is_empty() {
+            return read_chain_blocks(rel, self.start);
+        }
+        self.extents
+            .iter()
+            .flat_map(|extent| extent.start..extent.start + extent.nblocks)
+            .collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+struct FreeBlock {
+    blockno: pg_sys::BlockNumber,
+    /// The next transaction id at the time this block was freed.  The block can only be
+    /// recycled once no running transaction could still be reading the file it belonged to
+    freed_xid: u64,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct Catalog {
+    files: HashMap<PathBuf, FileEntry>,
+    free_blocks: Vec<FreeBlock>,
+}
+
+#[derive(Debug, Default)]
+struct BlockDirectoryState {
+    catalog: Catalog,
+    /// The blocks that hold the serialized `catalog`
+    catalog_blocks: Vec<pg_sys::BlockNumber>,
+    /// File contents that have either been read from the buffer manager or written by tantivy
+    cache: HashMap<PathBuf, OwnedBytes>,
+    /// Files that have been written by tantivy but not yet flushed to blocks
+    dirty: HashSet<PathBuf>,
+    /// Files that have been deleted by tantivy but are still in the catalog
+    deleted: HashSet<PathBuf>,
+}
+
+/// A read a tantivy thread needs the backend's main thread to do for it
+type ForwardedRead = Box<dyn FnOnce(&BlockDirectory) + Send>;
+
+/// A [`Directory`] backed by the blocks of a Postgres index relation.
+///
+/// Clones share the same underlying state, so the copy held by the [`tantivy::Index`] and the
+/// copy held by our [`crate::index::SearchIndex`] always agree on what has and hasn't been
+/// flushed.
+#[derive(Clone)]
+pub struct BlockDirectory {
+    relation_oid: pg_sys::Oid,
+    backend_thread: ThreadId,
+    state: Arc<RwLock<BlockDirectoryState>>,
+    forwarded_sender: Sender<ForwardedRead>,
+    forwarded_receiver: Receiver<ForwardedRead>,
+}
+
+impl std::fmt::Debug for BlockDirectory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlockDirectory")
+            .field("relation_oid", &self.relation_oid)
+            .finish()
+    }
+}
+
+impl BlockDirectory {
+    /// Open the directory stored in the index relation with the specified oid.  The caller
+    /// must already hold a lock on the relation.
+    pub fn open(relation_oid: pg_sys::Oid) -> Result<Self, BlockDirectoryError> {
+        let (forwarded_sender, forwarded_receiver) = crossbeam::channel::unbounded();
+        let directory = Self {
+            relation_oid,
+            backend_thread: std::thread::current().id(),
+            state: Default::default(),
+            forwarded_sender,
+            forwarded_receiver,
+        };
+        directory.refresh()?;
+        Ok(directory)
+    }
+
+    /// Initialize an empty directory in a brand-new index relation, writing its metapage.
+    pub fn create(relation_oid: pg_sys::Oid) -> Result<Self, BlockDirectoryError> {
+        unsafe {
+            let rel = RelationGuard::open(relation_oid)?;
+            if pg_sys::RelationGetNumberOfBlocksInFork(rel.0, pg_sys::ForkNumber::MAIN_FORKNUM) != 0
+            {
+                return Err(BlockDirectoryError::NotEmpty(relation_oid));
+            }
+
+            let blockno = allocate_new_block(rel.0);
+            assert_eq!(
+                blockno, METAPAGE_BLOCKNO,
+                "metapage must be the first block"
+            );
+            write_metapage(rel.0, pg_sys::InvalidBlockNumber, 0);
+        }
+
+        Self::open(relation_oid)
+    }
+
+    /// The oid of the index relation that backs this directory
+    pub fn relation_oid(&self) -> pg_sys::Oid {
+        self.relation_oid
+    }
+
+    /// The length, in bytes, of the file at `path`, if it exists
+    pub fn file_len(&self, path: &Path) -> Option<u64> {
+        let state = self.state.read().unwrap();
+        if state.deleted.contains(path) {
+            return None;
+        }
+        state
+            .cache
+            .get(path)
+            .map(|bytes| bytes.len() as u64)
+            .or_else(|| state.catalog.files.get(path).map(|entry| entry.len))
+    }
+
+    /// The paths of all files currently visible in this directory
+    pub fn list_files(&self) -> Vec<PathBuf> {
+        let state = self.state.read().unwrap();
+        state
+            .catalog
+            .files
+            .keys()
+            .chain(state.dirty.iter())
+            .filter(|path| !state.deleted.contains(*path))
+            .cloned()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Re-read the catalog from the metapage, discarding anything we know about files that
+    /// have since been replaced or removed.  Files we've written but not yet flushed are kept.
+    pub fn refresh(&self) -> Result<(), BlockDirectoryError> {
+        self.assert_backend_thread();
+        let (catalog, catalog_blocks) = unsafe {
+            let rel = RelationGuard::open(self.relation_oid)?;
+            read_catalog(rel.0, self.relation_oid)?
+        };
+
+        let mut state = self.state.write().unwrap();
+        let BlockDirectoryState {
+            cache,
+            dirty,
+            catalog: old_catalog,
+            ..
+        } = &mut *state;
+
+        // segment files are immutable, but `meta.json` and `.managed.json` are rewritten in place,
+        // so anything whose catalog entry moved needs to be read again
+        cache.retain(|path, _| {
+            dirty.contains(path)
+                || matches!(
+                    (old_catalog.files.get(path), catalog.files.get(path)),
+                    (Some(old), Some(new)) if old.start == new.start
+                )
+        });
+        state.catalog = catalog;
+        state.catalog_blocks = catalog_blocks;
+        Ok(())
+    }
+
+    /// Run `f` on another thread while this one, the backend's main thread, does the reads that
+    /// tantivy's threads forward to it.  A tantivy `IndexWriter` opens existing segments from its
+    /// commit and merge threads, so anything that waits on those threads must go through here.
+    pub fn serve_reads_while<T: Send>(&self, f: impl FnOnce() -> T + Send) -> T {
+        self.assert_backend_thread();
+        std::thread::scope(|scope| {
+            // never sent to, it disconnects once `f` returns or panics
+            let (done, finished) = crossbeam::channel::bounded::<()>(0);
+            let worker = scope.spawn(move || {
+                let _done = done;
+                f()
+            });
+
+            let _abandon = AbandonForwardedReads {
+                reads: &self.forwarded_receiver,
+                finished: &finished,
+            };
+            loop {
+                crossbeam::channel::select! {
+                    recv(self.forwarded_receiver) -> read => {
+                        read.expect("the directory holds its own sender")(self)
+                    }
+                    recv(finished) -> _ => break,
+                }
+            }
+
+            match worker.join() {
+                Ok(result) => result,
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        })
+    }
+
+    /// Write every file tantivy has created since the last flush into relation blocks, release
+    /// the blocks of every file it has deleted, and atomically point the metapage at the new
+    /// catalog.  Must be called from the backend's main thread while holding the writer lock.
+    pub fn flush(&self) -> Result<(), BlockDirectoryError> {
+        self.assert_backend_thread();
+
+        let mut state = self.state.write().unwrap();
+        if state.dirty.is_empty() && state.deleted.is_empty() {
+            return Ok(());
+        }
+
+        unsafe {
+            let rel = RelationGuard::open(self.relation_oid)?;
+            let freed_xid = pg_sys::ReadNextFullTransactionId().value;
+            let mut catalog = state.catalog.clone();
+            let mut newly_freed = vec![];
+
+            for path in state.deleted.iter() {
+                if let Some(entry) = catalog.files.remove(path) {
+                    newly_freed.extend(entry.blocks(rel.0));
+                }
+            }
+
+            let mut dirty = state.dirty.iter().cloned().collect::<Vec<_>>();
+            // write the files tantivy uses to find everything else last
+            dirty.sort_by_key(|path| is_atomic_file(path));
+            for path in dirty {
+                let bytes = state
+                    .cache
+                    .get(&path)
+                    .expect("dirty file should be cached")
+                    .clone();
+                let blocks = write_chain(rel.0, &mut catalog, bytes.as_slice());
+                if let Some(old) = catalog.files.insert(
+                    path,
+                    FileEntry {
+                        start: blocks[0],
+                        len: bytes.len() as u64,
+                        extents: extents(&blocks),
+                    },
+                ) {
+                    newly_freed.extend(old.blocks(rel.0));
+                }
+            }
+
+            newly_freed.extend(state.catalog_blocks.iter().copied());
+            catalog.free_blocks.extend(
+                newly_freed
+                    .into_iter()
+                    .map(|blockno| FreeBlock { blockno, freed_xid }),
+            );
+
+            // the catalog always goes into freshly extended blocks so that serializing it
+            // doesn't change the free list we're in the middle of serializing
+            let serialized = serde_json::to_vec(&catalog)?;
+            let nblocks = serialized.len().div_ceil(BLOCK_DATA_SIZE).max(1);
+            let catalog_blocks = (0..nblocks)
+                .map(|_| allocate_new_block(rel.0))
+                .collect::<Vec<_>>();
+            write_blocks(rel.0, &catalog_blocks, &serialized);
+            write_metapage(rel.0, catalog_blocks[0], serialized.len() as u64);
+
+            state.catalog = catalog;
+            state.catalog_blocks = catalog_blocks;
+            state.dirty.clear();
+            state.deleted.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Get the bytes of the file at `path`, reading it from the buffer manager if this is
+    /// the first time we've seen it
+    fn load(&self, path: &Path) -> Result<OwnedBytes, BlockDirectoryError> {
+        {
+            let state = self.state.read().unwrap();
+            if state.deleted.contains(path) {
+                return Err(BlockDirectoryError::FileNotFound(path.to_path_buf()));
+            }
+            if let Some(bytes) = state.cache.get(path) {
+                return Ok(bytes.clone());
+            }
+        }
+
+        if std::thread::current().id() != self.backend_thread {
+            let owned = path.to_path_buf();
+            return self.forward(path, move |directory| directory.load(&owned));
+        }
+
+        let entry = self.catalog_entry(path)?;
+        let bytes = unsafe {
+            let rel = RelationGuard::open(self.relation_oid)?;
+            let bytes = read_chain(rel.0, entry.start, entry.len as usize)?;
+            self.check_recovery_conflict(rel.0, path, &entry)?;
+            OwnedBytes::new(bytes)
+        };
+
+        self.state
+            .write()
+            .unwrap()
+            .cache
+            .insert(path.to_path_buf(), bytes.clone());
+        Ok(bytes)
+    }
+
+    /// Open the file at `path` for reading.  Files we already hold in memory are served from
+    /// there, and all others are read from their blocks a range at a time.
+    fn open_file(&self, path: &Path) -> Result<Arc<dyn FileHandle>, BlockDirectoryError> {
+        {
+            let state = self.state.read().unwrap();
+            if state.deleted.contains(path) {
+                return Err(BlockDirectoryError::FileNotFound(path.to_path_buf()));
+            }
+            if let Some(bytes) = state.cache.get(path) {
+                return Ok(Arc::new(bytes.clone()));
+            }
+        }
+
+        if std::thread::current().id() != self.backend_thread {
+            let owned = path.to_path_buf();
+            return self.forward(path, move |directory| directory.open_file(&owned));
+        }
+
+        let entry = self.catalog_entry(path)?;
+        let blocks = unsafe {
+            let rel = RelationGuard::open(self.relation_oid)?;
+            entry.blocks(rel.0).into()
+        };
+        Ok(Arc::new(BlockFileHandle {
+            directory: self.clone(),
+            path: path.to_path_buf(),
+            entry,
+            blocks,
+        }))
+    }
+
+    /// Read `range` of a file opened with [`BlockDirectory::open_file`]
+    fn read_range(
+        &self,
+        path: &Path,
+        entry: &FileEntry,
+        blocks: &Arc<[pg_sys::BlockNumber]>,
+        range: Range<usize>,
+    ) -> Result<OwnedBytes, BlockDirectoryError> {
+        if std::thread::current().id() != self.backend_thread {
+            let (owned, entry, blocks) = (path.to_path_buf(), entry.clone(), blocks.clone());
+            return self.forward(path, move |directory| {
+                directory.read_range(&owned, &entry, &blocks, range)
+            });
+        }
+
+        unsafe {
+            let rel = RelationGuard::open(self.relation_oid)?;
+            let bytes = read_blocks_range(rel.0, blocks, range)?;
+            self.check_recovery_conflict(rel.0, path, entry)?;
+            Ok(OwnedBytes::new(bytes))
+        }
+    }
+
+    /// Hand `read` to the backend's main thread and wait for it to be done there.  The main
+    /// thread only gets to it from inside [`BlockDirectory::serve_reads_while`]
+    fn forward<T: Send + 'static>(
+        &self,
+        path: &Path,
+        read: impl FnOnce(&BlockDirectory) -> Result<T, BlockDirectoryError> + Send + 'static,
+    ) -> Result<T, BlockDirectoryError> {
+        let (reply, response) = crossbeam::channel::bounded(1);
+        self.forwarded_sender
+            .send(Box::new(move |directory: &BlockDirectory| {
+                reply.send(read(directory)).ok();
+            }))
+            .expect("the directory holds its own receiver");
+        response
+            .recv()
+            .map_err(|_| BlockDirectoryError::ReadAbandoned(path.to_path_buf()))?
+    }
+
+    fn catalog_entry(&self, path: &Path) -> Result<FileEntry, BlockDirectoryError> {
+        self.state
+            .read()
+            .unwrap()
+            .catalog
+            .files
+            .get(path)
+            .cloned()
+            .ok_or_else(|| BlockDirectoryError::FileNotFound(path.to_path_buf()))
+    }
+
+    /// The primary only recycles a deleted file's blocks once none of *its* transactions could
+    /// still be reading them, but it has no idea what a hot standby is doing.  If the catalog
+    /// moved on while we were reading, make sure the file we read from wasn't one that got
+    /// deleted out from under us
+    unsafe fn check_recovery_conflict(
+        &self,
+        rel: pg_sys::Relation,
+        path: &Path,
+        entry: &FileEntry,
+    ) -> Result<(), BlockDirectoryError> {
+        if !pg_sys::RecoveryInProgress() {
+            return Ok(());
+        }
+
+        let catalog_start = self.state.read().unwrap().catalog_blocks.first().copied();
+        let metadata = read_metadata(rel, self.relation_oid)?;
+        if Some(metadata.catalog_start) != catalog_start {
+            let (catalog, _) = read_catalog(rel, self.relation_oid)?;
+            match catalog.files.get(path) {
+                Some(current) if current.start == entry.start && current.len == entry.len => {}
+                _ => return Err(BlockDirectoryError::RecoveryConflict(path.to_path_buf())),
+            }
+        }
+        Ok(())
+    }
+
+    fn store(&self, path: &Path, bytes: Vec<u8>) {
+        let mut state = self.state.write().unwrap();
+        state
+            .cache
+            .insert(path.to_path_buf(), OwnedBytes::new(bytes));
+        state.dirty.insert(path.to_path_buf());
+        state.deleted.remove(path);
+    }
+
+    fn assert_backend_thread(&self) {
+        assert_eq!(
+            std::thread::current().id(),
+            self.backend_thread,
+            "BlockDirectory buffer access must happen on the backend's main thread"
+        );
+    }
+}
+
+impl Directory for BlockDirectory {
+    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+        self.open_file(path)
+            .map_err(|e| e.into_open_read_error(path))
+    }
+
+    fn open_read(&self, path: &Path) -> Result<FileSlice, OpenReadError> {
+        Ok(FileSlice::new(self.get_file_handle(path)?))
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        if self.file_len(path).is_some() {
+            return Err(OpenWriteError::FileAlreadyExists(path.to_path_buf()));
+        }
+
+        // tantivy expects the file to exist as soon as it's been opened for writing
+        self.store(path, vec![]);
+        Ok(io::BufWriter::new(Box::new(BlockFileWriter {
+            path: path.to_path_buf(),
+            buffer: vec![],
+            directory: self.clone(),
+            is_flushed: true,
+        })))
+    }
+
+    fn atomic_write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.store(path, data.to_vec());
+        Ok(())
+    }
+
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        Ok(self
+            .load(path)
+            .map_err(|e| e.into_open_read_error(path))?
+            .as_slice()
+            .to_vec())
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), DeleteError> {
+        let mut state = self.state.write().unwrap();
+        let in_catalog = state.catalog.files.contains_key(path);
+        let was_dirty = state.dirty.remove(path);
+        state.cache.remove(path);
+
+        if in_catalog {
+            state.deleted.insert(path.to_path_buf());
+        } else if !was_dirty {
+            return Err(DeleteError::FileDoesNotExist(path.to_path_buf()));
+        }
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        Ok(self.file_len(path).is_some())
+    }
+
+    fn acquire_lock(&self, lock: &Lock) -> Result<DirectoryLock, LockError> {
+        if lock.filepath != INDEX_WRITER_LOCK.filepath {
+            // tantivy's other lock, the `META_LOCK`, only protects against garbage collecting
+            // files that a reader in this same process is in the middle of opening.  A deleted
+            // file's blocks are never recycled while any transaction could still be reading them,
+            // so it doesn't need to be a real lock
+            return Ok(DirectoryLock::from(Box::new(())));
+        }
+
+        // like the directory we replaced, the writer lock always blocks until it's available
+        // rather than failing.  The lock is a Postgres page lock on the index relation so that
+        // it's visible to every backend
+        unsafe {
+            let rel = RelationGuard::open(self.relation_oid)
+                .map_err(|e| LockError::IoError(Arc::new(io::Error::other(e))))?;
+            pg_sys::LockPage(
+                rel.0,
+                WRITER_LOCK_BLOCKNO,
+                pg_sys::ExclusiveLock as pg_sys::LOCKMODE,
+            );
+        }
+
+        let guard = WriterLockGuard {
+            relation_oid: self.relation_oid,
+        };
+
+        // another backend may have flushed changes while we waited for the lock, and tantivy is
+        // about to read `meta.json` to find out what segments exist
+        self.refresh()
+            .map_err(|e| LockError::IoError(Arc::new(io::Error::other(e))))?;
+
+        Ok(DirectoryLock::from(Box::new(guard)))
+    }
+
+    fn watch(&self, _watch_callback: WatchCallback) -> tantivy::Result<WatchHandle> {
+        // our readers always use `ReloadPolicy::Manual`
+        Ok(WatchHandle::empty())
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        // durability comes from WAL when the directory is flushed
+        Ok(())
+    }
+}
+
+/// Releases the writer page lock when tantivy drops its `DirectoryLock`
+struct WriterLockGuard {
+    relation_oid: pg_sys::Oid,
+}
+
+impl Drop for WriterLockGuard {
+    fn drop(&mut self) {
+        unsafe {
+            if !pg_sys::IsTransactionState() {
+                // the lock was already released by the transaction's end
+                return;
+            }
+            if let Ok(rel) = RelationGuard::open(self.relation_oid) {
+                pg_sys::UnlockPage(
+                    rel.0,
+                    WRITER_LOCK_BLOCKNO,
+                    pg_sys::ExclusiveLock as pg_sys::LOCKMODE,
+                );
+            }
+        }
+    }
+}
+
+/// Turns down the reads forwarded to a backend that's unwinding from an error, until the thread
+/// it was serving has finished, so that the thread isn't left waiting on it forever
+struct AbandonForwardedReads<'a> {
+    reads: &'a Receiver<ForwardedRead>,
+    finished: &'a Receiver<()>,
+}
+
+impl Drop for AbandonForwardedReads<'_> {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            return;
+        }
+        loop {
+            crossbeam::channel::select! {
+                // dropping the read drops its reply sender, and the reader gets an error instead
+                recv(self.reads) -> read => drop(read),
+                recv(self.finished) -> _ => return,
+            }
+        }
+    }
+}
+
+/// A file stored in the relation's blocks, read a byte range at a time as tantivy asks for it
+#[derive(Debug)]
+struct BlockFileHandle {
+    directory: BlockDirectory,
+    path: PathBuf,
+    entry: FileEntry,
+    blocks: Arc<[pg_sys::BlockNumber]>,
+}
+
+impl HasLen for BlockFileHandle {
+    fn len(&self) -> usize {
+        self.entry.len as usize
+    }
+}
+
+impl FileHandle for BlockFileHandle {
+    fn read_bytes(&self, range: Range<usize>) -> io::Result<OwnedBytes> {
+        if range.end > self.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "{:?} is {} bytes long, can't read {range:?}",
+                    self.path,
+                    self.len()
+                ),
+            ));
+        }
+        self.directory
+            .read_range(&self.path, &self.entry, &self.blocks, range)
+            .map_err(io::Error::other)
+    }
+}
+
+/// Buffers a file tantivy is writing, handing it to the [`BlockDirectory`] when it's terminated
+struct BlockFileWriter {
+    path: PathBuf,
+    buffer: Vec<u8>,
+    directory: BlockDirectory,
+    is_flushed: bool,
+}
+
+impl Write for BlockFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.is_flushed = false;
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.is_flushed = true;
+        self.directory.store(&self.path, self.buffer.clone());
+        Ok(())
+    }
+}
+
+impl TerminatingWrite for BlockFileWriter {
+    fn terminate_ref(&mut self, _: AntiCallToken) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl Drop for BlockFileWriter {
+    fn drop(&mut self) {
+        if !self.is_flushed {
+            // this can be called from one of tantivy's threads, so we can't use `pgrx::warning!()`
+            warn!(
+                "{:?} was dropped before being flushed, likely because the indexer failed",
+                self.path
+            )
+        }
+    }
+}
+
+/// Is this one of the files tantivy rewrites in place via [`Directory::atomic_write`]?
+fn is_atomic_file(path: &Path) -> bool {
+    matches!(
+        path.file_name().and_then(|name| name.to_str()),
+        Some("meta.json") | Some(".managed.json")
+    )
+}
+
+/// Holds a relcache reference to the index relation for the duration of a directory operation
+struct RelationGuard(pg_sys::Relation);
+
+impl RelationGuard {
+    unsafe fn open(relation_oid: pg_sys::Oid) -> Result<Self, BlockDirectoryError> {
+        let rel = pg_sys::RelationIdGetRelation(relation_oid);
+        if rel.is_null() {
+            return Err(BlockDirectoryError::RelationNotFound(relation_oid));
+        }
+        Ok(Self(rel))
+    }
+}
+
+impl Drop for RelationGuard {
+    fn drop(&mut self) {
+        unsafe { pg_sys::RelationClose(self.0) }
+    }
+}
+
+#[inline(always)]
+unsafe fn page_contents(page: pg_sys::Page) -> *mut u8 {
+    page.cast::<u8>().add(maxalign(PAGE_HEADER_SIZE))
+}
+
+#[inline(always)]
+unsafe fn page_special(page: pg_sys::Page) -> *mut BlockSpecial {
+    let header = page.cast::<pg_sys::PageHeaderData>();
+    page.cast::<u8>()
+        .add((*header).pd_special as usize)
+        .cast::<BlockSpecial>()
+}
+
+/// Extend the relation by one block and return its number.  The new block is left
+/// uninitialized -- it becomes valid when it's first written through [`write_blocks`].
+unsafe fn allocate_new_block(rel: pg_sys::Relation) -> pg_sys::BlockNumber {
+    pg_sys::LockRelationForExtension(rel, pg_sys::ExclusiveLock as pg_sys::LOCKMODE);
+    let buffer = pg_sys::ReadBufferExtended(
+        rel,
+        pg_sys::ForkNumber::MAIN_FORKNUM,
+        pg_sys::InvalidBlockNumber, // P_NEW
+        pg_sys::ReadBufferMode::RBM_NORMAL,
+        std::ptr::null_mut(),
+    );
+    pg_sys::UnlockRelationForExtension(rel, pg_sys::ExclusiveLock as pg_sys::LOCKMODE);
+    let blockno = pg_sys::BufferGetBlockNumber(buffer);
+    pg_sys::ReleaseBuffer(buffer);
+    blockno
+}
+
+/// Find a block to write to, preferring one from the catalog's free list that no running
+/// transaction could still be reading
+unsafe fn allocate_block(rel: pg_sys::Relation, catalog: &mut Catalog) -> pg_sys::BlockNumber {
+    if let Some(idx) = catalog
+        .free_blocks
+        .iter()
+        .position(|free| is_recyclable(rel, free.freed_xid))
+    {
+        return catalog.free_blocks.swap_remove(idx).blockno;
+    }
+    allocate_new_block(rel)
+}
+
+unsafe fn is_recyclable(rel: pg_sys::Relation, freed_xid: u64) -> bool {
+    let xid = freed_xid as pg_sys::TransactionId;
+
+    #[cfg(feature = "pg13")]
+    {
+        let _ = rel;
+        pg_sys::TransactionIdPrecedes(xid, pg_sys::RecentGlobalXmin)
+    }
+
+    #[cfg(any(feature = "pg14", feature = "pg15", feature = "pg16", feature = "pg17"))]
+    {
+        pg_sys::GlobalVisCheckRemovableXid(rel, xid)
+    }
+}
+
+/// Write `data` as a new chain of blocks and return the blocks, in order
+unsafe fn write_chain(
+    rel: pg_sys::Relation,
+    catalog: &mut Catalog,
+    data: &[u8],
+) -> Vec<pg_sys::BlockNumber> {
+    let nblocks = data.len().div_ceil(BLOCK_DATA_SIZE).max(1);
+    let blocks = (0..nblocks)
+        .map(|_| allocate_block(rel, catalog))
+        .collect::<Vec<_>>();
+    write_blocks(rel, &blocks, data);
+    blocks
+}
+
+/// Collapse `blocks` into runs of consecutive block numbers
+fn extents(blocks: &[pg_sys::BlockNumber]) -> Vec<Extent> {
+    let mut extents: Vec<Extent> = vec![];
+    for &blockno in blocks {
+        match extents.last_mut() {
+            Some(last) if last.start + last.nblocks == blockno => last.nblocks += 1,
+            _ => extents.push(Extent {
+                start: blockno,
+                nblocks: 1,
+            }),
+        }
+    }
+    extents
+}
+
+/// Write `data` across `blocks`, linking each block to the next, WAL-logging every page
+unsafe fn write_blocks(rel: pg_sys::Relation, blocks: &[pg_sys::BlockNumber], data: &[u8]) {
+    let mut chunks = data.chunks(BLOCK_DATA_SIZE);
+    for (i, blockno) in blocks.iter().enumerate() {
+        let chunk = chunks.next().unwrap_or_default();
+        let next_blockno = blocks
+            .get(i + 1)
+            .copied()
+            .unwrap_or(pg_sys::InvalidBlockNumber);
+
+        let buffer = pg_sys::ReadBufferExtended(
+            rel,
+            pg_sys::ForkNumber::MAIN_FORKNUM,
+            *blockno,
+            pg_sys::ReadBufferMode::RBM_NORMAL,
+            std::ptr::null_mut(),
+        );
+        pg_sys::LockBuffer(buffer, pg_sys::BUFFER_LOCK_EXCLUSIVE as i32);
+
+        let xlog_state = pg_sys::GenericXLogStart(rel);
+        let page = pg_sys::GenericXLogRegisterBuffer(
+            xlog_state,
+            buffer,
+            pg_sys::GENERIC_XLOG_FULL_IMAGE as _,
+        );
+        pg_sys::PageInit(
+            page,
+            pg_sys::BLCKSZ as usize,
+            std::mem::size_of::<BlockSpecial>(),
+        );
+        std::ptr::copy_nonoverlapping(chunk.as_ptr(), page_contents(page), chunk.len());
+        (*page.cast::<pg_sys::PageHeaderData>()).pd_lower =
+            (maxalign(PAGE_HEADER_SIZE) + chunk.len()) as _;
+        (*page_special(page)).next_blockno = next_blockno;
+        pg_sys::GenericXLogFinish(xlog_state);
+
+        pg_sys::UnlockReleaseBuffer(buffer);
+    }
+}
+
+/// Follow a chain of blocks starting at `start`, returning every block number in it
+unsafe fn read_chain_blocks(
+    rel: pg_sys::Relation,
+    start: pg_sys::BlockNumber,
+) -> Vec<pg_sys::BlockNumber> {
+    let mut blocks = vec![];
+    let mut blockno = start;
+    while blockno != pg_sys::InvalidBlockNumber {
+        blocks.push(blockno);
+        let buffer = pg_sys::ReadBufferExtended(
+            rel,
+            pg_sys::ForkNumber::MAIN_FORKNUM,
+            blockno,
+            pg_sys::ReadBufferMode::RBM_NORMAL,
+            std::ptr::null_mut(),
+        );
+        pg_sys::LockBuffer(buffer, pg_sys::BUFFER_LOCK_SHARE as i32);
+        blockno = (*page_special(pg_sys::BufferGetPage(buffer))).next_blockno;
+        pg_sys::UnlockReleaseBuffer(buffer);
+    }
+    blocks
+}
+
+/// Read `len` bytes from the chain of blocks starting at `start`
+unsafe fn read_chain(
+    rel: pg_sys::Relation,
+    start: pg_sys::BlockNumber,
+    len: usize,
+) -> Result<Vec<u8>, BlockDirectoryError> {
+    let mut data = Vec::with_capacity(len);
+    let mut blockno = start;
+    while data.len() < len {
+        if blockno == pg_sys::InvalidBlockNumber {
+            return Err(BlockDirectoryError::TruncatedChain(start, len, data.len()));
+        }
+
+        let buffer = pg_sys::ReadBufferExtended(
+            rel,
+            pg_sys::ForkNumber::MAIN_FORKNUM,
+            blockno,
+            pg_sys::ReadBufferMode::RBM_NORMAL,
+            std::ptr::null_mut(),
+        );
+        pg_sys::LockBuffer(buffer, pg_sys::BUFFER_LOCK_SHARE as i32);
+        let page = pg_sys::BufferGetPage(buffer);
+        let used =
+            (*page.cast::<pg_sys::PageHeaderData>()).pd_lower as usize - maxalign(PAGE_HEADER_SIZE);
+        let take = used.min(len - data.len());
+        data.extend_from_slice(std::slice::from_raw_parts(page_contents(page), take));
+        blockno = (*page_special(page)).next_blockno;
+        pg_sys::UnlockReleaseBuffer(buffer);
+    }
+    Ok(data)
+}
+
+/// Read `range` of the file stored in `blocks`.  Every block but a file's last one is full, so
+/// the blocks holding the range can be found without reading the ones before it.
+unsafe fn read_blocks_range(
+    rel: pg_sys::Relation,
+    blocks: &[pg_sys::BlockNumber],
+    range: Range<usize>,
+) -> Result<Vec<u8>, BlockDirectoryError> {
+    let mut data = Vec::with_capacity(range.len());
+    let mut offset = range.start;
+    while offset < range.end {
+        let idx = offset / BLOCK_DATA_SIZE;
+        let Some(&blockno) = blocks.get(idx) else {
+            return Err(BlockDirectoryError::TruncatedChain(
+                blocks
+                    .first()
+                    .copied()
+                    .unwrap_or(pg_sys::InvalidBlockNumber),
+                range.end,
+                offset,
+            ));
+        };
+
+        let buffer = pg_sys::ReadBufferExtended(
+            rel,
+            pg_sys::ForkNumber::MAIN_FORKNUM,
+            blockno,
+            pg_sys::ReadBufferMode::RBM_NORMAL,
+            std::ptr::null_mut(),
+        );
+        pg_sys::LockBuffer(buffer, pg_sys::BUFFER_LOCK_SHARE as i32);
+        let page = pg_sys::BufferGetPage(buffer);
+        let used =
+            (*page.cast::<pg_sys::PageHeaderData>()).pd_lower as usize - maxalign(PAGE_HEADER_SIZE);
+        let within = offset % BLOCK_DATA_SIZE;
+        let take = (BLOCK_DATA_SIZE - within).min(range.end - offset);
+        if within + take > used {
+            pg_sys::UnlockReleaseBuffer(buffer);
+            return Err(BlockDirectoryError::TruncatedChain(
+                blocks[0],
+                range.end,
+                offset + used.saturating_sub(within),
+            ));
+        }
+        data.extend_from_slice(std::slice::from_raw_parts(
+            page_contents(page).add(within),
+            take,
+        ));
+        pg_sys::UnlockReleaseBuffer(buffer);
+        offset += take;
+    }
+    Ok(data)
+}
+
+unsafe fn write_metapage(
+    rel: pg_sys::Relation,
+    catalog_start: pg_sys::BlockNumber,
+    catalog_len: u64,
+) {
+    let buffer = pg_sys::ReadBufferExtended(
+        rel,
+        pg_sys::ForkNumber::MAIN_FORKNUM,
+        METAPAGE_BLOCKNO,
+        pg_sys::ReadBufferMode::RBM_NORMAL,
+        std::ptr::null_mut(),
+    );
+    pg_sys::LockBuffer(buffer, pg_sys::BUFFER_LOCK_EXCLUSIVE as i32);
+
+    let xlog_state = pg_sys::GenericXLogStart(rel);
+    let page =
+        pg_sys::GenericXLogRegisterBuffer(xlog_state, buffer, pg_sys::GENERIC_XLOG_FULL_IMAGE as _);
+    pg_sys::PageInit(page, pg_sys::BLCKSZ as usize, 0);
+    let metadata = page_contents(page).cast::<BlockDirectoryMetaData>();
+    *metadata = BlockDirectoryMetaData {
+        magic: BLOCK_DIRECTORY_MAGIC,
+        version: BLOCK_DIRECTORY_VERSION,
+        catalog_start,
+        catalog_len,
+    };
+    (*page.cast::<pg_sys::PageHeaderData>()).pd_lower =
+        (maxalign(PAGE_HEADER_SIZE) + std::mem::size_of::<BlockDirectoryMetaData>()) as _;
+    pg_sys::GenericXLogFinish(xlog_state);
+
+    pg_sys::UnlockReleaseBuffer(buffer);
+}
+
+unsafe fn read_metadata(
+    rel: pg_sys::Relation,
+    relation_oid: pg_sys::Oid,
+) -> Result<BlockDirectoryMetaData, BlockDirectoryError> {
+    if pg_sys::RelationGetNumberOfBlocksInFork(rel, pg_sys::ForkNumber::MAIN_FORKNUM) == 0 {
+        return Err(BlockDirectoryError::NotInitialized(relation_oid));
+    }
+
+    let buffer = pg_sys::ReadBufferExtended(
+        rel,
+        pg_sys::ForkNumber::MAIN_FORKNUM,
+        METAPAGE_BLOCKNO,
+        pg_sys::ReadBufferMode::RBM_NORMAL,
+        std::ptr::null_mut(),
+    );
+    pg_sys::LockBuffer(buffer, pg_sys::BUFFER_LOCK_SHARE as i32);
+    let metadata = *page_contents(pg_sys::BufferGetPage(buffer)).cast::<BlockDirectoryMetaData>();
+    pg_sys::UnlockReleaseBuffer(buffer);
+
+    if metadata.magic != BLOCK_DIRECTORY_MAGIC {
+        return Err(BlockDirectoryError::BadMagic(relation_oid));
+    }
+    if metadata.version != BLOCK_DIRECTORY_VERSION {
+        return Err(BlockDirectoryError::UnsupportedVersion(
+            relation_oid,
+            metadata.version,
+        ));
+    }
+    Ok(metadata)
+}
+
+unsafe fn read_catalog(
+    rel: pg_sys::Relation,
+    relation_oid: pg_sys::Oid,
+) -> Result<(Catalog, Vec<pg_sys::BlockNumber>), BlockDirectoryError> {
+    loop {
+        let metadata = read_metadata(rel, relation_oid)?;
+        if metadata.catalog_start == pg_sys::InvalidBlockNumber {
+            return Ok((Catalog::default(), vec![]));
+        }
+
+        let bytes = read_chain(rel, metadata.catalog_start, metadata.catalog_len as usize)?;
+        let catalog_blocks = read_chain_blocks(rel, metadata.catalog_start);
+
+        // readers don't block writers from flushing, and on a hot standby they can't block WAL
+        // replay either.  If the metapage changed while we were reading, the old catalog's blocks
+        // may have been reused so start over with the new one
+        if read_metadata(rel, relation_oid)?.catalog_start != metadata.catalog_start {
+            continue;
+        }
+
+        let catalog = serde_json::from_slice(&bytes)?;
+        return Ok((catalog, catalog_blocks));
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BlockDirectoryError {
+    #[error("index relation {0:?} does not exist")]
+    RelationNotFound(pg_sys::Oid),
+
+    #[error("index relation {0:?} has not been initialized")]
+    NotInitialized(pg_sys::Oid),
+
+    #[error("index relation {0:?} already contains blocks")]
+    NotEmpty(pg_sys::Oid),
+
+    #[error("index relation {0:?} has an invalid metapage")]
+    BadMagic(pg_sys::Oid),
+
+    #[error("index relation {0:?} has unsupported directory version {1}")]
+    UnsupportedVersion(pg_sys::Oid, u32),
+
+    #[error("file {0:?} does not exist")]
+    FileNotFound(PathBuf),
+
+    #[error("file {0:?} was being read for a background thread when the read was abandoned")]
+    ReadAbandoned(PathBuf),
+
+    #[error(
+        "file {0:?} was removed by the primary while being read during recovery, please retry"
+    )]
+    RecoveryConflict(PathBuf),
+
+    #[error("block chain starting at {0} ended after {2} of {1} bytes")]
+    TruncatedChain(pg_sys::BlockNumber, usize, usize),
+
+    #[error(transparent)]
+    SerdeJsonError(#[from] serde_json::Error),
+}
+
+impl BlockDirectoryError {
+    fn into_open_read_error(self, path: &Path) -> OpenReadError {
+        match self {
+            BlockDirectoryError::FileNotFound(path) => OpenReadError::FileDoesNotExist(path),
+            other => OpenReadError::IoError {
+                io_error: Arc::new(io::Error::other(other)),
+                filepath: path.to_path_buf(),
+            },
+        }
+    }
+}
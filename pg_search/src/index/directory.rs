@@ -29,7 +29,7 @@ use thiserror::Error;
 use walkdir::WalkDir;
 
 static SEARCH_DIR_NAME: &str = "pg_search";
-static SEARCH_INDEX_CONFIG_FILE_NAME: &str = "search-index.json";
+pub(crate) static SEARCH_INDEX_CONFIG_FILE_NAME: &str = "search-index.json";
 static TANTIVY_DIR_NAME: &str = "tantivy";
 
 /// The top-level folder name for ParadeDB extension inside the Postgres data directory.
@@ -19,8 +19,9 @@
 use crate::index::reader::SearchIndexReader;
 use crate::postgres::types::TantivyValue;
 use crate::schema::SearchFieldType;
+use std::fmt::Debug;
 use std::sync::Arc;
-use tantivy::columnar::{ColumnValues, StrColumn};
+use tantivy::columnar::{Column, ColumnValues, StrColumn};
 use tantivy::fastfield::FastFieldReaders;
 use tantivy::schema::OwnedValue;
 use tantivy::{DocAddress, DocId};
@@ -86,6 +87,15 @@ pub enum FFType {
     U64(Arc<dyn ColumnValues<u64>>),
     Bool(Arc<dyn ColumnValues<bool>>),
     Date(Arc<dyn ColumnValues<tantivy::DateTime>>),
+
+    // the columns of multi-valued fields, such as those of array columns, whose values are read
+    // as an array rather than only the first of them
+    Texts(StrColumn),
+    I64s(Column<i64>),
+    F64s(Column<f64>),
+    U64s(Column<u64>),
+    Bools(Column<bool>),
+    Dates(Column<tantivy::DateTime>),
 }
 
 impl FFType {
@@ -94,17 +104,41 @@ impl FFType {
     #[track_caller]
     pub fn new(ffr: &FastFieldReaders, field_name: &str) -> Self {
         if let Ok(Some(ff)) = ffr.str(field_name) {
-            Self::Text(ff)
+            if ff.ords().get_cardinality().is_multivalue() {
+                Self::Texts(ff)
+            } else {
+                Self::Text(ff)
+            }
         } else if let Ok(ff) = ffr.u64(field_name) {
-            Self::U64(ff.first_or_default_col(0))
+            if ff.get_cardinality().is_multivalue() {
+                Self::U64s(ff)
+            } else {
+                Self::U64(ff.first_or_default_col(0))
+            }
         } else if let Ok(ff) = ffr.i64(field_name) {
-            Self::I64(ff.first_or_default_col(0))
+            if ff.get_cardinality().is_multivalue() {
+                Self::I64s(ff)
+            } else {
+                Self::I64(ff.first_or_default_col(0))
+            }
         } else if let Ok(ff) = ffr.f64(field_name) {
-            Self::F64(ff.first_or_default_col(0.0))
+            if ff.get_cardinality().is_multivalue() {
+                Self::F64s(ff)
+            } else {
+                Self::F64(ff.first_or_default_col(0.0))
+            }
         } else if let Ok(ff) = ffr.bool(field_name) {
-            Self::Bool(ff.first_or_default_col(false))
+            if ff.get_cardinality().is_multivalue() {
+                Self::Bools(ff)
+            } else {
+                Self::Bool(ff.first_or_default_col(false))
+            }
         } else if let Ok(ff) = ffr.date(field_name) {
-            Self::Date(ff.first_or_default_col(tantivy::DateTime::MIN))
+            if ff.get_cardinality().is_multivalue() {
+                Self::Dates(ff)
+            } else {
+                Self::Date(ff.first_or_default_col(tantivy::DateTime::MIN))
+            }
         } else {
             panic!("`{field_name}` is missing or is not configured as a fast field")
         }
@@ -130,6 +164,21 @@ impl FFType {
             FFType::U64(ff) => TantivyValue(ff.get_val(doc).into()),
             FFType::Bool(ff) => TantivyValue(ff.get_val(doc).into()),
             FFType::Date(ff) => TantivyValue(ff.get_val(doc).into()),
+            FFType::Texts(ff) => TantivyValue(OwnedValue::Array(
+                ff.term_ords(doc)
+                    .map(|ord| {
+                        let mut s = String::new();
+                        ff.ord_to_str(ord, &mut s)
+                            .expect("string should be retrievable for term ord");
+                        s.into()
+                    })
+                    .collect(),
+            )),
+            FFType::I64s(ff) => multi_value(ff, doc),
+            FFType::F64s(ff) => multi_value(ff, doc),
+            FFType::U64s(ff) => multi_value(ff, doc),
+            FFType::Bools(ff) => multi_value(ff, doc),
+            FFType::Dates(ff) => multi_value(ff, doc),
         };
 
         value
@@ -181,6 +230,17 @@ impl FFType {
     }
 }
 
+/// All the values of `doc` in the multi-valued column `ff`, as an array
+#[inline(always)]
+fn multi_value<T>(ff: &Column<T>, doc: DocId) -> TantivyValue
+where
+    T: PartialOrd + Copy + Debug + Send + Sync + 'static + Into<OwnedValue>,
+{
+    TantivyValue(OwnedValue::Array(
+        ff.values_for_doc(doc).map(Into::into).collect(),
+    ))
+}
+
 #[derive(Debug, Clone, Ord, Eq, PartialOrd, PartialEq)]
 pub enum WhichFastField {
     Junk(String),
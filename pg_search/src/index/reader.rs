@@ -15,19 +15,31 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use super::statistics::GlobalStatistics;
 use super::SearchIndex;
-use crate::query::SearchQueryInput;
-use crate::schema::{SearchFieldName, SearchIndexSchema};
+use crate::gucs;
+use crate::query::{exclude_nested_children, SearchQueryInput};
+use crate::schema::{SearchFieldName, SearchFieldType, SearchIndexSchema};
 use anyhow::Result;
+use multi_key_collector::MultiKeyCollector;
 use pgrx::PgRelation;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::fmt::{Debug, Formatter};
-use tantivy::collector::{Collector, TopDocs};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+use tantivy::aggregation::agg_req::Aggregations;
+use tantivy::aggregation::agg_result::AggregationResults;
+use tantivy::aggregation::{AggregationCollector, AggregationLimits};
+use tantivy::collector::{
+    Collector, Count, FacetCollector, FacetCounts, FilterCollector, MultiCollector, TopDocs,
+};
 use tantivy::fastfield::Column;
-use tantivy::query::QueryParser;
-use tantivy::schema::{FieldType, Value};
+use tantivy::query::{Bm25StatisticsProvider, EnableScoring, QueryParser};
+use tantivy::schema::{Facet, FieldType, Value};
 use tantivy::{
-    query::Query, DocAddress, DocId, Order, Score, Searcher, SegmentOrdinal, TantivyDocument,
+    query::Query, DocAddress, DocId, DocSet, Score, Searcher, SegmentOrdinal, TantivyDocument,
     TantivyError,
 };
 use tantivy::{snippet::SnippetGenerator, Executor};
@@ -61,12 +73,102 @@ impl PartialOrd for SearchIndexScore {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+/// The documents matching a query, and how many of them are filed under each of the immediate
+/// children of each facet that was asked for, collected in the same pass over the index as the
+/// search that found them.
+///
+/// The counts have no understanding of Postgres MVCC visibility.  The documents that aren't
+/// visible are taken back out by [`SearchIndexReader::visible_facet_counts`].
+#[derive(Default)]
+pub struct FacetMatches {
+    facets: Vec<(String, Facet)>,
+    counts: Vec<FacetCounts>,
+    docs: Vec<(u64, DocAddress)>,
+}
+
+impl FacetMatches {
+    /// The ctids of every document that matched
+    pub fn ctids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.docs.iter().map(|(ctid, _)| *ctid)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SortDirection {
     Asc,
     Desc,
 }
 
+impl Display for SortDirection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SortDirection::Asc => write!(f, "asc"),
+            SortDirection::Desc => write!(f, "desc"),
+        }
+    }
+}
+
+/// One key of a "top N" ordering, which is either the BM25 score or a fast field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SortKey {
+    /// The fast field to order by, or [`None`] to order by score
+    pub field: Option<String>,
+    pub direction: SortDirection,
+    pub nulls_first: bool,
+}
+
+impl Display for SortKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let field = self.field.as_deref().unwrap_or("paradedb.score()");
+        write!(f, "{field} {}", self.direction)?;
+
+        // only mention where the NULLs go when it isn't Postgres' default for the direction
+        match (self.direction, self.nulls_first) {
+            (SortDirection::Asc, true) => write!(f, " nulls first"),
+            (SortDirection::Desc, false) => write!(f, " nulls last"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parses the same "field [asc|desc] [nulls first|last]" form that [`SortKey`] displays as
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let words = s.split_whitespace().collect::<Vec<_>>();
+        let (field, rest) = words
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("sort key must not be empty"))?;
+        let field = match *field {
+            "paradedb.score()" => None,
+            field => Some(field.to_string()),
+        };
+
+        let rest = rest
+            .iter()
+            .map(|word| word.to_lowercase())
+            .collect::<Vec<_>>();
+        let (direction, rest) = match rest.split_first() {
+            Some((word, rest)) if word == "asc" => (SortDirection::Asc, rest),
+            Some((word, rest)) if word == "desc" => (SortDirection::Desc, rest),
+            _ => (SortDirection::Asc, rest.as_slice()),
+        };
+        let nulls_first = match rest {
+            [] => direction == SortDirection::Desc,
+            [nulls, first] if nulls == "nulls" && first == "first" => true,
+            [nulls, last] if nulls == "nulls" && last == "last" => false,
+            _ => anyhow::bail!("invalid sort key '{s}'"),
+        };
+
+        Ok(SortKey {
+            field,
+            direction,
+            nulls_first,
+        })
+    }
+}
+
 /// An iterator of the different styles of search results we can return
 #[derive(Default)]
 pub enum SearchResults {
@@ -75,7 +177,7 @@ pub enum SearchResults {
 
     TopNByScore(usize, std::vec::IntoIter<(OrderedScore, DocAddress)>),
 
-    TopNByField(usize, std::vec::IntoIter<(SearchIndexScore, DocAddress)>),
+    TopNByKeys(usize, std::vec::IntoIter<(SearchIndexScore, DocAddress)>),
 
     #[allow(clippy::type_complexity)]
     BufferedChannel(
@@ -102,10 +204,13 @@ pub struct OrderedScore {
 impl PartialOrd for OrderedScore {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         let cmp = self.score.partial_cmp(&other.score);
-        match self.dir {
+        let cmp = match self.dir {
             SortDirection::Desc => cmp,
             SortDirection::Asc => cmp.map(|o| o.reverse()),
-        }
+        };
+
+        // equal scores go in ctid order, so that the same query always returns the same top N
+        cmp.map(|o| o.then_with(|| other.score.ctid.cmp(&self.score.ctid)))
     }
 }
 
@@ -116,8 +221,8 @@ impl Debug for SearchResults {
             SearchResults::TopNByScore(count, iter) => {
                 write!(f, "SearchResults::TopNByScore({count}, {:?})", iter.len())
             }
-            SearchResults::TopNByField(count, iter) => {
-                write!(f, "SearchResults::TopNByField({count}, {:?})", iter.len())
+            SearchResults::TopNByKeys(count, iter) => {
+                write!(f, "SearchResults::TopNByKeys({count}, {:?})", iter.len())
             }
             SearchResults::BufferedChannel(iter) => {
                 write!(f, "SearchResults::BufferedChannel(~{:?})", iter.size_hint())
@@ -149,7 +254,7 @@ impl Iterator for SearchResults {
             SearchResults::TopNByScore(_, iter) => iter
                 .next()
                 .map(|(OrderedScore { score, .. }, doc_address)| (score, doc_address)),
-            SearchResults::TopNByField(_, iter) => iter.next(),
+            SearchResults::TopNByKeys(_, iter) => iter.next(),
             SearchResults::BufferedChannel(iter) => iter.next(),
             SearchResults::UnscoredBufferedChannel(iter, buffer) => loop {
                 if buffer.is_none() {
@@ -173,7 +278,7 @@ impl Iterator for SearchResults {
         match self {
             SearchResults::None => (0, Some(0)),
             SearchResults::TopNByScore(_, iter) => iter.size_hint(),
-            SearchResults::TopNByField(_, iter) => iter.size_hint(),
+            SearchResults::TopNByKeys(_, iter) => iter.size_hint(),
             SearchResults::BufferedChannel(iter) => iter.size_hint(),
             SearchResults::UnscoredBufferedChannel(iter, _) => iter.size_hint(),
             SearchResults::Channel(iter) => iter.size_hint(),
@@ -187,7 +292,7 @@ impl SearchResults {
         match self {
             SearchResults::None => Some(0),
             SearchResults::TopNByScore(count, _) => Some(*count),
-            SearchResults::TopNByField(count, _) => Some(*count),
+            SearchResults::TopNByKeys(count, _) => Some(*count),
             SearchResults::BufferedChannel(_) => None,
             SearchResults::UnscoredBufferedChannel(..) => None,
             SearchResults::Channel(_) => None,
@@ -201,6 +306,8 @@ pub struct SearchIndexReader {
     pub searcher: Searcher,
     pub schema: SearchIndexSchema,
     pub underlying_reader: tantivy::IndexReader,
+    /// When set, matches are scored with these statistics rather than the index's own
+    pub global_statistics: Option<Arc<GlobalStatistics>>,
 }
 
 impl SearchIndexReader {
@@ -216,9 +323,20 @@ impl SearchIndexReader {
             searcher,
             schema: schema.clone(),
             underlying_reader: reader,
+            global_statistics: None,
         })
     }
 
+    /// Score matches with `global_statistics`, which are shared with the readers of other indexes
+    pub fn with_global_statistics(mut self, global_statistics: Arc<GlobalStatistics>) -> Self {
+        self.global_statistics = Some(global_statistics);
+        self
+    }
+
+    fn statistics_provider(&self) -> &dyn Bm25StatisticsProvider {
+        statistics_provider(&self.searcher, &self.global_statistics)
+    }
+
     pub fn get_doc(&self, doc_address: DocAddress) -> tantivy::Result<TantivyDocument> {
         self.searcher.doc(doc_address)
     }
@@ -233,6 +351,7 @@ impl SearchIndexReader {
         let mut ctids_to_delete: Vec<u64> = vec![];
 
         let ctid_field = self.schema.ctid_field().id.0;
+        let nested_parent_field = self.schema.nested_parent_field();
         for segment_reader in self.searcher.segment_readers() {
             let store_reader = segment_reader
                 .get_store_reader(CACHE_NUM_BLOCKS)
@@ -249,6 +368,8 @@ impl SearchIndexReader {
                     } else {
                         not_deleted += 1;
                     }
+                } else if nested_parent_field.is_some_and(|field| doc.get_first(field).is_some()) {
+                    // nested children are deleted along with their parent
                 } else {
                     // NB:  in a perfect world, this shouldn't happen.  But we did have a bug where
                     // the "ctid" field was not being `STORED`, which caused this
@@ -287,8 +408,12 @@ impl SearchIndexReader {
         }
     }
 
-    /// Search the Tantivy index for matching documents, in the background, streaming the matching
-    /// documents back as they're found.
+    /// Search the Tantivy index for matching documents, sending them through a channel as
+    /// they're found.
+    ///
+    /// The search runs to completion on the calling thread before this returns, because tantivy
+    /// reads the index lazily while it searches and the index's blocks can only be read through
+    /// the buffer manager from the backend's main thread.
     ///
     /// The order of returned docs is unspecified.
     ///
@@ -303,85 +428,47 @@ impl SearchIndexReader {
         estimated_rows: Option<usize>,
     ) -> SearchResults {
         let estimated_rows = estimated_rows.unwrap_or(0);
+        let enable_scoring = if need_scores {
+            tantivy::query::EnableScoring::Enabled {
+                searcher: &self.searcher,
+                statistics_provider: self.statistics_provider(),
+            }
+        } else {
+            tantivy::query::EnableScoring::Disabled {
+                schema: &self.schema.schema,
+                searcher_opt: Some(&self.searcher),
+            }
+        };
 
         if estimated_rows == 0 || estimated_rows > 5_000 || sort_segments_by_ctid {
             if need_scores {
-                let (sender, receiver) = crossbeam::channel::bounded(
-                    std::thread::available_parallelism().unwrap().get(),
-                );
+                let (sender, receiver) = crossbeam::channel::unbounded();
                 let collector = buffered_channel::BufferedChannelCollector::new(
                     need_scores,
                     sort_segments_by_ctid,
                     sender,
                 );
-                let searcher = self.searcher.clone();
-                let owned_query = query.box_clone();
-                std::thread::spawn(move || {
-                    searcher
-                        .search_with_executor(
-                            &owned_query,
-                            &collector,
-                            executor,
-                            tantivy::query::EnableScoring::Enabled {
-                                searcher: &searcher,
-                                statistics_provider: &searcher,
-                            },
-                        )
-                        .expect("failed to search")
-                });
+                self.searcher
+                    .search_with_executor(query, &collector, executor, enable_scoring)
+                    .expect("failed to search");
 
                 SearchResults::BufferedChannel(receiver.into_iter().flatten())
             } else {
                 let (sender, receiver) = crossbeam::channel::unbounded();
                 let collector =
                     unscored_buffered_channel::UnscoredBufferedChannelCollector::new(sender);
-                let searcher = self.searcher.clone();
-                let schema = self.schema.schema.clone();
-
-                let owned_query = query.box_clone();
-                std::thread::spawn(move || {
-                    searcher
-                        .search_with_executor(
-                            &owned_query,
-                            &collector,
-                            executor,
-                            tantivy::query::EnableScoring::Disabled {
-                                schema: &schema,
-                                searcher_opt: Some(&searcher),
-                            },
-                        )
-                        .expect("failed to search")
-                });
+                self.searcher
+                    .search_with_executor(query, &collector, executor, enable_scoring)
+                    .expect("failed to search");
 
                 SearchResults::UnscoredBufferedChannel(receiver.into_iter(), None)
             }
         } else {
             let (sender, receiver) = crossbeam::channel::unbounded();
             let collector = channel::ChannelCollector::new(need_scores, sender);
-            let searcher = self.searcher.clone();
-            let schema = self.schema.schema.clone();
-
-            let owned_query = query.box_clone();
-            std::thread::spawn(move || {
-                searcher
-                    .search_with_executor(
-                        &owned_query,
-                        &collector,
-                        executor,
-                        if need_scores {
-                            tantivy::query::EnableScoring::Enabled {
-                                searcher: &searcher,
-                                statistics_provider: &searcher,
-                            }
-                        } else {
-                            tantivy::query::EnableScoring::Disabled {
-                                schema: &schema,
-                                searcher_opt: Some(&searcher),
-                            }
-                        },
-                    )
-                    .expect("failed to search")
-            });
+            self.searcher
+                .search_with_executor(query, &collector, executor, enable_scoring)
+                .expect("failed to search");
 
             SearchResults::Channel(receiver.into_iter())
         }
@@ -404,7 +491,7 @@ impl SearchIndexReader {
             .weight(if need_scores {
                 tantivy::query::EnableScoring::Enabled {
                     searcher: &self.searcher,
-                    statistics_provider: &self.searcher,
+                    statistics_provider: self.statistics_provider(),
                 }
             } else {
                 tantivy::query::EnableScoring::Disabled {
@@ -420,83 +507,264 @@ impl SearchIndexReader {
         SearchResults::SingleSegment(results.into_iter())
     }
 
-    /// Search the Tantivy index for the "top N" matching documents.
-    ///
-    /// The documents are returned in score order.  Most relevant first if `sortdir` is [`SortDirection::Desc`],
-    /// or least relevant first if it's [`SortDirection::Asc`].
+    /// Compute Tantivy `aggregations` over the documents matching `query`, ignoring any whose ctid
+    /// is in `excluded_ctids`.
     ///
     /// It has no understanding of Postgres MVCC visibility.  It is the caller's responsibility to
-    /// handle that, if it's necessary.
-    pub fn search_top_n(
+    /// exclude the documents that aren't visible, if it's necessary.
+    pub fn aggregate(
         &self,
         executor: &'static Executor,
         query: &dyn Query,
-        sort_field: Option<String>,
-        sortdir: SortDirection,
-        n: usize,
-    ) -> SearchResults {
-        if let Some(sort_field) = sort_field {
-            self.top_by_field(executor, query, sort_field, sortdir, n)
+        aggregations: Aggregations,
+        excluded_ctids: HashSet<u64>,
+    ) -> tantivy::Result<AggregationResults> {
+        // use tantivy's default bucket limit, but hold the buckets to the memory a Postgres
+        // sort or hash would be allowed
+        let collector = AggregationCollector::from_aggs(
+            aggregations,
+            AggregationLimits::new(Some(gucs::aggregate_memory_limit()), None),
+        );
+        let enable_scoring = tantivy::query::EnableScoring::Disabled {
+            schema: &self.schema.schema,
+            searcher_opt: Some(&self.searcher),
+        };
+
+        if excluded_ctids.is_empty() {
+            self.searcher
+                .search_with_executor(query, &collector, executor, enable_scoring)
         } else {
-            self.top_by_score(executor, query, sortdir, n)
+            let excluded_ctids = Arc::new(excluded_ctids);
+            let collector = FilterCollector::new(
+                "ctid".to_string(),
+                move |ctid: u64| !excluded_ctids.contains(&ctid),
+                collector,
+            );
+            self.searcher
+                .search_with_executor(query, &collector, executor, enable_scoring)
         }
     }
 
-    #[allow(non_local_definitions)]
-    fn top_by_field(
+    /// Count the documents matching `query` under each of the immediate children of each of
+    /// `facets`, a facet in the facet field it's paired with.
+    ///
+    /// It has no understanding of Postgres MVCC visibility.  It is the caller's responsibility to
+    /// take the documents that aren't visible back out, with [`Self::visible_facet_counts`].
+    pub fn facet_matches(
         &self,
-        executor: &Executor,
+        executor: &'static Executor,
         query: &dyn Query,
-        sort_field: String,
-        sortdir: SortDirection,
-        n: usize,
-    ) -> SearchResults {
-        impl From<SortDirection> for tantivy::Order {
-            fn from(value: SortDirection) -> Self {
-                match value {
-                    SortDirection::Asc => Order::Asc,
-                    SortDirection::Desc => Order::Desc,
+        facets: &[(String, Facet)],
+    ) -> tantivy::Result<FacetMatches> {
+        let enable_scoring = EnableScoring::Disabled {
+            schema: &self.schema.schema,
+            searcher_opt: Some(&self.searcher),
+        };
+        let (_, matches) =
+            self.search_with_facets(executor, query, Count, enable_scoring, facets)?;
+        Ok(matches)
+    }
+
+    /// The counts in `matches` without the documents whose ctid is in `excluded_ctids`, under
+    /// each facet in the order they were asked for.  Children that no documents are left under
+    /// are dropped.
+    pub fn visible_facet_counts(
+        &self,
+        matches: FacetMatches,
+        excluded_ctids: &HashSet<u64>,
+    ) -> tantivy::Result<Vec<Vec<(Facet, u64)>>> {
+        let excluded = matches
+            .docs
+            .iter()
+            .filter(|(ctid, _)| excluded_ctids.contains(ctid))
+            .map(|(_, doc_address)| *doc_address)
+            .collect::<Vec<_>>();
+
+        matches
+            .facets
+            .iter()
+            .zip(matches.counts)
+            .map(|((field, facet), facet_counts)| {
+                let mut counts = facet_counts
+                    .get(facet.clone())
+                    .map(|(child, count)| (child.clone(), count))
+                    .collect::<BTreeMap<_, _>>();
+
+                // a document is only counted once under each child, however many of its facets
+                // are filed there
+                for doc_address in &excluded {
+                    for child in self.facet_children(field, facet, *doc_address)? {
+                        if let Some(count) = counts.get_mut(&child) {
+                            *count -= 1;
+                        }
+                    }
                 }
+
+                Ok(counts.into_iter().filter(|(_, count)| *count > 0).collect())
+            })
+            .collect()
+    }
+
+    /// The immediate children of `facet` that the document at `doc_address` is filed under, in
+    /// the facet field named `field`
+    fn facet_children(
+        &self,
+        field: &str,
+        facet: &Facet,
+        doc_address: DocAddress,
+    ) -> tantivy::Result<BTreeSet<Facet>> {
+        let depth = if facet.is_root() {
+            0
+        } else {
+            facet.to_path().len()
+        };
+        let facet_reader = self
+            .searcher
+            .segment_reader(doc_address.segment_ord)
+            .facet_reader(field)?;
+
+        let mut children = BTreeSet::new();
+        let mut doc_facet = Facet::root();
+        for ord in facet_reader.facet_ords(doc_address.doc_id) {
+            facet_reader.facet_from_ord(ord, &mut doc_facet)?;
+
+            // the root isn't a prefix of anything, according to tantivy
+            let is_under = if facet.is_root() {
+                !doc_facet.is_root()
+            } else {
+                facet.is_prefix_of(&doc_facet)
+            };
+            if is_under {
+                children.insert(Facet::from_path(&doc_facet.to_path()[..=depth]));
             }
         }
+        Ok(children)
+    }
 
-        let sort_field = self
-            .schema
-            .get_search_field(&SearchFieldName(sort_field.clone()))
-            .expect("sort field should exist in index schema");
+    /// Search with `collector`.  If there are `facets` to count, the documents matching `query`
+    /// and their counts under each of them are collected in the same pass, alongside it.
+    fn search_with_facets<C: Collector>(
+        &self,
+        executor: &Executor,
+        query: &dyn Query,
+        collector: C,
+        enable_scoring: EnableScoring,
+        facets: &[(String, Facet)],
+    ) -> tantivy::Result<(C::Fruit, FacetMatches)> {
+        if facets.is_empty() {
+            let fruit =
+                self.searcher
+                    .search_with_executor(query, &collector, executor, enable_scoring)?;
+            return Ok((fruit, FacetMatches::default()));
+        }
 
-        let collector =
-            TopDocs::with_limit(n).order_by_u64_field(&sort_field.name.0, sortdir.into());
-        let top_docs = self
-            .searcher
-            .search_with_executor(
-                query,
-                &collector,
-                executor,
-                tantivy::query::EnableScoring::Enabled {
-                    searcher: &self.searcher,
-                    statistics_provider: &self.searcher,
-                },
-            )
-            .expect("failed to search");
+        let mut multi_collector = MultiCollector::new();
+        let fruit_handle = multi_collector.add_collector(collector);
+        let docs_handle = multi_collector.add_collector(vec_collector::VecCollector::new(false));
+        let counts_handles = facets
+            .iter()
+            .map(|(field, facet)| {
+                let mut facet_collector = FacetCollector::for_field(field);
+                facet_collector.add_facet(facet.clone());
+                multi_collector.add_collector(facet_collector)
+            })
+            .collect::<Vec<_>>();
+
+        let mut fruits = self.searcher.search_with_executor(
+            query,
+            &multi_collector,
+            executor,
+            enable_scoring,
+        )?;
 
-        let top_docs = top_docs
+        let fruit = fruit_handle.extract(&mut fruits);
+        let docs = docs_handle
+            .extract(&mut fruits)
             .into_iter()
-            .map(|(_, doc_address)| {
-                let ctid = self
-                    .searcher
-                    .segment_reader(doc_address.segment_ord)
-                    .fast_fields()
-                    .u64("ctid")
-                    .expect("ctid should be a fast field");
-                (
-                    SearchIndexScore::new(&ctid, doc_address.doc_id, 1.0),
-                    doc_address,
-                )
+            .flatten()
+            .map(|(scored, doc_address)| (scored.ctid, doc_address))
+            .collect();
+        let counts = counts_handles
+            .into_iter()
+            .map(|handle| handle.extract(&mut fruits))
+            .collect();
+
+        Ok((
+            fruit,
+            FacetMatches {
+                facets: facets.to_vec(),
+                counts,
+                docs,
+            },
+        ))
+    }
+
+    /// Search the Tantivy index for the "top N" matching documents.
+    ///
+    /// The documents are returned ordered by `sort_keys`, each of which is either the score or a
+    /// fast field.  Later keys break ties in the earlier ones, and the ctid breaks any that are
+    /// left, so the order is stable from one search to the next.
+    ///
+    /// It has no understanding of Postgres MVCC visibility.  It is the caller's responsibility to
+    /// handle that, if it's necessary.
+    pub fn search_top_n(
+        &self,
+        executor: &'static Executor,
+        query: &dyn Query,
+        sort_keys: &[SortKey],
+        n: usize,
+    ) -> SearchResults {
+        self.search_top_n_with_facets(executor, query, sort_keys, n, &[])
+            .0
+    }
+
+    /// [`Self::search_top_n`], also counting every document matching `query` under each of
+    /// `facets` in the same pass, as [`Self::facet_matches`] would.
+    pub fn search_top_n_with_facets(
+        &self,
+        executor: &'static Executor,
+        query: &dyn Query,
+        sort_keys: &[SortKey],
+        n: usize,
+        facets: &[(String, Facet)],
+    ) -> (SearchResults, FacetMatches) {
+        match sort_keys {
+            [SortKey {
+                field: None,
+                direction,
+                ..
+            }] => self.top_by_score(executor, query, *direction, n, facets),
+            _ => self.top_by_keys(executor, query, sort_keys, n, facets),
+        }
+    }
+
+    /// Score each of `docs` for `query`, with the same statistics as [`Self::search_top_n`].  A
+    /// doc that doesn't match `query` scores [`None`].
+    pub fn score_docs(&self, query: &dyn Query, docs: &[DocAddress]) -> Vec<Option<Score>> {
+        let weight = query
+            .weight(tantivy::query::EnableScoring::Enabled {
+                searcher: &self.searcher,
+                statistics_provider: self.statistics_provider(),
             })
-            .collect::<Vec<_>>();
+            .expect("weight should be constructable");
 
-        SearchResults::TopNByField(top_docs.len(), top_docs.into_iter())
+        docs.iter()
+            .map(|doc_address| {
+                let segment_reader = self.searcher.segment_reader(doc_address.segment_ord);
+                let mut scorer = weight
+                    .scorer(segment_reader, 1.0)
+                    .expect("scorer should be constructable");
+                // a new scorer is already on its first match, and must never seek backwards
+                let target = doc_address.doc_id;
+                if scorer.doc() > target {
+                    None
+                } else if scorer.doc() == target || scorer.seek(target) == target {
+                    Some(scorer.score())
+                } else {
+                    None
+                }
+            })
+            .collect()
     }
 
     fn top_by_score(
@@ -505,7 +773,8 @@ impl SearchIndexReader {
         query: &dyn Query,
         sortdir: SortDirection,
         n: usize,
-    ) -> SearchResults {
+        facets: &[(String, Facet)],
+    ) -> (SearchResults, FacetMatches) {
         let collector =
             TopDocs::with_limit(n).tweak_score(move |segment_reader: &tantivy::SegmentReader| {
                 let ctid_ff = segment_reader
@@ -519,21 +788,67 @@ impl SearchIndexReader {
                 }
             });
 
-        let top_docs = self
-            .searcher
-            .search_with_executor(
-                query,
-                &collector,
+        let (top_docs, facet_matches) = self
+            .search_with_facets(
                 executor,
-                tantivy::query::EnableScoring::Enabled {
+                query,
+                collector,
+                EnableScoring::Enabled {
                     searcher: &self.searcher,
-                    statistics_provider: &self.searcher,
+                    statistics_provider: self.statistics_provider(),
                 },
+                facets,
             )
-            .expect("failed to search")
-            .into_iter();
+            .expect("failed to search");
+
+        (
+            SearchResults::TopNByScore(top_docs.len(), top_docs.into_iter()),
+            facet_matches,
+        )
+    }
+
+    fn top_by_keys(
+        &self,
+        executor: &Executor,
+        query: &dyn Query,
+        sort_keys: &[SortKey],
+        n: usize,
+        facets: &[(String, Facet)],
+    ) -> (SearchResults, FacetMatches) {
+        let text_keys = sort_keys
+            .iter()
+            .map(|key| match &key.field {
+                Some(field) => {
+                    self.schema
+                        .get_search_field(&SearchFieldName(field.clone()))
+                        .expect("sort field should exist in index schema")
+                        .type_
+                        == SearchFieldType::Text
+                }
+                None => false,
+            })
+            .collect();
+
+        let collector = MultiKeyCollector::new(sort_keys.to_vec(), text_keys, n);
+        let enable_scoring = if collector.requires_scoring() {
+            tantivy::query::EnableScoring::Enabled {
+                searcher: &self.searcher,
+                statistics_provider: self.statistics_provider(),
+            }
+        } else {
+            tantivy::query::EnableScoring::Disabled {
+                schema: &self.schema.schema,
+                searcher_opt: Some(&self.searcher),
+            }
+        };
+        let (top_docs, facet_matches) = self
+            .search_with_facets(executor, query, collector, enable_scoring, facets)
+            .expect("failed to search");
 
-        SearchResults::TopNByScore(top_docs.len(), top_docs.into_iter())
+        (
+            SearchResults::TopNByKeys(top_docs.len(), top_docs.into_iter()),
+            facet_matches,
+        )
     }
 
     pub fn estimate_docs(
@@ -550,10 +865,13 @@ impl SearchIndexReader {
 
         let collector = tantivy::collector::Count;
         let schema = self.schema.schema.clone();
-        let query = &search_query_input
-            .clone()
-            .into_tantivy_query(&(indexrel, &self.schema), &mut query_parser, &self.searcher)
-            .expect("must be able to parse query");
+        let query = &exclude_nested_children(
+            search_query_input
+                .clone()
+                .into_tantivy_query(&(indexrel, &self.schema), &mut query_parser, self)
+                .expect("must be able to parse query"),
+            &self.schema.schema,
+        );
         let weight = match query.weight(tantivy::query::EnableScoring::Disabled {
             schema: &schema,
             searcher_opt: Some(&self.searcher),
@@ -590,6 +908,17 @@ impl SearchIndexReader {
     }
 }
 
+/// The statistics matches are scored with, which are `searcher`'s own unless there are global ones
+fn statistics_provider<'a>(
+    searcher: &'a Searcher,
+    global_statistics: &'a Option<Arc<GlobalStatistics>>,
+) -> &'a dyn Bm25StatisticsProvider {
+    match global_statistics {
+        Some(global_statistics) => global_statistics.as_ref(),
+        None => searcher,
+    }
+}
+
 mod buffered_channel {
     use crate::index::reader::SearchIndexScore;
     use tantivy::collector::{Collector, SegmentCollector};
@@ -1035,3 +1364,220 @@ mod vec_collector {
         }
     }
 }
+
+mod multi_key_collector {
+    use crate::index::reader::{SearchIndexScore, SortDirection, SortKey};
+    use std::cmp::Ordering;
+    use tantivy::collector::{Collector, SegmentCollector};
+    use tantivy::columnar::StrColumn;
+    use tantivy::fastfield::Column;
+    use tantivy::{DocAddress, DocId, Score, SegmentOrdinal, SegmentReader};
+
+    /// The value of one sort key for one document.
+    ///
+    /// While a segment is being collected a text key holds the term ordinal, which sorts the same
+    /// way as the term itself.  Ordinals from different segments can't be compared with each
+    /// other, so they're swapped for the terms once the segment has been harvested.
+    #[derive(Debug, Clone, PartialEq, PartialOrd)]
+    pub enum SortValue {
+        Score(Score),
+        U64(u64),
+        Str(String),
+    }
+
+    pub type SortValues = Vec<Option<SortValue>>;
+
+    fn compare(keys: &[SortKey], a: &SortValues, b: &SortValues) -> Ordering {
+        for ((key, a), b) in keys.iter().zip(a).zip(b) {
+            let ordering = match (a, b) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) if key.nulls_first => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) if key.nulls_first => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => {
+                    let ordering = a.partial_cmp(b).unwrap_or(Ordering::Equal);
+                    match key.direction {
+                        SortDirection::Asc => ordering,
+                        SortDirection::Desc => ordering.reverse(),
+                    }
+                }
+            };
+
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// Like [`compare`], but documents that tie on every key go in ctid order
+    fn compare_docs(
+        keys: &[SortKey],
+        (a, a_scored): (&SortValues, &SearchIndexScore),
+        (b, b_scored): (&SortValues, &SearchIndexScore),
+    ) -> Ordering {
+        compare(keys, a, b).then_with(|| a_scored.ctid.cmp(&b_scored.ctid))
+    }
+
+    pub enum KeyColumn {
+        Score,
+        // numeric, boolean, and date fast fields are all read as u64s, in an order-preserving way
+        Numeric(Option<Column<u64>>),
+        Text(Option<StrColumn>),
+    }
+
+    /// A [`Collector`] that keeps the top `n` matching documents, ordered by a list of sort keys
+    /// and then by ctid.
+    pub struct MultiKeyCollector {
+        keys: Vec<SortKey>,
+        text_keys: Vec<bool>,
+        n: usize,
+    }
+
+    impl MultiKeyCollector {
+        pub fn new(keys: Vec<SortKey>, text_keys: Vec<bool>, n: usize) -> Self {
+            Self {
+                keys,
+                text_keys,
+                n: n.max(1),
+            }
+        }
+    }
+
+    impl Collector for MultiKeyCollector {
+        type Fruit = Vec<(SearchIndexScore, DocAddress)>;
+        type Child = MultiKeySegmentCollector;
+
+        fn for_segment(
+            &self,
+            segment_local_id: SegmentOrdinal,
+            segment_reader: &SegmentReader,
+        ) -> tantivy::Result<Self::Child> {
+            let fast_fields = segment_reader.fast_fields();
+            let columns = self
+                .keys
+                .iter()
+                .zip(&self.text_keys)
+                .map(|(key, is_text)| {
+                    Ok(match (&key.field, is_text) {
+                        (None, _) => KeyColumn::Score,
+                        (Some(field), true) => KeyColumn::Text(fast_fields.str(field)?),
+                        (Some(field), false) => KeyColumn::Numeric(
+                            fast_fields
+                                .u64_lenient_for_type(None, field)?
+                                .map(|(column, _)| column),
+                        ),
+                    })
+                })
+                .collect::<tantivy::Result<Vec<_>>>()?;
+
+            Ok(MultiKeySegmentCollector {
+                keys: self.keys.clone(),
+                n: self.n,
+                segment_ord: segment_local_id,
+                ctid_ff: fast_fields
+                    .u64("ctid")
+                    .expect("ctid should be a u64 fast field"),
+                columns,
+                docs: vec![],
+            })
+        }
+
+        fn requires_scoring(&self) -> bool {
+            self.keys.iter().any(|key| key.field.is_none())
+        }
+
+        fn merge_fruits(
+            &self,
+            segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+        ) -> tantivy::Result<Self::Fruit> {
+            let mut docs = segment_fruits.into_iter().flatten().collect::<Vec<_>>();
+            docs.sort_by(|(a, a_scored, _), (b, b_scored, _)| {
+                compare_docs(&self.keys, (a, a_scored), (b, b_scored))
+            });
+            docs.truncate(self.n);
+
+            Ok(docs
+                .into_iter()
+                .map(|(_, scored, doc_address)| (scored, doc_address))
+                .collect())
+        }
+    }
+
+    pub struct MultiKeySegmentCollector {
+        keys: Vec<SortKey>,
+        n: usize,
+        segment_ord: SegmentOrdinal,
+        ctid_ff: Column<u64>,
+        columns: Vec<KeyColumn>,
+        docs: Vec<(SortValues, SearchIndexScore, DocId)>,
+    }
+
+    impl MultiKeySegmentCollector {
+        /// Throw away everything but the current top `n` documents
+        fn truncate(&mut self) {
+            if self.docs.len() > self.n {
+                let keys = &self.keys;
+                self.docs
+                    .select_nth_unstable_by(self.n, |(a, a_scored, _), (b, b_scored, _)| {
+                        compare_docs(keys, (a, a_scored), (b, b_scored))
+                    });
+                self.docs.truncate(self.n);
+            }
+        }
+    }
+
+    impl SegmentCollector for MultiKeySegmentCollector {
+        type Fruit = Vec<(SortValues, SearchIndexScore, DocAddress)>;
+
+        fn collect(&mut self, doc: DocId, score: Score) {
+            let values = self
+                .columns
+                .iter()
+                .map(|column| match column {
+                    KeyColumn::Score => Some(SortValue::Score(score)),
+                    KeyColumn::Numeric(column) => column.as_ref()?.first(doc).map(SortValue::U64),
+                    KeyColumn::Text(column) => {
+                        column.as_ref()?.ords().first(doc).map(SortValue::U64)
+                    }
+                })
+                .collect();
+            let scored = SearchIndexScore::new(&self.ctid_ff, doc, score);
+            self.docs.push((values, scored, doc));
+
+            // only trim the candidates every so often, so that keeping the top `n` is amortized
+            if self.docs.len() >= self.n * 2 {
+                self.truncate();
+            }
+        }
+
+        fn harvest(mut self) -> Self::Fruit {
+            self.truncate();
+
+            let MultiKeySegmentCollector {
+                segment_ord,
+                columns,
+                docs,
+                ..
+            } = self;
+            docs.into_iter()
+                .map(|(mut values, scored, doc)| {
+                    for (value, column) in values.iter_mut().zip(&columns) {
+                        if let KeyColumn::Text(Some(column)) = column {
+                            if let Some(SortValue::U64(ord)) = *value {
+                                let mut term = String::new();
+                                column
+                                    .ord_to_str(ord, &mut term)
+                                    .expect("term ordinal should be in the dictionary");
+                                *value = Some(SortValue::Str(term));
+                            }
+                        }
+                    }
+
+                    (values, scored, DocAddress::new(segment_ord, doc))
+                })
+                .collect()
+        }
+    }
+}
@@ -21,35 +21,36 @@ use crate::gucs;
 use crate::index::merge_policy::NPlusOneMergePolicy;
 use crate::index::SearchIndexWriter;
 use crate::index::{
-    BlockingDirectory, SearchDirectoryError, SearchFs, TantivyDirPath, WriterDirectory,
+    BlockDirectory, BlockDirectoryError, SearchDirectoryError, WriterDirectory,
+    SEARCH_INDEX_CONFIG_FILE_NAME,
 };
 use crate::postgres::options::SearchIndexCreateOptions;
-use crate::query::SearchQueryInput;
+use crate::query::{exclude_nested_children, RescoreWindow, SearchQueryInput};
 use crate::schema::{
     SearchDocument, SearchField, SearchFieldConfig, SearchFieldName, SearchFieldType,
     SearchIndexSchema, SearchIndexSchemaError,
 };
 use anyhow::Result;
 use once_cell::sync::Lazy;
-use pgrx::PgRelation;
+use pgrx::{pg_sys, PgRelation};
 use serde::{Deserialize, Deserializer, Serialize};
 use std::num::NonZeroUsize;
+use std::path::Path;
+use tantivy::directory::error::OpenReadError;
 use tantivy::indexer::NoMergePolicy;
 use tantivy::merge_policy::MergePolicy;
 use tantivy::query::Query;
-use tantivy::{query::QueryParser, Executor, Index};
+use tantivy::{query::QueryParser, Directory, Executor, Index};
 use thiserror::Error;
 use tokenizers::{create_normalizer_manager, create_tokenizer_manager};
 use tracing::trace;
 
 /// PostgreSQL operates in a process-per-client model, meaning every client connection
 /// to PostgreSQL results in a new backend process being spawned on the PostgreSQL server.
-pub static mut SEARCH_EXECUTOR: Lazy<Executor> = Lazy::new(|| {
-    let num_threads = std::thread::available_parallelism()
-        .expect("this computer should have at least one CPU")
-        .get();
-    Executor::multi_thread(num_threads, "prefix-here").expect("could not create search executor")
-});
+///
+/// Searches read the index through the buffer manager as they go, which can only be used from
+/// the backend's main thread, so every segment is searched on the calling thread.
+pub static mut SEARCH_EXECUTOR: Lazy<Executor> = Lazy::new(Executor::single_thread);
 
 pub enum WriterResources {
     CreateIndex,
@@ -95,6 +96,8 @@ pub struct SearchIndex {
     pub directory: WriterDirectory,
     #[serde(skip_serializing)]
     pub underlying_index: Index,
+    #[serde(skip_serializing)]
+    pub block_directory: BlockDirectory,
 }
 
 impl SearchIndex {
@@ -178,6 +181,8 @@ impl SearchIndex {
         Ok(SearchIndexWriter {
             underlying_writer: Some(underlying_writer),
             wants_merge,
+            directory: self.block_directory.clone(),
+            nested_parent_field: self.schema.nested_parent_field(),
         })
     }
 
@@ -215,15 +220,43 @@ impl SearchIndex {
     }
 
     pub fn from_disk(directory: &WriterDirectory) -> Result<Self, SearchIndexError> {
-        let mut new_self: Self = directory.load_index()?;
+        let block_directory = BlockDirectory::open(pg_sys::Oid::from(directory.index_oid))?;
+        let serialized = block_directory.atomic_read(Path::new(SEARCH_INDEX_CONFIG_FILE_NAME))?;
+        let SearchIndexConfig { schema, .. } = serde_json::from_slice(&serialized)?;
 
         // In the case of a physical replication of the database, the absolute path that is stored
         // in the serialized WriterDirectory might refer to the source database's file system.
         // We should overwrite it with the dynamically generated one that's been passed as an
         // argument here.
-        new_self.directory = directory.clone();
+        Ok(Self::open(schema, directory.clone(), block_directory)?)
+    }
+
+    /// Serialize this index's configuration into the index relation, alongside the tantivy
+    /// files, so that it can be loaded by other connections.
+    pub fn save(&self) -> Result<(), SearchIndexError> {
+        let serialized = serde_json::to_vec(self)?;
+        self.block_directory
+            .atomic_write(Path::new(SEARCH_INDEX_CONFIG_FILE_NAME), &serialized)?;
+        self.block_directory.flush()?;
+        Ok(())
+    }
 
-        Ok(new_self)
+    fn open(
+        schema: SearchIndexSchema,
+        directory: WriterDirectory,
+        block_directory: BlockDirectory,
+    ) -> tantivy::Result<Self> {
+        let mut underlying_index = Index::open(block_directory.clone())?;
+
+        // We need to setup tokenizers again after retrieving an index from disk.
+        Self::setup_tokenizers(&mut underlying_index, &schema);
+
+        Ok(SearchIndex {
+            underlying_index,
+            directory,
+            schema,
+            block_directory,
+        })
     }
 
     pub fn segment_count(&self) -> usize {
@@ -251,11 +284,33 @@ impl SearchIndex {
         reader: &SearchIndexReader,
     ) -> Box<dyn Query> {
         let mut parser = self.query_parser();
-        let searcher = reader.underlying_reader.searcher();
-        search_query_input
+        let query = search_query_input
+            .clone()
+            .into_tantivy_query(&(indexrel, &self.schema), &mut parser, reader)
+            .expect("must be able to parse query");
+
+        // the matches have to be rows, not the nested children of them
+        exclude_nested_children(query, &self.schema.schema)
+    }
+
+    /// The second pass of `search_query_input`, if it's a [`SearchQueryInput::Rescore`]
+    pub fn rescore_window(
+        &self,
+        indexrel: &PgRelation,
+        search_query_input: &SearchQueryInput,
+        reader: &SearchIndexReader,
+    ) -> Option<RescoreWindow> {
+        let (rescore_query, window_size, weight) = search_query_input.rescore()?;
+        let mut parser = self.query_parser();
+        let query = rescore_query
             .clone()
-            .into_tantivy_query(&(indexrel, &self.schema), &mut parser, &searcher)
-            .expect("must be able to parse query")
+            .into_tantivy_query(&(indexrel, &self.schema), &mut parser, reader)
+            .expect("must be able to parse rescore query");
+        Some(RescoreWindow {
+            query,
+            window_size,
+            weight,
+        })
     }
 
     pub fn insert(
@@ -308,38 +363,23 @@ impl SearchIndex {
     }
 }
 
+/// The parts of a [`SearchIndex`] that are serialized into the index relation.
+#[derive(Deserialize)]
+struct SearchIndexConfig {
+    schema: SearchIndexSchema,
+    directory: WriterDirectory,
+}
+
 impl<'de> Deserialize<'de> for SearchIndex {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        // A helper struct that lets us use the default serialization for most fields.
-        #[derive(Deserialize)]
-        struct SearchIndexHelper {
-            schema: SearchIndexSchema,
-            directory: WriterDirectory,
-        }
-
-        // Deserialize into the struct with automatic handling for most fields
-        let SearchIndexHelper { schema, directory } = SearchIndexHelper::deserialize(deserializer)?;
-
-        let TantivyDirPath(tantivy_dir_path) = directory
-            .tantivy_dir_path(true)
-            .expect("tantivy directory path should be valid");
-
-        let tantivy_dir = BlockingDirectory::open(tantivy_dir_path)
-            .expect("need a valid path to open a tantivy index");
-        let mut underlying_index = Index::open(tantivy_dir).map_err(serde::de::Error::custom)?;
-
-        // We need to setup tokenizers again after retrieving an index from disk.
-        Self::setup_tokenizers(&mut underlying_index, &schema);
+        let SearchIndexConfig { schema, directory } = SearchIndexConfig::deserialize(deserializer)?;
 
-        // Construct the SearchIndex.
-        Ok(SearchIndex {
-            underlying_index,
-            directory,
-            schema,
-        })
+        let block_directory = BlockDirectory::open(pg_sys::Oid::from(directory.index_oid))
+            .map_err(serde::de::Error::custom)?;
+        Self::open(schema, directory, block_directory).map_err(serde::de::Error::custom)
     }
 }
 
@@ -364,6 +404,15 @@ pub enum SearchIndexError {
     #[error(transparent)]
     WriterDirectoryError(#[from] SearchDirectoryError),
 
+    #[error(transparent)]
+    BlockDirectoryError(#[from] BlockDirectoryError),
+
+    #[error(transparent)]
+    OpenReadError(#[from] OpenReadError),
+
     #[error(transparent)]
     AnyhowError(#[from] anyhow::Error),
+
+    #[error("index '{0}' is the index of a partitioned table, and its documents are in the indexes of the partitions")]
+    PartitionedIndex(String),
 }
@@ -0,0 +1,217 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Term statistics summed over several indexes, such as those of the partitions of a table, so
+//! that each of them scores its matches as if all their docs were in one index.
+//!
+//! They're gathered once for all the indexes under an Append, when the query is planned, and
+//! travel with the plan to the scan of each of them, so no scan has to open the others.
+
+use crate::postgres::index::open_search_index;
+use crate::query::{AsFieldType, SearchQueryInput};
+use anyhow::Result;
+use pgrx::{pg_sys, PgRelation};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tantivy::postings::Postings;
+use tantivy::query::Bm25StatisticsProvider;
+use tantivy::schema::{Field, FieldType, IndexRecordOption, Schema};
+use tantivy::{DocSet, Searcher, Term, TERMINATED};
+
+/// The counts behind [`GlobalStatistics`], summed over every index
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct StatisticsCounts {
+    total_num_docs: u64,
+    total_num_tokens: Vec<(Field, u64)>,
+    /// The serialized terms of the query, with their doc frequency, and their total frequency if
+    /// their field's similarity needs it
+    terms: Vec<(Vec<u8>, u64, Option<u64>)>,
+}
+
+impl StatisticsCounts {
+    /// Gather the counts of the indexes `indexrelids` for the terms of `search_query_input`.
+    ///
+    /// An index is only included if its fields are the same as those of the first, as its terms
+    /// would otherwise mean something else.
+    pub fn gather(
+        indexrelids: &[pg_sys::Oid],
+        search_query_input: &SearchQueryInput,
+    ) -> Result<Self> {
+        let mut searchers: Vec<Searcher> = vec![];
+        let mut terms = vec![];
+        for indexrelid in indexrelids {
+            let indexrel =
+                unsafe { PgRelation::with_lock(*indexrelid, pg_sys::AccessShareLock as _) };
+            let search_index = open_search_index(&indexrel)?;
+            let reader = search_index.get_reader()?;
+
+            match searchers.first() {
+                Some(first) => {
+                    if !same_fields(first.schema(), reader.searcher.schema()) {
+                        pgrx::warning!(
+                            "index '{}' has different fields, so it isn't included in the statistics used for scoring",
+                            indexrel.name()
+                        );
+                        continue;
+                    }
+                }
+                None => {
+                    // every index has the same fields, so the query has the same terms in each
+                    let field_lookup = (&indexrel, &search_index.schema);
+                    let query = search_index.query(&indexrel, search_query_input, &reader);
+                    query.query_terms(&mut |term, _| {
+                        let needs_total_term_freq = field_lookup
+                            .similarity(term.field())
+                            .needs_total_term_freq();
+                        terms.push((term.clone(), needs_total_term_freq));
+                    });
+                    terms.sort();
+                    terms.dedup();
+                }
+            }
+            searchers.push(reader.searcher);
+        }
+
+        let mut counts = Self::default();
+        let mut total_num_tokens = HashMap::new();
+        for searcher in &searchers {
+            counts.total_num_docs += Bm25StatisticsProvider::total_num_docs(searcher)?;
+            for (field, entry) in searcher.schema().fields() {
+                if entry.is_indexed()
+                    && matches!(
+                        entry.field_type(),
+                        FieldType::Str(_) | FieldType::JsonObject(_)
+                    )
+                {
+                    *total_num_tokens.entry(field).or_insert(0) +=
+                        Bm25StatisticsProvider::total_num_tokens(searcher, field)?;
+                }
+            }
+        }
+        counts.total_num_tokens = total_num_tokens.into_iter().collect();
+
+        for (term, needs_total_term_freq) in terms {
+            let doc_freq = searchers
+                .iter()
+                .map(|searcher| searcher.doc_freq(&term))
+                .sum::<tantivy::Result<u64>>()?;
+            let total_term_freq = match needs_total_term_freq {
+                true => Some(
+                    searchers
+                        .iter()
+                        .map(|searcher| total_term_freq(searcher, &term))
+                        .sum::<tantivy::Result<u64>>()?,
+                ),
+                false => None,
+            };
+            counts
+                .terms
+                .push((term.serialized_term().to_vec(), doc_freq, total_term_freq));
+        }
+
+        Ok(counts)
+    }
+}
+
+/// Scores the matches of one index with the [`StatisticsCounts`] of every index.  The terms that
+/// weren't known when the query was planned, such as those `more_like_this` picks, fall back on
+/// the index's own statistics.
+#[derive(Debug)]
+pub struct GlobalStatistics {
+    /// The index being scored
+    searcher: Searcher,
+    total_num_docs: u64,
+    total_num_tokens: HashMap<Field, u64>,
+    terms: HashMap<Term, (u64, Option<u64>)>,
+}
+
+impl GlobalStatistics {
+    pub fn new(counts: StatisticsCounts, searcher: &Searcher) -> Self {
+        Self {
+            searcher: searcher.clone(),
+            total_num_docs: counts.total_num_docs,
+            total_num_tokens: counts.total_num_tokens.into_iter().collect(),
+            terms: counts
+                .terms
+                .into_iter()
+                .map(|(term, doc_freq, total_term_freq)| {
+                    (Term::wrap(term), (doc_freq, total_term_freq))
+                })
+                .collect(),
+        }
+    }
+
+    /// How many times `term` appears in every index
+    pub fn total_term_freq(&self, term: &Term) -> tantivy::Result<u64> {
+        match self.terms.get(term) {
+            Some((_, Some(total_term_freq))) => Ok(*total_term_freq),
+            _ => total_term_freq(&self.searcher, term),
+        }
+    }
+}
+
+/// How many times `term` appears in every segment of `searcher`, which means reading all of its
+/// postings
+pub fn total_term_freq(searcher: &Searcher, term: &Term) -> tantivy::Result<u64> {
+    let mut total = 0;
+    for segment_reader in searcher.segment_readers() {
+        let inverted_index = segment_reader.inverted_index(term.field())?;
+        if let Some(mut postings) =
+            inverted_index.read_postings(term, IndexRecordOption::WithFreqs)?
+        {
+            while postings.doc() != TERMINATED {
+                total += postings.term_freq() as u64;
+                postings.advance();
+            }
+        }
+    }
+    Ok(total)
+}
+
+fn same_fields(schema: &Schema, other: &Schema) -> bool {
+    let fields = schema.fields().collect::<Vec<_>>();
+    let other_fields = other.fields().collect::<Vec<_>>();
+    fields.len() == other_fields.len()
+        && fields
+            .iter()
+            .zip(&other_fields)
+            .all(|((field, entry), (other_field, other_entry))| {
+                field == other_field
+                    && entry.name() == other_entry.name()
+                    && entry.field_type().value_type() == other_entry.field_type().value_type()
+            })
+}
+
+impl Bm25StatisticsProvider for GlobalStatistics {
+    fn total_num_tokens(&self, field: Field) -> tantivy::Result<u64> {
+        match self.total_num_tokens.get(&field) {
+            Some(total_num_tokens) => Ok(*total_num_tokens),
+            None => Bm25StatisticsProvider::total_num_tokens(&self.searcher, field),
+        }
+    }
+
+    fn total_num_docs(&self) -> tantivy::Result<u64> {
+        Ok(self.total_num_docs)
+    }
+
+    fn doc_freq(&self, term: &Term) -> tantivy::Result<u64> {
+        match self.terms.get(term) {
+            Some((doc_freq, _)) => Ok(*doc_freq),
+            None => self.searcher.doc_freq(term),
+        }
+    }
+}
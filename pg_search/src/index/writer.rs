@@ -24,91 +24,15 @@ use crate::{
 };
 use anyhow::{Context, Result};
 use once_cell::sync::Lazy;
-use std::sync::Arc;
-use std::{collections::HashSet, path::Path};
-use std::{fs, io, result};
-use tantivy::directory::{
-    DirectoryClone, DirectoryLock, FileHandle, FileSlice, Lock, WatchCallback, WatchHandle,
-    WritePtr,
-};
-use tantivy::{
-    directory::error::{DeleteError, LockError, OpenReadError, OpenWriteError},
-    IndexSettings,
-};
-use tantivy::{directory::MmapDirectory, schema::Field, Directory, Index, IndexWriter};
+use pgrx::pg_sys;
+use std::collections::HashSet;
+use tantivy::indexer::UserOperation;
+use tantivy::IndexSettings;
+use tantivy::{schema::Field, Index, IndexWriter};
 use thiserror::Error;
 
-use super::directory::{SearchDirectoryError, SearchFs, WriterDirectory};
-
-/// We maintain our own tantivy::directory::Directory implementation for finer-grained
-/// control over the locking behavior, which enables us to manage Writer instances
-/// across multiple connections.
-#[derive(Debug)]
-pub struct BlockingDirectory(MmapDirectory);
-
-impl BlockingDirectory {
-    pub fn open(directory_path: impl AsRef<Path>) -> Result<Self> {
-        if !directory_path.as_ref().exists() {
-            fs::create_dir_all(&directory_path).expect("must be able to create index directory")
-        }
-        Ok(Self(MmapDirectory::open(directory_path)?))
-    }
-}
-
-impl DirectoryClone for BlockingDirectory {
-    fn box_clone(&self) -> Box<dyn Directory> {
-        Box::new(BlockingDirectory(self.0.clone()))
-    }
-}
-
-impl Directory for BlockingDirectory {
-    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
-        self.0.get_file_handle(path)
-    }
-
-    fn open_read(&self, path: &Path) -> result::Result<FileSlice, OpenReadError> {
-        self.0.open_read(path)
-    }
-
-    fn open_write(&self, path: &Path) -> result::Result<WritePtr, OpenWriteError> {
-        self.0.open_write(path)
-    }
-
-    fn atomic_write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
-        self.0.atomic_write(path, data)
-    }
-
-    fn atomic_read(&self, path: &Path) -> result::Result<Vec<u8>, OpenReadError> {
-        self.0.atomic_read(path)
-    }
-
-    fn delete(&self, path: &Path) -> result::Result<(), DeleteError> {
-        self.0.delete(path)
-    }
-
-    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
-        self.0.exists(path)
-    }
-
-    fn acquire_lock(&self, lock: &Lock) -> result::Result<DirectoryLock, LockError> {
-        // This is the only change we actually need to make to the Directory trait impl.
-        // We want the acquire_lock behavior to block and wait for a lock to be available,
-        // instead of panicking. Internally, Tantivy just polls for its availability.
-        let blocking_lock = Lock {
-            filepath: lock.filepath.clone(),
-            is_blocking: true,
-        };
-        self.0.acquire_lock(&blocking_lock)
-    }
-
-    fn watch(&self, watch_callback: WatchCallback) -> tantivy::Result<WatchHandle> {
-        self.0.watch(watch_callback)
-    }
-
-    fn sync_directory(&self) -> io::Result<()> {
-        self.0.sync_directory()
-    }
-}
+use super::block_directory::{BlockDirectory, BlockDirectoryError};
+use super::directory::{SearchDirectoryError, WriterDirectory};
 
 /// A global store of which indexes have been created during a transaction,
 /// so that they can be committed or rolled back in case of an abort.
@@ -121,36 +45,51 @@ static mut PENDING_INDEX_DROPS: Lazy<HashSet<WriterDirectory>> = Lazy::new(HashS
 /// The entity that interfaces with Tantivy indexes.
 pub struct SearchIndexWriter {
     // this is an Option<> because on drop we need to take ownership of the underlying
-    // IndexWriter instance so we can, in the background, wait for all merging threads to finish
+    // IndexWriter instance so we can wait for all merging threads to finish
     pub underlying_writer: Option<IndexWriter>,
     pub wants_merge: bool,
+    pub directory: BlockDirectory,
+    /// The field nested child documents keep their parent's ctid in, if the index has any
+    pub nested_parent_field: Option<Field>,
 }
 
 impl Drop for SearchIndexWriter {
     fn drop(&mut self) {
-        if let Some(writer) = self.underlying_writer.take() {
-            // wait for all merging threads to finish.  we do this in the background
-            // because we don't want to block the connection that created this SearchIndexWriter
-            // from being able to do more work.
-            if self.wants_merge {
-                std::thread::spawn(move || {
-                    if let Err(e) = writer.wait_merging_threads() {
-                        pgrx::warning!("`wait_merging_threads` failed: {e}");
-                    }
-                });
+        if self.underlying_writer.is_none() || !self.wants_merge {
+            return;
+        }
+
+        // merges write their new segments into the `BlockDirectory`, which can only be flushed
+        // to the index relation from this thread, so we have to wait for them here rather than
+        // in the background
+        unsafe {
+            if !pg_sys::IsTransactionState() {
+                // the transaction is going away, so whatever the merges produce is discarded
+                return;
             }
         }
+        if let Err(e) = self.wait_merging_threads() {
+            pgrx::warning!("`wait_merging_threads` failed: {e}");
+        }
     }
 }
 
 #[allow(static_mut_refs)]
 impl SearchIndexWriter {
     pub fn insert(&self, document: SearchDocument) -> Result<(), IndexError> {
-        // Add the Tantivy document to the index.
-        self.underlying_writer
-            .as_ref()
-            .unwrap()
-            .add_document(document.into())?;
+        let writer = self.underlying_writer.as_ref().unwrap();
+        if document.children.is_empty() {
+            // Add the Tantivy document to the index.
+            writer.add_document(document.into())?;
+        } else {
+            // a batch of operations is added to the same segment in order, which keeps the
+            // children in a block right before their parent
+            let operations = document
+                .into_documents()
+                .map(UserOperation::Add)
+                .collect::<Vec<_>>();
+            writer.run(operations)?;
+        }
 
         Ok(())
     }
@@ -162,16 +101,42 @@ impl SearchIndexWriter {
                 .as_ref()
                 .unwrap()
                 .delete_term(ctid_term);
+
+            if let Some(nested_parent_field) = self.nested_parent_field {
+                let parent_term = tantivy::Term::from_field_u64(nested_parent_field, *ctid);
+                self.underlying_writer
+                    .as_ref()
+                    .unwrap()
+                    .delete_term(parent_term);
+            }
         }
         Ok(())
     }
 
     pub fn commit(&mut self) -> Result<()> {
-        self.underlying_writer
-            .as_mut()
-            .unwrap()
-            .commit()
+        // applying deletes opens existing segments from tantivy's segment updater thread
+        let writer = self.underlying_writer.as_mut().unwrap();
+        self.directory
+            .serve_reads_while(|| writer.commit())
             .context("error committing to tantivy index")?;
+        self.directory
+            .flush()
+            .context("error flushing tantivy index to disk")?;
+
+        Ok(())
+    }
+
+    /// Wait for any merges started by previous commits to finish, and flush their results
+    /// to the index relation.  The underlying writer is consumed.
+    pub fn wait_merging_threads(&mut self) -> Result<()> {
+        if let Some(writer) = self.underlying_writer.take() {
+            self.directory
+                .serve_reads_while(|| writer.wait_merging_threads())
+                .context("error waiting for tantivy merges")?;
+            self.directory
+                .flush()
+                .context("error flushing merged segments to disk")?;
+        }
 
         Ok(())
     }
@@ -182,11 +147,9 @@ impl SearchIndexWriter {
     }
 
     pub fn vacuum(&self) -> Result<(), IndexError> {
-        self.underlying_writer
-            .as_ref()
-            .unwrap()
-            .garbage_collect_files()
-            .wait()?;
+        let writer = self.underlying_writer.as_ref().unwrap();
+        self.directory
+            .serve_reads_while(|| writer.garbage_collect_files().wait())?;
         Ok(())
     }
 
@@ -197,10 +160,12 @@ impl SearchIndexWriter {
     ) -> Result<()> {
         let schema = SearchIndexSchema::new(fields, key_field_index)?;
 
-        let tantivy_dir_path = directory.tantivy_dir_path(true)?;
-        let tantivy_dir = BlockingDirectory::open(tantivy_dir_path)?;
-        let mut underlying_index =
-            Index::create(tantivy_dir, schema.schema.clone(), IndexSettings::default())?;
+        let block_directory = BlockDirectory::create(pg_sys::Oid::from(directory.index_oid))?;
+        let mut underlying_index = Index::create(
+            block_directory.clone(),
+            schema.schema.clone(),
+            IndexSettings::default(),
+        )?;
 
         SearchIndex::setup_tokenizers(&mut underlying_index, &schema);
 
@@ -208,10 +173,11 @@ impl SearchIndexWriter {
             underlying_index,
             directory: directory.clone(),
             schema,
+            block_directory,
         };
 
-        // Serialize SearchIndex to disk so it can be initialized by other connections.
-        new_self.directory.save_index(&new_self)?;
+        // Serialize SearchIndex into the index relation so it can be initialized by other connections.
+        new_self.save()?;
 
         // Mark in our global store that this index is pending create, in case it
         // needs to be rolled back on abort.
@@ -259,6 +225,9 @@ pub enum IndexError {
     #[error(transparent)]
     TantivyValueError(#[from] TantivyValueError),
 
+    #[error(transparent)]
+    BlockDirectoryError(#[from] BlockDirectoryError),
+
     #[error("couldn't remove index files on drop_index: {0}")]
     DeleteDirectory(#[from] SearchDirectoryError),
 
@@ -83,6 +83,7 @@ pub unsafe extern "C" fn _PG_init() {
     #[allow(deprecated)]
     pgrx::hooks::register_hook(&mut TRACE_HOOK);
     customscan::register_rel_pathlist(customscan::pdbscan::PdbScan);
+    customscan::register_upper_path(customscan::pdbscan::PdbScan);
 }
 
 /// This module is required by `cargo pgrx test` invocations.
@@ -15,13 +15,16 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use crate::api::analyzer::resolve_analyzer;
 use crate::index::WriterDirectory;
 use crate::index::{SearchIndex, WriterResources};
 use crate::postgres::index::relfilenode_from_pg_relation;
 use crate::postgres::insert::init_insert_state;
 use crate::postgres::options::SearchIndexCreateOptions;
 use crate::postgres::utils::row_to_search_document;
-use crate::schema::{IndexRecordOption, SearchFieldConfig, SearchFieldName, SearchFieldType};
+use crate::schema::{
+    IndexRecordOption, SearchFieldConfig, SearchFieldName, SearchFieldType, SearchSimilarity,
+};
 use pgrx::*;
 use std::collections::HashMap;
 use std::ffi::CStr;
@@ -92,12 +95,25 @@ pub extern "C" fn ambuild(
 
     // Create a map from column name to column type. We'll use this to verify that index
     // configurations passed by the user reference the correct types for each column.
+    // The index's expressions are fields too, named after the index's attributes for them.
+    let expression_attributes = unsafe {
+        let num_index_attrs = (*index_info).ii_NumIndexAttrs as usize;
+        let index_tupdesc = index_relation.tuple_desc();
+        (0..num_index_attrs)
+            .filter(|i| (*index_info).ii_IndexAttrNumbers[*i] == 0)
+            .filter_map(|i| {
+                index_tupdesc
+                    .get(i)
+                    .map(|attribute| (attribute.name().to_string(), attribute.type_oid()))
+            })
+            .collect::<Vec<_>>()
+    };
     let name_type_map: HashMap<SearchFieldName, SearchFieldType> = heap_relation
         .tuple_desc()
         .into_iter()
-        .filter_map(|attribute| {
-            let attname = attribute.name();
-            let attribute_type_oid = attribute.type_oid();
+        .map(|attribute| (attribute.name().to_string(), attribute.type_oid()))
+        .chain(expression_attributes)
+        .filter_map(|(attname, attribute_type_oid)| {
             let array_type = unsafe { pg_sys::get_element_type(attribute_type_oid.value()) };
             let base_oid = if array_type != pg_sys::InvalidOid {
                 PgOid::from(array_type)
@@ -151,6 +167,12 @@ pub extern "C" fn ambuild(
         }
     }
 
+    for (name, _) in rdopts.get_facet_fields() {
+        if !matches!(name_type_map.get(&name), Some(SearchFieldType::Text)) {
+            panic!("'{name}' cannot be indexed as a facet field");
+        }
+    }
+
     let key_field = rdopts.get_key_field().expect("must specify key field");
     let key_field_type = match name_type_map.get(&key_field) {
         Some(field_type) => field_type,
@@ -172,6 +194,7 @@ pub extern "C" fn ambuild(
             tokenizer: SearchTokenizer::Raw(SearchTokenizerFilters::default()),
             record: IndexRecordOption::Basic,
             normalizer: SearchNormalizer::Raw,
+            similarity: SearchSimilarity::default(),
         },
         SearchFieldType::Json => SearchFieldConfig::Json {
             indexed: true,
@@ -179,11 +202,14 @@ pub extern "C" fn ambuild(
             stored: true,
             fieldnorms: false,
             expand_dots: false,
+            nested: false,
             tokenizer: SearchTokenizer::Raw(SearchTokenizerFilters::default()),
             record: IndexRecordOption::Basic,
             normalizer: SearchNormalizer::Raw,
+            similarity: SearchSimilarity::default(),
         },
         SearchFieldType::Range => SearchFieldConfig::Range { stored: true },
+        SearchFieldType::Facet => unreachable!("column types are never inferred to be facets"),
         SearchFieldType::Bool => SearchFieldConfig::Boolean {
             indexed: true,
             fast: true,
@@ -197,8 +223,8 @@ pub extern "C" fn ambuild(
     };
 
     // Concatenate the separate lists of fields.
-    let fields: Vec<_> = rdopts
-        .get_fields(&heap_relation, index_info)
+    let mut fields: Vec<_> = rdopts
+        .get_fields(&heap_relation, &index_relation, index_info)
         .into_iter()
         .filter(|(name, _, _)| name != &key_field) // Process key_field separately.
         .chain(std::iter::once((
@@ -215,6 +241,16 @@ pub extern "C" fn ambuild(
         )))
         .collect();
 
+    // Named analyzers are looked up now and kept in the index's schema, so that the index keeps
+    // tokenizing text the same way if they change.
+    for (_, config, _) in fields.iter_mut() {
+        if let SearchFieldConfig::Text { tokenizer, .. }
+        | SearchFieldConfig::Json { tokenizer, .. } = config
+        {
+            resolve_analyzer(tokenizer);
+        }
+    }
+
     let key_field_index = fields
         .iter()
         .position(|(name, _, _)| name == &key_field)
@@ -232,9 +268,11 @@ pub extern "C" fn ambuild(
     SearchIndex::create_index(directory, fields, key_field_index)
         .expect("error creating new index instance");
 
-    let state = do_heap_scan(index_info, &heap_relation, &index_relation);
+    let (heap_tuples, state) = do_heap_scan(index_info, &heap_relation, &index_relation);
     let mut result = unsafe { PgBox::<pg_sys::IndexBuildResult>::alloc0() };
-    result.heap_tuples = state.count as f64;
+    // a partial index only indexes the rows that satisfy its predicate, which Postgres checks
+    // before handing them to us
+    result.heap_tuples = heap_tuples;
     result.index_tuples = state.count as f64;
 
     result.into_pg()
@@ -247,15 +285,26 @@ fn do_heap_scan<'a>(
     index_info: *mut pg_sys::IndexInfo,
     heap_relation: &'a PgRelation,
     index_relation: &'a PgRelation,
-) -> BuildState {
+) -> (f64, BuildState) {
     let mut state = BuildState::new(index_relation, index_info);
-    unsafe {
-        pg_sys::IndexBuildHeapScan(
+    let heap_tuples = unsafe {
+        // this is what pgrx's `IndexBuildHeapScan()` does, except that we keep the number of heap
+        // tuples it returns, as that's more than the number we indexed for a partial index
+        let table_am = (*heap_relation.as_ptr()).rd_tableam;
+        let heap_tuples = (*table_am)
+            .index_build_range_scan
+            .expect("table access method should support index builds")(
             heap_relation.as_ptr(),
             index_relation.as_ptr(),
             index_info,
+            true,
+            false,
+            true,
+            0,
+            pg_sys::InvalidBlockNumber,
             Some(build_callback),
-            &mut state,
+            (&mut state as *mut BuildState).cast(),
+            std::ptr::null_mut(),
         );
 
         let insert_state = init_insert_state(
@@ -269,15 +318,14 @@ fn do_heap_scan<'a>(
                 .unwrap_or_else(|e| panic!("failed to commit new tantivy index: {e}"));
 
             writer
-                .underlying_writer
-                .take()
-                .unwrap()
                 .wait_merging_threads()
                 .unwrap_or_else(|e| panic!("failed to wait for index merge: {e}"));
         }
-    }
 
-    state
+        heap_tuples
+    };
+
+    (heap_tuples, state)
 }
 
 #[pg_guard]
@@ -82,8 +82,11 @@ pub unsafe extern "C" fn amcostestimate(
     // if in fact we were based on Postgres' block storage
     *index_pages = *index_selectivity * page_estimate as f64;
 
-    // total cost is just a hardcoded value of the cost to read a tuple from an index times the
-    // estimated number of rows we expect to return
-    *index_total_cost =
-        *index_startup_cost + *index_selectivity * reltuples * pg_sys::cpu_index_tuple_cost;
+    // total cost is the cost of reading those pages plus a hardcoded value of the cost to read a
+    // tuple from an index times the estimated number of rows we expect to return.  Postgres adds
+    // the heap access on top of this, which is where a bitmap heap scan's in-order page reads
+    // beat the random ones of an index scan when many rows match
+    *index_total_cost = *index_startup_cost
+        + *index_pages * pg_sys::random_page_cost
+        + *index_selectivity * reltuples * pg_sys::cpu_index_tuple_cost;
 }
@@ -96,6 +96,15 @@ impl OrderByStyle {
             (*self.pathkey()).pk_strategy.into()
         }
     }
+
+    pub fn nulls_first(&self) -> bool {
+        unsafe {
+            let pathkey = self.pathkey();
+            assert!(!pathkey.is_null());
+
+            (*pathkey).pk_nulls_first
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -184,6 +193,24 @@ impl<P: Into<*mut pg_sys::List> + Default> CustomPathBuilder<P> {
         }
     }
 
+    /// Create a builder for a path that computes `output_rel`, an upper relation such as the
+    /// result of grouping/aggregation, directly from the base relation `input_rel`
+    pub fn new_upper<CS: CustomScan>(
+        root: *mut pg_sys::PlannerInfo,
+        input_rel: *mut pg_sys::RelOptInfo,
+        output_rel: *mut pg_sys::RelOptInfo,
+    ) -> CustomPathBuilder<P> {
+        let mut builder = unsafe {
+            let rti = (*input_rel).relid;
+            let rte = *(*root).simple_rte_array.add(rti as usize);
+            Self::new::<CS>(root, input_rel, rti, rte)
+        };
+
+        builder.custom_path_node.path.parent = output_rel;
+        builder.custom_path_node.path.pathtarget = unsafe { *output_rel }.reltarget;
+        builder
+    }
+
     pub fn args(&self) -> &Args {
         &self.args
     }
@@ -207,9 +234,8 @@ impl<P: Into<*mut pg_sys::List> + Default> CustomPathBuilder<P> {
         }
     }
 
-    #[allow(dead_code)]
     pub fn path_target(&self) -> *mut pg_sys::PathTarget {
-        self.args.rel().reltarget
+        self.custom_path_node.path.pathtarget
     }
 
     #[allow(dead_code)]
@@ -257,15 +283,15 @@ impl<P: Into<*mut pg_sys::List> + Default> CustomPathBuilder<P> {
         self
     }
 
-    pub fn add_path_key(mut self, pathkey: &Option<OrderByStyle>) -> Self {
+    pub fn add_path_keys(mut self, pathkeys: &[OrderByStyle]) -> Self {
         unsafe {
-            if let Some(style) = pathkey {
-                let mut pklist =
-                    PgList::<pg_sys::PathKey>::from_pg(self.custom_path_node.path.pathkeys);
+            let mut pklist =
+                PgList::<pg_sys::PathKey>::from_pg(self.custom_path_node.path.pathkeys);
+            for style in pathkeys {
                 pklist.push(style.pathkey());
-
-                self.custom_path_node.path.pathkeys = pklist.into_pg();
             }
+
+            self.custom_path_node.path.pathkeys = pklist.into_pg();
             self
         }
     }
@@ -112,6 +112,13 @@ impl<P: Into<*mut pg_sys::List> + From<*mut pg_sys::List> + Default> CustomScanB
         &mut self.custom_private
     }
 
+    /// Describe the tuples this scan produces when they aren't rows of its base relation, such
+    /// as when it computes an upper relation.  Postgres rewrites the plan's targetlist in terms
+    /// of these entries.
+    pub fn set_custom_scan_tlist(&mut self, tlist: *mut pg_sys::List) {
+        self.custom_scan_node.custom_scan_tlist = tlist;
+    }
+
     pub fn build(self) -> pg_sys::CustomScan {
         let mut node = self.custom_scan_node;
         node.custom_private = self.custom_private.into();
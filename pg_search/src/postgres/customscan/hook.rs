@@ -15,6 +15,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use crate::api::operator::{require_bm25_index_predicate, use_partition_bm25_index};
 use crate::gucs;
 use crate::postgres::customscan::builders::custom_path::{CustomPathBuilder, Flags};
 use crate::postgres::customscan::CustomScan;
@@ -58,6 +59,40 @@ pub fn register_rel_pathlist<CS: CustomScan + 'static>(_: CS) {
     }
 }
 
+pub fn register_upper_path<CS: CustomScan + 'static>(_: CS) {
+    unsafe {
+        static mut PREV_HOOKS: Lazy<
+            FxHashMap<std::any::TypeId, pg_sys::create_upper_paths_hook_type>,
+        > = Lazy::new(Default::default);
+
+        #[pg_guard]
+        extern "C" fn __priv_callback<CS: CustomScan + 'static>(
+            root: *mut pg_sys::PlannerInfo,
+            stage: pg_sys::UpperRelationKind::Type,
+            input_rel: *mut pg_sys::RelOptInfo,
+            output_rel: *mut pg_sys::RelOptInfo,
+            extra: *mut std::os::raw::c_void,
+        ) {
+            unsafe {
+                #[allow(static_mut_refs)]
+                if let Some(Some(prev_hook)) = PREV_HOOKS.get(&std::any::TypeId::of::<CS>()) {
+                    (*prev_hook)(root, stage, input_rel, output_rel, extra);
+                }
+
+                paradedb_upper_path_callback::<CS>(root, stage, input_rel, output_rel);
+            }
+        }
+
+        #[allow(static_mut_refs)]
+        match PREV_HOOKS.entry(std::any::TypeId::of::<CS>()) {
+            Entry::Occupied(_) => panic!("{} is already registered", std::any::type_name::<CS>()),
+            Entry::Vacant(entry) => entry.insert(pg_sys::create_upper_paths_hook),
+        };
+
+        pg_sys::create_upper_paths_hook = Some(__priv_callback::<CS>);
+    }
+}
+
 /// Although this hook function can be used to examine, modify, or remove paths generated by the
 /// core system, a custom scan provider will typically confine itself to generating CustomPath
 /// objects and adding them to rel using add_path. The custom scan provider is responsible for
@@ -70,6 +105,11 @@ pub extern "C" fn paradedb_rel_pathlist_callback<CS: CustomScan>(
     rte: *mut pg_sys::RangeTblEntry,
 ) {
     unsafe {
+        // whichever scan of the relation we end up choosing, its `@@@` quals need to search an
+        // index of the relation's own that has every row they could match
+        use_partition_bm25_index(rel, rte);
+        require_bm25_index_predicate(rel, rte);
+
         if !gucs::enable_custom_scan() {
             return;
         }
@@ -91,3 +131,30 @@ pub extern "C" fn paradedb_rel_pathlist_callback<CS: CustomScan>(
         }
     }
 }
+
+#[pg_guard]
+pub extern "C" fn paradedb_upper_path_callback<CS: CustomScan>(
+    root: *mut pg_sys::PlannerInfo,
+    stage: pg_sys::UpperRelationKind::Type,
+    input_rel: *mut pg_sys::RelOptInfo,
+    output_rel: *mut pg_sys::RelOptInfo,
+) {
+    unsafe {
+        if !gucs::enable_custom_scan() {
+            return;
+        }
+
+        // we only know how to compute upper relations directly on top of a single table
+        if (*input_rel).reloptkind != pg_sys::RelOptKind::RELOPT_BASEREL {
+            return;
+        }
+
+        let builder = CustomPathBuilder::new_upper::<CS>(root, input_rel, output_rel);
+        if let Some(mut path) = CS::upper_callback(builder, stage) {
+            let custom_path = PgMemoryContexts::CurrentMemoryContext
+                .copy_ptr_into(&mut path, std::mem::size_of_val(&path));
+
+            pg_sys::add_path(output_rel, custom_path.cast());
+        }
+    }
+}
@@ -45,7 +45,7 @@ use crate::postgres::customscan::builders::custom_state::{
 use crate::postgres::customscan::explainer::Explainer;
 use crate::postgres::customscan::path::{plan_custom_path, reparameterize_custom_path_by_child};
 use crate::postgres::customscan::scan::create_custom_scan_state;
-pub use hook::register_rel_pathlist;
+pub use hook::{register_rel_pathlist, register_upper_path};
 use std::ptr::NonNull;
 
 pub trait CustomScanState: Default {
@@ -106,6 +106,15 @@ pub trait CustomScan: ExecMethod + Default + Sized {
 
     fn callback(builder: CustomPathBuilder<Self::PrivateData>) -> Option<pg_sys::CustomPath>;
 
+    /// Called from Postgres' `create_upper_paths_hook` for an upper relation directly above a
+    /// single base relation, so the scan can offer to compute that upper relation itself.
+    fn upper_callback(
+        builder: CustomPathBuilder<Self::PrivateData>,
+        stage: pg_sys::UpperRelationKind::Type,
+    ) -> Option<pg_sys::CustomPath> {
+        None
+    }
+
     fn plan_custom_path(builder: CustomScanBuilder<Self::PrivateData>) -> pg_sys::CustomScan;
 
     fn create_custom_scan_state(
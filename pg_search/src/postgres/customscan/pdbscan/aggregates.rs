@@ -0,0 +1,357 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Recognizing `COUNT`/`SUM`/`AVG`/`MIN`/`MAX` aggregates, optionally grouped by columns, that
+//! the `ParadeDB Scan` can compute from fast fields with a Tantivy aggregation rather than
+//! returning every matching row to Postgres.
+
+use crate::nodecast;
+use crate::schema::SearchIndexSchema;
+use pgrx::{pg_sys, PgBuiltInOids, PgList, PgOid, PgRelation};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::ffi::CStr;
+
+/// The bucket key Tantivy is asked to use for documents with no value in a text GROUP BY column
+const TEXT_MISSING_KEY: &str = "\u{0}";
+
+/// The bucket key Tantivy is asked to use for documents with no value in an integer GROUP BY column
+const INT_MISSING_KEY: f64 = i64::MIN as f64;
+
+/// 2^53, below which every integer is exactly representable as an f64
+pub const MAX_EXACT_F64_INT: f64 = (1u64 << f64::MANTISSA_DIGITS) as f64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AggregateKind {
+    CountStar,
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupKey {
+    pub field: String,
+    pub typoid: u32,
+}
+
+/// One column of the tuples the scan produces, in the order of its `custom_scan_tlist`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AggregateTarget {
+    /// The value of the GROUP BY column at this index of [`AggregatePlan::group_by`]
+    GroupKey(usize),
+    Aggregate {
+        kind: AggregateKind,
+        field: Option<String>,
+        restype: u32,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AggregatePlan {
+    pub group_by: Vec<GroupKey>,
+    pub targets: Vec<AggregateTarget>,
+}
+
+impl GroupKey {
+    pub fn typoid(&self) -> PgOid {
+        PgOid::from(pg_sys::Oid::from(self.typoid))
+    }
+
+    pub fn missing_key(&self) -> Value {
+        match self.typoid() {
+            PgOid::BuiltIn(PgBuiltInOids::TEXTOID | PgBuiltInOids::VARCHAROID) => {
+                json!(TEXT_MISSING_KEY)
+            }
+            _ => json!(INT_MISSING_KEY),
+        }
+    }
+
+    /// Is this bucket key the one standing in for a NULL value?
+    pub fn is_missing_key(&self, key: &Value) -> bool {
+        match key {
+            Value::String(s) => s == TEXT_MISSING_KEY,
+            Value::Number(n) => n.as_f64() == Some(INT_MISSING_KEY),
+            _ => false,
+        }
+    }
+}
+
+impl AggregatePlan {
+    /// The name of the Tantivy metric aggregation that computes the stats for `field`.  `COUNT(*)`
+    /// counts the `ctid` field, as every document has one.
+    pub fn metric_name(field: Option<&str>) -> String {
+        format!("stats_{}", field.unwrap_or("ctid"))
+    }
+
+    /// The name of the Tantivy terms aggregation that buckets by the GROUP BY column at `depth`
+    pub fn group_name(depth: usize) -> String {
+        format!("group_{depth}")
+    }
+
+    /// Build the Tantivy aggregation request, as json: one nested `terms` aggregation per GROUP BY
+    /// column, with a `stats` aggregation for each field the targets need at the innermost level.
+    pub fn to_tantivy(&self) -> Value {
+        let mut aggs = Map::new();
+        for target in &self.targets {
+            if let AggregateTarget::Aggregate { field, .. } = target {
+                aggs.insert(
+                    Self::metric_name(field.as_deref()),
+                    json!({ "stats": { "field": field.as_deref().unwrap_or("ctid") } }),
+                );
+            }
+        }
+
+        for (depth, key) in self.group_by.iter().enumerate().rev() {
+            let mut grouped = Map::new();
+            grouped.insert(
+                Self::group_name(depth),
+                json!({
+                    "terms": {
+                        "field": key.field,
+                        "size": u32::MAX,
+                        "segment_size": u32::MAX,
+                        "missing": key.missing_key(),
+                    },
+                    "aggs": aggs,
+                }),
+            );
+            aggs = grouped;
+        }
+
+        Value::Object(aggs)
+    }
+
+    /// A short description of the pushed-down aggregates, for EXPLAIN
+    pub fn describe_aggregates(&self) -> String {
+        self.targets
+            .iter()
+            .filter_map(|target| match target {
+                AggregateTarget::GroupKey(_) => None,
+                AggregateTarget::Aggregate { kind, field, .. } => Some(match kind {
+                    AggregateKind::CountStar => "COUNT(*)".to_string(),
+                    AggregateKind::Count => format!("COUNT({})", field.as_ref().unwrap()),
+                    AggregateKind::Sum => format!("SUM({})", field.as_ref().unwrap()),
+                    AggregateKind::Avg => format!("AVG({})", field.as_ref().unwrap()),
+                    AggregateKind::Min => format!("MIN({})", field.as_ref().unwrap()),
+                    AggregateKind::Max => format!("MAX({})", field.as_ref().unwrap()),
+                }),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn describe_group_by(&self) -> String {
+        self.group_by
+            .iter()
+            .map(|key| key.field.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Flatten the expressions of a targetlist into the distinct Vars and Aggrefs they're computed
+/// from.  These are the columns the scan has to produce, and Postgres computes the rest of each
+/// expression on top of them.
+///
+/// This is called both when we create the path and when we plan it, so it must always find the
+/// same nodes in the same order.
+pub unsafe fn flatten_targets(
+    exprs: impl Iterator<Item = *mut pg_sys::Node>,
+) -> Vec<*mut pg_sys::Node> {
+    let mut flattened: Vec<*mut pg_sys::Node> = vec![];
+    for expr in exprs {
+        let nodes = PgList::<pg_sys::Node>::from_pg(pg_sys::pull_var_clause(
+            expr,
+            (pg_sys::PVC_INCLUDE_AGGREGATES
+                | pg_sys::PVC_INCLUDE_WINDOWFUNCS
+                | pg_sys::PVC_INCLUDE_PLACEHOLDERS) as _,
+        ));
+        for node in nodes.iter_ptr() {
+            if !flattened
+                .iter()
+                .any(|existing| pg_sys::equal(existing.cast(), node.cast()))
+            {
+                flattened.push(node);
+            }
+        }
+    }
+    flattened
+}
+
+/// Decide if the grouping and aggregates of the query being planned, whose grouped output has
+/// the expressions in `target_exprs`, can be computed from the index on relation `rti`.
+pub unsafe fn plan_aggregates(
+    root: *mut pg_sys::PlannerInfo,
+    rti: pg_sys::Index,
+    heaprel: &PgRelation,
+    schema: &SearchIndexSchema,
+    target_exprs: *mut pg_sys::List,
+) -> Option<AggregatePlan> {
+    let parse = (*root).parse;
+    if !(*parse).groupingSets.is_null() || !(*parse).havingQual.is_null() || (*parse).hasTargetSRFs
+    {
+        return None;
+    }
+
+    let tupdesc = heaprel.tuple_desc();
+    let mut plan = AggregatePlan::default();
+
+    let mut group_vars = vec![];
+    let group_clause = PgList::<pg_sys::SortGroupClause>::from_pg((*parse).groupClause);
+    for sgc in group_clause.iter_ptr() {
+        let expr = pg_sys::get_sortgroupclause_expr(sgc, (*parse).targetList);
+        let var = nodecast!(Var, T_Var, expr)?;
+        let field = var_field_name(var, rti, &tupdesc)?;
+        let typoid = PgOid::from((*var).vartype);
+
+        let supported = match typoid {
+            PgOid::BuiltIn(PgBuiltInOids::TEXTOID | PgBuiltInOids::VARCHAROID) => {
+                schema.is_field_raw_sortable(&field)
+            }
+            // wider types don't survive the trip through the f64 keys of Tantivy's terms buckets
+            PgOid::BuiltIn(PgBuiltInOids::INT2OID | PgBuiltInOids::INT4OID) => {
+                schema.is_numeric_fast_field(&field)
+            }
+            _ => false,
+        };
+        if !supported {
+            return None;
+        }
+
+        group_vars.push(var);
+        plan.group_by.push(GroupKey {
+            field,
+            typoid: (*var).vartype.as_u32(),
+        });
+    }
+
+    let target_exprs = PgList::<pg_sys::Node>::from_pg(target_exprs);
+    for node in flatten_targets(target_exprs.iter_ptr()) {
+        if let Some(var) = nodecast!(Var, T_Var, node) {
+            let idx = group_vars.iter().position(|group_var| {
+                (**group_var).varno == (*var).varno && (**group_var).varattno == (*var).varattno
+            })?;
+            plan.targets.push(AggregateTarget::GroupKey(idx));
+        } else if let Some(aggref) = nodecast!(Aggref, T_Aggref, node) {
+            plan.targets
+                .push(plan_aggref(aggref, rti, heaprel, &tupdesc, schema)?);
+        } else {
+            return None;
+        }
+    }
+
+    Some(plan)
+}
+
+unsafe fn plan_aggref(
+    aggref: *mut pg_sys::Aggref,
+    rti: pg_sys::Index,
+    heaprel: &PgRelation,
+    tupdesc: &pgrx::PgTupleDesc,
+    schema: &SearchIndexSchema,
+) -> Option<AggregateTarget> {
+    if !(*aggref).aggdistinct.is_null()
+        || !(*aggref).aggorder.is_null()
+        || !(*aggref).aggfilter.is_null()
+        || (*aggref).aggvariadic
+        || (*aggref).agglevelsup != 0
+        || (*aggref).aggkind as u8 != pg_sys::AGGKIND_NORMAL as u8
+    {
+        return None;
+    }
+
+    // only the builtin aggregates, not some user-defined function that happens to share a name
+    if pg_sys::get_func_namespace((*aggref).aggfnoid)
+        != pg_sys::Oid::from(pg_sys::PG_CATALOG_NAMESPACE)
+    {
+        return None;
+    }
+    let funcname = pg_sys::get_func_name((*aggref).aggfnoid);
+    if funcname.is_null() {
+        return None;
+    }
+
+    let kind = match CStr::from_ptr(funcname).to_str().ok()? {
+        "count" if (*aggref).aggstar => AggregateKind::CountStar,
+        "count" => AggregateKind::Count,
+        "sum" => AggregateKind::Sum,
+        "avg" => AggregateKind::Avg,
+        "min" => AggregateKind::Min,
+        "max" => AggregateKind::Max,
+        _ => return None,
+    };
+    let restype = (*aggref).aggtype.as_u32();
+
+    if kind == AggregateKind::CountStar {
+        return Some(AggregateTarget::Aggregate {
+            kind,
+            field: None,
+            restype,
+        });
+    }
+
+    let args = PgList::<pg_sys::TargetEntry>::from_pg((*aggref).args);
+    if args.len() != 1 {
+        return None;
+    }
+    let var = nodecast!(Var, T_Var, (*args.get_ptr(0)?).expr)?;
+    let field = var_field_name(var, rti, tupdesc)?;
+    if !schema.is_numeric_fast_field(&field) {
+        return None;
+    }
+
+    // Tantivy computes its stats as f64, so only push down what that computes exactly.  An int8 or
+    // numeric value may not even fit, and an int4 sum only fits while every partial sum stays
+    // below 2^53, which enough maximal values will exceed.  The sum is checked again once we know
+    // the actual values, but it's better to not push down what's likely to fail.
+    let sums = matches!(kind, AggregateKind::Sum | AggregateKind::Avg);
+    let supported = match PgOid::from((*var).vartype) {
+        PgOid::BuiltIn(
+            PgBuiltInOids::INT2OID | PgBuiltInOids::FLOAT4OID | PgBuiltInOids::FLOAT8OID,
+        ) => true,
+        PgOid::BuiltIn(PgBuiltInOids::INT4OID) => {
+            !sums
+                || (heaprel.reltuples().unwrap_or(0.0) as f64)
+                    <= MAX_EXACT_F64_INT / i32::MAX as f64
+        }
+        PgOid::BuiltIn(PgBuiltInOids::INT8OID) => kind == AggregateKind::Count,
+        _ => false,
+    };
+
+    supported.then_some(AggregateTarget::Aggregate {
+        kind,
+        field: Some(field),
+        restype,
+    })
+}
+
+/// The name of the indexed field a plain column reference of relation `rti` refers to
+pub(super) unsafe fn var_field_name(
+    var: *mut pg_sys::Var,
+    rti: pg_sys::Index,
+    tupdesc: &pgrx::PgTupleDesc,
+) -> Option<String> {
+    if (*var).varno as pg_sys::Index != rti || (*var).varlevelsup != 0 || (*var).varattno <= 0 {
+        return None;
+    }
+    tupdesc
+        .get((*var).varattno as usize - 1)
+        .map(|att| att.name().to_string())
+}
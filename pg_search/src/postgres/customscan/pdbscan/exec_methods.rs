@@ -15,11 +15,15 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+pub(crate) mod aggregate;
 pub(crate) mod normal;
 pub(crate) mod top_n;
 
+use crate::postgres::customscan::pdbscan::projections::facets::FacetInfo;
 use crate::postgres::customscan::pdbscan::scan_state::PdbScanState;
 use pgrx::pg_sys;
+use std::collections::HashMap;
+use tantivy::schema::Facet;
 use tantivy::{DocAddress, Score};
 
 pub enum ExecState {
@@ -61,6 +65,11 @@ pub trait ExecMethod {
     }
 
     fn internal_next(&mut self) -> ExecState;
+
+    /// The counts of the scan's facets, if they were collected alongside its search in `init()`
+    fn facet_counts(&mut self) -> Option<HashMap<FacetInfo, Vec<(Facet, u64)>>> {
+        None
+    }
 }
 
 struct UnknownScanStyle;
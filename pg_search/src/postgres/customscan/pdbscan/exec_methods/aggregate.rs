@@ -0,0 +1,263 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::index::SearchIndex;
+use crate::postgres::customscan::pdbscan::aggregates::{
+    AggregateKind, AggregatePlan, AggregateTarget, GroupKey, MAX_EXACT_F64_INT,
+};
+use crate::postgres::customscan::pdbscan::exec_methods::{ExecMethod, ExecState};
+use crate::postgres::customscan::pdbscan::scan_state::PdbScanState;
+use crate::postgres::visibility_checker::invisible_ctids;
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::{
+    function_name, pg_sys, AnyNumeric, IntoDatum, PgBuiltInOids, PgLogLevel, PgOid, PgSqlErrorCode,
+};
+use serde_json::{Map, Value};
+use tantivy::aggregation::agg_req::Aggregations;
+
+type Row = Vec<Option<pg_sys::Datum>>;
+
+/// Computes the pushed-down aggregates with a single Tantivy aggregation over the matching
+/// documents, and returns one virtual tuple per group, shaped like the scan's `custom_scan_tlist`.
+pub struct AggregateScanExecState {
+    plan: AggregatePlan,
+
+    heaprel: pg_sys::Relation,
+    cstate: *mut pg_sys::CustomScanState,
+
+    rows: std::vec::IntoIter<Row>,
+    did_query: bool,
+}
+
+impl AggregateScanExecState {
+    pub fn new(plan: AggregatePlan) -> Self {
+        Self {
+            plan,
+            heaprel: std::ptr::null_mut(),
+            cstate: std::ptr::null_mut(),
+            rows: Vec::new().into_iter(),
+            did_query: false,
+        }
+    }
+}
+
+impl ExecMethod for AggregateScanExecState {
+    fn init(&mut self, state: &PdbScanState, cstate: *mut pg_sys::CustomScanState) {
+        self.heaprel = state.heaprel.unwrap();
+        self.cstate = cstate;
+        self.rows = Vec::new().into_iter();
+        self.did_query = false;
+    }
+
+    fn query(&mut self, state: &PdbScanState) -> bool {
+        if self.did_query {
+            return false;
+        }
+
+        let search_reader = state.search_reader.as_ref().unwrap();
+        let query = state.query.as_ref().unwrap();
+
+        // Tantivy doesn't know about MVCC, so the aggregation has to skip the matching documents
+        // that aren't visible to us
+        let invisible = invisible_ctids(
+            self.heaprel,
+            unsafe { pg_sys::GetActiveSnapshot() },
+            search_reader,
+            query,
+        );
+        let aggregations: Aggregations = serde_json::from_value(self.plan.to_tantivy())
+            .expect("aggregate plan should be a valid tantivy aggregation");
+        let results = search_reader
+            .aggregate(SearchIndex::executor(), query, aggregations, invisible)
+            .expect("aggregation should succeed");
+        let results = serde_json::to_value(results).expect("aggregation results should serialize");
+
+        let mut rows = vec![];
+        if let Value::Object(results) = results {
+            self.collect_rows(&results, &mut vec![], &mut rows);
+        }
+        self.rows = rows.into_iter();
+        self.did_query = true;
+        true
+    }
+
+    fn internal_next(&mut self) -> ExecState {
+        match self.rows.next() {
+            None => ExecState::Eof,
+            Some(row) => unsafe {
+                let scanslot = (*self.cstate).ss.ss_ScanTupleSlot;
+                let tts_ops = (*scanslot).tts_ops;
+                ((*tts_ops).clear.unwrap())(scanslot);
+
+                let natts = (*(*scanslot).tts_tupleDescriptor).natts as usize;
+                let values = std::slice::from_raw_parts_mut((*scanslot).tts_values, natts);
+                let isnull = std::slice::from_raw_parts_mut((*scanslot).tts_isnull, natts);
+                for (i, datum) in row.into_iter().enumerate() {
+                    values[i] = datum.unwrap_or(pg_sys::Datum::null());
+                    isnull[i] = datum.is_none();
+                }
+                pg_sys::ExecStoreVirtualTuple(scanslot);
+
+                // the plan's targetlist is written in terms of our tuples, so evaluate it to get
+                // whatever Postgres actually asked for
+                let projinfo = (*self.cstate).ss.ps.ps_ProjInfo;
+                let slot = if projinfo.is_null() {
+                    scanslot
+                } else {
+                    let econtext = (*projinfo).pi_exprContext;
+                    pg_sys::MemoryContextReset((*econtext).ecxt_per_tuple_memory);
+                    (*econtext).ecxt_scantuple = scanslot;
+                    pg_sys::ExecProject(projinfo)
+                };
+
+                ExecState::Virtual { slot }
+            },
+        }
+    }
+}
+
+impl AggregateScanExecState {
+    /// Walk the nested `terms` buckets of the aggregation results, producing one row for each
+    /// innermost bucket (or a single row if there's no GROUP BY)
+    fn collect_rows(&self, aggs: &Map<String, Value>, keys: &mut Vec<Value>, rows: &mut Vec<Row>) {
+        let depth = keys.len();
+        if depth == self.plan.group_by.len() {
+            rows.push(self.make_row(aggs, keys));
+            return;
+        }
+
+        let buckets = aggs
+            .get(&AggregatePlan::group_name(depth))
+            .and_then(|group| group.get("buckets"))
+            .and_then(|buckets| buckets.as_array());
+        for bucket in buckets.into_iter().flatten() {
+            if let Value::Object(bucket) = bucket {
+                keys.push(bucket.get("key").cloned().unwrap_or(Value::Null));
+                self.collect_rows(bucket, keys, rows);
+                keys.pop();
+            }
+        }
+    }
+
+    fn make_row(&self, aggs: &Map<String, Value>, keys: &[Value]) -> Row {
+        self.plan
+            .targets
+            .iter()
+            .map(|target| match target {
+                AggregateTarget::GroupKey(idx) => key_datum(&keys[*idx], &self.plan.group_by[*idx]),
+                AggregateTarget::Aggregate {
+                    kind,
+                    field,
+                    restype,
+                } => {
+                    let stats = aggs.get(&AggregatePlan::metric_name(field.as_deref()));
+                    let stat = |name: &str| stats.and_then(|stats| stats.get(name));
+                    let count = stat("count").and_then(Value::as_u64).unwrap_or(0);
+                    let restype = PgOid::from(pg_sys::Oid::from(*restype));
+
+                    match kind {
+                        AggregateKind::CountStar | AggregateKind::Count => {
+                            (count as i64).into_datum()
+                        }
+
+                        // like Postgres, the sum and average of no values is NULL
+                        _ if count == 0 => None,
+
+                        AggregateKind::Sum => {
+                            number_datum(integer_sum(&stat, field, count, restype)?, restype)
+                        }
+                        AggregateKind::Avg => {
+                            let sum = integer_sum(&stat, field, count, restype)?;
+                            if matches!(restype, PgOid::BuiltIn(PgBuiltInOids::NUMERICOID)) {
+                                // match the numeric division Postgres uses to average integers
+                                (AnyNumeric::try_from(sum).ok()? / AnyNumeric::from(count as i64))
+                                    .into_datum()
+                            } else {
+                                number_datum(sum / count as f64, restype)
+                            }
+                        }
+                        AggregateKind::Min => number_datum(stat("min")?.as_f64()?, restype),
+                        AggregateKind::Max => number_datum(stat("max")?.as_f64()?, restype),
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// The sum from the stats of an aggregate, making sure that it's exact if it's the sum of an
+/// integer column.  Tantivy adds the values as f64, which is exact so long as no partial sum can
+/// reach 2^53, and planning declines the columns whose sums are likely to.
+fn integer_sum<'a>(
+    stat: &impl Fn(&str) -> Option<&'a Value>,
+    field: &Option<String>,
+    count: u64,
+    restype: PgOid,
+) -> Option<f64> {
+    let sum = stat("sum")?.as_f64()?;
+
+    // the sum of integers is an int8 or numeric, and the average of integers is a numeric
+    if matches!(
+        restype,
+        PgOid::BuiltIn(PgBuiltInOids::INT8OID | PgBuiltInOids::NUMERICOID)
+    ) {
+        let largest = stat("min")?
+            .as_f64()?
+            .abs()
+            .max(stat("max")?.as_f64()?.abs());
+        if largest * count as f64 >= MAX_EXACT_F64_INT {
+            ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_NUMERIC_VALUE_OUT_OF_RANGE,
+                format!(
+                    "the sum of `{}` is too large to compute exactly from the index",
+                    field.as_deref().unwrap_or_default()
+                ),
+                function_name!(),
+            )
+            .set_hint("SET paradedb.enable_aggregate_custom_scan = off to compute it in Postgres")
+            .report(PgLogLevel::ERROR);
+        }
+    }
+
+    Some(sum)
+}
+
+/// Convert a terms bucket key back into a value of its GROUP BY column
+fn key_datum(key: &Value, group_key: &GroupKey) -> Option<pg_sys::Datum> {
+    if group_key.is_missing_key(key) {
+        return None;
+    }
+
+    match group_key.typoid() {
+        PgOid::BuiltIn(PgBuiltInOids::TEXTOID | PgBuiltInOids::VARCHAROID) => {
+            key.as_str().into_datum()
+        }
+        typoid => number_datum(key.as_f64()?, typoid),
+    }
+}
+
+fn number_datum(value: f64, typoid: PgOid) -> Option<pg_sys::Datum> {
+    match typoid {
+        PgOid::BuiltIn(PgBuiltInOids::INT2OID) => (value as i16).into_datum(),
+        PgOid::BuiltIn(PgBuiltInOids::INT4OID) => (value as i32).into_datum(),
+        PgOid::BuiltIn(PgBuiltInOids::INT8OID) => (value as i64).into_datum(),
+        PgOid::BuiltIn(PgBuiltInOids::FLOAT4OID) => (value as f32).into_datum(),
+        PgOid::BuiltIn(PgBuiltInOids::FLOAT8OID) => value.into_datum(),
+        PgOid::BuiltIn(PgBuiltInOids::NUMERICOID) => AnyNumeric::try_from(value).ok().into_datum(),
+        other => panic!("unsupported aggregate result type: {other:?}"),
+    }
+}
@@ -15,13 +15,20 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
-use crate::index::reader::{SearchIndexReader, SearchResults};
+use crate::index::reader::{
+    SearchIndexReader, SearchIndexScore, SearchResults, SortDirection, SortKey,
+};
 use crate::index::SearchIndex;
-use crate::postgres::customscan::builders::custom_path::SortDirection;
 use crate::postgres::customscan::pdbscan::exec_methods::{ExecMethod, ExecState};
+use crate::postgres::customscan::pdbscan::projections::facets::FacetInfo;
 use crate::postgres::customscan::pdbscan::scan_state::PdbScanState;
+use crate::postgres::visibility_checker::{invisible_ctids_among, VisibilityChecker};
+use crate::query::RescoreWindow;
 use pgrx::{direct_function_call, pg_sys, IntoDatum};
+use std::collections::{HashMap, VecDeque};
 use tantivy::query::{Query, QueryClone};
+use tantivy::schema::Facet;
+use tantivy::DocAddress;
 
 // TODO:  should these be GUCs?  I think yes, probably
 const SUBSEQUENT_RETRY_SCALE_FACTOR: usize = 2;
@@ -32,142 +39,244 @@ pub struct TopNScanExecState {
     // required
     heaprelid: pg_sys::Oid,
     limit: usize,
-    sort_direction: SortDirection,
 
     // set during init
     have_less: bool,
     query: Option<Box<dyn Query>>,
     search_reader: Option<SearchIndexReader>,
-    sort_field: Option<String>,
+    sort_keys: Vec<SortKey>,
     search_results: SearchResults,
+    rescore: Option<RescoreWindow>,
+    rescored: VecDeque<(SearchIndexScore, DocAddress)>,
+    facet_counts: Option<HashMap<FacetInfo, Vec<(Facet, u64)>>>,
 
     // state tracking
-    last_ctid: u64,
+    visible_before: usize,
     found: usize,
     chunk_size: usize,
     retry_count: usize,
 }
 
 impl TopNScanExecState {
-    pub fn new(heaprelid: pg_sys::Oid, limit: usize, sort_direction: SortDirection) -> Self {
+    /// `limit` is the number of rows the query wants back, including any it's going to skip
+    /// over for an `OFFSET`
+    pub fn new(heaprelid: pg_sys::Oid, limit: usize) -> Self {
         Self {
             heaprelid,
             limit,
-            sort_direction,
             ..Default::default()
         }
     }
+
+    /// The query's [`RescoreWindow`], if it has one and the docs are being returned best score
+    /// first.  In any other order, its window of top docs doesn't mean anything.
+    fn rescore(&self) -> Option<&RescoreWindow> {
+        match self.sort_keys.as_slice() {
+            [SortKey {
+                field: None,
+                direction: SortDirection::Desc,
+                ..
+            }] => self.rescore.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Take the first `window_size` visible docs off the first pass, add their weighted score for
+    /// the rescore query, and put them back in order of their new scores.
+    ///
+    /// Invisible docs are passed over here, so that they don't take up space in the window.
+    fn rescore_window(&mut self, heaprel: pg_sys::Relation, window_size: usize) {
+        let mut window = Vec::with_capacity(window_size);
+        unsafe {
+            let mut visibility_checker =
+                VisibilityChecker::with_rel_and_snap(heaprel, pg_sys::GetActiveSnapshot());
+            let slot = pg_sys::table_slot_create(heaprel, std::ptr::null_mut());
+
+            while window.len() < window_size {
+                let Some((scored, doc_address)) = self.search_results.next() else {
+                    break;
+                };
+                self.found += 1;
+
+                if visibility_checker
+                    .exec_if_visible(scored.ctid, slot, |_| ())
+                    .is_some()
+                {
+                    window.push((scored, doc_address));
+                }
+            }
+
+            pg_sys::ExecDropSingleTupleTableSlot(slot);
+        }
+
+        let rescore = self.rescore().unwrap();
+        let doc_addresses = window
+            .iter()
+            .map(|(_, doc_address)| *doc_address)
+            .collect::<Vec<_>>();
+        let rescores = self
+            .search_reader
+            .as_ref()
+            .unwrap()
+            .score_docs(rescore.query.as_ref(), &doc_addresses);
+
+        for ((scored, _), score) in window.iter_mut().zip(rescores) {
+            if let Some(score) = score {
+                scored.bm25 += rescore.weight * score;
+            }
+        }
+
+        // equal scores go in ctid order, the same as the first pass
+        window
+            .sort_by(|(a, _), (b, _)| b.bm25.total_cmp(&a.bm25).then_with(|| a.ctid.cmp(&b.ctid)));
+        self.rescored = window.into();
+    }
 }
 
 impl ExecMethod for TopNScanExecState {
     fn init(&mut self, state: &PdbScanState, _cstate: *mut pg_sys::CustomScanState) {
-        let sort_field = state.sort_field.clone();
+        let sort_keys = state.sort_keys.clone();
         let search_reader = state.search_reader.as_ref().unwrap();
         let query = state.query.as_ref().map(|q| q.box_clone());
 
         self.query = query;
-        self.sort_field = sort_field;
-        self.search_results = search_reader.search_top_n(
+        self.sort_keys = sort_keys;
+        self.rescore = state.rescore.clone();
+
+        // a rescore window can be bigger than the limit, and it's filled from the top of the
+        // first pass, so that much of it needs to be searched up front
+        let window_size = self.rescore().map_or(0, |rescore| rescore.window_size);
+        self.chunk_size = self.limit.max(window_size);
+
+        // the facets are counted over every matching doc, in the same pass as the first search
+        let facets = state.facet_counts.keys().cloned().collect::<Vec<_>>();
+        let facet_fields = facets
+            .iter()
+            .map(|facet_info| (facet_info.field.clone(), facet_info.facet()))
+            .collect::<Vec<_>>();
+        let (search_results, facet_matches) = search_reader.search_top_n_with_facets(
             SearchIndex::executor(),
             self.query.as_ref().unwrap(),
-            self.sort_field.clone(),
-            self.sort_direction.into(),
-            self.limit,
+            &self.sort_keys,
+            self.chunk_size,
+            &facet_fields,
         );
+        self.search_results = search_results;
 
         let len = self
             .search_results
             .len()
             .expect("search_results should not be empty");
 
-        self.have_less = len < self.limit;
+        self.have_less = len < self.chunk_size;
         self.search_reader = state.search_reader.clone();
+
+        // the scan's count of visible tuples carries on across rescans
+        self.visible_before = state.heap_tuple_check_count;
+        self.found = 0;
+        self.retry_count = 0;
+
+        self.rescored = VecDeque::new();
+        if window_size > 0 {
+            self.rescore_window(state.heaprel.unwrap(), window_size);
+        }
+
+        self.facet_counts = None;
+        if !facets.is_empty() {
+            let invisible = invisible_ctids_among(
+                state.heaprel(),
+                unsafe { pg_sys::GetActiveSnapshot() },
+                facet_matches.ctids(),
+            );
+            let counts = search_reader
+                .visible_facet_counts(facet_matches, &invisible)
+                .expect("facet counting should not fail");
+            self.facet_counts = Some(facets.into_iter().zip(counts).collect());
+        }
     }
 
-    fn internal_next(&mut self) -> ExecState {
-        unsafe {
-            let mut next = self.search_results.next();
-            loop {
-                match next {
-                    None => {
-                        if self.found == self.limit || self.have_less {
-                            // we found all the matching rows
-                            return ExecState::Eof;
-                        }
-                    }
-                    Some((scored, doc_address)) => {
-                        return ExecState::RequiresVisibilityCheck {
-                            ctid: scored.ctid,
-                            score: scored.bm25,
-                            doc_address,
-                        }
-                    }
-                }
+    fn query(&mut self, state: &PdbScanState) -> bool {
+        // we've returned everything we found.  if that was every matching doc, or enough of them
+        // were visible to fill the limit, we're done
+        let visible = state.heap_tuple_check_count - self.visible_before;
+        if self.have_less || visible >= self.limit {
+            return false;
+        }
 
-                // we underflowed our tuples, so go get some more, if there are any
-                self.retry_count += 1;
+        // we underflowed our tuples, so go get some more, if there are any
+        self.retry_count += 1;
 
-                // calculate a scaling factor to use against the limit
-                let factor = if self.chunk_size == 0 {
-                    // if we haven't done any chunking yet, calculate the scaling factor
-                    // based on the proportion of dead tuples compared to live tuples
-                    let heaprelid = self.heaprelid;
-                    let n_dead = direct_function_call::<i64>(
+        // calculate a scaling factor to use against the rows we're still missing
+        let factor = if self.retry_count == 1 {
+            // if we haven't done any chunking yet, calculate the scaling factor
+            // based on the proportion of dead tuples compared to live tuples
+            let heaprelid = self.heaprelid;
+            let (n_dead, n_live) = unsafe {
+                (
+                    direct_function_call::<i64>(
                         pg_sys::pg_stat_get_dead_tuples,
                         &[heaprelid.into_datum()],
                     )
-                    .unwrap();
-                    let n_live = direct_function_call::<i64>(
+                    .unwrap(),
+                    direct_function_call::<i64>(
                         pg_sys::pg_stat_get_live_tuples,
                         &[heaprelid.into_datum()],
                     )
-                    .unwrap();
+                    .unwrap(),
+                )
+            };
 
-                    (1.0 + ((1.0 + n_dead as f64) / (1.0 + n_live as f64))).ceil() as usize
-                } else {
-                    // we've already done chunking, so just use a default scaling factor
-                    // to avoid exponentially growing the chunk size
-                    SUBSEQUENT_RETRY_SCALE_FACTOR
-                };
+            (1.0 + ((1.0 + n_dead as f64) / (1.0 + n_live as f64))).ceil() as usize
+        } else {
+            // we've already done chunking, so just use a default scaling factor
+            // to avoid exponentially growing the chunk size
+            SUBSEQUENT_RETRY_SCALE_FACTOR
+        };
 
-                // set the chunk size to the scaling factor times the limit
-                self.chunk_size = (self.chunk_size * factor)
-                    .max(self.limit * factor)
-                    .min(MAX_CHUNK_SIZE);
-
-                let mut results = self.search_reader.as_ref().unwrap().search_top_n(
-                    SearchIndex::executor(),
-                    self.query.as_ref().unwrap(),
-                    self.sort_field.clone(),
-                    self.sort_direction.into(),
-                    self.chunk_size,
-                );
-
-                // fast forward and stop on the ctid we last found
-                for (scored, doc_address) in &mut results {
-                    if scored.ctid == self.last_ctid {
-                        // we've now advanced to the last ctid we found
-                        break;
-                    }
-                }
+        // grow the chunk past what we've already returned by the scaled number of missing rows,
+        // within reason
+        let missing = self.limit - visible;
+        self.chunk_size = self.found + (missing * factor).min(MAX_CHUNK_SIZE).max(missing);
 
-                // this should be the next valid tuple after that
-                next = match results.next() {
-                    // ... and there it is!
-                    Some(next) => Some(next),
+        let mut results = self.search_reader.as_ref().unwrap().search_top_n(
+            SearchIndex::executor(),
+            self.query.as_ref().unwrap(),
+            &self.sort_keys,
+            self.chunk_size,
+        );
+        let len = results.len().expect("search_results should not be empty");
+        self.have_less = len < self.chunk_size;
 
-                    // there wasn't one, so we've now read all possible matches
-                    None => {
-                        return ExecState::Eof;
-                    }
-                };
+        // the top N are always in the same order, so the ones we've already returned come first
+        results.by_ref().take(self.found).for_each(drop);
+        self.search_results = results;
+        true
+    }
 
-                // we now have a new iterator of results to use going forward
-                self.search_results = results;
+    fn internal_next(&mut self) -> ExecState {
+        // the rescored window was already counted as found when it was taken off the first pass
+        if let Some((scored, doc_address)) = self.rescored.pop_front() {
+            return ExecState::RequiresVisibilityCheck {
+                ctid: scored.ctid,
+                score: scored.bm25,
+                doc_address,
+            };
+        }
 
-                // but we'll loop back around and evaluate whatever `next` is now pointing to
-                continue;
+        match self.search_results.next() {
+            None => ExecState::Eof,
+            Some((scored, doc_address)) => {
+                self.found += 1;
+                ExecState::RequiresVisibilityCheck {
+                    ctid: scored.ctid,
+                    score: scored.bm25,
+                    doc_address,
+                }
             }
         }
     }
+
+    fn facet_counts(&mut self) -> Option<HashMap<FacetInfo, Vec<(Facet, u64)>>> {
+        self.facet_counts.take()
+    }
 }
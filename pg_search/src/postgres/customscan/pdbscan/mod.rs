@@ -16,9 +16,11 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 #![allow(clippy::unnecessary_cast)] // helps with integer casting differences between postgres versions
+mod aggregates;
 mod exec_methods;
 mod privdat;
 mod projections;
+mod pushdown;
 mod qual_inspect;
 mod scan_state;
 
@@ -26,6 +28,7 @@ use crate::api::operator::{
     anyelement_query_input_opoid, attname_from_var, estimate_selectivity, find_var_relation,
 };
 use crate::api::{AsCStr, AsInt, Cardinality};
+use crate::index::statistics::{GlobalStatistics, StatisticsCounts};
 use crate::index::SearchIndex;
 use crate::postgres::customscan::builders::custom_path::{CustomPathBuilder, Flags, OrderByStyle};
 use crate::postgres::customscan::builders::custom_scan::CustomScanBuilder;
@@ -33,7 +36,11 @@ use crate::postgres::customscan::builders::custom_state::{
     CustomScanStateBuilder, CustomScanStateWrapper,
 };
 use crate::postgres::customscan::explainer::Explainer;
+use crate::postgres::customscan::pdbscan::aggregates::{flatten_targets, plan_aggregates};
 use crate::postgres::customscan::pdbscan::privdat::PrivateData;
+use crate::postgres::customscan::pdbscan::projections::facets::{
+    facets_funcoid, facets_json, uses_facets, FacetInfo,
+};
 use crate::postgres::customscan::pdbscan::projections::score::{
     is_score_func, score_funcoid, uses_scores,
 };
@@ -43,23 +50,31 @@ use crate::postgres::customscan::pdbscan::projections::snippet::{
 use crate::postgres::customscan::pdbscan::projections::{
     inject_placeholders, maybe_needs_const_projections, pullout_funcexprs,
 };
-use crate::postgres::customscan::pdbscan::qual_inspect::extract_quals;
+use crate::postgres::customscan::pdbscan::qual_inspect::{extract_quals, Qual};
 use crate::postgres::customscan::pdbscan::scan_state::PdbScanState;
 use crate::postgres::customscan::{CustomScan, CustomScanState, ExecMethod, PlainExecCapable};
 use crate::postgres::index::open_search_index;
 use crate::postgres::rel_get_bm25_index;
-use crate::postgres::visibility_checker::VisibilityChecker;
+use crate::postgres::visibility_checker::{invisible_ctids_among, VisibilityChecker};
 use crate::query::SearchQueryInput;
-use crate::{nodecast, DEFAULT_STARTUP_COST, UNKNOWN_SELECTIVITY};
+use crate::schema::{SearchFieldType, SearchIndexSchema};
+use crate::{gucs, nodecast, DEFAULT_STARTUP_COST, UNKNOWN_SELECTIVITY};
+use exec_methods::aggregate::AggregateScanExecState;
 use exec_methods::normal::NormalScanExecState;
 use exec_methods::top_n::TopNScanExecState;
 use exec_methods::ExecState;
 use pgrx::itemptr::item_pointer_get_block_number;
+use pgrx::pg_sys::panic::ErrorReport;
 use pgrx::pg_sys::{AsPgCStr, CustomExecMethods};
-use pgrx::{direct_function_call, pg_sys, IntoDatum, PgList, PgMemoryContexts, PgRelation};
+use pgrx::{
+    direct_function_call, function_name, pg_guard, pg_sys, FromDatum, IntoDatum, PgList,
+    PgLogLevel, PgMemoryContexts, PgRelation, PgSqlErrorCode,
+};
 use std::collections::HashMap;
 use std::ffi::CStr;
 use std::ptr::addr_of_mut;
+use std::sync::Arc;
+use tantivy::schema::Facet;
 use tantivy::snippet::SnippetGenerator;
 
 #[derive(Default)]
@@ -101,6 +116,9 @@ impl CustomScan for PdbScan {
 
                 // and that relation must have a `USING bm25` index
                 let (table, bm25_index) = rel_get_bm25_index(rte.relid)?;
+                if !index_predicate_ok(builder.args().rel, &bm25_index) {
+                    return None;
+                }
 
                 (table, bm25_index, rte.rtekind == pg_sys::RTEKind::RTE_JOIN)
             };
@@ -108,15 +126,24 @@ impl CustomScan for PdbScan {
             let root = builder.args().root;
             let search_index =
                 open_search_index(&bm25_index).expect("should be able to open search index");
-            let pathkey = pullup_orderby_pathkey(&mut builder, rti, &search_index, root);
+            let pathkeys = pullup_orderby_pathkeys(&mut builder, rti, &search_index, root);
 
             #[cfg(any(feature = "pg13", feature = "pg14", feature = "pg15"))]
             let baserels = (*builder.args().root).all_baserels;
             #[cfg(any(feature = "pg16", feature = "pg17"))]
             let baserels = (*builder.args().root).all_query_rels;
 
+            // a partition's rows are all rows of the partitioned table (and likewise for the other
+            // children of an Append), so it can take the limit of the query on that table.  the
+            // Append above merges the partitions' top rows and applies the limit once
+            let is_append_child = !(*builder.args().rel).top_parent_relids.is_null();
+            let relids = if is_append_child {
+                (*builder.args().rel).top_parent_relids
+            } else {
+                (*builder.args().rel).relids
+            };
             let limit = if (*builder.args().root).limit_tuples > -1.0
-                && pg_sys::bms_equal((*builder.args().rel).relids, baserels)
+                && pg_sys::bms_equal(relids, baserels)
             {
                 // we can only use the limit for estimates if a) we have one, and b) we know
                 // the query is only querying one relation
@@ -129,7 +156,7 @@ impl CustomScan for PdbScan {
             let target_list = (*(*builder.args().root).parse).targetList;
             let maybe_needs_const_projections = maybe_needs_const_projections(target_list.cast());
             let ff_cnt = 0;
-            let is_topn = limit.is_some() && pathkey.is_some();
+            let is_topn = limit.is_some() && !pathkeys.is_empty();
 
             //
             // look for quals we can support
@@ -139,8 +166,26 @@ impl CustomScan for PdbScan {
                 rti,
                 restrict_info.as_ptr().cast(),
                 anyelement_query_input_opoid(),
-            ) {
-                let selectivity = if let Some(limit) = limit {
+                &table.tuple_desc(),
+                &search_index.schema,
+            )
+            .filter(Qual::uses_search_operator)
+            {
+                let pruned = search_refutes_partition(
+                    &table,
+                    &SearchQueryInput::from(&quals),
+                    &search_index.schema,
+                );
+                let quals = if pruned {
+                    // nothing in this partition can match, so don't even look
+                    Qual::PushedDownExpression(SearchQueryInput::Empty)
+                } else {
+                    quals
+                };
+
+                let selectivity = if pruned {
+                    0.0
+                } else if let Some(limit) = limit {
                     // use the limit
                     limit
                         / table
@@ -162,18 +207,36 @@ impl CustomScan for PdbScan {
                 builder.custom_private().set_quals(quals);
                 builder.custom_private().set_limit(limit);
 
+                if gucs::enable_global_statistics() {
+                    let indexrelids = append_sibling_bm25_indexes(root, rti);
+                    if indexrelids.len() > 1 {
+                        builder
+                            .custom_private()
+                            .set_statistics_indexrelids(&indexrelids);
+                    }
+                }
+
                 // we must use this path if we need to do const projections for scores or snippets
                 builder = builder.set_force_path(maybe_needs_const_projections || is_topn);
 
                 if is_topn {
-                    // sorting by a field only works if we're not doing const projections
+                    // sorting only by fields only works if we're not doing const projections, as
+                    // the scores aren't computed then
                     //
-                    // and sorting by score always works
-                    if !(maybe_needs_const_projections
-                        && matches!(&pathkey, Some(OrderByStyle::Field(..))))
-                    {
-                        builder = builder.add_path_key(&pathkey);
-                        builder.custom_private().set_sort_info(&pathkey);
+                    // and sorting by score, even alongside fields, always works
+                    let sorts_by_score = pathkeys
+                        .iter()
+                        .any(|style| matches!(style, OrderByStyle::Score(..)));
+                    if !maybe_needs_const_projections || sorts_by_score {
+                        builder = builder.add_path_keys(&pathkeys);
+                        builder.custom_private().set_sort_info(&pathkeys);
+                        // a partition's rows are merged with those of the others before the
+                        // OFFSET is applied, so it can't tell which of its rows will be skipped
+                        if !is_append_child {
+                            builder
+                                .custom_private()
+                                .set_offset(const_limit_offset((*root).parse));
+                        }
                     }
                 }
 
@@ -211,8 +274,143 @@ impl CustomScan for PdbScan {
         None
     }
 
+    fn upper_callback(
+        mut builder: CustomPathBuilder<Self::PrivateData>,
+        stage: pg_sys::UpperRelationKind::Type,
+    ) -> Option<pg_sys::CustomPath> {
+        unsafe {
+            if stage != pg_sys::UpperRelationKind::UPPERREL_GROUP_AGG
+                || !gucs::enable_aggregate_custom_scan()
+            {
+                return None;
+            }
+
+            let rti = builder.args().rti;
+            let (table, bm25_index) = {
+                let rte = builder.args().rte();
+
+                // only a plain relation, without inheritance children or row-level security
+                if rte.rtekind != pg_sys::RTEKind::RTE_RELATION
+                    || rte.inh
+                    || !rte.securityQuals.is_null()
+                {
+                    return None;
+                }
+                let relkind = pg_sys::get_rel_relkind(rte.relid) as u8;
+                if relkind != pg_sys::RELKIND_RELATION && relkind != pg_sys::RELKIND_MATVIEW {
+                    return None;
+                }
+
+                // and that relation must have a `USING bm25` index
+                let (table, bm25_index) = rel_get_bm25_index(rte.relid)?;
+                if !index_predicate_ok(builder.args().rel, &bm25_index) {
+                    return None;
+                }
+                (table, bm25_index)
+            };
+
+            // every qual must be something the index can answer, otherwise we'd be aggregating
+            // over rows Postgres would have filtered out
+            let restrict_info = builder.restrict_info();
+            if restrict_info.is_empty() || !builder.args().rel().joininfo.is_null() {
+                return None;
+            }
+            let search_index =
+                open_search_index(&bm25_index).expect("should be able to open search index");
+            let quals = extract_quals(
+                rti,
+                restrict_info.as_ptr().cast(),
+                anyelement_query_input_opoid(),
+                &table.tuple_desc(),
+                &search_index.schema,
+            )
+            .filter(Qual::uses_search_operator)?;
+            if SearchQueryInput::from(&quals).contains_more_like_this() {
+                return None;
+            }
+
+            let root = builder.args().root;
+            let aggregates = plan_aggregates(
+                root,
+                rti,
+                &table,
+                &search_index.schema,
+                (*builder.path_target()).exprs,
+            )?;
+
+            let rows = if aggregates.group_by.is_empty() {
+                1.0
+            } else {
+                let parse = (*root).parse;
+                let group_exprs =
+                    pg_sys::get_sortgrouplist_exprs((*parse).groupClause, (*parse).targetList);
+                let input_rows = builder.args().rel().rows;
+
+                #[cfg(feature = "pg13")]
+                let groups = pg_sys::estimate_num_groups(
+                    root,
+                    group_exprs,
+                    input_rows,
+                    std::ptr::null_mut(),
+                );
+                #[cfg(not(feature = "pg13"))]
+                let groups = pg_sys::estimate_num_groups(
+                    root,
+                    group_exprs,
+                    input_rows,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                );
+
+                groups.max(1.0)
+            };
+
+            builder.custom_private().set_heaprelid(table.oid());
+            builder.custom_private().set_indexrelid(bm25_index.oid());
+            builder.custom_private().set_range_table_index(rti);
+            builder.custom_private().set_quals(quals);
+            builder.custom_private().set_aggregates(&aggregates);
+
+            // the matching rows never leave the index, so this is far cheaper than any plan that
+            // scans them and aggregates them in Postgres
+            let startup_cost = DEFAULT_STARTUP_COST;
+            let total_cost = startup_cost + rows * pg_sys::cpu_tuple_cost;
+
+            builder = builder.set_rows(rows);
+            builder = builder.set_startup_cost(startup_cost);
+            builder = builder.set_total_cost(total_cost);
+            builder = builder.set_flag(Flags::Projection);
+
+            Some(builder.build())
+        }
+    }
+
     fn plan_custom_path(mut builder: CustomScanBuilder<Self::PrivateData>) -> pg_sys::CustomScan {
         unsafe {
+            if builder.custom_private().aggregates().is_some() {
+                // we produce the grouping columns and aggregate values, in the same order they
+                // were planned in, and Postgres evaluates the rest of the targetlist on top of them
+                let tlist = PgList::<pg_sys::TargetEntry>::from_pg(builder.args().tlist.as_ptr());
+                let mut scan_tlist = PgList::<pg_sys::TargetEntry>::new();
+                for (i, node) in flatten_targets(tlist.iter_ptr().map(|te| (*te).expr.cast()))
+                    .into_iter()
+                    .enumerate()
+                {
+                    scan_tlist.push(pg_sys::makeTargetEntry(
+                        pg_sys::copyObjectImpl(node.cast()).cast(),
+                        (i + 1) as _,
+                        std::ptr::null_mut(),
+                        false,
+                    ));
+                }
+
+                builder.set_custom_scan_tlist(scan_tlist.into_pg());
+                builder
+                    .custom_private_mut()
+                    .set_var_attname_lookup(PgList::<pg_sys::Node>::new().into_pg());
+                return builder.build();
+            }
+
             let private_data = builder.custom_private();
 
             let mut tlist = PgList::<pg_sys::TargetEntry>::from_pg(builder.args().tlist.as_ptr());
@@ -228,9 +426,13 @@ impl CustomScan for PdbScan {
             let mut attname_lookup = PgList::<pg_sys::Node>::new();
             let score_funcoid = score_funcoid();
             let snippet_funcoid = snippet_funcoid();
+            let facets_funcoid = facets_funcoid();
             for te in processed_tlist.iter_ptr() {
-                let func_vars_at_level =
-                    pullout_funcexprs(te.cast(), &[score_funcoid, snippet_funcoid], rti);
+                let func_vars_at_level = pullout_funcexprs(
+                    te.cast(),
+                    &[score_funcoid, snippet_funcoid, facets_funcoid],
+                    rti,
+                );
 
                 for (funcexpr, var) in func_vars_at_level {
                     // if we have a tlist, then we need to add the specific function that uses
@@ -261,6 +463,20 @@ impl CustomScan for PdbScan {
             builder
                 .custom_private_mut()
                 .set_var_attname_lookup(attname_lookup.into_pg());
+
+            let indexrelids = builder.custom_private().statistics_indexrelids();
+            if !indexrelids.is_empty() {
+                let quals = builder
+                    .custom_private()
+                    .quals()
+                    .expect("should have a Qual structure");
+                let counts = planned_statistics(
+                    builder.args().root,
+                    &indexrelids,
+                    &SearchQueryInput::from(&quals),
+                );
+                builder.custom_private_mut().set_global_statistics(&counts);
+            }
             builder.build()
         }
     }
@@ -287,8 +503,13 @@ impl CustomScan for PdbScan {
 
             // information about if we're sorted by score and our limit
             builder.custom_state().limit = builder.custom_private().limit();
-            builder.custom_state().sort_field = builder.custom_private().sort_field();
+            builder.custom_state().offset = builder.custom_private().offset();
+            builder.custom_state().sort_keys = builder.custom_private().sort_keys();
             builder.custom_state().sort_direction = builder.custom_private().sort_direction();
+            builder.custom_state().aggregates = builder.custom_private().aggregates();
+            builder.custom_state().statistics_indexrelids =
+                builder.custom_private().statistics_indexrelids();
+            builder.custom_state().global_statistics = builder.custom_private().global_statistics();
 
             // store our query quals into our custom state too
             let quals = builder
@@ -342,9 +563,11 @@ impl CustomScan for PdbScan {
 
             let score_funcoid = score_funcoid();
             let snippet_funcoid = snippet_funcoid();
+            let facets_funcoid = facets_funcoid();
 
             builder.custom_state().score_funcoid = score_funcoid;
             builder.custom_state().snippet_funcoid = snippet_funcoid;
+            builder.custom_state().facets_funcoid = facets_funcoid;
 
             builder.custom_state().need_scores = uses_scores(
                 builder.target_list().as_ptr().cast(),
@@ -360,15 +583,23 @@ impl CustomScan for PdbScan {
                     .into_iter()
                     .map(|field| (field, None))
                     .collect();
+            builder.custom_state().facet_counts = uses_facets(rti, node, facets_funcoid)
+                .into_iter()
+                .map(|facet_info| (facet_info, None))
+                .collect();
 
             let need_snippets = builder.custom_state().need_snippets();
-            if let Some((limit, sort_direction)) = builder.custom_state().is_top_n_capable() {
+            if let Some(aggregates) = builder.custom_state().aggregates.clone() {
+                builder
+                    .custom_state()
+                    .assign_exec_method(AggregateScanExecState::new(aggregates));
+            } else if let Some((limit, _)) = builder.custom_state().is_top_n_capable() {
                 // having a valid limit and sort direction means we can do a TopN query
                 // and TopN can do snippets
                 let heaprelid = builder.custom_state().heaprelid;
                 builder
                     .custom_state()
-                    .assign_exec_method(TopNScanExecState::new(heaprelid, limit, sort_direction));
+                    .assign_exec_method(TopNScanExecState::new(heaprelid, limit));
             } else {
                 builder
                     .custom_state()
@@ -417,17 +648,47 @@ impl CustomScan for PdbScan {
                 .unwrap(),
         );
         explainer.add_bool("Scores", state.custom_state().need_scores());
+        if state.custom_state().need_scores()
+            && !state.custom_state().statistics_indexrelids.is_empty()
+        {
+            explainer.add_unsigned_integer(
+                "   Global Statistics Indexes",
+                state.custom_state().statistics_indexrelids.len() as u64,
+                None,
+            );
+        }
+        if let Some(aggregates) = &state.custom_state().aggregates {
+            explainer.add_text("   Aggregates", aggregates.describe_aggregates());
+            if !aggregates.group_by.is_empty() {
+                explainer.add_text("   Group By", aggregates.describe_group_by());
+            }
+        }
         if let (Some(sort_direction), Some(limit)) = (
             state.custom_state().sort_direction,
             state.custom_state().limit,
         ) {
-            if let Some(sort_field) = &state.custom_state().sort_field {
-                explainer.add_text("   Sort Field", sort_field);
+            if let [sort_key] = state.custom_state().sort_keys.as_slice() {
+                explainer.add_text(
+                    "   Sort Field",
+                    sort_key.field.as_deref().unwrap_or("paradedb.score()"),
+                );
+                explainer.add_text("   Sort Direction", sort_direction);
             } else {
-                explainer.add_text("   Sort Field", "paradedb.score()");
+                explainer.add_text(
+                    "   Sort Keys",
+                    state
+                        .custom_state()
+                        .sort_keys
+                        .iter()
+                        .map(|sort_key| sort_key.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                );
             }
-            explainer.add_text("   Sort Direction", sort_direction);
             explainer.add_unsigned_integer("   Top N Limit", limit as u64, None);
+            if let Some(offset) = state.custom_state().offset.filter(|offset| *offset > 0) {
+                explainer.add_unsigned_integer("   Top N Offset", offset as u64, None);
+            }
             if explainer.is_analyze() && state.custom_state().retry_count > 0 {
                 explainer.add_unsigned_integer(
                     "   Invisible Tuple Retries",
@@ -479,19 +740,23 @@ impl CustomScan for PdbScan {
                 VisibilityChecker::with_rel_and_snap(heaprel, pg_sys::GetActiveSnapshot()),
             );
 
-            // and finally, get the custom scan itself properly initialized
-            let tupdesc = state.custom_state().heaptupdesc();
-            pg_sys::ExecInitScanTupleSlot(
-                estate,
-                addr_of_mut!(state.csstate.ss),
-                tupdesc,
-                pg_sys::table_slot_callbacks(state.custom_state().heaprel()),
-            );
-            pg_sys::ExecInitResultTypeTL(addr_of_mut!(state.csstate.ss.ps));
-            pg_sys::ExecAssignProjectionInfo(
-                state.planstate(),
-                (*state.csstate.ss.ss_ScanTupleSlot).tts_tupleDescriptor,
-            );
+            // and finally, get the custom scan itself properly initialized.  When we're computing
+            // aggregates, Postgres has already set up our scan slot and projection from the
+            // plan's `custom_scan_tlist`, as our tuples aren't heap tuples
+            if state.custom_state().aggregates.is_none() {
+                let tupdesc = state.custom_state().heaptupdesc();
+                pg_sys::ExecInitScanTupleSlot(
+                    estate,
+                    addr_of_mut!(state.csstate.ss),
+                    tupdesc,
+                    pg_sys::table_slot_callbacks(state.custom_state().heaprel()),
+                );
+                pg_sys::ExecInitResultTypeTL(addr_of_mut!(state.csstate.ss.ps));
+                pg_sys::ExecAssignProjectionInfo(
+                    state.planstate(),
+                    (*state.csstate.ss.ss_ScanTupleSlot).tts_tupleDescriptor,
+                );
+            }
         }
 
         if eflags & (pg_sys::EXEC_FLAG_EXPLAIN_ONLY as i32) != 0 {
@@ -515,20 +780,52 @@ impl CustomScan for PdbScan {
 
         let search_index =
             open_search_index(&indexrel).expect("should be able to open search index");
-        let search_reader = search_index
+        for facet_info in state.custom_state().facet_counts.keys() {
+            let search_field = search_index
+                .schema
+                .get_search_field(&facet_info.field.as_str().into());
+            if !search_field
+                .is_some_and(|search_field| search_field.type_ == SearchFieldType::Facet)
+            {
+                ErrorReport::new(
+                    PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+                    format!("'{}' is not a facet field of the index", facet_info.field),
+                    function_name!(),
+                )
+                .report(PgLogLevel::ERROR);
+            }
+        }
+        let mut search_reader = search_index
             .get_reader()
             .expect("search index reader should have been constructed correctly");
+        if let (true, Some(counts)) = (
+            state.custom_state().need_scores(),
+            &state.custom_state().global_statistics,
+        ) {
+            // score with the statistics of every index under the same Append as this one, which
+            // were gathered when the query was planned
+            let global_statistics = GlobalStatistics::new(counts.clone(), &search_reader.searcher);
+            search_reader = search_reader.with_global_statistics(Arc::new(global_statistics));
+        }
         let query = search_index.query(
             &indexrel,
             &state.custom_state().search_query_input,
             &search_reader,
         );
+        let rescore = search_index.rescore_window(
+            &indexrel,
+            &state.custom_state().search_query_input,
+            &search_reader,
+        );
 
         state.custom_state_mut().search_reader = Some(search_reader);
         state.custom_state_mut().query = Some(query);
+        state.custom_state_mut().rescore = rescore;
 
         let csstate = addr_of_mut!(state.csstate);
         state.custom_state_mut().init_exec_method(csstate);
+        state.custom_state_mut().offset_rows_remaining =
+            state.custom_state().offset.unwrap_or_default();
 
         if need_snippets {
             let mut snippet_generators: HashMap<SnippetInfo, Option<SnippetGenerator>> = state
@@ -551,8 +848,21 @@ impl CustomScan for PdbScan {
             state.custom_state_mut().snippet_generators = snippet_generators;
         }
 
+        if state.custom_state().need_facets() {
+            let facet_counts = match state.custom_state_mut().exec_method().facet_counts() {
+                Some(facet_counts) => facet_counts,
+
+                // only a TopN search collects them alongside its own, so count them on their own
+                None => count_facets(state.custom_state()),
+            };
+            state.custom_state_mut().facet_counts = facet_counts
+                .into_iter()
+                .map(|(facet_info, counts)| (facet_info, Some(counts)))
+                .collect();
+        }
+
         unsafe {
-            inject_score_and_snippet_placeholders(state);
+            inject_projection_placeholders(state);
         }
     }
 
@@ -589,9 +899,10 @@ impl CustomScan for PdbScan {
 
                         if !state.custom_state().need_scores()
                             && !state.custom_state().need_snippets()
+                            && !state.custom_state().need_facets()
                         {
                             //
-                            // we don't need scores or snippets
+                            // we don't need scores, snippets, or facets
                             // do the projection and return
                             //
 
@@ -599,10 +910,11 @@ impl CustomScan for PdbScan {
                             return pg_sys::ExecProject(state.projection_info());
                         } else {
                             //
-                            // we do need scores or snippets
+                            // we do need scores, snippets, or facets
                             //
                             // replace their placeholder values and then rebuild the ProjectionInfo
-                            // and project it
+                            // and project it.  the facets' values are the same for every row,
+                            // and were already put in place by `rescan_custom_scan()`
                             //
 
                             let mut per_tuple_context = PgMemoryContexts::For(
@@ -619,7 +931,21 @@ impl CustomScan for PdbScan {
                                 (*const_score_node).constisnull = false;
                             }
 
-                            if state.custom_state().need_snippets() {
+                            // the rows inside the OFFSET are about to be thrown away by the Limit
+                            // node above us, so don't bother making their snippets
+                            let within_offset = state.custom_state().offset_rows_remaining > 0;
+                            if within_offset {
+                                state.custom_state_mut().offset_rows_remaining -= 1;
+                            }
+
+                            if state.custom_state().need_snippets() && within_offset {
+                                for const_snippet_node in
+                                    state.custom_state().const_snippet_nodes.values()
+                                {
+                                    (**const_snippet_node).constvalue = pg_sys::Datum::null();
+                                    (**const_snippet_node).constisnull = true;
+                                }
+                            } else if state.custom_state().need_snippets() {
                                 per_tuple_context.switch_to(|_| {
                                     for (snippet_info, const_snippet_node) in
                                         &state.custom_state().const_snippet_nodes
@@ -707,89 +1033,326 @@ fn check_visibility(
         .exec_if_visible(ctid, bslot.cast(), move |heaprel| bslot.cast())
 }
 
-unsafe fn inject_score_and_snippet_placeholders(state: &mut CustomScanStateWrapper<PdbScan>) {
-    if !state.custom_state().need_scores() && !state.custom_state().need_snippets() {
-        // scores/snippets aren't necessary so we use whatever we originally setup as our ProjectionInfo
+unsafe fn inject_projection_placeholders(state: &mut CustomScanStateWrapper<PdbScan>) {
+    if !state.custom_state().need_scores()
+        && !state.custom_state().need_snippets()
+        && !state.custom_state().need_facets()
+    {
+        // scores/snippets/facets aren't necessary so we use whatever we originally setup as our ProjectionInfo
         return;
     }
 
-    // inject score and/or snippet placeholder [`pg_sys::Const`] nodes into what is a copy of the Plan's
-    // targetlist.  We store this in our custom state's "placeholder_targetlist" for use during the
-    // forced projection we must do later.
+    // inject score, snippet, and/or facets placeholder [`pg_sys::Const`] nodes into what is a copy
+    // of the Plan's targetlist.  We store this in our custom state's "placeholder_targetlist" for
+    // use during the forced projection we must do later.
 
     let planstate = state.planstate();
-    let (targetlist, const_score_node, const_snippet_nodes) = inject_placeholders(
-        (*(*planstate).plan).targetlist,
-        state.custom_state().rti,
-        state.custom_state().score_funcoid,
-        state.custom_state().snippet_funcoid,
-        &state.custom_state().var_attname_lookup,
-        &state.custom_state().snippet_generators,
-    );
+    let (targetlist, const_score_node, const_snippet_nodes, const_facet_nodes) =
+        inject_placeholders(
+            (*(*planstate).plan).targetlist,
+            state.custom_state().rti,
+            state.custom_state().score_funcoid,
+            state.custom_state().snippet_funcoid,
+            &state.custom_state().var_attname_lookup,
+            &state.custom_state().snippet_generators,
+            state.custom_state().facets_funcoid,
+        );
+
+    // the facets' counts are already known, and are the same for every row, so they're only set
+    // the once, where they'll last until the end of the query
+    PgMemoryContexts::For((*(*planstate).state).es_query_cxt).switch_to(|_| {
+        for (facet_info, const_facet_node) in &const_facet_nodes {
+            let counts = state
+                .custom_state()
+                .facet_counts
+                .get(facet_info)
+                .and_then(|counts| counts.as_deref())
+                .unwrap_or_default();
+            (**const_facet_node).constvalue = facets_json(counts).into_datum().unwrap();
+            (**const_facet_node).constisnull = false;
+        }
+    });
 
     state.custom_state_mut().placeholder_targetlist = Some(targetlist);
     state.custom_state_mut().const_score_node = Some(const_score_node);
     state.custom_state_mut().const_snippet_nodes = const_snippet_nodes;
+    state.custom_state_mut().const_facet_nodes = const_facet_nodes;
+}
+
+/// Count the facets `paradedb.facets()` asks for over every doc matching the scan's query that's
+/// visible, with a search of their own.
+fn count_facets(state: &PdbScanState) -> HashMap<FacetInfo, Vec<(Facet, u64)>> {
+    let search_reader = state.search_reader.as_ref().unwrap();
+    let facets = state.facet_counts.keys().cloned().collect::<Vec<_>>();
+    let facet_fields = facets
+        .iter()
+        .map(|facet_info| (facet_info.field.clone(), facet_info.facet()))
+        .collect::<Vec<_>>();
+
+    let facet_matches = search_reader
+        .facet_matches(
+            SearchIndex::executor(),
+            state.query.as_ref().unwrap(),
+            &facet_fields,
+        )
+        .expect("facet counting should not fail");
+    let invisible = invisible_ctids_among(
+        state.heaprel(),
+        unsafe { pg_sys::GetActiveSnapshot() },
+        facet_matches.ctids(),
+    );
+    let counts = search_reader
+        .visible_facet_counts(facet_matches, &invisible)
+        .expect("facet counting should not fail");
+    facets.into_iter().zip(counts).collect()
 }
 
-unsafe fn pullup_orderby_pathkey<P: Into<*mut pg_sys::List> + Default>(
+/// Find the leading `ORDER BY` keys that the index can produce itself, each of which is either
+/// the score or a fast field.  Ordering stops at the first key it can't, as the rest are only
+/// tie-breakers for that one.
+unsafe fn pullup_orderby_pathkeys<P: Into<*mut pg_sys::List> + Default>(
     builder: &mut CustomPathBuilder<P>,
     rti: pg_sys::Index,
     search_index: &SearchIndex,
     root: *mut pg_sys::PlannerInfo,
-) -> Option<OrderByStyle> {
+) -> Vec<OrderByStyle> {
     let pathkeys = PgList::<pg_sys::PathKey>::from_pg((*builder.args().root).query_pathkeys);
 
-    if let Some(first_pathkey) = pathkeys.get_ptr(0) {
-        let equivclass = (*first_pathkey).pk_eclass;
-        let members = PgList::<pg_sys::EquivalenceMember>::from_pg((*equivclass).ec_members);
+    let mut styles = vec![];
+    for pathkey in pathkeys.iter_ptr() {
+        match pathkey_to_orderby_style(pathkey, rti, search_index, root) {
+            Some(style) => styles.push(style),
+            None => break,
+        }
+    }
 
-        for member in members.iter_ptr() {
-            let expr = (*member).em_expr;
+    // every key has to be one we can handle, or the ties of the last one we could would be in
+    // some arbitrary order, and then it's not safe to cut the results off at the LIMIT
+    if styles.len() == pathkeys.len() {
+        styles
+    } else {
+        vec![]
+    }
+}
 
-            if is_score_func(expr.cast(), rti as _) {
-                return Some(OrderByStyle::Score(first_pathkey));
-            } else if let Some(var) = is_lower_func(expr.cast(), rti as _) {
-                let (heaprelid, attno, _) = find_var_relation(var, root);
-                let heaprel = PgRelation::with_lock(heaprelid, pg_sys::AccessShareLock as _);
-                let tupdesc = heaprel.tuple_desc();
-                if let Some(att) = tupdesc.get(attno as usize - 1) {
-                    if search_index.schema.is_field_lower_sortable(att.name()) {
-                        return Some(OrderByStyle::Field(first_pathkey, att.name().to_string()));
-                    }
-                }
-            } else if let Some(relabel) = nodecast!(RelabelType, T_RelabelType, expr) {
-                if let Some(var) = nodecast!(Var, T_Var, (*relabel).arg) {
-                    let (heaprelid, attno, _) = find_var_relation(var, root);
-                    let heaprel = PgRelation::with_lock(heaprelid, pg_sys::AccessShareLock as _);
-                    let tupdesc = heaprel.tuple_desc();
-                    if let Some(att) = tupdesc.get(attno as usize - 1) {
-                        if search_index.schema.is_field_raw_sortable(att.name()) {
-                            return Some(OrderByStyle::Field(
-                                first_pathkey,
-                                att.name().to_string(),
-                            ));
-                        }
-                    }
+/// The value of the query's `OFFSET`, if it has one that's a constant
+unsafe fn const_limit_offset(parse: *mut pg_sys::Query) -> Option<usize> {
+    let offset = nodecast!(Const, T_Const, (*parse).limitOffset)?;
+    if (*offset).constisnull {
+        return None;
+    }
+    i64::from_datum((*offset).constvalue, false).map(|offset| offset.max(0) as usize)
+}
+
+/// The bm25 indexes of every table under the same Append as the relation `rti`, such as all the
+/// (unpruned) partitions of a partitioned table, including its own.  It's empty if `rti` isn't
+/// under an Append.
+unsafe fn append_sibling_bm25_indexes(
+    root: *mut pg_sys::PlannerInfo,
+    rti: pg_sys::Index,
+) -> Vec<pg_sys::Oid> {
+    let append_rel_infos = PgList::<pg_sys::AppendRelInfo>::from_pg((*root).append_rel_list);
+    let parent_of = |relid: pg_sys::Index| {
+        append_rel_infos
+            .iter_ptr()
+            .find(|info| (**info).child_relid == relid)
+            .map(|info| (*info).parent_relid)
+    };
+
+    // start from the topmost parent, so that the partitions of sub-partitions are included too
+    let Some(mut top) = parent_of(rti) else {
+        return vec![];
+    };
+    while let Some(parent) = parent_of(top) {
+        top = parent;
+    }
+
+    let mut indexrelids = vec![];
+    let mut pending = vec![top];
+    while let Some(relid) = pending.pop() {
+        let children = append_rel_infos
+            .iter_ptr()
+            .filter(|info| (**info).parent_relid == relid)
+            .map(|info| (*info).child_relid)
+            .collect::<Vec<_>>();
+        if !children.is_empty() {
+            pending.extend(children);
+            continue;
+        }
+
+        let rte = *(*root).simple_rte_array.add(relid as usize);
+        if rte.is_null() || (*rte).rtekind != pg_sys::RTEKind::RTE_RELATION {
+            continue;
+        }
+        if let Some((_, bm25_index)) = rel_get_bm25_index((*rte).relid) {
+            indexrelids.push(bm25_index.oid());
+        }
+    }
+
+    indexrelids.sort();
+    indexrelids.dedup();
+    indexrelids
+}
+
+/// The statistics gathered for each Append of the query being planned, by its indexes and query
+#[allow(clippy::type_complexity)]
+static mut PLANNED_STATISTICS: Option<(
+    *mut pg_sys::PlannerGlobal,
+    HashMap<(Vec<pg_sys::Oid>, String), StatisticsCounts>,
+)> = None;
+
+/// The statistics of the indexes `indexrelids` under an Append, for `search_query_input`.  They're
+/// gathered for the first of its partitions to be planned, and the rest share them, so that each
+/// index is opened once per Append rather than once per partition.
+unsafe fn planned_statistics(
+    root: *mut pg_sys::PlannerInfo,
+    indexrelids: &[pg_sys::Oid],
+    search_query_input: &SearchQueryInput,
+) -> StatisticsCounts {
+    #[pg_guard]
+    unsafe extern "C" fn forget_planned_statistics(_: *mut std::ffi::c_void) {
+        PLANNED_STATISTICS = None;
+    }
+
+    #[allow(static_mut_refs)]
+    let planned = PLANNED_STATISTICS
+        .as_ref()
+        .is_some_and(|(glob, _)| *glob == (*root).glob);
+    if !planned {
+        // forget them along with the rest of the planner's memory, so that they're never taken for
+        // those of a later query
+        let callback = pg_sys::palloc0(std::mem::size_of::<pg_sys::MemoryContextCallback>())
+            .cast::<pg_sys::MemoryContextCallback>();
+        (*callback).func = Some(forget_planned_statistics);
+        pg_sys::MemoryContextRegisterResetCallback(pg_sys::CurrentMemoryContext, callback);
+        PLANNED_STATISTICS = Some(((*root).glob, HashMap::new()));
+    }
+
+    #[allow(static_mut_refs)]
+    let (_, statistics) = PLANNED_STATISTICS.as_mut().unwrap();
+    let key = (
+        indexrelids.to_vec(),
+        serde_json::to_string(search_query_input).expect("query should serialize"),
+    );
+    statistics
+        .entry(key)
+        .or_insert_with(|| {
+            StatisticsCounts::gather(indexrelids, search_query_input)
+                .expect("should be able to gather the statistics of every index")
+        })
+        .clone()
+}
+
+/// Can the `USING bm25` index `indexrel` answer a scan of `rel`?  A partial index only has the
+/// rows that satisfy its predicate, so it can only be used when the query's quals imply it, which
+/// Postgres has already worked out for the indexes of `rel`.
+unsafe fn index_predicate_ok(rel: *mut pg_sys::RelOptInfo, indexrel: &PgRelation) -> bool {
+    let indexlist = PgList::<pg_sys::IndexOptInfo>::from_pg((*rel).indexlist);
+    match indexlist
+        .iter_ptr()
+        .find(|index| (**index).indexoid == indexrel.oid())
+    {
+        Some(index) => (*index).indpred.is_null() || (*index).predOK,
+        None => pg_sys::RelationGetIndexPredicate(indexrel.as_ptr()).is_null(),
+    }
+}
+
+/// Do the terms `query` requires on the partition key rule out every row of the partition
+/// `table`?  Postgres' own partition pruning doesn't look inside the search query, so it can't
+/// tell on its own.
+unsafe fn search_refutes_partition(
+    table: &PgRelation,
+    query: &SearchQueryInput,
+    schema: &SearchIndexSchema,
+) -> bool {
+    if !(*table.rd_rel).relispartition {
+        return false;
+    }
+    let partition_qual = pg_sys::get_partition_qual_relid(table.oid());
+    if partition_qual.is_null() {
+        return false;
+    }
+
+    // the partition's constraint refers to the table as relation 1, so the clauses do too
+    let mut clauses = PgList::<pg_sys::Expr>::new();
+    for clause in pushdown::implied_equalities(query, 1, &table.tuple_desc(), schema) {
+        clauses.push(clause);
+    }
+    if clauses.is_empty() {
+        return false;
+    }
+    pg_sys::predicate_refuted_by(
+        pg_sys::make_ands_implicit(partition_qual),
+        clauses.into_pg(),
+        false,
+    )
+}
+
+unsafe fn pathkey_to_orderby_style(
+    pathkey: *mut pg_sys::PathKey,
+    rti: pg_sys::Index,
+    search_index: &SearchIndex,
+    root: *mut pg_sys::PlannerInfo,
+) -> Option<OrderByStyle> {
+    let equivclass = (*pathkey).pk_eclass;
+    let members = PgList::<pg_sys::EquivalenceMember>::from_pg((*equivclass).ec_members);
+
+    for member in members.iter_ptr() {
+        let expr = (*member).em_expr;
+
+        if is_score_func(expr.cast(), rti as _) {
+            return Some(OrderByStyle::Score(pathkey));
+        } else if let Some(var) = is_lower_func(expr.cast(), rti as _) {
+            let (heaprelid, attno, _) = find_var_relation(var, root);
+            let heaprel = PgRelation::with_lock(heaprelid, pg_sys::AccessShareLock as _);
+            let tupdesc = heaprel.tuple_desc();
+            if let Some(att) = tupdesc.get(attno as usize - 1) {
+                if search_index.schema.is_field_lower_sortable(att.name()) {
+                    return Some(OrderByStyle::Field(pathkey, att.name().to_string()));
                 }
-            } else if let Some(var) = nodecast!(Var, T_Var, expr) {
+            }
+        } else if let Some(relabel) = nodecast!(RelabelType, T_RelabelType, expr) {
+            if let Some(var) = nodecast!(Var, T_Var, (*relabel).arg) {
                 let (heaprelid, attno, _) = find_var_relation(var, root);
-                if heaprelid == pg_sys::Oid::INVALID {
-                    return None;
-                }
                 let heaprel = PgRelation::with_lock(heaprelid, pg_sys::AccessShareLock as _);
                 let tupdesc = heaprel.tuple_desc();
                 if let Some(att) = tupdesc.get(attno as usize - 1) {
-                    if search_index.schema.is_field_raw_sortable(att.name()) {
-                        return Some(OrderByStyle::Field(first_pathkey, att.name().to_string()));
+                    if search_index.schema.is_field_raw_sortable(att.name())
+                        && !is_array_type(att.atttypid)
+                    {
+                        return Some(OrderByStyle::Field(pathkey, att.name().to_string()));
                     }
                 }
             }
+        } else if let Some(var) = nodecast!(Var, T_Var, expr) {
+            let (heaprelid, attno, _) = find_var_relation(var, root);
+            if heaprelid == pg_sys::Oid::INVALID {
+                return None;
+            }
+            if attno == pg_sys::SelfItemPointerAttributeNumber as pg_sys::AttrNumber {
+                // every document has its ctid as a fast field
+                return Some(OrderByStyle::Field(pathkey, "ctid".to_string()));
+            }
+            let heaprel = PgRelation::with_lock(heaprelid, pg_sys::AccessShareLock as _);
+            let tupdesc = heaprel.tuple_desc();
+            if let Some(att) = tupdesc.get(attno as usize - 1) {
+                if search_index.schema.is_field_raw_sortable(att.name())
+                    && !is_array_type(att.atttypid)
+                {
+                    return Some(OrderByStyle::Field(pathkey, att.name().to_string()));
+                }
+            }
         }
     }
     None
 }
 
+/// Tantivy orders the docs of a multi-valued fast field by one of their values, which isn't how
+/// Postgres orders arrays
+unsafe fn is_array_type(typoid: pg_sys::Oid) -> bool {
+    pg_sys::get_element_type(typoid) != pg_sys::InvalidOid
+}
+
 unsafe fn is_lower_func(node: *mut pg_sys::Node, rti: i32) -> Option<*mut pg_sys::Var> {
     let funcexpr = nodecast!(FuncExpr, T_FuncExpr, node)?;
     if (*funcexpr).funcid == text_lower_funcoid() {
@@ -16,8 +16,11 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 use crate::api::Cardinality;
+use crate::index::reader::SortKey;
+use crate::index::statistics::StatisticsCounts;
 use crate::postgres::customscan::builders::custom_path::OrderByStyle;
 use crate::postgres::customscan::builders::custom_path::SortDirection;
+use crate::postgres::customscan::pdbscan::aggregates::AggregatePlan;
 use crate::postgres::customscan::pdbscan::qual_inspect::Qual;
 use pgrx::{pg_sys, PgList};
 
@@ -28,10 +31,14 @@ pub struct PrivateData {
     range_table_index: Option<pg_sys::Index>,
     quals: Option<*mut pg_sys::List>,
     limit: Option<usize>,
-    sort_field: Option<String>,
+    sort_keys: Option<String>,
     sort_direction: Option<SortDirection>,
     var_attname_lookup: Option<*mut pg_sys::List>,
     maybe_ff: bool,
+    aggregates: Option<String>,
+    offset: Option<usize>,
+    statistics_indexrelids: Option<String>,
+    global_statistics: Option<String>,
 }
 
 impl From<*mut pg_sys::List> for PrivateData {
@@ -72,13 +79,26 @@ impl PrivateData {
         self.limit = limit.map(|l| l.round() as usize);
     }
 
-    pub fn set_sort_info(&mut self, pathkey: &Option<OrderByStyle>) {
-        if let Some(style) = pathkey {
-            match style {
-                OrderByStyle::Score(_) => {}
-                OrderByStyle::Field(_, name) => self.sort_field = Some(name.clone()),
-            }
-            self.sort_direction = Some(style.direction())
+    pub fn set_offset(&mut self, offset: Option<usize>) {
+        self.offset = offset;
+    }
+
+    pub fn set_sort_info(&mut self, pathkeys: &[OrderByStyle]) {
+        if let Some(first) = pathkeys.first() {
+            let sort_keys = pathkeys
+                .iter()
+                .map(|style| SortKey {
+                    field: match style {
+                        OrderByStyle::Score(_) => None,
+                        OrderByStyle::Field(_, name) => Some(name.clone()),
+                    },
+                    direction: style.direction().into(),
+                    nulls_first: style.nulls_first(),
+                })
+                .collect::<Vec<_>>();
+            self.sort_keys =
+                Some(serde_json::to_string(&sort_keys).expect("sort keys should serialize"));
+            self.sort_direction = Some(first.direction())
         }
     }
 
@@ -89,6 +109,25 @@ impl PrivateData {
     pub fn set_maybe_ff(&mut self, maybe: bool) {
         self.maybe_ff = maybe;
     }
+
+    pub fn set_aggregates(&mut self, aggregates: &AggregatePlan) {
+        self.aggregates =
+            Some(serde_json::to_string(aggregates).expect("aggregate plan should serialize"));
+    }
+
+    pub fn set_statistics_indexrelids(&mut self, indexrelids: &[pg_sys::Oid]) {
+        let indexrelids = indexrelids
+            .iter()
+            .map(|oid| oid.as_u32())
+            .collect::<Vec<_>>();
+        self.statistics_indexrelids =
+            Some(serde_json::to_string(&indexrelids).expect("index oids should serialize"));
+    }
+
+    pub fn set_global_statistics(&mut self, counts: &StatisticsCounts) {
+        self.global_statistics =
+            Some(serde_json::to_string(counts).expect("statistics should serialize"));
+    }
 }
 
 //
@@ -117,8 +156,15 @@ impl PrivateData {
         self.limit
     }
 
-    pub fn sort_field(&self) -> Option<String> {
-        self.sort_field.clone()
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    pub fn sort_keys(&self) -> Vec<SortKey> {
+        self.sort_keys
+            .as_ref()
+            .map(|json| serde_json::from_str(json).expect("sort keys should deserialize"))
+            .unwrap_or_default()
     }
 
     pub fn sort_direction(&self) -> Option<SortDirection> {
@@ -133,6 +179,31 @@ impl PrivateData {
     pub fn maybe_ff(&self) -> bool {
         self.maybe_ff
     }
+
+    pub fn aggregates(&self) -> Option<AggregatePlan> {
+        self.aggregates
+            .as_ref()
+            .map(|json| serde_json::from_str(json).expect("aggregate plan should deserialize"))
+    }
+
+    pub fn statistics_indexrelids(&self) -> Vec<pg_sys::Oid> {
+        self.statistics_indexrelids
+            .as_ref()
+            .map(|json| {
+                serde_json::from_str::<Vec<u32>>(json)
+                    .expect("index oids should deserialize")
+                    .into_iter()
+                    .map(pg_sys::Oid::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn global_statistics(&self) -> Option<StatisticsCounts> {
+        self.global_statistics
+            .as_ref()
+            .map(|json| serde_json::from_str(json).expect("statistics should deserialize"))
+    }
 }
 
 #[allow(non_snake_case)]
@@ -256,12 +327,16 @@ pub mod serialize {
         ser.push(makeInteger(privdat.range_table_index));
         ser.push(unwrapOrNull(privdat.quals.map(|l| l.cast())));
         ser.push(makeString(privdat.limit));
-        ser.push(makeString(privdat.sort_field));
+        ser.push(makeString(privdat.sort_keys));
         ser.push(makeInteger(privdat.sort_direction));
         ser.push(unwrapOrNull(
             privdat.var_attname_lookup.map(|v| v.cast::<pg_sys::Node>()),
         ));
         ser.push(makeBoolean(Some(privdat.maybe_ff)));
+        ser.push(makeString(privdat.aggregates));
+        ser.push(makeString(privdat.offset));
+        ser.push(makeString(privdat.statistics_indexrelids));
+        ser.push(makeString(privdat.global_statistics));
 
         ser
     }
@@ -302,7 +377,7 @@ pub mod deserialize {
             range_table_index: input.get_ptr(2).and_then(|n| decodeInteger(n)),
             quals: input.get_ptr(3).and_then(|n| nodecast!(List, T_List, n)),
             limit: input.get_ptr(4).and_then(|n| decodeString(n)),
-            sort_field: input.get_ptr(5).and_then(|n| decodeString(n)),
+            sort_keys: input.get_ptr(5).and_then(|n| decodeString(n)),
             sort_direction: input.get_ptr(6).and_then(|n| decodeInteger(n)),
             var_attname_lookup: input
                 .get_ptr(7)
@@ -311,6 +386,10 @@ pub mod deserialize {
                 .get_ptr(8)
                 .and_then(|n| decodeBoolean(n))
                 .unwrap_or_default(),
+            aggregates: input.get_ptr(9).and_then(|n| decodeString(n)),
+            offset: input.get_ptr(10).and_then(|n| decodeString(n)),
+            statistics_indexrelids: input.get_ptr(11).and_then(|n| decodeString(n)),
+            global_statistics: input.get_ptr(12).and_then(|n| decodeString(n)),
         }
     }
 }
@@ -0,0 +1,158 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::nodecast;
+use pgrx::pg_sys::expression_tree_walker;
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::{
+    default, direct_function_call, extension_sql, function_name, pg_extern, pg_guard, pg_sys,
+    AnyElement, FromDatum, IntoDatum, JsonB, PgList, PgLogLevel, PgSqlErrorCode,
+};
+use std::ptr::addr_of_mut;
+use tantivy::schema::Facet;
+
+#[derive(Debug, Clone, Eq, Hash, PartialEq)]
+pub struct FacetInfo {
+    pub field: String,
+    pub facet: String,
+}
+
+impl FacetInfo {
+    /// The facet whose immediate children are counted
+    pub fn facet(&self) -> Facet {
+        Facet::from_text(&self.facet).unwrap_or_else(|_| {
+            ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_INVALID_PARAMETER_VALUE,
+                format!(
+                    "'{}' is not a valid facet path, which must start with '/'",
+                    self.facet
+                ),
+                function_name!(),
+            )
+            .report(PgLogLevel::ERROR);
+            unreachable!("ERROR reports don't return")
+        })
+    }
+}
+
+/// The number of the matching rows filed under each of the immediate children of `facet`, in the
+/// facet field `field`, as a JSON object keyed by the children's paths.  Every row a search
+/// returns gets the same counts.
+#[pg_extern(name = "facets", stable, parallel_safe)]
+fn facets_from_relation(
+    _relation_reference: AnyElement,
+    field: String,
+    facet: default!(String, "'/'"),
+) -> Option<JsonB> {
+    None
+}
+
+extension_sql!(
+    r#"
+ALTER FUNCTION facets SUPPORT placeholder_support;
+"#,
+    name = "facets_placeholder",
+    requires = [facets_from_relation, placeholder_support]
+);
+
+pub fn facets_funcoid() -> pg_sys::Oid {
+    unsafe {
+        direct_function_call::<pg_sys::Oid>(
+            pg_sys::regprocedurein,
+            &[c"paradedb.facets(anyelement, text, text)".into_datum()],
+        )
+        .expect("the `paradedb.facets(anyelement, text, text)` function should exist")
+    }
+}
+
+/// The field and facet a call to `paradedb.facets()` asks for
+pub unsafe fn facet_info(funcexpr: *mut pg_sys::FuncExpr) -> FacetInfo {
+    let args = PgList::<pg_sys::Node>::from_pg((*funcexpr).args);
+
+    // this should be equal to the number of args in the `facets()` function above
+    assert!(args.len() == 3);
+
+    let field_arg = nodecast!(Const, T_Const, args.get_ptr(1).unwrap());
+    let facet_arg = nodecast!(Const, T_Const, args.get_ptr(2).unwrap());
+
+    if let (Some(field_arg), Some(facet_arg)) = (field_arg, facet_arg) {
+        let field = String::from_datum((*field_arg).constvalue, (*field_arg).constisnull);
+        let facet = String::from_datum((*facet_arg).constvalue, (*facet_arg).constisnull);
+        FacetInfo {
+            field: field.expect("`paradedb.facets()`'s field should not be NULL"),
+            facet: facet.unwrap_or_else(|| "/".into()),
+        }
+    } else {
+        panic!("`paradedb.facets()`'s field and facet must be literals")
+    }
+}
+
+pub unsafe fn uses_facets(
+    rti: pg_sys::Index,
+    node: *mut pg_sys::Node,
+    facets_funcoid: pg_sys::Oid,
+) -> Vec<FacetInfo> {
+    struct Context {
+        rti: pg_sys::Index,
+        facets_funcoid: pg_sys::Oid,
+        facet_info: Vec<FacetInfo>,
+    }
+
+    #[pg_guard]
+    unsafe extern "C" fn walker(node: *mut pg_sys::Node, data: *mut core::ffi::c_void) -> bool {
+        if node.is_null() {
+            return false;
+        }
+
+        if let Some(funcexpr) = nodecast!(FuncExpr, T_FuncExpr, node) {
+            let context = &mut *data.cast::<Context>();
+
+            if (*funcexpr).funcid == context.facets_funcoid {
+                let args = PgList::<pg_sys::Node>::from_pg((*funcexpr).args);
+                if let Some(var) = nodecast!(Var, T_Var, args.get_ptr(0).unwrap()) {
+                    if (*var).varno as i32 == context.rti as i32 {
+                        let facet_info = facet_info(funcexpr);
+                        if !context.facet_info.contains(&facet_info) {
+                            context.facet_info.push(facet_info);
+                        }
+                    }
+                }
+            }
+        }
+
+        expression_tree_walker(node, Some(walker), data)
+    }
+
+    let mut context = Context {
+        rti,
+        facets_funcoid,
+        facet_info: vec![],
+    };
+
+    walker(node, addr_of_mut!(context).cast());
+    context.facet_info
+}
+
+/// The children and their counts as the JSON object `paradedb.facets()` returns
+pub fn facets_json(counts: &[(Facet, u64)]) -> JsonB {
+    JsonB(serde_json::Value::Object(
+        counts
+            .iter()
+            .map(|(facet, count)| (facet.to_path_string(), (*count).into()))
+            .collect(),
+    ))
+}
@@ -15,11 +15,15 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+pub mod facets;
 pub mod score;
 pub mod snippet;
 
 use crate::api::operator::{find_vars, ReturnedNodePointer};
 use crate::nodecast;
+use crate::postgres::customscan::pdbscan::projections::facets::{
+    facet_info, facets_funcoid, FacetInfo,
+};
 use crate::postgres::customscan::pdbscan::projections::score::score_funcoid;
 use crate::postgres::customscan::pdbscan::projections::snippet::{snippet_funcoid, SnippetInfo};
 use pgrx::pg_sys::expression_tree_walker;
@@ -91,6 +95,7 @@ pub unsafe fn maybe_needs_const_projections(node: *mut pg_sys::Node) -> bool {
             let data = &*data.cast::<Data>();
             if (*funcexpr).funcid == data.score_funcoid
                 || (*funcexpr).funcid == data.snipped_funcoid
+                || (*funcexpr).funcid == data.facets_funcoid
             {
                 return true;
             }
@@ -102,11 +107,13 @@ pub unsafe fn maybe_needs_const_projections(node: *mut pg_sys::Node) -> bool {
     struct Data {
         score_funcoid: pg_sys::Oid,
         snipped_funcoid: pg_sys::Oid,
+        facets_funcoid: pg_sys::Oid,
     }
 
     let mut data = Data {
         score_funcoid: score_funcoid(),
         snipped_funcoid: snippet_funcoid(),
+        facets_funcoid: facets_funcoid(),
     };
 
     let data = addr_of_mut!(data).cast();
@@ -172,10 +179,12 @@ pub unsafe fn inject_placeholders(
     snippet_funcoid: pg_sys::Oid,
     attname_lookup: &HashMap<(i32, pg_sys::AttrNumber), String>,
     snippet_infos: &HashMap<SnippetInfo, Option<SnippetGenerator>>,
+    facets_funcoid: pg_sys::Oid,
 ) -> (
     *mut pg_sys::List,
     *mut pg_sys::Const,
     HashMap<SnippetInfo, *mut pg_sys::Const>,
+    HashMap<FacetInfo, *mut pg_sys::Const>,
 ) {
     #[pg_guard]
     unsafe extern "C" fn walker(
@@ -214,6 +223,26 @@ pub unsafe fn inject_placeholders(
                         }
                     }
                 }
+            } else if (*funcexpr).funcid == data.facets_funcoid {
+                let var = nodecast!(Var, T_Var, args.get_ptr(0)?)?;
+                if (*var).varno as i32 == data.rti as i32 {
+                    // the counts are the same for every call asking for the same facet
+                    let const_ = *data
+                        .const_facet_nodes
+                        .entry(facet_info(funcexpr))
+                        .or_insert_with(|| {
+                            pg_sys::makeConst(
+                                pg_sys::JSONBOID,
+                                -1,
+                                pg_sys::Oid::INVALID,
+                                -1,
+                                pg_sys::Datum::null(),
+                                true,
+                                false,
+                            )
+                        });
+                    return Some(const_.cast());
+                }
             }
 
             None
@@ -247,6 +276,9 @@ pub unsafe fn inject_placeholders(
         attname_lookup: &'a HashMap<(i32, pg_sys::AttrNumber), String>,
         snippet_infos: &'a HashMap<SnippetInfo, Option<SnippetGenerator>>,
         const_snippet_nodes: HashMap<SnippetInfo, *mut pg_sys::Const>,
+
+        facets_funcoid: pg_sys::Oid,
+        const_facet_nodes: HashMap<FacetInfo, *mut pg_sys::Const>,
     }
 
     let mut data = Data {
@@ -267,11 +299,15 @@ pub unsafe fn inject_placeholders(
         attname_lookup,
         snippet_infos,
         const_snippet_nodes: Default::default(),
+
+        facets_funcoid,
+        const_facet_nodes: Default::default(),
     };
     let targetlist = walker(targetlist.cast(), addr_of_mut!(data).cast());
     (
         targetlist.cast(),
         data.const_score_node,
         data.const_snippet_nodes,
+        data.const_facet_nodes,
     )
 }
@@ -0,0 +1,500 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Translating ordinary `=`, `<`, `>`, `BETWEEN`, `IN` and `IS NULL` predicates on indexed
+//! fields, as well as `= ANY`, `@>` and `&&` on array columns, into Tantivy queries, so that a
+//! search such as
+//! `description @@@ 'shoes' AND rating > 3` can be answered by the index alone.
+//!
+//! The `ParadeDB Scan` doesn't recheck its quals, so a predicate is only translated when the
+//! index is certain to match exactly the rows Postgres would.
+
+use crate::nodecast;
+use crate::postgres::customscan::pdbscan::aggregates::var_field_name;
+use crate::postgres::types::TantivyValue;
+use crate::query::{SearchQueryInput, TermInput};
+use crate::schema::{SearchFieldConfig, SearchFieldName, SearchIndexSchema};
+use pgrx::{pg_sys, PgBuiltInOids, PgList, PgOid, PgTupleDesc};
+use std::ffi::CStr;
+use std::ops::Bound;
+use tantivy::schema::OwnedValue;
+
+/// The kinds of indexed fields whose values Tantivy compares the same way Postgres does
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum FieldKind {
+    Integer,
+    Float,
+    Bool,
+    Date,
+    /// A text field whose tokenizer indexes values shorter than this many bytes unchanged
+    VerbatimText(usize),
+}
+
+struct PushdownField {
+    name: String,
+    kind: FieldKind,
+    fast: bool,
+}
+
+/// Translate `column <op> constant` (or `constant <op> column`)
+pub unsafe fn opexpr(
+    rti: pg_sys::Index,
+    opexpr: *mut pg_sys::OpExpr,
+    tupdesc: &PgTupleDesc,
+    schema: &SearchIndexSchema,
+) -> Option<SearchQueryInput> {
+    let args = PgList::<pg_sys::Node>::from_pg((*opexpr).args);
+    if args.len() != 2 {
+        return None;
+    }
+    let (lhs, rhs) = (args.get_ptr(0)?, args.get_ptr(1)?);
+
+    // we want the column on the left, so flip the operator around if it was written the other way
+    let (var, konst, opno) = match (column(lhs), nodecast!(Const, T_Const, rhs)) {
+        (Some(var), Some(konst)) => (var, konst, (*opexpr).opno),
+        _ => (
+            column(rhs)?,
+            nodecast!(Const, T_Const, lhs)?,
+            pg_sys::get_commutator((*opexpr).opno),
+        ),
+    };
+
+    if is_array(var) {
+        return array_opexpr(rti, var, konst, opno, tupdesc, schema);
+    }
+
+    let field = lookup_field(var, rti, tupdesc, schema)?;
+    let value = const_value(konst, field.kind)?;
+    let query = match (builtin_opname(opno)?.as_str(), field.kind) {
+        ("=", _) => SearchQueryInput::Term {
+            field: Some(field.name),
+            value,
+            is_datetime: field.kind == FieldKind::Date,
+        },
+        // text ordering depends on the collation, and there's no point to ordering booleans
+        (_, FieldKind::VerbatimText(_) | FieldKind::Bool) => return None,
+        ("<", _) => range(field, Bound::Unbounded, Bound::Excluded(value)),
+        ("<=", _) => range(field, Bound::Unbounded, Bound::Included(value)),
+        (">", _) => range(field, Bound::Excluded(value), Bound::Unbounded),
+        (">=", _) => range(field, Bound::Included(value), Bound::Unbounded),
+        _ => return None,
+    };
+
+    Some(filter(query))
+}
+
+/// Translate `array_column @> constant array` and `array_column && constant array`.  Each element
+/// of an array is indexed as a separate value of the column's field, so a term matches the rows
+/// with any element equal to it.
+unsafe fn array_opexpr(
+    rti: pg_sys::Index,
+    var: *mut pg_sys::Var,
+    konst: *mut pg_sys::Const,
+    opno: pg_sys::Oid,
+    tupdesc: &PgTupleDesc,
+    schema: &SearchIndexSchema,
+) -> Option<SearchQueryInput> {
+    let field = lookup_element_field(var, rti, tupdesc, schema)?;
+    let query = match builtin_opname(opno)?.as_str() {
+        "&&" => SearchQueryInput::TermSet {
+            terms: const_array_terms(konst, &field)?,
+        },
+        "@>" => {
+            // every array contains an empty one, and none contains a NULL
+            let terms = const_array_terms(konst, &field)?;
+            if terms.is_empty() || const_array_has_nulls(konst) {
+                return None;
+            }
+            SearchQueryInput::Boolean {
+                must: terms
+                    .into_iter()
+                    .map(|term| SearchQueryInput::Term {
+                        field: Some(term.field),
+                        value: term.value,
+                        is_datetime: term.is_datetime,
+                    })
+                    .collect(),
+                should: Default::default(),
+                must_not: Default::default(),
+                minimum_should_match: None,
+            }
+        }
+        _ => return None,
+    };
+
+    Some(filter(query))
+}
+
+/// Translate `column = ANY(constant array)`, which is what `column IN (...)` becomes, and
+/// `constant = ANY(array_column)`
+pub unsafe fn scalar_array_opexpr(
+    rti: pg_sys::Index,
+    saop: *mut pg_sys::ScalarArrayOpExpr,
+    tupdesc: &PgTupleDesc,
+    schema: &SearchIndexSchema,
+) -> Option<SearchQueryInput> {
+    if !(*saop).useOr || builtin_opname((*saop).opno)? != "=" {
+        return None;
+    }
+
+    let args = PgList::<pg_sys::Node>::from_pg((*saop).args);
+    if args.len() != 2 {
+        return None;
+    }
+    let (lhs, rhs) = (args.get_ptr(0)?, args.get_ptr(1)?);
+
+    if let (Some(konst), Some(var)) = (nodecast!(Const, T_Const, lhs), column(rhs)) {
+        let field = lookup_element_field(var, rti, tupdesc, schema)?;
+        return Some(filter(SearchQueryInput::Term {
+            field: Some(field.name),
+            value: const_value(konst, field.kind)?,
+            is_datetime: field.kind == FieldKind::Date,
+        }));
+    }
+
+    let var = column(lhs)?;
+    let konst = nodecast!(Const, T_Const, rhs)?;
+    let field = lookup_field(var, rti, tupdesc, schema)?;
+    Some(filter(SearchQueryInput::TermSet {
+        terms: const_array_terms(konst, &field)?,
+    }))
+}
+
+/// Translate `column IS [NOT] NULL`
+pub unsafe fn nulltest(
+    rti: pg_sys::Index,
+    nulltest: *mut pg_sys::NullTest,
+    tupdesc: &PgTupleDesc,
+    schema: &SearchIndexSchema,
+) -> Option<SearchQueryInput> {
+    if (*nulltest).argisrow {
+        return None;
+    }
+
+    // text fast fields drop overly long values just like the tokenizer does, so only the other
+    // kinds of fast fields reliably know if a value is present
+    let field = lookup_field(column((*nulltest).arg.cast())?, rti, tupdesc, schema)?;
+    if !field.fast || matches!(field.kind, FieldKind::VerbatimText(_)) {
+        return None;
+    }
+
+    let exists = SearchQueryInput::Exists { field: field.name };
+    let query = match (*nulltest).nulltesttype {
+        pg_sys::NullTestType::IS_NOT_NULL => exists,
+        pg_sys::NullTestType::IS_NULL => SearchQueryInput::Boolean {
+            must: vec![SearchQueryInput::All],
+            should: Default::default(),
+            must_not: vec![exists],
+            minimum_should_match: None,
+        },
+        _ => return None,
+    };
+
+    Some(filter(query))
+}
+
+/// Predicates are filters that shouldn't contribute to the BM25 score of the `@@@` query they're
+/// combined with
+fn filter(query: SearchQueryInput) -> SearchQueryInput {
+    SearchQueryInput::ConstScore {
+        query: Box::new(query),
+        score: 0.0,
+    }
+}
+
+fn range(
+    field: PushdownField,
+    lower: Bound<OwnedValue>,
+    upper: Bound<OwnedValue>,
+) -> SearchQueryInput {
+    SearchQueryInput::Range {
+        field: field.name,
+        lower_bound: lower,
+        upper_bound: upper,
+        is_datetime: field.kind == FieldKind::Date,
+    }
+}
+
+/// The `column = constant` clauses that every row matching `query` satisfies, going the other way
+/// from the translations above: they come from the terms `query` requires on fields the index
+/// keeps exactly.  Their Vars are of the relation `varno`, with the attribute numbers of
+/// `tupdesc`.
+pub unsafe fn implied_equalities(
+    query: &SearchQueryInput,
+    varno: pg_sys::Index,
+    tupdesc: &PgTupleDesc,
+    schema: &SearchIndexSchema,
+) -> Vec<*mut pg_sys::Expr> {
+    let mut terms = vec![];
+    required_terms(query, &mut terms);
+
+    let mut clauses = vec![];
+    for (name, value) in terms {
+        let Some(att) = tupdesc
+            .iter()
+            .find(|att| !att.is_dropped() && att.name() == name)
+        else {
+            continue;
+        };
+        let Some(field) = field_of_type(name, att.atttypid, schema) else {
+            continue;
+        };
+        let Some(value) = checked_value(value, field.kind) else {
+            continue;
+        };
+        let Ok(Some(datum)) = TantivyValue(value).try_into_datum(PgOid::from(att.atttypid)) else {
+            continue;
+        };
+        let eq_opr =
+            (*pg_sys::lookup_type_cache(att.atttypid, pg_sys::TYPECACHE_EQ_OPR as _)).eq_opr;
+        if eq_opr == pg_sys::InvalidOid {
+            continue;
+        }
+
+        let var = pg_sys::makeVar(
+            varno as _,
+            att.attnum,
+            att.atttypid,
+            att.atttypmod,
+            att.attcollation,
+            0,
+        );
+        let konst = pg_sys::makeConst(
+            att.atttypid,
+            att.atttypmod,
+            att.attcollation,
+            att.attlen as _,
+            datum,
+            false,
+            att.attbyval,
+        );
+        let clause = pg_sys::make_opclause(
+            eq_opr,
+            pg_sys::BOOLOID,
+            false,
+            var.cast(),
+            konst.cast(),
+            pg_sys::InvalidOid,
+            att.attcollation,
+        );
+        pg_sys::set_opfuncid(clause.cast());
+        clauses.push(clause);
+    }
+    clauses
+}
+
+/// The `(field, value)` terms a doc must have to match `query`
+fn required_terms(query: &SearchQueryInput, terms: &mut Vec<(String, OwnedValue)>) {
+    match query {
+        SearchQueryInput::Term {
+            field: Some(field),
+            value,
+            ..
+        } => terms.push((field.clone(), value.clone())),
+        SearchQueryInput::Boolean { must, .. } => {
+            for query in must {
+                required_terms(query, terms);
+            }
+        }
+        SearchQueryInput::Boost { query, .. }
+        | SearchQueryInput::ConstScore { query, .. }
+        | SearchQueryInput::WithIndex { query, .. } => required_terms(query, terms),
+        _ => {}
+    }
+}
+
+/// The column `node` refers to, looking through binary-compatible casts like `varchar` to `text`
+unsafe fn column(node: *mut pg_sys::Node) -> Option<*mut pg_sys::Var> {
+    if let Some(relabel) = nodecast!(RelabelType, T_RelabelType, node) {
+        return column((*relabel).arg.cast());
+    }
+    nodecast!(Var, T_Var, node)
+}
+
+/// The name of operator `opno`, as long as it's one of Postgres' own
+unsafe fn builtin_opname(opno: pg_sys::Oid) -> Option<String> {
+    if opno == pg_sys::InvalidOid || opno.as_u32() >= pg_sys::FirstNormalObjectId {
+        return None;
+    }
+    let opname = pg_sys::get_opname(opno);
+    if opname.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(opname).to_string_lossy().into_owned())
+}
+
+unsafe fn lookup_field(
+    var: *mut pg_sys::Var,
+    rti: pg_sys::Index,
+    tupdesc: &PgTupleDesc,
+    schema: &SearchIndexSchema,
+) -> Option<PushdownField> {
+    let name = var_field_name(var, rti, tupdesc)?;
+    field_of_type(name, (*var).vartype, schema)
+}
+
+/// The indexed field of an array column, whose elements are the field's values
+unsafe fn lookup_element_field(
+    var: *mut pg_sys::Var,
+    rti: pg_sys::Index,
+    tupdesc: &PgTupleDesc,
+    schema: &SearchIndexSchema,
+) -> Option<PushdownField> {
+    let name = var_field_name(var, rti, tupdesc)?;
+    field_of_type(name, pg_sys::get_element_type((*var).vartype), schema)
+}
+
+unsafe fn is_array(var: *mut pg_sys::Var) -> bool {
+    pg_sys::get_element_type((*var).vartype) != pg_sys::InvalidOid
+}
+
+/// The indexed field `name`, for a column of type `typoid`
+fn field_of_type(
+    name: String,
+    typoid: pg_sys::Oid,
+    schema: &SearchIndexSchema,
+) -> Option<PushdownField> {
+    let search_field = schema.get_search_field(&SearchFieldName(name.clone()))?;
+
+    // `numeric` and `real` columns are indexed as approximations, and timestamps are indexed at
+    // a coarser precision than Postgres keeps them at, so we leave those alone
+    let (kind, fast) = match (PgOid::from(typoid), &search_field.config) {
+        (
+            PgOid::BuiltIn(
+                PgBuiltInOids::INT2OID | PgBuiltInOids::INT4OID | PgBuiltInOids::INT8OID,
+            ),
+            SearchFieldConfig::Numeric {
+                indexed: true,
+                fast,
+                ..
+            },
+        ) => (FieldKind::Integer, *fast),
+        (
+            PgOid::BuiltIn(PgBuiltInOids::FLOAT8OID),
+            SearchFieldConfig::Numeric {
+                indexed: true,
+                fast,
+                ..
+            },
+        ) => (FieldKind::Float, *fast),
+        (
+            PgOid::BuiltIn(PgBuiltInOids::BOOLOID),
+            SearchFieldConfig::Boolean {
+                indexed: true,
+                fast,
+                ..
+            },
+        ) => (FieldKind::Bool, *fast),
+        (
+            PgOid::BuiltIn(PgBuiltInOids::DATEOID),
+            SearchFieldConfig::Date {
+                indexed: true,
+                fast,
+                ..
+            },
+        ) => (FieldKind::Date, *fast),
+        (
+            PgOid::BuiltIn(PgBuiltInOids::TEXTOID | PgBuiltInOids::VARCHAROID),
+            SearchFieldConfig::Text {
+                indexed: true,
+                fast,
+                tokenizer,
+                ..
+            },
+        ) => (
+            FieldKind::VerbatimText(tokenizer.verbatim_token_limit()?),
+            *fast,
+        ),
+        _ => return None,
+    };
+
+    Some(PushdownField { name, kind, fast })
+}
+
+/// Can a constant of type `typoid` be compared against a field of kind `kind`?
+fn accepts_type(kind: FieldKind, typoid: PgOid) -> bool {
+    matches!(
+        (kind, typoid),
+        (
+            FieldKind::Integer,
+            PgOid::BuiltIn(
+                PgBuiltInOids::INT2OID | PgBuiltInOids::INT4OID | PgBuiltInOids::INT8OID
+            )
+        ) | (FieldKind::Float, PgOid::BuiltIn(PgBuiltInOids::FLOAT8OID))
+            | (FieldKind::Bool, PgOid::BuiltIn(PgBuiltInOids::BOOLOID))
+            | (FieldKind::Date, PgOid::BuiltIn(PgBuiltInOids::DATEOID))
+            | (
+                FieldKind::VerbatimText(_),
+                PgOid::BuiltIn(PgBuiltInOids::TEXTOID | PgBuiltInOids::VARCHAROID)
+            )
+    )
+}
+
+unsafe fn const_value(konst: *mut pg_sys::Const, kind: FieldKind) -> Option<OwnedValue> {
+    let typoid = PgOid::from((*konst).consttype);
+    if (*konst).constisnull || !accepts_type(kind, typoid) {
+        return None;
+    }
+    let TantivyValue(value) = TantivyValue::try_from_datum((*konst).constvalue, typoid).ok()?;
+    checked_value(value, kind)
+}
+
+/// A term for each element of a constant array
+unsafe fn const_array_terms(
+    konst: *mut pg_sys::Const,
+    field: &PushdownField,
+) -> Option<Vec<TermInput>> {
+    if (*konst).constisnull {
+        return None;
+    }
+    let element_type = PgOid::from(pg_sys::get_element_type((*konst).consttype));
+    if !accepts_type(field.kind, element_type) {
+        return None;
+    }
+
+    // NULL elements can never compare equal, so it's fine that they're skipped here
+    let mut terms = vec![];
+    for TantivyValue(value) in
+        TantivyValue::try_from_datum_array((*konst).constvalue, element_type).ok()?
+    {
+        terms.push(TermInput {
+            field: field.name.clone(),
+            value: checked_value(value, field.kind)?,
+            is_datetime: field.kind == FieldKind::Date,
+        });
+    }
+    Some(terms)
+}
+
+unsafe fn const_array_has_nulls(konst: *mut pg_sys::Const) -> bool {
+    let array = pg_sys::pg_detoast_datum((*konst).constvalue.cast_mut_ptr());
+    pg_sys::array_contains_nulls(array.cast())
+}
+
+/// Reject the values the index can't compare exactly
+fn checked_value(value: OwnedValue, kind: FieldKind) -> Option<OwnedValue> {
+    match (&value, kind) {
+        // a value the tokenizer would have dropped is in the heap, but not in the index
+        (OwnedValue::Str(text), FieldKind::VerbatimText(limit)) if text.len() >= limit => None,
+        // exclusive integer bounds get turned into inclusive ones by adding or subtracting one
+        (OwnedValue::I64(i64::MIN | i64::MAX), _) => None,
+        (OwnedValue::F64(f64), _) if f64.is_nan() => None,
+        // Postgres says -0.0 = 0.0, but they're indexed as different terms
+        (OwnedValue::F64(f64), _) if *f64 == 0.0 => None,
+        _ => Some(value),
+    }
+}
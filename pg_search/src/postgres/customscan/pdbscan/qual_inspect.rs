@@ -20,8 +20,10 @@ use crate::postgres::customscan::pdbscan::privdat::deserialize::decodeString;
 use crate::postgres::customscan::pdbscan::privdat::serialize::{
     makeInteger, makeString, AsValueNode,
 };
+use crate::postgres::customscan::pdbscan::pushdown;
 use crate::query::SearchQueryInput;
-use pgrx::{node_to_string, pg_sys, FromDatum, PgList};
+use crate::schema::SearchIndexSchema;
+use pgrx::{node_to_string, pg_sys, FromDatum, PgList, PgTupleDesc};
 
 #[derive(Debug, Clone)]
 pub enum Qual {
@@ -31,11 +33,36 @@ pub enum Qual {
         opno: pg_sys::Oid,
         val: *mut pg_sys::Const,
     },
+    /// A predicate that doesn't use `@@@`, but which the index can answer exactly
+    PushedDownExpression(SearchQueryInput),
     And(Vec<Qual>),
     Or(Vec<Qual>),
     Not(Box<Qual>),
 }
 
+impl Qual {
+    /// Does this qual use the `@@@` operator anywhere?
+    pub fn uses_search_operator(&self) -> bool {
+        match self {
+            Qual::Ignore | Qual::OperatorExpression { .. } => true,
+            Qual::PushedDownExpression(_) => false,
+            Qual::And(quals) | Qual::Or(quals) => quals.iter().any(Qual::uses_search_operator),
+            Qual::Not(qual) => qual.uses_search_operator(),
+        }
+    }
+
+    fn contains_pushed_down_expression(&self) -> bool {
+        match self {
+            Qual::Ignore | Qual::OperatorExpression { .. } => false,
+            Qual::PushedDownExpression(_) => true,
+            Qual::And(quals) | Qual::Or(quals) => {
+                quals.iter().any(Qual::contains_pushed_down_expression)
+            }
+            Qual::Not(qual) => qual.contains_pushed_down_expression(),
+        }
+    }
+}
+
 impl From<&Qual> for SearchQueryInput {
     fn from(value: &Qual) -> Self {
         match value {
@@ -44,6 +71,7 @@ impl From<&Qual> for SearchQueryInput {
                 SearchQueryInput::from_datum((**val).constvalue, (**val).constisnull)
                     .expect("rhs of @@@ operator Qual must not be null")
             },
+            Qual::PushedDownExpression(query) => query.clone(),
 
             Qual::And(quals) => {
                 let must = quals.iter().map(SearchQueryInput::from).collect::<Vec<_>>();
@@ -55,6 +83,7 @@ impl From<&Qual> for SearchQueryInput {
                         must,
                         should: Default::default(),
                         must_not: Default::default(),
+                        minimum_should_match: None,
                     },
                 }
             }
@@ -68,6 +97,7 @@ impl From<&Qual> for SearchQueryInput {
                         must: Default::default(),
                         should,
                         must_not: Default::default(),
+                        minimum_should_match: None,
                     },
                 }
             }
@@ -78,6 +108,7 @@ impl From<&Qual> for SearchQueryInput {
                     must: Default::default(),
                     should: Default::default(),
                     must_not,
+                    minimum_should_match: None,
                 }
             }
         }
@@ -97,6 +128,13 @@ impl From<Qual> for PgList<pg_sys::Node> {
                     list.push(makeInteger(Some(opno)));
                     list.push(val.cast());
                 }
+                Qual::PushedDownExpression(query) => {
+                    list.push(makeString(Some("PUSHED_DOWN_EXPRESSION")));
+                    list.push(makeString(Some(
+                        serde_json::to_string(&query)
+                            .expect("SearchQueryInput should serialize to json"),
+                    )));
+                }
                 Qual::And(quals) => {
                     list.push(makeString(Some("AND")));
                     list.push(makeInteger(Some(quals.len())));
@@ -142,6 +180,13 @@ impl From<PgList<pg_sys::Node>> for Qual {
                             );
                             Some(Qual::OperatorExpression { var, opno, val })
                         }
+                        "PUSHED_DOWN_EXPRESSION" => {
+                            let json = decodeString::<String>(value.get_ptr(1)?)?;
+                            Some(Qual::PushedDownExpression(
+                                serde_json::from_str(&json)
+                                    .expect("pushed down expression should be valid json"),
+                            ))
+                        }
                         "AND" => {
                             let len = usize::from_value_node(value.get_ptr(1)?)?;
                             let mut quals = Vec::with_capacity(len);
@@ -188,14 +233,19 @@ impl From<PgList<pg_sys::Node>> for Qual {
     }
 }
 
+/// Extract the quals of relation `rti` that the index can answer.  That's every use of the `@@@`
+/// operator, along with the ordinary predicates on indexed fields `pushdown` knows how to
+/// translate.  Returns `None` if there's anything else.
 pub unsafe fn extract_quals(
     rti: pg_sys::Index,
     node: *mut pg_sys::Node,
     pdbopoid: pg_sys::Oid,
+    tupdesc: &PgTupleDesc,
+    schema: &SearchIndexSchema,
 ) -> Option<Qual> {
     match (*node).type_ {
         pg_sys::NodeTag::T_List => {
-            let mut quals = list(rti, node.cast(), pdbopoid)?;
+            let mut quals = list(rti, node.cast(), pdbopoid, tupdesc, schema)?;
             if quals.len() == 1 {
                 quals.pop()
             } else {
@@ -213,20 +263,39 @@ pub unsafe fn extract_quals(
             } else {
                 (*ri).clause
             };
-            extract_quals(rti, clause.cast(), pdbopoid)
+            extract_quals(rti, clause.cast(), pdbopoid, tupdesc, schema)
         }
 
-        pg_sys::NodeTag::T_OpExpr => opexpr(rti, node, pdbopoid),
+        pg_sys::NodeTag::T_OpExpr => opexpr(rti, node, pdbopoid, tupdesc, schema),
+
+        pg_sys::NodeTag::T_ScalarArrayOpExpr => {
+            let saop = nodecast!(ScalarArrayOpExpr, T_ScalarArrayOpExpr, node)?;
+            pushdown::scalar_array_opexpr(rti, saop, tupdesc, schema)
+                .map(Qual::PushedDownExpression)
+        }
+
+        pg_sys::NodeTag::T_NullTest => {
+            let nulltest = nodecast!(NullTest, T_NullTest, node)?;
+            pushdown::nulltest(rti, nulltest, tupdesc, schema).map(Qual::PushedDownExpression)
+        }
 
         pg_sys::NodeTag::T_BoolExpr => {
             let boolexpr = nodecast!(BoolExpr, T_BoolExpr, node)?;
-            let args = PgList::<pg_sys::Node>::from_pg((*boolexpr).args);
-            let mut quals = list(rti, (*boolexpr).args, pdbopoid)?;
+            let mut quals = list(rti, (*boolexpr).args, pdbopoid, tupdesc, schema)?;
 
             match (*boolexpr).boolop {
                 pg_sys::BoolExprType::AND_EXPR => Some(Qual::And(quals)),
                 pg_sys::BoolExprType::OR_EXPR => Some(Qual::Or(quals)),
-                pg_sys::BoolExprType::NOT_EXPR => Some(Qual::Not(Box::new(quals.pop()?))),
+                pg_sys::BoolExprType::NOT_EXPR => {
+                    let not = quals.pop()?;
+
+                    // a predicate on a NULL value is neither true nor false, so negating it
+                    // doesn't select the rows the index would find by excluding its matches
+                    if not.contains_pushed_down_expression() {
+                        return None;
+                    }
+                    Some(Qual::Not(Box::new(not)))
+                }
                 _ => panic!("unexpected `BoolExprType`: {}", (*boolexpr).boolop),
             }
         }
@@ -243,11 +312,13 @@ unsafe fn list(
     rti: pg_sys::Index,
     list: *mut pg_sys::List,
     pdbopoid: pg_sys::Oid,
+    tupdesc: &PgTupleDesc,
+    schema: &SearchIndexSchema,
 ) -> Option<Vec<Qual>> {
     let args = PgList::<pg_sys::Node>::from_pg(list);
     let mut quals = Vec::new();
     for child in args.iter_ptr() {
-        quals.push(extract_quals(rti, child, pdbopoid)?)
+        quals.push(extract_quals(rti, child, pdbopoid, tupdesc, schema)?)
     }
     Some(quals)
 }
@@ -256,8 +327,14 @@ unsafe fn opexpr(
     rti: pg_sys::Index,
     node: *mut pg_sys::Node,
     pdbopoid: pg_sys::Oid,
+    tupdesc: &PgTupleDesc,
+    schema: &SearchIndexSchema,
 ) -> Option<Qual> {
     let opexpr = nodecast!(OpExpr, T_OpExpr, node)?;
+    if (*opexpr).opno != pdbopoid {
+        return pushdown::opexpr(rti, opexpr, tupdesc, schema).map(Qual::PushedDownExpression);
+    }
+
     let args = PgList::<pg_sys::Node>::from_pg((*opexpr).args);
     let (lhs, rhs) = (
         nodecast!(Var, T_Var, args.get_ptr(0)?),
@@ -273,17 +350,13 @@ unsafe fn opexpr(
     }
     let (lhs, rhs) = (lhs?, rhs?);
 
-    if (*opexpr).opno == pdbopoid {
-        if (*lhs).varno as i32 != rti as i32 {
-            Some(Qual::Ignore)
-        } else {
-            Some(Qual::OperatorExpression {
-                var: lhs,
-                opno: (*opexpr).opno,
-                val: rhs,
-            })
-        }
+    if (*lhs).varno as i32 != rti as i32 {
+        Some(Qual::Ignore)
     } else {
-        None
+        Some(Qual::OperatorExpression {
+            var: lhs,
+            opno: (*opexpr).opno,
+            val: rhs,
+        })
     }
 }
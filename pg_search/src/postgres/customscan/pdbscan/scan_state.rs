@@ -16,18 +16,22 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 use crate::index::fast_fields_helper::WhichFastField;
-use crate::index::reader::{SearchIndexReader, SearchResults};
+use crate::index::reader::{SearchIndexReader, SearchResults, SortKey};
+use crate::index::statistics::StatisticsCounts;
 use crate::postgres::customscan::builders::custom_path::SortDirection;
+use crate::postgres::customscan::pdbscan::aggregates::AggregatePlan;
 use crate::postgres::customscan::pdbscan::exec_methods::ExecMethod;
+use crate::postgres::customscan::pdbscan::projections::facets::FacetInfo;
 use crate::postgres::customscan::pdbscan::projections::snippet::SnippetInfo;
 use crate::postgres::customscan::CustomScanState;
 use crate::postgres::options::SearchIndexCreateOptions;
 use crate::postgres::visibility_checker::VisibilityChecker;
-use crate::query::SearchQueryInput;
+use crate::query::{RescoreWindow, SearchQueryInput};
 use pgrx::{name_data_to_str, pg_sys, PgRelation};
 use std::cell::UnsafeCell;
 use std::collections::HashMap;
 use tantivy::query::Query;
+use tantivy::schema::Facet;
 use tantivy::snippet::SnippetGenerator;
 use tantivy::DocAddress;
 
@@ -37,6 +41,7 @@ pub struct PdbScanState {
 
     pub query: Option<Box<dyn Query>>,
     pub search_query_input: SearchQueryInput,
+    pub rescore: Option<RescoreWindow>,
     pub search_reader: Option<SearchIndexReader>,
 
     pub search_results: SearchResults,
@@ -44,8 +49,11 @@ pub struct PdbScanState {
     pub targetlist_len: usize,
 
     pub limit: Option<usize>,
-    pub sort_field: Option<String>,
+    pub offset: Option<usize>,
+    pub offset_rows_remaining: usize,
+    pub sort_keys: Vec<SortKey>,
     pub sort_direction: Option<SortDirection>,
+    pub aggregates: Option<AggregatePlan>,
     pub retry_count: usize,
     pub heap_tuple_check_count: usize,
     pub virtual_tuple_count: usize,
@@ -60,6 +68,10 @@ pub struct PdbScanState {
     pub visibility_checker: Option<VisibilityChecker>,
 
     pub need_scores: bool,
+    /// The bm25 indexes whose statistics are shared to score with, when there are any
+    pub statistics_indexrelids: Vec<pg_sys::Oid>,
+    /// The statistics of those indexes, gathered when the query was planned
+    pub global_statistics: Option<StatisticsCounts>,
     pub const_score_node: Option<*mut pg_sys::Const>,
     pub score_funcoid: pg_sys::Oid,
 
@@ -68,6 +80,11 @@ pub struct PdbScanState {
     pub snippet_generators: HashMap<SnippetInfo, Option<SnippetGenerator>>,
     pub var_attname_lookup: HashMap<(i32, pg_sys::AttrNumber), String>,
 
+    pub const_facet_nodes: HashMap<FacetInfo, *mut pg_sys::Const>,
+    pub facets_funcoid: pg_sys::Oid,
+    /// The facets `paradedb.facets()` asks for, and their counts once the scan has them
+    pub facet_counts: HashMap<FacetInfo, Option<Vec<(Facet, u64)>>>,
+
     pub placeholder_targetlist: Option<*mut pg_sys::List>,
 
     exec_method: UnsafeCell<Box<dyn ExecMethod>>,
@@ -130,6 +147,11 @@ impl PdbScanState {
         !self.snippet_generators.is_empty()
     }
 
+    #[inline(always)]
+    pub fn need_facets(&self) -> bool {
+        !self.facet_counts.is_empty()
+    }
+
     #[track_caller]
     #[inline(always)]
     pub fn heaprel(&self) -> pg_sys::Relation {
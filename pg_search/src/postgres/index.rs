@@ -16,12 +16,19 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 use crate::index::{SearchIndex, SearchIndexError, WriterDirectory};
+use crate::postgres::utils::is_partitioned_index;
 use pgrx::{pg_sys, PgRelation};
 
 /// Open the underlying [`SearchIndex`] for the specified Postgres index relation
 pub fn open_search_index(
     index_relation: &PgRelation,
 ) -> anyhow::Result<SearchIndex, SearchIndexError> {
+    if is_partitioned_index(index_relation) {
+        return Err(SearchIndexError::PartitionedIndex(
+            index_relation.name().to_string(),
+        ));
+    }
+
     let database_oid = unsafe { pg_sys::MyDatabaseId };
     let index_oid = index_relation.oid();
     let relfilenode = relfilenode_from_pg_relation(index_relation);
@@ -26,6 +26,7 @@ mod range;
 mod scan;
 mod vacuum;
 mod validate;
+mod verify;
 
 pub mod customscan;
 pub mod datetime;
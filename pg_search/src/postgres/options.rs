@@ -20,7 +20,7 @@ use memoffset::*;
 use pgrx::pg_sys::AsPgCStr;
 use pgrx::*;
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi::CStr;
 
 use crate::schema::{SearchFieldConfig, SearchFieldName, SearchFieldType};
@@ -53,6 +53,7 @@ pub struct SearchIndexCreateOptions {
     json_fields_offset: i32,
     range_fields_offset: i32,
     datetime_fields_offset: i32,
+    facet_fields_offset: i32,
     key_field_offset: i32,
     target_segment_count: i32,
     merge_on_insert: bool,
@@ -130,6 +131,18 @@ extern "C" fn validate_datetime_fields(value: *const std::os::raw::c_char) {
     );
 }
 
+#[pg_guard]
+extern "C" fn validate_facet_fields(value: *const std::os::raw::c_char) {
+    let json_str = cstr_to_rust_str(value);
+    if json_str.is_empty() {
+        return;
+    }
+    SearchIndexCreateOptions::deserialize_config_fields(
+        json_str,
+        &SearchFieldConfig::facet_from_json,
+    );
+}
+
 #[pg_guard]
 extern "C" fn validate_fields(value: *const std::os::raw::c_char) {
     let json_str = cstr_to_rust_str(value);
@@ -159,7 +172,7 @@ fn cstr_to_rust_str(value: *const std::os::raw::c_char) -> String {
         .to_string()
 }
 
-const NUM_REL_OPTS: usize = 9;
+const NUM_REL_OPTS: usize = 10;
 #[pg_guard]
 pub unsafe extern "C" fn amoptions(
     reloptions: pg_sys::Datum,
@@ -196,6 +209,11 @@ pub unsafe extern "C" fn amoptions(
             opttype: pg_sys::relopt_type::RELOPT_TYPE_STRING,
             offset: offset_of!(SearchIndexCreateOptions, datetime_fields_offset) as i32,
         },
+        pg_sys::relopt_parse_elt {
+            optname: "facet_fields".as_pg_cstr(),
+            opttype: pg_sys::relopt_type::RELOPT_TYPE_STRING,
+            offset: offset_of!(SearchIndexCreateOptions, facet_fields_offset) as i32,
+        },
         pg_sys::relopt_parse_elt {
             optname: "key_field".as_pg_cstr(),
             opttype: pg_sys::relopt_type::RELOPT_TYPE_STRING,
@@ -308,14 +326,31 @@ impl SearchIndexCreateOptions {
         Self::deserialize_config_fields(config, &SearchFieldConfig::date_from_json)
     }
 
+    pub fn get_facet_fields(&self) -> Vec<(SearchFieldName, SearchFieldConfig)> {
+        let config = self.get_str(self.facet_fields_offset, "".to_string());
+        if config.is_empty() {
+            return Vec::new();
+        }
+        Self::deserialize_config_fields(config, &SearchFieldConfig::facet_from_json)
+    }
+
     #[allow(unused)]
     pub fn get_fields(
         &self,
         heaprel: &PgRelation,
+        indexrel: &PgRelation,
         index_info: *mut pg_sys::IndexInfo,
     ) -> Vec<(SearchFieldName, SearchFieldConfig, SearchFieldType)> {
         let tupdesc = heaprel.tuple_desc();
 
+        // a facet column is a text column as far as Postgres knows, so the only way to tell it
+        // apart is that it's listed in `facet_fields`
+        let facet_fields = self
+            .get_facet_fields()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<HashSet<_>>();
+
         let mut config_by_name = [
             self.text_fields_offset,
             self.numeric_fields_offset,
@@ -323,6 +358,7 @@ impl SearchIndexCreateOptions {
             self.json_fields_offset,
             self.range_fields_offset,
             self.datetime_fields_offset,
+            self.facet_fields_offset,
         ]
         .into_iter()
         .map(|offset| self.get_str(offset, "".to_string()))
@@ -334,28 +370,24 @@ impl SearchIndexCreateOptions {
         })
         .collect::<HashMap<_, _>>();
 
-        let _ = unsafe {
-            let num_attrs = (*index_info).ii_NumIndexAttrs;
-            (0..num_attrs)
-                .map(|i| {
-                    let attr_number = (*index_info).ii_IndexAttrNumbers[i as usize];
-                    let attribute = tupdesc
-                        .get((attr_number - 1) as usize)
-                        .expect("attribute should exist");
-                    let column_name = attribute.name();
-                    let column_type_oid = attribute.type_oid();
-                    (column_name, column_type_oid)
-                })
-                .collect::<Vec<_>>()
-        };
-
+        let index_tupdesc = indexrel.tuple_desc();
         let num_index_attrs = unsafe { (*index_info).ii_NumIndexAttrs };
         (0..num_index_attrs)
             .map(|i| {
                 let attr_number = unsafe { (*index_info).ii_IndexAttrNumbers[i as usize] };
-                let attribute = tupdesc
-                    .get((attr_number - 1) as usize)
-                    .expect("attribute should exist");
+
+                // an expression has no column of its own, so it's a virtual field named after the
+                // index's attribute for it, such as `lower` for `lower(title)` or `expr` for an
+                // operator expression
+                let attribute = if attr_number == 0 {
+                    index_tupdesc
+                        .get(i as usize)
+                        .expect("index attribute should exist")
+                } else {
+                    tupdesc
+                        .get((attr_number - 1) as usize)
+                        .expect("attribute should exist")
+                };
                 let column_name = attribute.name();
                 let column_type_oid = attribute.type_oid();
 
@@ -366,9 +398,13 @@ impl SearchIndexCreateOptions {
                     column_type_oid
                 };
 
-                let field_type = SearchFieldType::try_from(&base_oid).unwrap_or_else(|err| {
-                    panic!("cannot index column '{column_name}' with type {base_oid:?}: {err}")
-                });
+                let field_type = if facet_fields.contains(&SearchFieldName::from(column_name)) {
+                    SearchFieldType::Facet
+                } else {
+                    SearchFieldType::try_from(&base_oid).unwrap_or_else(|err| {
+                        panic!("cannot index column '{column_name}' with type {base_oid:?}: {err}")
+                    })
+                };
 
                 let field_config = config_by_name
                     .remove(column_name)
@@ -385,6 +421,7 @@ impl SearchIndexCreateOptions {
                         SearchFieldType::Json => SearchFieldConfig::json_from_json(field_config),
                         SearchFieldType::Date => SearchFieldConfig::date_from_json(field_config),
                         SearchFieldType::Range => SearchFieldConfig::range_from_json(field_config),
+                        SearchFieldType::Facet => SearchFieldConfig::facet_from_json(field_config),
                     }
                     .expect("field config should be valid for SearchFieldConfig::{field_name}"),
                     field_type,
@@ -478,6 +515,14 @@ pub unsafe fn init() {
         Some(validate_datetime_fields),
         pg_sys::AccessExclusiveLock as pg_sys::LOCKMODE,
     );
+    pg_sys::add_string_reloption(
+        RELOPT_KIND_PDB,
+        "facet_fields".as_pg_cstr(),
+        "JSON string specifying which text fields should be indexed as facets".as_pg_cstr(),
+        std::ptr::null(),
+        Some(validate_facet_fields),
+        pg_sys::AccessExclusiveLock as pg_sys::LOCKMODE,
+    );
     pg_sys::add_string_reloption(
         RELOPT_KIND_PDB,
         "key_field".as_pg_cstr(),
@@ -26,6 +26,9 @@ use pgrx::pg_sys::IndexScanDesc;
 use pgrx::*;
 use tantivy::query::Query;
 
+/// How many ctids [`amgetbitmap`] collects before adding them to the bitmap
+const BITMAP_BATCH_SIZE: usize = 1024;
+
 pub struct Bm25ScanState {
     need_scores: bool,
     fast_fields: FFHelper,
@@ -71,6 +74,7 @@ pub extern "C" fn amrescan(
                     query_string,
                     lenient: None,
                     conjunction_mode: None,
+                    minimum_should_match: None,
                 }
             },
             ScanStrategy::SearchQueryInput => unsafe {
@@ -102,6 +106,7 @@ pub extern "C" fn amrescan(
             must: vec![search_query_input, key],
             should: vec![],
             must_not: vec![],
+            minimum_should_match: None,
         };
     }
 
@@ -278,6 +283,11 @@ pub extern "C" fn amgettuple(
     }
 }
 
+/// Add every matching ctid to `tbm`.
+///
+/// This is also all a Parallel Bitmap Heap Scan needs from us.  A bitmap index scan is never
+/// parallel-aware, so Postgres has just one of the processes call this, into a `tbm` that lives
+/// in shared memory, and then hands the heap pages in it out to all of them.
 #[pg_guard]
 pub extern "C" fn amgetbitmap(scan: pg_sys::IndexScanDesc, tbm: *mut pg_sys::TIDBitmap) -> i64 {
     assert!(!tbm.is_null());
@@ -290,16 +300,23 @@ pub extern "C" fn amgetbitmap(scan: pg_sys::IndexScanDesc, tbm: *mut pg_sys::TID
     }
     .expect("no scan.opaque state");
 
+    // hand the matching ctids to the bitmap in batches rather than one at a time
+    let mut tids = Vec::with_capacity(BITMAP_BATCH_SIZE);
+    let add_tuples = |tids: &mut Vec<pg_sys::ItemPointerData>| unsafe {
+        // SAFETY:  `tbm` has been asserted to be non-null and `tids` holds `tids.len()`
+        // initialized ItemPointerData
+        pg_sys::tbm_add_tuples(tbm, tids.as_mut_ptr(), tids.len() as _, false);
+        tids.clear();
+    };
+
     let mut cnt = 0i64;
     loop {
         for (scored, _) in state.results.by_ref() {
             let mut ipd = pg_sys::ItemPointerData::default();
             crate::postgres::utils::u64_to_item_pointer(scored.ctid, &mut ipd);
-
-            unsafe {
-                // SAFETY:  `tbm` has been asserted to be non-null and our `&mut tid` has been
-                // initialized as a stack-allocated ItemPointerData
-                pg_sys::tbm_add_tuples(tbm, &mut ipd, 1, false);
+            tids.push(ipd);
+            if tids.len() == BITMAP_BATCH_SIZE {
+                add_tuples(&mut tids);
             }
 
             cnt += 1;
@@ -313,6 +330,10 @@ pub extern "C" fn amgetbitmap(scan: pg_sys::IndexScanDesc, tbm: *mut pg_sys::TID
         break;
     }
 
+    if !tids.is_empty() {
+        add_tuples(&mut tids);
+    }
+
     cnt
 }
 
@@ -33,7 +33,7 @@ use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::num::ParseFloatError;
 use std::str::FromStr;
-use tantivy::schema::OwnedValue;
+use tantivy::schema::{Facet, OwnedValue};
 use thiserror::Error;
 
 #[derive(Clone, Debug, Eq, PartialEq, PostgresType)]
@@ -50,11 +50,29 @@ impl TantivyValue {
         self.0.clone()
     }
 
+    /// Reinterpret a text value as a hierarchical facet path, such as `/electronics/phones`
+    pub fn try_into_facet(self) -> Result<Self, TantivyValueError> {
+        match self.0 {
+            OwnedValue::Str(path) => match Facet::from_text(&path) {
+                Ok(facet) => Ok(TantivyValue(OwnedValue::Facet(facet))),
+                Err(_) => Err(TantivyValueError::InvalidFacetPath(path)),
+            },
+            _ => Err(TantivyValueError::UnsupportedIntoConversion(
+                "facet".to_string(),
+            )),
+        }
+    }
+
     pub unsafe fn try_into_datum(self, oid: PgOid) -> Result<Option<Datum>, TantivyValueError> {
         if matches!(self.0, OwnedValue::Null) {
             return Ok(None);
         }
 
+        let element_oid = pgrx::pg_sys::get_element_type(oid.value());
+        if element_oid != pgrx::pg_sys::InvalidOid {
+            return self.try_into_array_datum(PgOid::from(element_oid));
+        }
+
         match &oid {
             PgOid::BuiltIn(builtin) => {
                 let datum = match builtin {
@@ -91,7 +109,59 @@ impl TantivyValue {
         }
     }
 
-    fn json_value_to_tantivy_value(value: Value) -> Vec<TantivyValue> {
+    /// Build an array of `element_oid` out of the values of a multi-valued field.  A single value
+    /// isn't an array, as we can't tell whether it's the only element or the first of several.
+    unsafe fn try_into_array_datum(
+        self,
+        element_oid: PgOid,
+    ) -> Result<Option<Datum>, TantivyValueError> {
+        let values: Vec<TantivyValue> = match self.0 {
+            OwnedValue::Array(values) => values.into_iter().map(TantivyValue).collect(),
+            _ => return Err(TantivyValueError::NotAnArray(element_oid.value())),
+        };
+
+        match &element_oid {
+            PgOid::BuiltIn(builtin) => match builtin {
+                PgBuiltInOids::BOOLOID => array_datum(values, bool::try_from),
+                PgBuiltInOids::INT2OID => array_datum(values, i16::try_from),
+                PgBuiltInOids::INT4OID => array_datum(values, i32::try_from),
+                PgBuiltInOids::INT8OID => array_datum(values, i64::try_from),
+                PgBuiltInOids::OIDOID => {
+                    array_datum(values, |value| Ok(Oid::from(u32::try_from(value)?)))
+                }
+                PgBuiltInOids::FLOAT4OID => array_datum(values, f32::try_from),
+                PgBuiltInOids::FLOAT8OID => array_datum(values, f64::try_from),
+                PgBuiltInOids::NUMERICOID => array_datum(values, pgrx::AnyNumeric::try_from),
+                PgBuiltInOids::TEXTOID => array_datum(values, String::try_from),
+                PgBuiltInOids::VARCHAROID => {
+                    // `varchar` is stored the same way as `text`, but the array records which
+                    // one its elements are
+                    let datum = array_datum(values, String::try_from)?;
+                    if let Some(datum) = datum {
+                        (*datum.cast_mut_ptr::<pgrx::pg_sys::ArrayType>()).elemtype =
+                            element_oid.value();
+                    }
+                    Ok(datum)
+                }
+                PgBuiltInOids::DATEOID => array_datum(values, pgrx::datum::Date::try_from),
+                PgBuiltInOids::TIMESTAMPOID => {
+                    array_datum(values, pgrx::datum::Timestamp::try_from)
+                }
+                PgBuiltInOids::TIMESTAMPTZOID => {
+                    array_datum(values, pgrx::datum::TimestampWithTimeZone::try_from)
+                }
+                PgBuiltInOids::TIMEOID => array_datum(values, pgrx::datum::Time::try_from),
+                PgBuiltInOids::TIMETZOID => {
+                    array_datum(values, pgrx::datum::TimeWithTimeZone::try_from)
+                }
+                PgBuiltInOids::UUIDOID => array_datum(values, pgrx::datum::Uuid::try_from),
+                _ => Err(TantivyValueError::UnsupportedArrayOid(element_oid.value())),
+            },
+            _ => Err(TantivyValueError::InvalidOid),
+        }
+    }
+
+    pub fn json_value_to_tantivy_value(value: Value) -> Vec<TantivyValue> {
         let mut tantivy_values = vec![];
         match value {
             // A tantivy JSON value can't be a top-level array, so we have to make
@@ -146,24 +216,27 @@ impl TantivyValue {
         datum: Datum,
         oid: PgOid,
     ) -> Result<Vec<Self>, TantivyValueError> {
+        // Tantivy has a limitation that prevents JSON top-level arrays from being
+        // inserted into the index. Therefore, we need to flatten the array elements
+        // individually before converting them into Tantivy values.
+        Ok(Self::json_value_to_tantivy_value(Self::json_from_datum(
+            datum, oid,
+        )?))
+    }
+
+    /// Read a `json` or `jsonb` datum as a [`Value`]
+    pub unsafe fn json_from_datum(datum: Datum, oid: PgOid) -> Result<Value, TantivyValueError> {
         match &oid {
             PgOid::BuiltIn(builtin) => match builtin {
-                // Tantivy has a limitation that prevents JSON top-level arrays from being
-                // inserted into the index. Therefore, we need to flatten the array elements
-                // individually before converting them into Tantivy values.
                 PgBuiltInOids::JSONBOID => {
                     let pgrx_value = pgrx::JsonB::from_datum(datum, false)
                         .ok_or(TantivyValueError::DatumDeref)?;
-                    let json_value: Value =
-                        serde_json::from_slice(&serde_json::to_vec(&pgrx_value.0)?)?;
-                    Ok(Self::json_value_to_tantivy_value(json_value))
+                    Ok(serde_json::from_slice(&serde_json::to_vec(&pgrx_value.0)?)?)
                 }
                 PgBuiltInOids::JSONOID => {
                     let pgrx_value = pgrx::Json::from_datum(datum, false)
                         .ok_or(TantivyValueError::DatumDeref)?;
-                    let json_value: Value =
-                        serde_json::from_slice(&serde_json::to_vec(&pgrx_value.0)?)?;
-                    Ok(Self::json_value_to_tantivy_value(json_value))
+                    Ok(serde_json::from_slice(&serde_json::to_vec(&pgrx_value.0)?)?)
                 }
                 _ => Err(TantivyValueError::UnsupportedJsonOid(oid.value())),
             },
@@ -252,7 +325,28 @@ impl TantivyValue {
                     )
                     .ok_or(TantivyValueError::DatumDeref)?,
                 ),
-                _ => Err(TantivyValueError::UnsupportedOid(oid.value())),
+                _ => {
+                    let element_oid = pgrx::pg_sys::get_element_type(oid.value());
+                    if element_oid == pgrx::pg_sys::InvalidOid {
+                        return Err(TantivyValueError::UnsupportedOid(oid.value()));
+                    }
+
+                    // unlike the values that are indexed, the value of a whole array keeps its
+                    // NULL elements, so that it can be turned back into the same array
+                    let array: pgrx::Array<Datum> = pgrx::Array::from_datum(datum, false)
+                        .ok_or(TantivyValueError::DatumDeref)?;
+                    let values = array
+                        .iter()
+                        .map(|element_datum| match element_datum {
+                            Some(element_datum) => {
+                                Self::try_from_datum(element_datum, PgOid::from(element_oid))
+                                    .map(|TantivyValue(value)| value)
+                            }
+                            None => Ok(OwnedValue::Null),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok(TantivyValue(OwnedValue::Array(values)))
+                }
             },
             PgOid::Custom(custom) => {
                 if pgrx::pg_sys::type_is_enum(*custom) {
@@ -276,6 +370,21 @@ impl TantivyValue {
     }
 }
 
+/// Convert each value with `convert`, keeping nulls, and collect them into a Postgres array
+fn array_datum<T: IntoDatum>(
+    values: Vec<TantivyValue>,
+    convert: impl Fn(TantivyValue) -> Result<T, TantivyValueError>,
+) -> Result<Option<Datum>, TantivyValueError> {
+    let elements = values
+        .into_iter()
+        .map(|value| match value.0 {
+            OwnedValue::Null => Ok(None),
+            _ => convert(value).map(Some),
+        })
+        .collect::<Result<Vec<Option<T>>, _>>()?;
+    Ok(elements.into_datum())
+}
+
 impl fmt::Display for TantivyValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self.tantivy_schema_value() {
@@ -1004,6 +1113,9 @@ pub enum TantivyValueError {
     #[error("Arrays of type {0:?} are not yet supported")]
     UnsupportedArrayOid(Oid),
 
+    #[error("Cannot convert a single value to an array of type {0:?}")]
+    NotAnArray(Oid),
+
     #[error("Cannot convert builtin json oid of {0:?} to TantivyValue")]
     UnsupportedJsonOid(Oid),
 
@@ -1012,4 +1124,108 @@ pub enum TantivyValueError {
 
     #[error("Cannot convert TantivyValue to type {0}")]
     UnsupportedIntoConversion(String),
+
+    #[error("'{0}' is not a valid facet path, which must start with '/'")]
+    InvalidFacetPath(String),
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use super::{TantivyValue, TantivyValueError};
+    use pgrx::prelude::*;
+    use pgrx::{direct_function_call, FromDatum, IntoDatum, PgOid};
+    use std::ffi::CStr;
+    use tantivy::schema::OwnedValue;
+
+    /// Parse `text` into an array of `element_oid` the way Postgres does for an array literal
+    unsafe fn array_in(text: &CStr, element_oid: pg_sys::Oid) -> pg_sys::Datum {
+        direct_function_call::<pg_sys::Datum>(
+            pg_sys::array_in,
+            &[
+                text.into_datum(),
+                element_oid.into_datum(),
+                (-1i32).into_datum(),
+            ],
+        )
+        .expect("array_in should return an array")
+    }
+
+    /// Convert `datum`, an array of type `array_oid`, into a [`TantivyValue`] and back
+    unsafe fn round_trip(
+        datum: pg_sys::Datum,
+        array_oid: pg_sys::Oid,
+    ) -> (TantivyValue, pg_sys::Datum) {
+        let value = TantivyValue::try_from_datum(datum, PgOid::from(array_oid))
+            .expect("array should convert to a TantivyValue");
+        let datum = value
+            .clone()
+            .try_into_datum(PgOid::from(array_oid))
+            .expect("TantivyValue should convert to an array")
+            .expect("array should not be NULL");
+        (value, datum)
+    }
+
+    unsafe fn element_type(datum: pg_sys::Datum) -> pg_sys::Oid {
+        (*datum.cast_mut_ptr::<pg_sys::ArrayType>()).elemtype
+    }
+
+    #[pg_test]
+    fn text_array_round_trip() {
+        unsafe {
+            let datum = array_in(c"{red,NULL,blue}", pg_sys::TEXTOID);
+            let (value, datum) = round_trip(datum, pg_sys::TEXTARRAYOID);
+
+            assert_eq!(
+                value,
+                TantivyValue(OwnedValue::Array(vec![
+                    OwnedValue::Str("red".into()),
+                    OwnedValue::Null,
+                    OwnedValue::Str("blue".into()),
+                ]))
+            );
+            assert_eq!(element_type(datum), pg_sys::TEXTOID);
+            assert_eq!(
+                Vec::<Option<String>>::from_datum(datum, false),
+                Some(vec![Some("red".into()), None, Some("blue".into())])
+            );
+        }
+    }
+
+    #[pg_test]
+    fn varchar_array_round_trip() {
+        unsafe {
+            let datum = array_in(c"{NULL,red,blue}", pg_sys::VARCHAROID);
+            let (_, datum) = round_trip(datum, pg_sys::VARCHARARRAYOID);
+
+            assert_eq!(element_type(datum), pg_sys::VARCHAROID);
+            assert_eq!(
+                Vec::<Option<String>>::from_datum(datum, false),
+                Some(vec![None, Some("red".into()), Some("blue".into())])
+            );
+        }
+    }
+
+    #[pg_test]
+    fn int8_array_round_trip() {
+        unsafe {
+            let datum = array_in(c"{1,NULL,-3}", pg_sys::INT8OID);
+            let (_, datum) = round_trip(datum, pg_sys::INT8ARRAYOID);
+
+            assert_eq!(element_type(datum), pg_sys::INT8OID);
+            assert_eq!(
+                Vec::<Option<i64>>::from_datum(datum, false),
+                Some(vec![Some(1), None, Some(-3)])
+            );
+        }
+    }
+
+    #[pg_test]
+    fn scalar_is_not_an_array() {
+        unsafe {
+            let result = TantivyValue(OwnedValue::Str("red".into()))
+                .try_into_datum(PgOid::from(pg_sys::TEXTARRAYOID));
+            assert!(matches!(result, Err(TantivyValueError::NotAnArray(_))));
+        }
+    }
 }
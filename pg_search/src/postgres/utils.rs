@@ -19,14 +19,18 @@ use std::str::FromStr;
 
 use crate::index::IndexError;
 use crate::postgres::types::TantivyValue;
-use crate::schema::{SearchDocument, SearchFieldName, SearchIndexSchema};
+use crate::schema::{SearchDocument, SearchFieldName, SearchFieldType, SearchIndexSchema};
 use anyhow::{anyhow, Result};
 use chrono::{NaiveDate, NaiveTime};
 use pgrx::itemptr::{item_pointer_get_both, item_pointer_set_all};
 use pgrx::*;
+use tantivy::schema::OwnedValue;
 
 /// Finds and returns the `USING bm25` index on the specified relation with the
 /// highest OID, or [`None`] if there aren't any.
+///
+/// For a partitioned table, that's its partitioned index.  See [`bm25_index_partitions`] for
+/// the indexes that hold its documents.
 pub fn locate_bm25_index(heaprelid: pg_sys::Oid) -> Option<PgRelation> {
     unsafe {
         let heaprel = PgRelation::open(heaprelid);
@@ -44,6 +48,33 @@ pub fn locate_bm25_index(heaprelid: pg_sys::Oid) -> Option<PgRelation> {
     }
 }
 
+/// Is `indexrel` the `USING bm25` index of a partitioned table?  Such an index has no documents
+/// of its own, they're all in the indexes of its partitions.
+pub fn is_partitioned_index(indexrel: &PgRelation) -> bool {
+    unsafe { (*indexrel.rd_rel).relkind as u8 == pg_sys::RELKIND_PARTITIONED_INDEX }
+}
+
+/// The indexes that hold the documents of the `USING bm25` index `indexrel`.  That's the index
+/// itself, unless it's a partitioned index, in which case it's the index of every partition
+/// under it, including those of sub-partitions.
+pub fn bm25_index_partitions(indexrel: &PgRelation) -> Vec<pg_sys::Oid> {
+    if !is_partitioned_index(indexrel) {
+        return vec![indexrel.oid()];
+    }
+
+    unsafe {
+        let inheritors = PgList::<pg_sys::Oid>::from_pg(pg_sys::find_all_inheritors(
+            indexrel.oid(),
+            pg_sys::AccessShareLock as _,
+            std::ptr::null_mut(),
+        ));
+        inheritors
+            .iter_oid()
+            .filter(|oid| pg_sys::get_rel_relkind(*oid) as u8 == pg_sys::RELKIND_INDEX)
+            .collect()
+    }
+}
+
 /// Rather than using pgrx' version of this function, we use our own, which doesn't leave 2
 /// empty bytes in the middle of the 64bit representation.  A ctid being only 48bits means
 /// if we leave the upper 16 bits (2 bytes) empty, tantivy will have a better chance of
@@ -119,10 +150,41 @@ pub unsafe fn row_to_search_document(
             continue;
         }
 
-        if is_array {
+        if search_field.type_ == SearchFieldType::Facet {
+            let values = if is_array {
+                TantivyValue::try_from_datum_array(datum, base_oid)?
+            } else {
+                vec![TantivyValue::try_from_datum(datum, base_oid)?]
+            };
+            for value in values {
+                document.insert(
+                    search_field.id,
+                    value.try_into_facet()?.tantivy_schema_value(),
+                );
+            }
+        } else if is_array {
             for value in TantivyValue::try_from_datum_array(datum, base_oid)? {
                 document.insert(search_field.id, value.tantivy_schema_value());
             }
+        } else if is_json && search_field.is_nested() {
+            let json = TantivyValue::json_from_datum(datum, base_oid)?;
+            for (path, child) in nested_children(&attname, &json) {
+                let mut child_document = schema.new_document();
+                child_document.insert(search_field.id, OwnedValue::from(child));
+                if let (Some(path_field), Some(parent_field)) =
+                    (schema.nested_path_field(), schema.nested_parent_field())
+                {
+                    child_document.insert(path_field.into(), OwnedValue::Str(path));
+                    child_document.insert(parent_field.into(), item_pointer_to_u64(ctid).into());
+                }
+                document.children.push(child_document.doc);
+            }
+
+            // the whole value is indexed into the row too, so that queries outside of
+            // `nested` still see it
+            for value in TantivyValue::json_value_to_tantivy_value(json) {
+                document.insert(search_field.id, value.tantivy_schema_value());
+            }
         } else if is_json {
             for value in TantivyValue::try_from_datum_json(datum, base_oid)? {
                 document.insert(search_field.id, value.tantivy_schema_value());
@@ -142,6 +204,54 @@ pub unsafe fn row_to_search_document(
     Ok(document)
 }
 
+/// The objects in the arrays of a nested JSON field's value, each with the dotted path of its
+/// array, and wrapped in objects up to the field so that they have the same paths as in the row
+fn nested_children(
+    field_name: &str,
+    value: &serde_json::Value,
+) -> Vec<(String, serde_json::Value)> {
+    fn walk<'a>(
+        path: &mut Vec<&'a str>,
+        value: &'a serde_json::Value,
+        children: &mut Vec<(Vec<&'a str>, &'a serde_json::Value)>,
+    ) {
+        match value {
+            serde_json::Value::Object(object) => {
+                for (key, value) in object {
+                    path.push(key);
+                    walk(path, value, children);
+                    path.pop();
+                }
+            }
+            serde_json::Value::Array(elements) => {
+                for element in elements {
+                    if element.is_object() {
+                        children.push((path.clone(), element));
+                    }
+                    walk(path, element, children);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut children = vec![];
+    walk(&mut vec![], value, &mut children);
+    children
+        .into_iter()
+        .map(|(path, element)| {
+            let child = path.iter().rev().fold(element.clone(), |child, key| {
+                serde_json::Value::Object(serde_json::Map::from_iter([(key.to_string(), child)]))
+            });
+            let path = std::iter::once(field_name)
+                .chain(path)
+                .collect::<Vec<_>>()
+                .join(".");
+            (path, child)
+        })
+        .collect()
+}
+
 /// Utility function for easy `f64` to `u32` conversion
 fn f64_to_u32(n: f64) -> Result<u32> {
     let truncated = n.trunc();
@@ -57,13 +57,9 @@ pub extern "C" fn amvacuumcleanup(
     // we also need to make sure segments get merged.
     //
     // we can force this by doing a .commit(), even tho we don't have changes
-    // then directly taking control of the underlying_writer and waiting for the merge threads
-    // to complete
+    // then waiting for the merge threads to complete and flushing their output
     writer.commit().expect("commit should succeed");
     writer
-        .underlying_writer
-        .take()
-        .unwrap()
         .wait_merging_threads()
         .expect("wait_merging_threads() should succeed");
 
@@ -0,0 +1,418 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::index::fast_fields_helper::FFHelper;
+use crate::index::{
+    SearchFs, SearchIndex, SearchIndexWriter, WriterDirectory, WriterResources,
+    SEARCH_INDEX_CONFIG_FILE_NAME,
+};
+use crate::postgres::index::open_search_index;
+use crate::postgres::options::SearchIndexCreateOptions;
+use crate::postgres::types::TantivyValue;
+use crate::postgres::utils::{item_pointer_to_u64, row_to_search_document};
+use crate::postgres::visibility_checker::VisibilityChecker;
+use anyhow::{bail, Result};
+use pgrx::callconv::{Arg, ArgAbi};
+use pgrx::pgrx_sql_entity_graph::metadata::{
+    ArgumentError, Returns, ReturnsError, SqlMapping, SqlTranslatable,
+};
+use pgrx::prelude::*;
+use pgrx::{FromDatum, PgMemoryContexts, PgOid, PgRelation, PgTupleDesc};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::PathBuf;
+use tantivy::{Directory, DocAddress};
+
+/// The kinds of problems [`verify_index`] can report, as they appear in its `problem` column
+const DANGLING_CTID: &str = "dangling_ctid";
+const DUPLICATE_CTID: &str = "duplicate_ctid";
+const MISSING_CTID: &str = "missing_ctid";
+const MISSING_FILE: &str = "missing_file";
+const CORRUPT_FILE: &str = "corrupt_file";
+const ORPHANED_FILE: &str = "orphaned_file";
+const ORPHANED_DIRECTORY: &str = "orphaned_directory";
+
+/// Files tantivy and pg_search keep in the index that don't belong to any one segment
+const INDEX_LEVEL_FILES: [&str; 3] = ["meta.json", ".managed.json", SEARCH_INDEX_CONFIG_FILE_NAME];
+
+type Problem = (String, String, bool);
+
+/// A `regclass` argument that, unlike a [`PgRelation`] one, isn't opened and locked before the
+/// function is called.  That's left to the function, so it can pick the order locks are taken in.
+pub struct RegClassOid(pg_sys::Oid);
+
+impl FromDatum for RegClassOid {
+    unsafe fn from_polymorphic_datum(
+        datum: pg_sys::Datum,
+        is_null: bool,
+        _typoid: pg_sys::Oid,
+    ) -> Option<Self> {
+        if is_null {
+            None
+        } else {
+            pg_sys::Oid::from_datum(datum, false).map(RegClassOid)
+        }
+    }
+}
+
+unsafe impl SqlTranslatable for RegClassOid {
+    fn argument_sql() -> Result<SqlMapping, ArgumentError> {
+        Ok(SqlMapping::literal("regclass"))
+    }
+
+    fn return_sql() -> Result<Returns, ReturnsError> {
+        Ok(Returns::One(SqlMapping::literal("regclass")))
+    }
+}
+
+unsafe impl<'fcx> ArgAbi<'fcx> for RegClassOid
+where
+    Self: 'fcx,
+{
+    unsafe fn unbox_arg_unchecked(arg: Arg<'_, 'fcx>) -> Self {
+        let index = arg.index();
+        unsafe {
+            arg.unbox_arg_using_from_datum()
+                .unwrap_or_else(|| panic!("argument {index} must not be null"))
+        }
+    }
+}
+
+/// Cross-check a `USING bm25` index against its heap and its own files.
+///
+/// One row is returned per problem found, so a healthy index returns no rows.  With
+/// `repair => true`, dangling, duplicate, and missing index entries are fixed in place by
+/// deleting and (re)inserting only the affected documents, and orphaned files are removed.
+/// Segment files that are missing or fail their checksum can't be repaired incrementally --
+/// the index needs a `REINDEX`.
+#[pg_extern]
+fn verify_index(
+    index: RegClassOid,
+    repair: default!(bool, false),
+) -> Result<
+    TableIterator<
+        'static,
+        (
+            name!(problem, String),
+            name!(detail, String),
+            name!(repaired, bool),
+        ),
+    >,
+> {
+    // # Safety
+    //
+    // A ShareLock on the heap is what a non-concurrent CREATE INDEX takes.  It keeps every
+    // other backend from modifying the table (and therefore the index) while we compare the
+    // two, but still allows them to read it.  Like Postgres itself, we lock the heap before
+    // the index so that we can't deadlock with something like a DROP INDEX.
+    let RegClassOid(index_oid) = index;
+    let heap_oid = unsafe { pg_sys::IndexGetRelation(index_oid, true) };
+    if heap_oid == pg_sys::InvalidOid {
+        bail!("relation with oid {} is not an index", index_oid.as_u32());
+    }
+    let heap = unsafe { PgRelation::with_lock(heap_oid, pg_sys::ShareLock as _) };
+    let index = unsafe { PgRelation::with_lock(index_oid, pg_sys::AccessShareLock as _) };
+
+    // the index may have been dropped and its oid reused while we waited for the heap's lock
+    if unsafe { pg_sys::IndexGetRelation(index_oid, true) } != heap_oid {
+        bail!("could not open the table of index \"{}\"", index.name());
+    }
+
+    let search_index = open_search_index(&index)?;
+
+    // the writer has to be acquired before we look at anything, as acquiring it refreshes
+    // our view of the index's files and keeps any other writer out until we're done
+    let mut writer = if repair {
+        let options = index.rd_options as *mut SearchIndexCreateOptions;
+        Some(search_index.get_writer(WriterResources::Vacuum, unsafe {
+            options.as_ref().expect("index should have options")
+        })?)
+    } else {
+        None
+    };
+
+    let mut problems = verify_files(&index, &search_index, repair)?;
+    if problems
+        .iter()
+        .any(|(problem, _, _)| problem == MISSING_FILE || problem == CORRUPT_FILE)
+    {
+        // the segments themselves can't be trusted, so neither can the ctids we'd read from them
+        warning!(
+            "index '{}' has damaged segment files and must be rebuilt with REINDEX",
+            index.name()
+        );
+    } else {
+        problems.extend(verify_ctids(&index, &heap, &search_index, writer.as_ref())?);
+    }
+
+    if let Some(writer) = writer.as_mut() {
+        writer.commit()?;
+        writer.wait_merging_threads()?;
+    }
+
+    Ok(TableIterator::new(problems))
+}
+
+/// Find segment files that are missing, corrupt, or no longer referenced by the index.  Orphaned
+/// files, and any directory left on disk from before the index was stored in its relation, are
+/// removed when `repair` is set.
+fn verify_files(
+    index: &PgRelation,
+    search_index: &SearchIndex,
+    repair: bool,
+) -> Result<Vec<Problem>> {
+    let mut problems = vec![];
+    let block_directory = &search_index.block_directory;
+
+    let mut referenced = INDEX_LEVEL_FILES
+        .iter()
+        .map(PathBuf::from)
+        .collect::<HashSet<_>>();
+    for meta in search_index.underlying_index.searchable_segment_metas()? {
+        for path in meta.list_files() {
+            if block_directory.file_len(&path).is_none() {
+                problems.push((MISSING_FILE.into(), path.display().to_string(), false));
+            }
+            referenced.insert(path);
+        }
+    }
+
+    let mut corrupt = search_index
+        .underlying_index
+        .validate_checksum()?
+        .into_iter()
+        .collect::<Vec<_>>();
+    corrupt.sort();
+    for path in corrupt {
+        problems.push((CORRUPT_FILE.into(), path.display().to_string(), false));
+    }
+
+    let mut orphaned = block_directory
+        .list_files()
+        .into_iter()
+        .filter(|path| !referenced.contains(path))
+        .collect::<Vec<_>>();
+    orphaned.sort();
+    for path in orphaned {
+        if repair {
+            block_directory.delete(&path)?;
+        }
+        problems.push((ORPHANED_FILE.into(), path.display().to_string(), repair));
+    }
+
+    let database_oid = unsafe { pg_sys::MyDatabaseId };
+    for directory in WriterDirectory::relfile_paths(database_oid.as_u32(), index.oid().as_u32())? {
+        let path = directory.search_index_dir_path(false)?.0;
+        if repair {
+            directory.remove()?;
+        }
+        problems.push((
+            ORPHANED_DIRECTORY.into(),
+            path.display().to_string(),
+            repair,
+        ));
+    }
+
+    Ok(problems)
+}
+
+/// Compare the ctids in the index with the tuples in the heap, in both directions.
+fn verify_ctids(
+    index: &PgRelation,
+    heap: &PgRelation,
+    search_index: &SearchIndex,
+    writer: Option<&SearchIndexWriter>,
+) -> Result<Vec<Problem>> {
+    let mut problems = vec![];
+    let repair = writer.is_some();
+
+    // the key of each indexed ctid, to tell if the tuple at that ctid is still the one indexed
+    let mut indexed = BTreeMap::new();
+    let mut duplicates = BTreeSet::new();
+    let reader = search_index.get_reader()?;
+    let key_field = search_index.key_field();
+    let fast_fields = FFHelper::with_fields(
+        &reader,
+        &[(key_field.name.0.clone(), key_field.type_.into()).into()],
+    );
+    for (segment_ord, segment_reader) in reader.searcher.segment_readers().iter().enumerate() {
+        let ctid_ff = segment_reader.fast_fields().u64("ctid")?;
+        for doc in segment_reader.doc_ids_alive() {
+            if let Some(ctid) = ctid_ff.first(doc) {
+                let key = fast_fields.value(0, DocAddress::new(segment_ord as u32, doc));
+                if indexed.insert(ctid, key).is_some() {
+                    duplicates.insert(ctid);
+                }
+            }
+        }
+    }
+
+    // an index entry is dangling if there's no tuple at all at its ctid, which we determine
+    // with SnapshotAny so that tuples which are dead, but not yet vacuumed, still count.  It's
+    // also dangling if the line pointer has since been reused by a different row, which we can
+    // tell by its key.  Such a row is then missing from the index.
+    let key_att = heap
+        .tuple_desc()
+        .iter()
+        .find(|att| !att.is_dropped() && att.name() == key_field.name.0)
+        .map(|att| (att.attnum, att.atttypid));
+    let mut dangling = vec![];
+    let mut reused = BTreeSet::new();
+    unsafe {
+        let mut visibility_checker = VisibilityChecker::with_rel_and_snap(
+            heap.as_ptr(),
+            std::ptr::addr_of_mut!(pg_sys::SnapshotAnyData),
+        );
+        let slot = pg_sys::table_slot_create(heap.as_ptr(), std::ptr::null_mut());
+        for (ctid, key) in &indexed {
+            check_for_interrupts!();
+            match visibility_checker.exec_if_visible(*ctid, slot, |_| {
+                key_att.and_then(|key_att| slot_key(slot, key_att))
+            }) {
+                None => dangling.push(*ctid),
+                Some(Some(heap_key))
+                    if !duplicates.contains(ctid) && key.as_ref() != Some(&heap_key) =>
+                {
+                    dangling.push(*ctid);
+                    reused.insert(*ctid);
+                }
+                Some(_) => {}
+            }
+        }
+        pg_sys::ExecDropSingleTupleTableSlot(slot);
+    }
+
+    for ctid in &dangling {
+        problems.push((DANGLING_CTID.into(), format_ctid(*ctid), repair));
+    }
+    for ctid in &duplicates {
+        problems.push((DUPLICATE_CTID.into(), format_ctid(*ctid), repair));
+    }
+
+    if let Some(writer) = writer {
+        // duplicated entries are removed entirely, and the heap scan below puts a single one
+        // back for each whose tuple still exists
+        let ctid_field = search_index.schema.ctid_field().id.0;
+        let to_delete = dangling
+            .iter()
+            .chain(duplicates.iter())
+            .copied()
+            .collect::<Vec<_>>();
+        writer.delete(&ctid_field, &to_delete)?;
+    }
+
+    let mut state = VerifyState {
+        indexed: &indexed,
+        duplicates: &duplicates,
+        reused: &reused,
+        missing: vec![],
+        search_index,
+        writer,
+        tupdesc: unsafe { PgTupleDesc::from_pg_copy(index.rd_att) },
+        memctx: PgMemoryContexts::new("pg_search_verify_index"),
+    };
+    unsafe {
+        let index_info = pg_sys::BuildIndexInfo(index.as_ptr());
+        pg_sys::IndexBuildHeapScan(
+            heap.as_ptr(),
+            index.as_ptr(),
+            index_info,
+            Some(verify_callback),
+            &mut state,
+        );
+    }
+
+    for ctid in state.missing {
+        problems.push((MISSING_CTID.into(), format_ctid(ctid), repair));
+    }
+
+    Ok(problems)
+}
+
+struct VerifyState<'a> {
+    indexed: &'a BTreeMap<u64, Option<TantivyValue>>,
+    duplicates: &'a BTreeSet<u64>,
+    /// ctids whose index entries belong to a row that used to be there
+    reused: &'a BTreeSet<u64>,
+    missing: Vec<u64>,
+    search_index: &'a SearchIndex,
+    writer: Option<&'a SearchIndexWriter>,
+    tupdesc: PgTupleDesc<'static>,
+    memctx: PgMemoryContexts,
+}
+
+#[pg_guard]
+unsafe extern "C" fn verify_callback(
+    _index: pg_sys::Relation,
+    ctid: pg_sys::ItemPointer,
+    values: *mut pg_sys::Datum,
+    isnull: *mut bool,
+    _tuple_is_alive: bool,
+    state: *mut std::os::raw::c_void,
+) {
+    check_for_interrupts!();
+    let state = (state as *mut VerifyState)
+        .as_mut()
+        .expect("VerifyState pointer should not be null");
+
+    let ctid_val = item_pointer_to_u64(*ctid);
+    let is_missing = !state.indexed.contains_key(&ctid_val) || state.reused.contains(&ctid_val);
+    if is_missing {
+        state.missing.push(ctid_val);
+    }
+
+    if let Some(writer) = state.writer {
+        if is_missing || state.duplicates.contains(&ctid_val) {
+            let search_index = state.search_index;
+            let tupdesc = &state.tupdesc;
+            state.memctx.reset();
+            state.memctx.switch_to(|_| {
+                let search_document =
+                    row_to_search_document(*ctid, tupdesc, values, isnull, &search_index.schema)
+                        .unwrap_or_else(|err| {
+                            panic!(
+                                "error creating index entry for {}: {err}",
+                                format_ctid(ctid_val)
+                            )
+                        });
+                search_index
+                    .insert(writer, search_document)
+                    .unwrap_or_else(|err| panic!("error repairing index entry: {err}"));
+            });
+            state.memctx.reset();
+        }
+    }
+}
+
+/// The value of the key_field column, at `attno` and of type `typoid`, of the tuple in `slot`
+unsafe fn slot_key(
+    slot: *mut pg_sys::TupleTableSlot,
+    (attno, typoid): (pg_sys::AttrNumber, pg_sys::Oid),
+) -> Option<TantivyValue> {
+    if (*slot).tts_nvalid < attno {
+        pg_sys::slot_getsomeattrs_int(slot, attno as _);
+    }
+    let idx = attno as usize - 1;
+    if *(*slot).tts_isnull.add(idx) {
+        return None;
+    }
+    TantivyValue::try_from_datum(*(*slot).tts_values.add(idx), PgOid::from(typoid)).ok()
+}
+
+/// Format a ctid the way Postgres displays them, as `(blockno,offno)`
+fn format_ctid(ctid: u64) -> String {
+    format!("({},{})", ctid >> 16, ctid & 0xFFFF)
+}
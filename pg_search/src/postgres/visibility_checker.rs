@@ -15,8 +15,13 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+use crate::index::reader::SearchIndexReader;
+use crate::index::SearchIndex;
+use crate::postgres::customscan::pdbscan::is_block_all_visible;
 use crate::postgres::utils;
 use pgrx::pg_sys;
+use std::collections::HashSet;
+use tantivy::query::Query;
 
 /// Helper to manage the information necessary to validate that a "ctid" is currently visible to
 /// a snapshot
@@ -82,3 +87,58 @@ impl VisibilityChecker {
         }
     }
 }
+
+/// Find the ctids of the documents matching `query` whose tuples aren't visible to `snapshot`.
+///
+/// Anything Tantivy computes over the matching documents itself, such as aggregates, knows
+/// nothing about MVCC, and needs to be told to skip these.
+pub fn invisible_ctids(
+    heaprel: pg_sys::Relation,
+    snapshot: pg_sys::Snapshot,
+    search_reader: &SearchIndexReader,
+    query: &dyn Query,
+) -> HashSet<u64> {
+    let search_results =
+        search_reader.search_via_channel(false, false, SearchIndex::executor(), query, None);
+    invisible_ctids_among(
+        heaprel,
+        snapshot,
+        search_results.map(|(scored, _)| scored.ctid),
+    )
+}
+
+/// Find which of `ctids` are of tuples that aren't visible to `snapshot`.  Tuples on all-visible
+/// pages are assumed visible without visiting the heap.
+pub fn invisible_ctids_among(
+    heaprel: pg_sys::Relation,
+    snapshot: pg_sys::Snapshot,
+    ctids: impl IntoIterator<Item = u64>,
+) -> HashSet<u64> {
+    let mut invisible = HashSet::default();
+    unsafe {
+        let mut visibility_checker = VisibilityChecker::with_rel_and_snap(heaprel, snapshot);
+        let slot = pg_sys::table_slot_create(heaprel, std::ptr::null_mut());
+        let mut vmbuff = pg_sys::InvalidBuffer as pg_sys::Buffer;
+
+        for ctid in ctids {
+            let mut tid = pg_sys::ItemPointerData::default();
+            utils::u64_to_item_pointer(ctid, &mut tid);
+            if is_block_all_visible(heaprel, &mut vmbuff, tid, (*heaprel).rd_id) {
+                continue;
+            }
+
+            if visibility_checker
+                .exec_if_visible(ctid, slot, |_| ())
+                .is_none()
+            {
+                invisible.insert(ctid);
+            }
+        }
+
+        if vmbuff != pg_sys::InvalidBuffer as pg_sys::Buffer {
+            pg_sys::ReleaseBuffer(vmbuff);
+        }
+        pg_sys::ExecDropSingleTupleTableSlot(slot);
+    }
+    invisible
+}
@@ -0,0 +1,536 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Function scores, which blend a query's scores with functions of each doc's fast field values.
+
+use crate::query::{AsFieldType, QueryError, TantivyDateTime};
+use serde::{Deserialize, Serialize};
+use tantivy::columnar::Column;
+use tantivy::fastfield::FastFieldReaders;
+use tantivy::query::{EnableScoring, Explanation, Query, Scorer, Weight};
+use tantivy::schema::{FieldType, OwnedValue};
+use tantivy::{DocId, DocSet, Score, SegmentReader, Term};
+
+/// A function of a numeric or date fast field's value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreFunction {
+    /// `modifier(factor * value)`, where docs without a value use `missing`
+    FieldValueFactor {
+        field: String,
+        #[serde(default = "default_factor")]
+        factor: f64,
+        #[serde(default)]
+        modifier: FieldValueModifier,
+        #[serde(default)]
+        missing: Option<f64>,
+    },
+    Gauss(DecayFunction),
+    Exp(DecayFunction),
+    Linear(DecayFunction),
+}
+
+fn default_factor() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldValueModifier {
+    #[default]
+    None,
+    Log,
+    Log1p,
+    Log2p,
+    Ln,
+    Ln1p,
+    Ln2p,
+    Square,
+    Sqrt,
+    Reciprocal,
+}
+
+/// Scores docs by how far their value is from `origin`.  Values within `offset` of it score 1,
+/// and values `offset + scale` away from it score `decay`.
+///
+/// For date fields, `origin` is a date and defaults to now, and `scale` and `offset` are durations
+/// like `"7d"`, `"12h"`, `"30m"` or `"45s"`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DecayFunction {
+    pub field: String,
+    #[serde(default)]
+    pub origin: Option<OwnedValue>,
+    pub scale: OwnedValue,
+    #[serde(default)]
+    pub offset: Option<OwnedValue>,
+    #[serde(default = "default_decay")]
+    pub decay: f64,
+}
+
+fn default_decay() -> f64 {
+    0.5
+}
+
+/// How the values of the functions are combined with each other
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FunctionScoreMode {
+    #[default]
+    Multiply,
+    Sum,
+    Avg,
+    First,
+    Max,
+    Min,
+}
+
+/// How the combined value of the functions is combined with the query's score
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FunctionBoostMode {
+    #[default]
+    Multiply,
+    Replace,
+    Sum,
+    Avg,
+    Max,
+    Min,
+}
+
+impl FunctionScoreMode {
+    fn combine(&self, values: impl Iterator<Item = f64>) -> f64 {
+        let mut values = values.peekable();
+        if values.peek().is_none() {
+            return 1.0;
+        }
+        match self {
+            FunctionScoreMode::Multiply => values.product(),
+            FunctionScoreMode::Sum => values.sum(),
+            FunctionScoreMode::Avg => {
+                let (sum, count) =
+                    values.fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+                sum / count as f64
+            }
+            FunctionScoreMode::First => values.next().unwrap(),
+            FunctionScoreMode::Max => values.fold(f64::MIN, f64::max),
+            FunctionScoreMode::Min => values.fold(f64::MAX, f64::min),
+        }
+    }
+}
+
+impl FunctionBoostMode {
+    fn combine(&self, score: f64, value: f64) -> f64 {
+        match self {
+            FunctionBoostMode::Multiply => score * value,
+            FunctionBoostMode::Replace => value,
+            FunctionBoostMode::Sum => score + value,
+            FunctionBoostMode::Avg => (score + value) / 2.0,
+            FunctionBoostMode::Max => score.max(value),
+            FunctionBoostMode::Min => score.min(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DecayShape {
+    Gauss,
+    Exp,
+    Linear,
+}
+
+/// A [`ScoreFunction`] checked against the schema, with its dates and durations in seconds
+#[derive(Debug, Clone)]
+pub struct ResolvedFunction {
+    field: String,
+    kind: FunctionKind,
+}
+
+#[derive(Debug, Clone)]
+enum FunctionKind {
+    FieldValueFactor {
+        factor: f64,
+        modifier: FieldValueModifier,
+        missing: Option<f64>,
+    },
+    Decay {
+        shape: DecayShape,
+        origin: f64,
+        scale: f64,
+        offset: f64,
+        decay: f64,
+    },
+}
+
+impl ScoreFunction {
+    pub(super) fn resolve(
+        self,
+        field_lookup: &impl AsFieldType<String>,
+    ) -> Result<ResolvedFunction, QueryError> {
+        let (field, decay, shape) = match self {
+            ScoreFunction::FieldValueFactor {
+                field,
+                factor,
+                modifier,
+                missing,
+            } => {
+                check_field(&field, field_lookup)?;
+                return Ok(ResolvedFunction {
+                    field,
+                    kind: FunctionKind::FieldValueFactor {
+                        factor,
+                        modifier,
+                        missing,
+                    },
+                });
+            }
+            ScoreFunction::Gauss(decay) => (decay.field.clone(), decay, DecayShape::Gauss),
+            ScoreFunction::Exp(decay) => (decay.field.clone(), decay, DecayShape::Exp),
+            ScoreFunction::Linear(decay) => (decay.field.clone(), decay, DecayShape::Linear),
+        };
+
+        let is_date = check_field(&field, field_lookup)?;
+        let invalid =
+            |message: &str| QueryError::InvalidScoreFunction(format!("{field}: {message}"));
+
+        let origin = match (decay.origin, is_date) {
+            (Some(origin), true) => date_seconds(&origin).ok_or_else(|| {
+                invalid("origin must be a date like '2024-01-01' or '2024-01-01T00:00:00Z'")
+            })?,
+            (None, true) => chrono::Utc::now().timestamp_micros() as f64 / 1_000_000.0,
+            (Some(origin), false) => {
+                number(&origin).ok_or_else(|| invalid("origin must be a number"))?
+            }
+            (None, false) => return Err(invalid("origin is required for numeric fields")),
+        };
+        let distance = |value: &OwnedValue| {
+            if is_date {
+                duration_seconds(value)
+            } else {
+                number(value)
+            }
+        };
+        let scale = distance(&decay.scale)
+            .filter(|scale| *scale > 0.0)
+            .ok_or_else(|| invalid("scale must be a positive number, or a duration like '7d'"))?;
+        let offset = match &decay.offset {
+            Some(offset) => distance(offset)
+                .filter(|offset| *offset >= 0.0)
+                .ok_or_else(|| invalid("offset must not be negative"))?,
+            None => 0.0,
+        };
+        if !(decay.decay > 0.0 && decay.decay < 1.0) {
+            return Err(invalid("decay must be between 0 and 1"));
+        }
+
+        Ok(ResolvedFunction {
+            field,
+            kind: FunctionKind::Decay {
+                shape,
+                origin,
+                scale,
+                offset,
+                decay: decay.decay,
+            },
+        })
+    }
+}
+
+/// Make sure `field` is a numeric or date fast field, and say whether it's a date
+fn check_field(field: &str, field_lookup: &impl AsFieldType<String>) -> Result<bool, QueryError> {
+    match field_lookup.as_field_type(&field.to_string()) {
+        Some((field_type, _, _)) if field_type.is_fast() => match field_type {
+            FieldType::I64(_) | FieldType::U64(_) | FieldType::F64(_) => Ok(false),
+            FieldType::Date(_) => Ok(true),
+            _ => Err(QueryError::NotAScoreField(field.to_string())),
+        },
+        _ => Err(QueryError::NotAScoreField(field.to_string())),
+    }
+}
+
+fn number(value: &OwnedValue) -> Option<f64> {
+    match value {
+        OwnedValue::I64(value) => Some(*value as f64),
+        OwnedValue::U64(value) => Some(*value as f64),
+        OwnedValue::F64(value) => Some(*value),
+        OwnedValue::Str(value) => value.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn date_seconds(value: &OwnedValue) -> Option<f64> {
+    let OwnedValue::Str(text) = value else {
+        return None;
+    };
+    let micros = match TantivyDateTime::try_from(text.as_str()) {
+        Ok(TantivyDateTime(date)) => date.into_timestamp_micros(),
+        Err(_) => chrono::NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S")
+            .or_else(|_| {
+                chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                    .map(|date| date.and_hms_opt(0, 0, 0).unwrap())
+            })
+            .ok()?
+            .and_utc()
+            .timestamp_micros(),
+    };
+    Some(micros as f64 / 1_000_000.0)
+}
+
+/// A number of seconds, or a duration like `"7d"`
+fn duration_seconds(value: &OwnedValue) -> Option<f64> {
+    let OwnedValue::Str(text) = value else {
+        return number(value);
+    };
+    let text = text.trim();
+    let split = text
+        .find(|ch: char| ch.is_ascii_alphabetic())
+        .unwrap_or(text.len());
+    let (amount, unit) = text.split_at(split);
+    let amount = amount.trim().parse::<f64>().ok()?;
+    let seconds = match unit {
+        "" | "s" => 1.0,
+        "ms" => 0.001,
+        "m" => 60.0,
+        "h" => 60.0 * 60.0,
+        "d" => 24.0 * 60.0 * 60.0,
+        "w" => 7.0 * 24.0 * 60.0 * 60.0,
+        _ => return None,
+    };
+    Some(amount * seconds)
+}
+
+impl ResolvedFunction {
+    fn evaluate(&self, value: Option<f64>) -> f64 {
+        let result = match &self.kind {
+            FunctionKind::FieldValueFactor {
+                factor,
+                modifier,
+                missing,
+            } => {
+                let Some(value) = value.or(*missing) else {
+                    return 1.0;
+                };
+                let value = factor * value;
+                match modifier {
+                    FieldValueModifier::None => value,
+                    FieldValueModifier::Log => value.log10(),
+                    FieldValueModifier::Log1p => (value + 1.0).log10(),
+                    FieldValueModifier::Log2p => (value + 2.0).log10(),
+                    FieldValueModifier::Ln => value.ln(),
+                    FieldValueModifier::Ln1p => value.ln_1p(),
+                    FieldValueModifier::Ln2p => (value + 2.0).ln(),
+                    FieldValueModifier::Square => value * value,
+                    FieldValueModifier::Sqrt => value.sqrt(),
+                    FieldValueModifier::Reciprocal => 1.0 / value,
+                }
+            }
+            FunctionKind::Decay {
+                shape,
+                origin,
+                scale,
+                offset,
+                decay,
+            } => {
+                let Some(value) = value else {
+                    return 1.0;
+                };
+                let distance = ((value - origin).abs() - offset).max(0.0) / scale;
+                match shape {
+                    DecayShape::Gauss => decay.powf(distance * distance),
+                    DecayShape::Exp => decay.powf(distance),
+                    DecayShape::Linear => (1.0 - distance * (1.0 - decay)).max(0.0),
+                }
+            }
+        };
+
+        // a modifier applied outside of its domain can't say anything useful about the doc
+        if result.is_finite() {
+            result
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FunctionScoreQuery {
+    query: Box<dyn Query>,
+    functions: Vec<ResolvedFunction>,
+    score_mode: FunctionScoreMode,
+    boost_mode: FunctionBoostMode,
+}
+
+impl Clone for FunctionScoreQuery {
+    fn clone(&self) -> Self {
+        Self {
+            query: self.query.box_clone(),
+            functions: self.functions.clone(),
+            score_mode: self.score_mode,
+            boost_mode: self.boost_mode,
+        }
+    }
+}
+
+impl FunctionScoreQuery {
+    pub fn new(
+        query: Box<dyn Query>,
+        functions: Vec<ResolvedFunction>,
+        score_mode: FunctionScoreMode,
+        boost_mode: FunctionBoostMode,
+    ) -> Self {
+        Self {
+            query,
+            functions,
+            score_mode,
+            boost_mode,
+        }
+    }
+}
+
+impl Query for FunctionScoreQuery {
+    fn weight(&self, enable_scoring: EnableScoring<'_>) -> tantivy::Result<Box<dyn Weight>> {
+        let weight = self.query.weight(enable_scoring)?;
+        if !enable_scoring.is_scoring_enabled() {
+            // the functions only change the scores, not which docs match
+            return Ok(weight);
+        }
+
+        Ok(Box::new(FunctionScoreWeight {
+            weight,
+            functions: self.functions.clone(),
+            score_mode: self.score_mode,
+            boost_mode: self.boost_mode,
+        }))
+    }
+
+    fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
+        self.query.query_terms(visitor)
+    }
+}
+
+struct FunctionScoreWeight {
+    weight: Box<dyn Weight>,
+    functions: Vec<ResolvedFunction>,
+    score_mode: FunctionScoreMode,
+    boost_mode: FunctionBoostMode,
+}
+
+impl Weight for FunctionScoreWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        let columns = self
+            .functions
+            .iter()
+            .map(|function| NumericColumn::open(reader.fast_fields(), &function.field))
+            .collect::<tantivy::Result<Vec<_>>>()?;
+
+        Ok(Box::new(FunctionScorer {
+            scorer: self.weight.scorer(reader, boost)?,
+            functions: self.functions.clone(),
+            columns,
+            score_mode: self.score_mode,
+            boost_mode: self.boost_mode,
+        }))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+        if scorer.seek(doc) != doc {
+            return Err(tantivy::TantivyError::InvalidArgument(format!(
+                "Document #({doc}) does not match"
+            )));
+        }
+        let mut explanation = Explanation::new("FunctionScore", scorer.score());
+        explanation.add_detail(self.weight.explain(reader, doc)?);
+        Ok(explanation)
+    }
+}
+
+struct FunctionScorer {
+    scorer: Box<dyn Scorer>,
+    functions: Vec<ResolvedFunction>,
+    columns: Vec<NumericColumn>,
+    score_mode: FunctionScoreMode,
+    boost_mode: FunctionBoostMode,
+}
+
+impl DocSet for FunctionScorer {
+    fn advance(&mut self) -> DocId {
+        self.scorer.advance()
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        self.scorer.seek(target)
+    }
+
+    fn doc(&self) -> DocId {
+        self.scorer.doc()
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.scorer.size_hint()
+    }
+}
+
+impl Scorer for FunctionScorer {
+    fn score(&mut self) -> Score {
+        let doc = self.scorer.doc();
+        let value = self.score_mode.combine(
+            self.functions
+                .iter()
+                .zip(&self.columns)
+                .map(|(function, column)| function.evaluate(column.value(doc))),
+        );
+        self.boost_mode.combine(self.scorer.score() as f64, value) as Score
+    }
+}
+
+/// A numeric or date fast field, read as an `f64`, with dates in seconds
+enum NumericColumn {
+    I64(Column<i64>),
+    U64(Column<u64>),
+    F64(Column<f64>),
+    Date(Column<tantivy::DateTime>),
+}
+
+impl NumericColumn {
+    fn open(ffr: &FastFieldReaders, field_name: &str) -> tantivy::Result<Self> {
+        if let Ok(column) = ffr.u64(field_name) {
+            Ok(Self::U64(column))
+        } else if let Ok(column) = ffr.i64(field_name) {
+            Ok(Self::I64(column))
+        } else if let Ok(column) = ffr.f64(field_name) {
+            Ok(Self::F64(column))
+        } else if let Ok(column) = ffr.date(field_name) {
+            Ok(Self::Date(column))
+        } else {
+            Err(tantivy::TantivyError::SchemaError(format!(
+                "`{field_name}` is missing or is not configured as a numeric or date fast field"
+            )))
+        }
+    }
+
+    #[inline(always)]
+    fn value(&self, doc: DocId) -> Option<f64> {
+        match self {
+            NumericColumn::I64(column) => column.first(doc).map(|value| value as f64),
+            NumericColumn::U64(column) => column.first(doc).map(|value| value as f64),
+            NumericColumn::F64(column) => column.first(doc),
+            NumericColumn::Date(column) => column
+                .first(doc)
+                .map(|value| value.into_timestamp_micros() as f64 / 1_000_000.0),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// How many of a boolean query's `should` clauses have to match, either as a count like `3`, or
+/// as a percentage of the clauses like `75%`, which is rounded down.  A negative value is how
+/// many of the clauses don't have to match instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MinimumShouldMatch {
+    Count(i64),
+    Percentage(f64),
+}
+
+impl MinimumShouldMatch {
+    /// The number of the `should` clauses that have to match
+    pub fn required(&self, should: usize) -> usize {
+        let should = should as i64;
+        let required = match *self {
+            MinimumShouldMatch::Count(count) if count < 0 => should + count,
+            MinimumShouldMatch::Count(count) => count,
+            MinimumShouldMatch::Percentage(percent) => {
+                let clauses = (should as f64 * percent.abs() / 100.0).floor() as i64;
+                if percent < 0.0 {
+                    should - clauses
+                } else {
+                    clauses
+                }
+            }
+        };
+        required.clamp(0, should) as usize
+    }
+}
+
+impl Display for MinimumShouldMatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MinimumShouldMatch::Count(count) => write!(f, "{count}"),
+            MinimumShouldMatch::Percentage(percent) => write!(f, "{percent}%"),
+        }
+    }
+}
+
+impl FromStr for MinimumShouldMatch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        let parsed = match s.strip_suffix('%') {
+            Some(percent) => percent
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|percent| percent.is_finite() && percent.abs() <= 100.0)
+                .map(MinimumShouldMatch::Percentage),
+            None => s.parse::<i64>().ok().map(MinimumShouldMatch::Count),
+        };
+        parsed.ok_or_else(|| {
+            anyhow::anyhow!(
+                "invalid minimum_should_match '{s}', which must be a count like '3' or a percentage like '75%'"
+            )
+        })
+    }
+}
+
+impl Serialize for MinimumShouldMatch {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            MinimumShouldMatch::Count(count) => serializer.serialize_i64(*count),
+            percentage => serializer.serialize_str(&percentage.to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MinimumShouldMatch {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Count(i64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Count(count) => Ok(MinimumShouldMatch::Count(count)),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
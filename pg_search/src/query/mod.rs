@@ -15,17 +15,39 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+mod function_score;
+mod minimum_should_match;
+mod nested;
 mod range;
-
+mod rescore;
+mod search_after;
+mod similarity;
+mod span;
+mod synonyms;
+mod wildcard;
+
+use crate::index::reader::{SearchIndexReader, SortKey};
 use crate::postgres::utils::convert_pg_date_string;
 use crate::query::range::{Comparison, RangeField};
-use crate::schema::IndexRecordOption;
+use crate::schema::{IndexRecordOption, SearchSimilarity};
 use anyhow::Result;
 use core::panic;
+use function_score::FunctionScoreQuery;
+pub use function_score::{
+    DecayFunction, FieldValueModifier, FunctionBoostMode, FunctionScoreMode, ScoreFunction,
+};
+pub use minimum_should_match::MinimumShouldMatch;
+use nested::NestedQuery;
+pub use nested::{exclude_nested_children, NestedScoreMode};
 use pgrx::{pg_sys, PgBuiltInOids, PgOid, PostgresType};
 use range::{deserialize_bound, serialize_bound};
+pub use rescore::{RescoreQuery, RescoreWindow};
+use search_after::{CursorKey, SearchAfterQuery};
 use serde::{Deserialize, Serialize};
+use similarity::apply_similarity;
+use span::{Span, SpanQuery};
 use std::{collections::HashMap, ops::Bound};
+use synonyms::Synonyms;
 use tantivy::DateTime;
 use tantivy::{
     collector::DocSetCollector,
@@ -37,8 +59,8 @@ use tantivy::{
         TermSetQuery,
     },
     query_grammar::Occur,
-    schema::{Field, FieldType, OwnedValue, DATE_TIME_PRECISION_INDEXED},
-    Searcher, Term,
+    schema::{Facet, Field, FieldType, OwnedValue, DATE_TIME_PRECISION_INDEXED},
+    Term,
 };
 use thiserror::Error;
 
@@ -58,6 +80,10 @@ pub enum SearchQueryInput {
         #[serde(default)]
         #[serde(skip_serializing_if = "Vec::is_empty")]
         must_not: Vec<SearchQueryInput>,
+
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        minimum_should_match: Option<MinimumShouldMatch>,
     },
     Boost {
         query: Box<SearchQueryInput>,
@@ -76,6 +102,10 @@ pub enum SearchQueryInput {
     Exists {
         field: String,
     },
+    Facet {
+        field: String,
+        path: String,
+    },
     FastFieldRangeWeight {
         field: String,
         #[serde(
@@ -89,6 +119,14 @@ pub enum SearchQueryInput {
         )]
         upper_bound: std::ops::Bound<u64>,
     },
+    FunctionScore {
+        query: Box<SearchQueryInput>,
+        functions: Vec<ScoreFunction>,
+        #[serde(default)]
+        score_mode: FunctionScoreMode,
+        #[serde(default)]
+        boost_mode: FunctionBoostMode,
+    },
     FuzzyTerm {
         field: String,
         value: String,
@@ -116,16 +154,24 @@ pub enum SearchQueryInput {
         document_fields: Option<Vec<(String, tantivy::schema::OwnedValue)>>,
         document_id: Option<tantivy::schema::OwnedValue>,
     },
+    Nested {
+        path: String,
+        query: Box<SearchQueryInput>,
+        #[serde(default)]
+        score_mode: NestedScoreMode,
+    },
     Parse {
         query_string: String,
         lenient: Option<bool>,
         conjunction_mode: Option<bool>,
+        minimum_should_match: Option<MinimumShouldMatch>,
     },
     ParseWithField {
         field: String,
         query_string: String,
         lenient: Option<bool>,
         conjunction_mode: Option<bool>,
+        minimum_should_match: Option<MinimumShouldMatch>,
     },
     Phrase {
         field: String,
@@ -213,6 +259,44 @@ pub enum SearchQueryInput {
         slop: Option<u32>,
         max_expansions: Option<u32>,
     },
+    Rescore {
+        query: Box<SearchQueryInput>,
+        rescore_query: Box<SearchQueryInput>,
+        #[serde(default = "rescore::default_window_size")]
+        window_size: u32,
+        #[serde(default = "rescore::default_weight")]
+        query_weight: f32,
+        #[serde(default = "rescore::default_weight")]
+        rescore_query_weight: f32,
+    },
+    SearchAfter {
+        query: Box<SearchQueryInput>,
+        sort_keys: Vec<SortKey>,
+        values: Vec<tantivy::schema::OwnedValue>,
+        ctid: u64,
+    },
+    SpanNear {
+        clauses: Vec<SearchQueryInput>,
+        #[serde(default)]
+        slop: u32,
+        #[serde(default)]
+        in_order: bool,
+    },
+    SpanNot {
+        include: Box<SearchQueryInput>,
+        exclude: Box<SearchQueryInput>,
+        #[serde(default)]
+        pre: u32,
+        #[serde(default)]
+        post: u32,
+    },
+    SpanOr {
+        clauses: Vec<SearchQueryInput>,
+    },
+    SpanTerm {
+        field: String,
+        value: String,
+    },
     Term {
         field: Option<String>,
         value: tantivy::schema::OwnedValue,
@@ -222,6 +306,12 @@ pub enum SearchQueryInput {
     TermSet {
         terms: Vec<TermInput>,
     },
+    Wildcard {
+        field: String,
+        pattern: String,
+        #[serde(default)]
+        case_insensitive: bool,
+    },
     WithIndex {
         oid: pg_sys::Oid,
         query: Box<SearchQueryInput>,
@@ -235,6 +325,7 @@ impl SearchQueryInput {
                 must,
                 should,
                 must_not,
+                ..
             } => must
                 .iter()
                 .chain(should.iter())
@@ -242,14 +333,39 @@ impl SearchQueryInput {
                 .any(Self::contains_more_like_this),
             SearchQueryInput::Boost { query, .. } => Self::contains_more_like_this(query),
             SearchQueryInput::ConstScore { query, .. } => Self::contains_more_like_this(query),
+            SearchQueryInput::FunctionScore { query, .. } => Self::contains_more_like_this(query),
             SearchQueryInput::DisjunctionMax { disjuncts, .. } => {
                 disjuncts.iter().any(Self::contains_more_like_this)
             }
+            SearchQueryInput::Rescore {
+                query,
+                rescore_query,
+                ..
+            } => {
+                Self::contains_more_like_this(query) || Self::contains_more_like_this(rescore_query)
+            }
+            SearchQueryInput::SearchAfter { query, .. } => Self::contains_more_like_this(query),
             SearchQueryInput::WithIndex { query, .. } => Self::contains_more_like_this(query),
+            SearchQueryInput::Nested { query, .. } => Self::contains_more_like_this(query),
             SearchQueryInput::MoreLikeThis { .. } => true,
             _ => false,
         }
     }
+
+    /// The rescore query, window size and rescore query weight of this query, if it's a
+    /// [`SearchQueryInput::Rescore`]
+    pub fn rescore(&self) -> Option<(&SearchQueryInput, usize, f32)> {
+        match self {
+            SearchQueryInput::WithIndex { query, .. } => query.rescore(),
+            SearchQueryInput::Rescore {
+                rescore_query,
+                window_size,
+                rescore_query_weight,
+                ..
+            } => Some((rescore_query, *window_size as usize, *rescore_query_weight)),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -287,6 +403,15 @@ pub trait AsFieldType<T> {
 
     fn as_field_type(&self, from: &T) -> Option<(FieldType, PgOid, Field)>;
 
+    /// The name of the synonym set that queries against the text field `field` are expanded with
+    fn synonym_set(&self, field: Field) -> Option<String>;
+
+    /// How matches in the field `field` are scored
+    fn similarity(&self, field: Field) -> SearchSimilarity;
+
+    /// Does the tokenizer of the text or JSON field `field` lowercase what it indexes?
+    fn lowercases(&self, field: Field) -> bool;
+
     fn is_field_type(&self, from: &T, value: &OwnedValue) -> bool {
         matches!(
             (self.as_field_type(from), value),
@@ -493,46 +618,89 @@ fn check_range_bounds(
 }
 
 impl SearchQueryInput {
+    /// Build the Tantivy query `self` describes.  The leaf queries score with their field's
+    /// similarity, which is applied here as each of them is built, since the compound queries
+    /// that are built out of them can't be looked inside.
     pub fn into_tantivy_query(
         self,
         field_lookup: &impl AsFieldType<String>,
         parser: &mut QueryParser,
-        searcher: &Searcher,
+        reader: &SearchIndexReader,
     ) -> Result<Box<dyn Query>, Box<dyn std::error::Error>> {
+        // a query string's leaves are rewritten as it's parsed
+        let is_compound = matches!(
+            self,
+            Self::Boolean { .. }
+                | Self::Boost { .. }
+                | Self::ConstScore { .. }
+                | Self::DisjunctionMax { .. }
+                | Self::FunctionScore { .. }
+                | Self::Nested { .. }
+                | Self::Parse { .. }
+                | Self::ParseWithField { .. }
+                | Self::Rescore { .. }
+                | Self::SearchAfter { .. }
+                | Self::WithIndex { .. }
+        );
+        let query = self.build_tantivy_query(field_lookup, parser, reader)?;
+        match is_compound {
+            true => Ok(query),
+            false => Ok(apply_similarity(
+                query,
+                field_lookup,
+                &reader.global_statistics,
+            )),
+        }
+    }
+
+    fn build_tantivy_query(
+        self,
+        field_lookup: &impl AsFieldType<String>,
+        parser: &mut QueryParser,
+        reader: &SearchIndexReader,
+    ) -> Result<Box<dyn Query>, Box<dyn std::error::Error>> {
+        let searcher = &reader.searcher;
         match self {
             Self::All => Ok(Box::new(AllQuery)),
             Self::Boolean {
                 must,
                 should,
                 must_not,
+                minimum_should_match,
             } => {
+                let required = minimum_should_match.map(|minimum| minimum.required(should.len()));
                 let mut subqueries = vec![];
                 for input in must {
                     subqueries.push((
                         Occur::Must,
-                        input.into_tantivy_query(field_lookup, parser, searcher)?,
+                        input.into_tantivy_query(field_lookup, parser, reader)?,
                     ));
                 }
                 for input in should {
                     subqueries.push((
                         Occur::Should,
-                        input.into_tantivy_query(field_lookup, parser, searcher)?,
+                        input.into_tantivy_query(field_lookup, parser, reader)?,
                     ));
                 }
                 for input in must_not {
                     subqueries.push((
                         Occur::MustNot,
-                        input.into_tantivy_query(field_lookup, parser, searcher)?,
+                        input.into_tantivy_query(field_lookup, parser, reader)?,
                     ));
                 }
-                Ok(Box::new(BooleanQuery::new(subqueries)))
+                match required {
+                    Some(required) => Ok(Box::new(BooleanQuery::with_minimum_required_clauses(
+                        subqueries, required,
+                    ))),
+                    None => Ok(Box::new(BooleanQuery::new(subqueries))),
+                }
             }
             Self::Boost { query, factor } => Ok(Box::new(BoostQuery::new(
-                query.into_tantivy_query(field_lookup, parser, searcher)?,
+                query.into_tantivy_query(field_lookup, parser, reader)?,
                 factor,
             ))),
             Self::ConstScore { query, score } => Ok(Box::new(ConstScoreQuery::new(
-                query.into_tantivy_query(field_lookup, parser, searcher)?,
+                query.into_tantivy_query(field_lookup, parser, reader)?,
                 score,
             ))),
             Self::DisjunctionMax {
@@ -541,7 +709,7 @@ impl SearchQueryInput {
             } => {
                 let disjuncts = disjuncts
                     .into_iter()
-                    .map(|query| query.into_tantivy_query(field_lookup, parser, searcher))
+                    .map(|query| query.into_tantivy_query(field_lookup, parser, reader))
                     .collect::<Result<_, _>>()?;
                 if let Some(tie_breaker) = tie_breaker {
                     Ok(Box::new(DisjunctionMaxQuery::with_tie_breaker(
@@ -554,6 +722,20 @@ impl SearchQueryInput {
             }
             Self::Empty => Ok(Box::new(EmptyQuery)),
             Self::Exists { field } => Ok(Box::new(ExistsQuery::new_exists_query(field))),
+            Self::Facet { field, path } => {
+                let field = field_lookup
+                    .as_facet(&field)
+                    .ok_or_else(|| QueryError::WrongFieldType(field.clone()))?;
+                let facet = Facet::from_text(&path)
+                    .map_err(|_| QueryError::InvalidFacetPath(path.clone()))?;
+
+                // every ancestor of a document's facet is indexed alongside it, so this also
+                // matches all the documents filed anywhere underneath `path`
+                Ok(Box::new(TermQuery::new(
+                    Term::from_facet(field, &facet),
+                    IndexRecordOption::Basic.into(),
+                )))
+            }
             Self::FastFieldRangeWeight {
                 field,
                 lower_bound,
@@ -581,6 +763,23 @@ impl SearchQueryInput {
                     new_upper_bound,
                 )))
             }
+            Self::FunctionScore {
+                query,
+                functions,
+                score_mode,
+                boost_mode,
+            } => {
+                let functions = functions
+                    .into_iter()
+                    .map(|function| function.resolve(field_lookup))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Box::new(FunctionScoreQuery::new(
+                    query.into_tantivy_query(field_lookup, parser, reader)?,
+                    functions,
+                    score_mode,
+                    boost_mode,
+                )))
+            }
             Self::FuzzyTerm {
                 field,
                 value,
@@ -773,41 +972,56 @@ impl SearchQueryInput {
                 }
                 Ok(Box::new(query))
             }
+            Self::Nested {
+                path,
+                query,
+                score_mode,
+            } => {
+                let (field, _) = split_field_and_path(&path);
+                if !matches!(
+                    field_lookup.as_field_type(&field),
+                    Some((FieldType::JsonObject(_), _, _))
+                ) {
+                    return Err(Box::new(QueryError::NotANestedPath(path)));
+                }
+                Ok(Box::new(NestedQuery::new(
+                    &path,
+                    query.into_tantivy_query(field_lookup, parser, reader)?,
+                    score_mode,
+                    searcher,
+                )?))
+            }
             Self::Parse {
                 query_string,
                 lenient,
                 conjunction_mode,
-            } => {
-                if let Some(true) = conjunction_mode {
-                    parser.set_conjunction_by_default();
-                }
-
-                match lenient {
-                    Some(true) => {
-                        let (parsed_query, _) = parser.parse_query_lenient(&query_string);
-                        Ok(Box::new(parsed_query))
-                    }
-                    _ => {
-                        Ok(Box::new(parser.parse_query(&query_string).map_err(
-                            |err| QueryError::ParseError(err, query_string),
-                        )?))
-                    }
-                }
-            }
+                minimum_should_match,
+            } => parse_query_string(
+                None,
+                &query_string,
+                lenient,
+                conjunction_mode,
+                minimum_should_match,
+                field_lookup,
+                parser,
+                reader,
+            ),
             Self::ParseWithField {
                 field,
                 query_string,
                 lenient,
                 conjunction_mode,
-            } => {
-                let query_string = format!("{field}:({query_string})");
-                Self::Parse {
-                    query_string,
-                    lenient,
-                    conjunction_mode,
-                }
-                .into_tantivy_query(field_lookup, parser, searcher)
-            }
+                minimum_should_match,
+            } => parse_query_string(
+                Some(&field),
+                &query_string,
+                lenient,
+                conjunction_mode,
+                minimum_should_match,
+                field_lookup,
+                parser,
+                reader,
+            ),
             Self::Phrase {
                 field,
                 phrases,
@@ -817,6 +1031,11 @@ impl SearchQueryInput {
                 let (field_type, _, field) = field_lookup
                     .as_field_type(&field)
                     .ok_or(QueryError::NonIndexedField(field))?;
+                if matches!(field_type, FieldType::Str(_)) && path.is_none() {
+                    if let Some(synonyms) = Synonyms::load(field, field_lookup, searcher)? {
+                        return Ok(synonyms.phrase_query(field, &phrases, slop.unwrap_or(0)));
+                    }
+                }
                 let terms = phrases.clone().into_iter().map(|phrase| {
                     value_to_term(
                         field,
@@ -1538,6 +1757,14 @@ impl SearchQueryInput {
                         .as_field_type(&field)
                         .ok_or(QueryError::NonIndexedField(field))?;
 
+                    if let (FieldType::Str(_), OwnedValue::Str(text), None) =
+                        (&field_type, &value, &path)
+                    {
+                        if let Some(synonyms) = Synonyms::load(field, field_lookup, searcher)? {
+                            return Ok(synonyms.phrase_query(field, &[text.clone()], 0));
+                        }
+                    }
+
                     let is_datetime = is_datetime_typeoid(typeoid) || is_datetime;
                     let term =
                         value_to_term(field, &value, &field_type, path.as_deref(), is_datetime)?;
@@ -1583,13 +1810,146 @@ impl SearchQueryInput {
 
                 Ok(Box::new(TermSetQuery::new(terms)))
             }
-            Self::WithIndex { query, .. } => {
-                query.into_tantivy_query(field_lookup, parser, searcher)
+            Self::Rescore {
+                query,
+                rescore_query,
+                query_weight,
+                rescore_query_weight,
+                // the window is applied by the top N scan, see `SearchQueryInput::rescore`
+                ..
+            } => {
+                if ![query_weight, rescore_query_weight]
+                    .iter()
+                    .all(|weight| weight.is_finite() && *weight >= 0.0)
+                {
+                    return Err(Box::new(QueryError::InvalidRescoreWeights(
+                        query_weight,
+                        rescore_query_weight,
+                    )));
+                }
+                // nested children mustn't take up the window
+                Ok(Box::new(RescoreQuery::new(
+                    exclude_nested_children(
+                        query.into_tantivy_query(field_lookup, parser, reader)?,
+                        searcher.schema(),
+                    ),
+                    rescore_query.into_tantivy_query(field_lookup, parser, reader)?,
+                    query_weight,
+                )))
             }
+            Self::SearchAfter {
+                query,
+                sort_keys,
+                values,
+                ctid,
+            } => {
+                if sort_keys.len() != values.len() {
+                    return Err(Box::new(QueryError::SearchAfterMismatch(
+                        sort_keys.len(),
+                        values.len(),
+                    )));
+                }
+                let keys = sort_keys
+                    .into_iter()
+                    .zip(values)
+                    .map(|(key, value)| CursorKey::new(field_lookup, key, value))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Box::new(SearchAfterQuery::new(
+                    query.into_tantivy_query(field_lookup, parser, reader)?,
+                    keys,
+                    ctid,
+                )))
+            }
+            span @ (Self::SpanNear { .. }
+            | Self::SpanNot { .. }
+            | Self::SpanOr { .. }
+            | Self::SpanTerm { .. }) => Ok(Box::new(SpanQuery::new(Span::from_input(
+                span,
+                field_lookup,
+            )?))),
+            Self::Wildcard {
+                field,
+                pattern,
+                case_insensitive,
+            } => Ok(wildcard::wildcard_query(
+                &field,
+                &pattern,
+                case_insensitive,
+                field_lookup,
+            )?),
+            Self::WithIndex { query, .. } => query.into_tantivy_query(field_lookup, parser, reader),
         }
     }
 }
 
+/// Parse `query_string`, against `default_field` if there is one, expanding the synonyms and
+/// wildcards the query parser doesn't know about
+#[allow(clippy::too_many_arguments)]
+fn parse_query_string(
+    default_field: Option<&str>,
+    query_string: &str,
+    lenient: Option<bool>,
+    conjunction_mode: Option<bool>,
+    minimum_should_match: Option<MinimumShouldMatch>,
+    field_lookup: &impl AsFieldType<String>,
+    parser: &mut QueryParser,
+    reader: &SearchIndexReader,
+) -> Result<Box<dyn Query>, Box<dyn std::error::Error>> {
+    if let Some(true) = conjunction_mode {
+        parser.set_conjunction_by_default();
+    }
+
+    let synonyms = synonyms::load_all(field_lookup, &reader.searcher)?;
+    let parsed_query = wildcard::parse_query_string(
+        query_string,
+        default_field,
+        lenient == Some(true),
+        conjunction_mode == Some(true),
+        parser,
+        field_lookup,
+        |query| {
+            apply_similarity(
+                synonyms::expand_query(query, &synonyms),
+                field_lookup,
+                &reader.global_statistics,
+            )
+        },
+    )?;
+    match minimum_should_match {
+        Some(minimum) => Ok(require_should_clauses(parsed_query, minimum)),
+        None => Ok(parsed_query),
+    }
+}
+
+/// Require some of the `should` clauses of the outermost boolean query the query parser built,
+/// looking through the single-clause booleans that wrap groups like `field:(a b c)`
+fn require_should_clauses(
+    query: Box<dyn Query>,
+    minimum_should_match: MinimumShouldMatch,
+) -> Box<dyn Query> {
+    let Some(boolean) = query.downcast_ref::<BooleanQuery>() else {
+        return query;
+    };
+    let clauses = boolean.clauses();
+    if let [(occur, inner)] = clauses {
+        if *occur != Occur::MustNot && inner.downcast_ref::<BooleanQuery>().is_some() {
+            return require_should_clauses(inner.box_clone(), minimum_should_match);
+        }
+    }
+
+    let should = clauses
+        .iter()
+        .filter(|(occur, _)| *occur == Occur::Should)
+        .count();
+    Box::new(BooleanQuery::with_minimum_required_clauses(
+        clauses
+            .iter()
+            .map(|(occur, query)| (*occur, query.box_clone()))
+            .collect(),
+        minimum_should_match.required(should),
+    ))
+}
+
 fn value_to_json_term(
     field: Field,
     value: &OwnedValue,
@@ -1735,6 +2095,30 @@ enum QueryError {
     NonIndexedField(String),
     #[error("wrong type given for field")]
     FieldTypeMismatch,
+    #[error("'{0}' is not a valid facet path, which must start with '/'")]
+    InvalidFacetPath(String),
+    #[error("search_after has {0} sort keys but {1} values")]
+    SearchAfterMismatch(usize, usize),
+    #[error("span queries can only combine other span queries")]
+    NotASpanQuery,
+    #[error("all the terms of a span query must be in the same field")]
+    SpanFieldMismatch,
+    #[error("field '{0}' must be indexed with positions to be used in a span query")]
+    NoPositions(String),
+    #[error("span queries need at least one clause")]
+    EmptySpanClauses,
+    #[error("an unordered span_near can have at most {0} clauses")]
+    TooManyUnorderedClauses(usize),
+    #[error("field '{0}' must be a numeric or date fast field to be used in a score function")]
+    NotAScoreField(String),
+    #[error("invalid score function for field {0}")]
+    InvalidScoreFunction(String),
+    #[error("'{0}' is not the path of an array in a nested JSON field")]
+    NotANestedPath(String),
+    #[error("rescore weights must be finite and not negative, but got {0} and {1}")]
+    InvalidRescoreWeights(f32, f32),
+    #[error("could not read synonym set '{0}': {1}")]
+    SynonymSet(String, #[source] pgrx::spi::Error),
     #[error("could not build regex with pattern '{1}': {0}")]
     RegexError(#[source] tantivy::TantivyError, String),
     #[error(
@@ -0,0 +1,250 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Queries over the objects in the arrays of nested JSON fields.  Each object is indexed as a
+//! child document in a block right before its row's document, and has the path of its array
+//! and the ctid of its row in hidden fields, while rows never have a parent ctid.
+
+use crate::query::QueryError;
+use crate::schema::{NESTED_PARENT_FIELD, NESTED_PATH_FIELD};
+use serde::{Deserialize, Serialize};
+use tantivy::columnar::Column;
+use tantivy::query::{
+    BooleanQuery, ConstScoreQuery, EnableScoring, ExistsQuery, Explanation, Query, Scorer,
+    TermQuery, Weight,
+};
+use tantivy::query_grammar::Occur;
+use tantivy::schema::{IndexRecordOption, Schema};
+use tantivy::{DocId, DocSet, Score, Searcher, SegmentReader, Term, TERMINATED};
+
+/// How the scores of the matching children of a row are combined into the row's score
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NestedScoreMode {
+    #[default]
+    Avg,
+    Max,
+    Min,
+    Sum,
+    None,
+}
+
+/// Stop `query` from matching any nested child documents, which is needed wherever a query's
+/// matches are taken to be rows
+pub fn exclude_nested_children(query: Box<dyn Query>, schema: &Schema) -> Box<dyn Query> {
+    if schema.get_field(NESTED_PARENT_FIELD).is_err() {
+        // the index has no nested fields, so there are no children
+        return query;
+    }
+
+    Box::new(BooleanQuery::new(vec![
+        (Occur::Must, query),
+        (
+            Occur::MustNot,
+            Box::new(ExistsQuery::new_exists_query(
+                NESTED_PARENT_FIELD.to_string(),
+            )),
+        ),
+    ]))
+}
+
+/// Matches the rows with a child at `path` that matches `query`, so that all of the query's
+/// conditions have to be met by the same object
+#[derive(Debug)]
+pub struct NestedQuery {
+    child_query: Box<dyn Query>,
+    score_mode: NestedScoreMode,
+}
+
+impl Clone for NestedQuery {
+    fn clone(&self) -> Self {
+        Self {
+            child_query: self.child_query.box_clone(),
+            score_mode: self.score_mode,
+        }
+    }
+}
+
+impl NestedQuery {
+    pub fn new(
+        path: &str,
+        query: Box<dyn Query>,
+        score_mode: NestedScoreMode,
+        searcher: &Searcher,
+    ) -> Result<Self, QueryError> {
+        let path_field = searcher
+            .schema()
+            .get_field(NESTED_PATH_FIELD)
+            .map_err(|_| QueryError::NotANestedPath(path.to_string()))?;
+
+        // the path only adds a filter, not to the children's scores
+        let path_query = TermQuery::new(
+            Term::from_field_text(path_field, path),
+            IndexRecordOption::Basic,
+        );
+        Ok(Self {
+            child_query: Box::new(BooleanQuery::new(vec![
+                (Occur::Must, query),
+                (
+                    Occur::Must,
+                    Box::new(ConstScoreQuery::new(Box::new(path_query), 0.0)),
+                ),
+            ])),
+            score_mode,
+        })
+    }
+}
+
+impl Query for NestedQuery {
+    fn weight(&self, enable_scoring: EnableScoring<'_>) -> tantivy::Result<Box<dyn Weight>> {
+        Ok(Box::new(NestedWeight {
+            child_weight: self.child_query.weight(enable_scoring)?,
+            score_mode: self.score_mode,
+        }))
+    }
+
+    fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
+        self.child_query.query_terms(visitor)
+    }
+}
+
+struct NestedWeight {
+    child_weight: Box<dyn Weight>,
+    score_mode: NestedScoreMode,
+}
+
+impl Weight for NestedWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        let parent_column = reader.fast_fields().u64(NESTED_PARENT_FIELD)?;
+        let mut child_scorer = self.child_weight.scorer(reader, boost)?;
+
+        // the children come in doc order, so their parents do too
+        let mut parents: Vec<ParentScore> = vec![];
+        let mut child = child_scorer.doc();
+        while child != TERMINATED {
+            let score = child_scorer.score();
+            match parents.last_mut() {
+                Some(parent) if parent.doc > child => parent.add(score),
+                _ => {
+                    if let Some(doc) = parent_of(&parent_column, child, reader.max_doc()) {
+                        parents.push(ParentScore::new(doc, score));
+                    }
+                }
+            }
+            child = child_scorer.advance();
+        }
+
+        let docs = parents
+            .into_iter()
+            .map(|parent| (parent.doc, parent.score(self.score_mode)))
+            .collect::<Vec<_>>();
+        Ok(Box::new(NestedScorer { docs, idx: 0 }))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+        if scorer.seek(doc) != doc {
+            return Err(tantivy::TantivyError::InvalidArgument(format!(
+                "Document #({doc}) does not match"
+            )));
+        }
+        Ok(Explanation::new(
+            format!("Nested, score_mode={:?}", self.score_mode),
+            scorer.score(),
+        ))
+    }
+}
+
+/// The row a child belongs to, which is the first doc after it without a parent ctid
+fn parent_of(parent_column: &Column<u64>, child: DocId, max_doc: DocId) -> Option<DocId> {
+    (child + 1..max_doc).find(|doc| parent_column.first(*doc).is_none())
+}
+
+struct ParentScore {
+    doc: DocId,
+    sum: Score,
+    min: Score,
+    max: Score,
+    count: u32,
+}
+
+impl ParentScore {
+    fn new(doc: DocId, score: Score) -> Self {
+        Self {
+            doc,
+            sum: score,
+            min: score,
+            max: score,
+            count: 1,
+        }
+    }
+
+    fn add(&mut self, score: Score) {
+        self.sum += score;
+        self.min = self.min.min(score);
+        self.max = self.max.max(score);
+        self.count += 1;
+    }
+
+    fn score(&self, score_mode: NestedScoreMode) -> Score {
+        match score_mode {
+            NestedScoreMode::Avg => self.sum / self.count as Score,
+            NestedScoreMode::Max => self.max,
+            NestedScoreMode::Min => self.min,
+            NestedScoreMode::Sum => self.sum,
+            NestedScoreMode::None => 0.0,
+        }
+    }
+}
+
+struct NestedScorer {
+    docs: Vec<(DocId, Score)>,
+    idx: usize,
+}
+
+impl DocSet for NestedScorer {
+    fn advance(&mut self) -> DocId {
+        self.idx += 1;
+        self.doc()
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        self.idx +=
+            self.docs[self.idx.min(self.docs.len())..].partition_point(|(doc, _)| *doc < target);
+        self.doc()
+    }
+
+    fn doc(&self) -> DocId {
+        self.docs
+            .get(self.idx)
+            .map(|(doc, _)| *doc)
+            .unwrap_or(TERMINATED)
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.docs.len().saturating_sub(self.idx) as u32
+    }
+}
+
+impl Scorer for NestedScorer {
+    fn score(&mut self) -> Score {
+        self.docs
+            .get(self.idx)
+            .map(|(_, score)| *score)
+            .unwrap_or(0.0)
+    }
+}
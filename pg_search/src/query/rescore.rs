@@ -0,0 +1,93 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Rescoring the top docs of one query with another, which is usually too expensive to run
+//! over every match.
+
+use tantivy::query::{BoostQuery, EnableScoring, Query, Weight};
+use tantivy::{Score, Term};
+
+pub fn default_window_size() -> u32 {
+    10
+}
+
+pub fn default_weight() -> f32 {
+    1.0
+}
+
+/// Matches the same docs as `query`, scored `query_weight * score`.
+///
+/// The second pass happens in the top N scan, once it knows which docs are visible, with the
+/// [`RescoreWindow`] of the same [`crate::query::SearchQueryInput::Rescore`].  Anywhere else, only
+/// the first pass applies.
+#[derive(Debug)]
+pub struct RescoreQuery {
+    query: Box<dyn Query>,
+    rescore_query: Box<dyn Query>,
+    query_weight: Score,
+}
+
+impl Clone for RescoreQuery {
+    fn clone(&self) -> Self {
+        Self {
+            query: self.query.box_clone(),
+            rescore_query: self.rescore_query.box_clone(),
+            query_weight: self.query_weight,
+        }
+    }
+}
+
+impl RescoreQuery {
+    pub fn new(query: Box<dyn Query>, rescore_query: Box<dyn Query>, query_weight: Score) -> Self {
+        Self {
+            query,
+            rescore_query,
+            query_weight,
+        }
+    }
+}
+
+/// The second pass of a rescore: the top `window_size` visible docs of the first pass get
+/// `weight * rescore` added, where `rescore` is their score for `query`, or 0 if they don't
+/// match it.
+#[derive(Debug)]
+pub struct RescoreWindow {
+    pub query: Box<dyn Query>,
+    pub window_size: usize,
+    pub weight: Score,
+}
+
+impl Clone for RescoreWindow {
+    fn clone(&self) -> Self {
+        Self {
+            query: self.query.box_clone(),
+            window_size: self.window_size,
+            weight: self.weight,
+        }
+    }
+}
+
+impl Query for RescoreQuery {
+    fn weight(&self, enable_scoring: EnableScoring<'_>) -> tantivy::Result<Box<dyn Weight>> {
+        BoostQuery::new(self.query.box_clone(), self.query_weight).weight(enable_scoring)
+    }
+
+    fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
+        self.query.query_terms(visitor);
+        self.rescore_query.query_terms(visitor);
+    }
+}
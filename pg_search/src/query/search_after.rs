@@ -0,0 +1,398 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::index::reader::{SortDirection, SortKey};
+use crate::query::{AsFieldType, QueryError};
+use std::cmp::Ordering;
+use tantivy::columnar::{MonotonicallyMappableToU64, StrColumn};
+use tantivy::fastfield::Column;
+use tantivy::query::{EnableScoring, Explanation, Query, Scorer, Weight};
+use tantivy::schema::{FieldType, OwnedValue};
+use tantivy::{DocId, DocSet, Score, SegmentReader, Term, TERMINATED};
+
+/// Where the cursor sits along one sort key.  Numeric, boolean, and date values are kept in the
+/// same order-preserving u64 form that their fast field columns are read in.
+#[derive(Clone, Debug)]
+enum CursorValue {
+    Score(Score),
+    U64(u64),
+    Str(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct CursorKey {
+    key: SortKey,
+    is_text: bool,
+    value: Option<CursorValue>,
+}
+
+impl CursorKey {
+    /// Convert the `value` a document had for `key` into a cursor position, according to the type
+    /// of the key's field in the index
+    pub(super) fn new(
+        field_lookup: &impl AsFieldType<String>,
+        key: SortKey,
+        value: OwnedValue,
+    ) -> Result<Self, QueryError> {
+        let field_type = match &key.field {
+            None => None,
+            Some(field) => Some(
+                field_lookup
+                    .as_field_type(field)
+                    .ok_or_else(|| QueryError::NonIndexedField(field.clone()))?
+                    .0,
+            ),
+        };
+        let is_text = matches!(field_type, Some(FieldType::Str(_)));
+
+        let value = match (field_type, value) {
+            (_, OwnedValue::Null) => None,
+            (None, value) => Some(CursorValue::Score(as_f64(&value)? as Score)),
+            (Some(FieldType::Str(options)), OwnedValue::Str(text)) => {
+                // fast text fields hold their normalized value
+                match options.get_fast_field_tokenizer_name() {
+                    Some("lowercase") => Some(CursorValue::Str(text.to_lowercase())),
+                    _ => Some(CursorValue::Str(text)),
+                }
+            }
+            (Some(FieldType::I64(_)), value) => Some(CursorValue::U64(as_i64(&value)?.to_u64())),
+            (Some(FieldType::U64(_)), value) => Some(CursorValue::U64(as_u64(&value)?)),
+            (Some(FieldType::F64(_)), value) => Some(CursorValue::U64(as_f64(&value)?.to_u64())),
+            (Some(FieldType::Bool(_)), OwnedValue::Bool(value)) => {
+                Some(CursorValue::U64(value.to_u64()))
+            }
+            (Some(FieldType::Date(options)), OwnedValue::Str(text)) => {
+                let date = parse_date(&text)?.truncate(options.get_precision());
+                Some(CursorValue::U64(date.to_u64()))
+            }
+            (Some(FieldType::Date(options)), OwnedValue::Date(date)) => Some(CursorValue::U64(
+                date.truncate(options.get_precision()).to_u64(),
+            )),
+            _ => return Err(QueryError::FieldTypeMismatch),
+        };
+
+        Ok(Self {
+            key,
+            is_text,
+            value,
+        })
+    }
+}
+
+fn as_i64(value: &OwnedValue) -> Result<i64, QueryError> {
+    match value {
+        OwnedValue::I64(value) => Ok(*value),
+        OwnedValue::U64(value) => (*value)
+            .try_into()
+            .map_err(|_| QueryError::FieldTypeMismatch),
+        _ => Err(QueryError::FieldTypeMismatch),
+    }
+}
+
+fn as_u64(value: &OwnedValue) -> Result<u64, QueryError> {
+    match value {
+        OwnedValue::U64(value) => Ok(*value),
+        OwnedValue::I64(value) => (*value)
+            .try_into()
+            .map_err(|_| QueryError::FieldTypeMismatch),
+        _ => Err(QueryError::FieldTypeMismatch),
+    }
+}
+
+fn as_f64(value: &OwnedValue) -> Result<f64, QueryError> {
+    match value {
+        OwnedValue::F64(value) => Ok(*value),
+        OwnedValue::I64(value) => Ok(*value as f64),
+        OwnedValue::U64(value) => Ok(*value as f64),
+        _ => Err(QueryError::FieldTypeMismatch),
+    }
+}
+
+/// Dates arrive as they're rendered by `to_jsonb()`:  with a time zone offset for `timestamptz`,
+/// without one for `timestamp`, and without a time at all for `date`
+fn parse_date(text: &str) -> Result<tantivy::DateTime, QueryError> {
+    let micros = if let Ok(datetime) = chrono::DateTime::parse_from_rfc3339(text) {
+        datetime.timestamp_micros()
+    } else if let Ok(datetime) = chrono::NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f")
+    {
+        datetime.and_utc().timestamp_micros()
+    } else if let Ok(date) = chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        date.and_hms_opt(0, 0, 0)
+            .expect("midnight should be a valid time")
+            .and_utc()
+            .timestamp_micros()
+    } else {
+        return Err(QueryError::FieldTypeMismatch);
+    };
+    Ok(tantivy::DateTime::from_timestamp_micros(micros))
+}
+
+/// Order a document's value for `key` against the cursor's, in the key's direction, with `cmp`
+/// doing the comparison when both are non-null
+fn key_ordering<T>(
+    key: &SortKey,
+    doc: Option<T>,
+    cursor: Option<&CursorValue>,
+    cmp: impl FnOnce(T, &CursorValue) -> Ordering,
+) -> Ordering {
+    match (doc, cursor) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) if key.nulls_first => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) if key.nulls_first => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(doc), Some(cursor)) => {
+            let ordering = cmp(doc, cursor);
+            match key.direction {
+                SortDirection::Asc => ordering,
+                SortDirection::Desc => ordering.reverse(),
+            }
+        }
+    }
+}
+
+/// Matches the documents of the wrapped query that sort strictly after a cursor, which is the
+/// sort key values and ctid of the last document already seen.
+///
+/// Documents that tie on every sort key are ordered by ctid, which is how the "top N" scan
+/// orders them too, so that paging through the results never skips or repeats a document.
+#[derive(Clone, Debug)]
+pub struct SearchAfterQuery {
+    query: Box<dyn Query>,
+    keys: Vec<CursorKey>,
+    ctid: u64,
+}
+
+impl SearchAfterQuery {
+    pub fn new(query: Box<dyn Query>, keys: Vec<CursorKey>, ctid: u64) -> Self {
+        Self { query, keys, ctid }
+    }
+}
+
+impl Query for SearchAfterQuery {
+    fn weight(&self, enable_scoring: EnableScoring<'_>) -> tantivy::Result<Box<dyn Weight>> {
+        // comparing against a score cursor needs the real scores, even if nobody else does
+        let needs_scores = self.keys.iter().any(|key| key.key.field.is_none());
+        let enable_scoring = match enable_scoring.searcher() {
+            Some(searcher) if needs_scores && !enable_scoring.is_scoring_enabled() => {
+                EnableScoring::enabled_from_searcher(searcher)
+            }
+            _ => enable_scoring,
+        };
+
+        Ok(Box::new(SearchAfterWeight {
+            weight: self.query.weight(enable_scoring)?,
+            keys: self.keys.clone(),
+            ctid: self.ctid,
+        }))
+    }
+
+    fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
+        self.query.query_terms(visitor)
+    }
+}
+
+struct SearchAfterWeight {
+    weight: Box<dyn Weight>,
+    keys: Vec<CursorKey>,
+    ctid: u64,
+}
+
+impl Weight for SearchAfterWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        let fast_fields = reader.fast_fields();
+        let columns = self
+            .keys
+            .iter()
+            .map(|cursor| {
+                Ok(match (&cursor.key.field, &cursor.value) {
+                    (None, _) => KeyColumn::Score,
+                    (Some(field), value) if cursor.is_text => {
+                        let column = fast_fields.str(field)?;
+                        let position = match (&column, value) {
+                            (Some(column), Some(CursorValue::Str(text))) => {
+                                Some(TermPosition::find(column, text)?)
+                            }
+                            _ => None,
+                        };
+                        KeyColumn::Text(column, position)
+                    }
+                    (Some(field), _) => KeyColumn::Numeric(
+                        fast_fields
+                            .u64_lenient_for_type(None, field)?
+                            .map(|(column, _)| column),
+                    ),
+                })
+            })
+            .collect::<tantivy::Result<Vec<_>>>()?;
+
+        let mut scorer = SearchAfterScorer {
+            scorer: self.weight.scorer(reader, boost)?,
+            keys: self.keys.clone(),
+            columns,
+            ctid_ff: fast_fields.u64("ctid")?,
+            ctid: self.ctid,
+        };
+        if scorer.doc() != TERMINATED && !scorer.is_after_cursor() {
+            scorer.advance();
+        }
+        Ok(Box::new(scorer))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+        if scorer.seek(doc) != doc {
+            return Err(tantivy::TantivyError::InvalidArgument(format!(
+                "Document #({doc}) does not match"
+            )));
+        }
+        self.weight.explain(reader, doc)
+    }
+}
+
+/// Where a text cursor's value falls among the terms of one segment's dictionary:  the ordinal
+/// of the first term that isn't less than it, and whether that term is the value itself
+#[derive(Clone, Copy)]
+struct TermPosition {
+    ord: u64,
+    exact: bool,
+}
+
+impl TermPosition {
+    fn find(column: &StrColumn, text: &str) -> std::io::Result<Self> {
+        let (mut lo, mut hi) = (0u64, column.num_terms() as u64);
+        let mut term = String::new();
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            term.clear();
+            column.ord_to_str(mid, &mut term)?;
+            if term.as_str() < text {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        let exact = lo < column.num_terms() as u64 && {
+            term.clear();
+            column.ord_to_str(lo, &mut term)?;
+            term == text
+        };
+        Ok(Self { ord: lo, exact })
+    }
+
+    fn cmp_ord(&self, ord: u64) -> Ordering {
+        match ord.cmp(&self.ord) {
+            Ordering::Equal if !self.exact => Ordering::Less,
+            ordering => ordering,
+        }
+    }
+}
+
+enum KeyColumn {
+    Score,
+    Numeric(Option<Column<u64>>),
+    Text(Option<StrColumn>, Option<TermPosition>),
+}
+
+struct SearchAfterScorer {
+    scorer: Box<dyn Scorer>,
+    keys: Vec<CursorKey>,
+    columns: Vec<KeyColumn>,
+    ctid_ff: Column<u64>,
+    ctid: u64,
+}
+
+impl SearchAfterScorer {
+    fn is_after_cursor(&mut self) -> bool {
+        let doc = self.scorer.doc();
+        for (cursor, column) in self.keys.iter().zip(&self.columns) {
+            let ordering = match column {
+                KeyColumn::Score => {
+                    let score = self.scorer.score();
+                    key_ordering(
+                        &cursor.key,
+                        Some(score),
+                        cursor.value.as_ref(),
+                        |score, value| match value {
+                            CursorValue::Score(cursor) => {
+                                score.partial_cmp(cursor).unwrap_or(Ordering::Equal)
+                            }
+                            _ => unreachable!("score keys should have a score cursor"),
+                        },
+                    )
+                }
+                KeyColumn::Numeric(column) => key_ordering(
+                    &cursor.key,
+                    column.as_ref().and_then(|column| column.first(doc)),
+                    cursor.value.as_ref(),
+                    |value, cursor| match cursor {
+                        CursorValue::U64(cursor) => value.cmp(cursor),
+                        _ => unreachable!("numeric keys should have a numeric cursor"),
+                    },
+                ),
+                KeyColumn::Text(column, position) => key_ordering(
+                    &cursor.key,
+                    column.as_ref().and_then(|column| column.ords().first(doc)),
+                    cursor.value.as_ref(),
+                    |ord, _| {
+                        position
+                            .expect("a segment with text values should have a cursor position")
+                            .cmp_ord(ord)
+                    },
+                ),
+            };
+
+            match ordering {
+                Ordering::Greater => return true,
+                Ordering::Less => return false,
+                Ordering::Equal => continue,
+            }
+        }
+
+        let ctid = self
+            .ctid_ff
+            .first(doc)
+            .expect("ctid should have a non-null value");
+        ctid > self.ctid
+    }
+}
+
+impl DocSet for SearchAfterScorer {
+    fn advance(&mut self) -> DocId {
+        loop {
+            let doc = self.scorer.advance();
+            if doc == TERMINATED || self.is_after_cursor() {
+                return doc;
+            }
+        }
+    }
+
+    fn doc(&self) -> DocId {
+        self.scorer.doc()
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.scorer.size_hint()
+    }
+}
+
+impl Scorer for SearchAfterScorer {
+    fn score(&mut self) -> Score {
+        self.scorer.score()
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Scoring term and phrase queries over fields that are configured with a similarity other than
+//! Tantivy's own BM25, which is built into its term and phrase scorers.
+
+use crate::index::statistics::{self, GlobalStatistics};
+use crate::query::AsFieldType;
+use crate::schema::{SearchSimilarity, TermStatistics};
+use std::sync::Arc;
+use tantivy::fieldnorm::FieldNormReader;
+use tantivy::postings::{Postings, SegmentPostings};
+use tantivy::query::{
+    BooleanQuery, EnableScoring, Explanation, PhrasePrefixQuery, PhraseQuery, Query, Scorer,
+    TermQuery, Weight,
+};
+use tantivy::schema::{Field, IndexRecordOption};
+use tantivy::{DocId, DocSet, Score, SegmentReader, Term};
+
+/// Rewrite a leaf query, or the term and phrase queries of the booleans it's made of, to score
+/// with their field's similarity, when it isn't the default.
+///
+/// Every leaf query comes through here as it's built, before any boost, disjunction max or other
+/// compound query wraps it, because those can't be looked inside afterwards.  The total term
+/// frequencies some similarities need come from `global_statistics` when it's given.
+pub fn apply_similarity(
+    query: Box<dyn Query>,
+    field_lookup: &impl AsFieldType<String>,
+    global_statistics: &Option<Arc<GlobalStatistics>>,
+) -> Box<dyn Query> {
+    if let Some(boolean) = query.downcast_ref::<BooleanQuery>() {
+        return Box::new(BooleanQuery::new(
+            boolean
+                .clauses()
+                .iter()
+                .map(|(occur, query)| {
+                    (
+                        *occur,
+                        apply_similarity(query.box_clone(), field_lookup, global_statistics),
+                    )
+                })
+                .collect(),
+        ));
+    }
+
+    let (field, terms) = if let Some(term_query) = query.downcast_ref::<TermQuery>() {
+        (term_query.term().field(), vec![term_query.term().clone()])
+    } else if let Some(phrase_query) = query.downcast_ref::<PhraseQuery>() {
+        (phrase_query.field(), phrase_query.phrase_terms())
+    } else if let Some(phrase_prefix_query) = query.downcast_ref::<PhrasePrefixQuery>() {
+        // the prefix's expansions are only known per segment, so only the rest is scored
+        (
+            phrase_prefix_query.field(),
+            phrase_prefix_query.phrase_terms(),
+        )
+    } else {
+        return query;
+    };
+
+    let similarity = field_lookup.similarity(field);
+    if similarity.is_default() {
+        return query;
+    }
+    Box::new(SimilarityQuery {
+        query,
+        field,
+        terms,
+        similarity,
+        global_statistics: global_statistics.clone(),
+    })
+}
+
+/// Matches the same docs as `query`, and scores each of them as the sum of the scores of
+/// `terms`, as `similarity` scores them.  For a phrase, those are the terms in it.
+#[derive(Debug)]
+pub struct SimilarityQuery {
+    query: Box<dyn Query>,
+    field: Field,
+    terms: Vec<Term>,
+    similarity: SearchSimilarity,
+    global_statistics: Option<Arc<GlobalStatistics>>,
+}
+
+impl Clone for SimilarityQuery {
+    fn clone(&self) -> Self {
+        Self {
+            query: self.query.box_clone(),
+            field: self.field,
+            terms: self.terms.clone(),
+            similarity: self.similarity,
+            global_statistics: self.global_statistics.clone(),
+        }
+    }
+}
+
+impl Query for SimilarityQuery {
+    fn weight(&self, enable_scoring: EnableScoring<'_>) -> tantivy::Result<Box<dyn Weight>> {
+        let EnableScoring::Enabled {
+            searcher,
+            statistics_provider,
+        } = enable_scoring
+        else {
+            return self.query.weight(enable_scoring);
+        };
+
+        let total_num_docs = statistics_provider.total_num_docs()?;
+        let average_fieldnorm = if total_num_docs > 0 {
+            statistics_provider.total_num_tokens(self.field)? as Score / total_num_docs as Score
+        } else {
+            0.0
+        };
+        let terms = self
+            .terms
+            .iter()
+            .map(|term| {
+                let total_term_freq = match &self.global_statistics {
+                    _ if !self.similarity.needs_total_term_freq() => 0,
+                    Some(global_statistics) => global_statistics.total_term_freq(term)?,
+                    None => statistics::total_term_freq(searcher, term)?,
+                };
+                Ok((
+                    term.clone(),
+                    TermStatistics {
+                        total_num_docs,
+                        doc_freq: statistics_provider.doc_freq(term)?,
+                        total_term_freq,
+                        average_fieldnorm,
+                    },
+                ))
+            })
+            .collect::<tantivy::Result<Vec<_>>>()?;
+
+        // the wrapped query only decides which docs match
+        let weight = self.query.weight(EnableScoring::Disabled {
+            schema: searcher.schema(),
+            searcher_opt: Some(searcher),
+        })?;
+
+        Ok(Box::new(SimilarityWeight {
+            weight,
+            field: self.field,
+            terms,
+            similarity: self.similarity,
+        }))
+    }
+
+    fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
+        self.query.query_terms(visitor);
+    }
+}
+
+struct SimilarityWeight {
+    weight: Box<dyn Weight>,
+    field: Field,
+    terms: Vec<(Term, TermStatistics)>,
+    similarity: SearchSimilarity,
+}
+
+impl Weight for SimilarityWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        let inverted_index = reader.inverted_index(self.field)?;
+        let terms = self
+            .terms
+            .iter()
+            .map(|(term, stats)| {
+                Ok((
+                    inverted_index.read_postings(term, IndexRecordOption::WithFreqs)?,
+                    *stats,
+                ))
+            })
+            .collect::<tantivy::Result<Vec<_>>>()?;
+
+        Ok(Box::new(SimilarityScorer {
+            scorer: self.weight.scorer(reader, boost)?,
+            terms,
+            fieldnorms: reader.fieldnorms_readers().get_field(self.field)?,
+            similarity: self.similarity,
+            boost,
+        }))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+        if scorer.seek(doc) != doc {
+            return Err(tantivy::TantivyError::InvalidArgument(format!(
+                "Document #({doc}) does not match"
+            )));
+        }
+        Ok(Explanation::new(
+            format!("{:?}", self.similarity),
+            scorer.score(),
+        ))
+    }
+}
+
+struct SimilarityScorer {
+    scorer: Box<dyn Scorer>,
+    terms: Vec<(Option<SegmentPostings>, TermStatistics)>,
+    fieldnorms: Option<FieldNormReader>,
+    similarity: SearchSimilarity,
+    boost: Score,
+}
+
+impl DocSet for SimilarityScorer {
+    fn advance(&mut self) -> DocId {
+        self.scorer.advance()
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        self.scorer.seek(target)
+    }
+
+    fn doc(&self) -> DocId {
+        self.scorer.doc()
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.scorer.size_hint()
+    }
+}
+
+impl Scorer for SimilarityScorer {
+    fn score(&mut self) -> Score {
+        let doc = self.scorer.doc();
+        // without fieldnorms, every doc is taken to be of average length
+        let fieldnorm = self
+            .fieldnorms
+            .as_ref()
+            .map(|fieldnorms| fieldnorms.fieldnorm(doc) as Score)
+            .unwrap_or(0.0);
+
+        let mut score = 0.0;
+        for (postings, stats) in &mut self.terms {
+            let Some(postings) = postings else {
+                continue;
+            };
+            if postings.doc() < doc {
+                postings.seek(doc);
+            }
+            if postings.doc() == doc {
+                score += self
+                    .similarity
+                    .score(stats, postings.term_freq(), fieldnorm);
+            }
+        }
+        self.boost * score
+    }
+}
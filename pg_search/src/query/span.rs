@@ -0,0 +1,565 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Span queries, which match terms by where they appear relative to each other.
+//!
+//! A span is a range of token positions, `[start, end)`.  A term's spans are each position it
+//! appears at, and the other span queries combine the spans of their clauses into new ones, so
+//! they can be nested.  The spans are worked out from the term positions of each candidate doc,
+//! which means the field must be indexed with `IndexRecordOption::WithFreqsAndPositions`.
+
+use crate::query::{AsFieldType, QueryError, SearchQueryInput};
+use tantivy::fieldnorm::FieldNormReader;
+use tantivy::postings::{Postings, SegmentPostings};
+use tantivy::query::{Bm25Weight, EnableScoring, Explanation, Query, Scorer, Weight};
+use tantivy::schema::{Field, FieldType, IndexRecordOption};
+use tantivy::{DocId, DocSet, InvertedIndexReader, Score, SegmentReader, Term, TERMINATED};
+
+/// An unordered `span_near` tries every order of its clauses, so it can't have too many of them
+const MAX_UNORDERED_CLAUSES: usize = 6;
+
+/// A span query, with its terms resolved against the index's schema
+#[derive(Clone, Debug)]
+pub enum Span {
+    Term(Term),
+    Near {
+        clauses: Vec<Span>,
+        slop: u32,
+        in_order: bool,
+    },
+    Or(Vec<Span>),
+    Not {
+        include: Box<Span>,
+        exclude: Box<Span>,
+        pre: u32,
+        post: u32,
+    },
+}
+
+impl Span {
+    /// Resolve a tree of `span_*` query inputs.  All of their terms must be in the same field.
+    pub(super) fn from_input(
+        input: SearchQueryInput,
+        field_lookup: &impl AsFieldType<String>,
+    ) -> Result<Self, QueryError> {
+        let span = Self::resolve(input, field_lookup)?;
+
+        let mut terms = vec![];
+        span.terms(&mut terms, true);
+        if terms
+            .windows(2)
+            .any(|pair| pair[0].field() != pair[1].field())
+        {
+            return Err(QueryError::SpanFieldMismatch);
+        }
+        Ok(span)
+    }
+
+    fn resolve(
+        input: SearchQueryInput,
+        field_lookup: &impl AsFieldType<String>,
+    ) -> Result<Self, QueryError> {
+        match input {
+            SearchQueryInput::SpanTerm { field, value } => {
+                let (field_type, _, indexed_field) = field_lookup
+                    .as_field_type(&field)
+                    .ok_or_else(|| QueryError::NonIndexedField(field.clone()))?;
+                let has_positions = match field_type {
+                    FieldType::Str(options) => options
+                        .get_indexing_options()
+                        .is_some_and(|indexing| indexing.index_option().has_positions()),
+                    _ => false,
+                };
+                if !has_positions {
+                    return Err(QueryError::NoPositions(field));
+                }
+                Ok(Span::Term(Term::from_field_text(indexed_field, &value)))
+            }
+            SearchQueryInput::SpanNear {
+                clauses,
+                slop,
+                in_order,
+            } => {
+                if clauses.is_empty() {
+                    return Err(QueryError::EmptySpanClauses);
+                }
+                if !in_order && clauses.len() > MAX_UNORDERED_CLAUSES {
+                    return Err(QueryError::TooManyUnorderedClauses(MAX_UNORDERED_CLAUSES));
+                }
+                Ok(Span::Near {
+                    clauses: clauses
+                        .into_iter()
+                        .map(|clause| Self::resolve(clause, field_lookup))
+                        .collect::<Result<_, _>>()?,
+                    slop,
+                    in_order,
+                })
+            }
+            SearchQueryInput::SpanOr { clauses } => {
+                if clauses.is_empty() {
+                    return Err(QueryError::EmptySpanClauses);
+                }
+                Ok(Span::Or(
+                    clauses
+                        .into_iter()
+                        .map(|clause| Self::resolve(clause, field_lookup))
+                        .collect::<Result<_, _>>()?,
+                ))
+            }
+            SearchQueryInput::SpanNot {
+                include,
+                exclude,
+                pre,
+                post,
+            } => Ok(Span::Not {
+                include: Box::new(Self::resolve(*include, field_lookup)?),
+                exclude: Box::new(Self::resolve(*exclude, field_lookup)?),
+                pre,
+                post,
+            }),
+            _ => Err(QueryError::NotASpanQuery),
+        }
+    }
+
+    /// Collect the terms of this span, optionally including the ones it excludes
+    fn terms<'a>(&'a self, terms: &mut Vec<&'a Term>, with_excluded: bool) {
+        match self {
+            Span::Term(term) => terms.push(term),
+            Span::Near { clauses, .. } | Span::Or(clauses) => {
+                for clause in clauses {
+                    clause.terms(terms, with_excluded);
+                }
+            }
+            Span::Not {
+                include, exclude, ..
+            } => {
+                include.terms(terms, with_excluded);
+                if with_excluded {
+                    exclude.terms(terms, with_excluded);
+                }
+            }
+        }
+    }
+
+    fn field(&self) -> Field {
+        let mut terms = vec![];
+        self.terms(&mut terms, false);
+        terms[0].field()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SpanQuery {
+    span: Span,
+}
+
+impl SpanQuery {
+    pub fn new(span: Span) -> Self {
+        Self { span }
+    }
+}
+
+impl Query for SpanQuery {
+    fn weight(&self, enable_scoring: EnableScoring<'_>) -> tantivy::Result<Box<dyn Weight>> {
+        // a span scores like a phrase made of the terms it matches
+        let bm25 = match enable_scoring {
+            EnableScoring::Enabled {
+                statistics_provider,
+                ..
+            } => {
+                let mut terms = vec![];
+                self.span.terms(&mut terms, false);
+                let terms = terms.into_iter().cloned().collect::<Vec<_>>();
+                Some(Bm25Weight::for_terms(statistics_provider, &terms)?)
+            }
+            EnableScoring::Disabled { .. } => None,
+        };
+
+        Ok(Box::new(SpanWeight {
+            span: self.span.clone(),
+            bm25,
+        }))
+    }
+
+    fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
+        let mut terms = vec![];
+        self.span.terms(&mut terms, false);
+        for term in terms {
+            visitor(term, true);
+        }
+    }
+}
+
+struct SpanWeight {
+    span: Span,
+    bm25: Option<Bm25Weight>,
+}
+
+impl Weight for SpanWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        let field = self.span.field();
+        let inverted_index = reader.inverted_index(field)?;
+        let fieldnorms = match reader.fieldnorms_readers().get_field(field)? {
+            Some(fieldnorms) if self.bm25.is_some() => fieldnorms,
+            _ => FieldNormReader::constant(reader.max_doc(), 1),
+        };
+
+        let mut scorer = SpanScorer {
+            spans: SegmentSpans::open(&self.span, &inverted_index)?,
+            doc: 0,
+            freq: 0,
+            fieldnorms,
+            bm25: self.bm25.as_ref().map(|bm25| bm25.boost_by(boost)),
+        };
+        scorer.advance_to(0);
+        Ok(Box::new(scorer))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+        if scorer.seek(doc) != doc {
+            return Err(tantivy::TantivyError::InvalidArgument(format!(
+                "Document #({doc}) does not match"
+            )));
+        }
+        Ok(Explanation::new("SpanQuery", scorer.score()))
+    }
+}
+
+/// The spans of one segment, positioned on a candidate doc
+enum SegmentSpans {
+    Term(Option<SegmentPostings>, Vec<u32>),
+    Near {
+        clauses: Vec<SegmentSpans>,
+        slop: u32,
+        in_order: bool,
+        doc: DocId,
+    },
+    Or {
+        clauses: Vec<SegmentSpans>,
+        doc: DocId,
+    },
+    Not {
+        include: Box<SegmentSpans>,
+        exclude: Box<SegmentSpans>,
+        pre: u32,
+        post: u32,
+    },
+}
+
+impl SegmentSpans {
+    fn open(span: &Span, inverted_index: &InvertedIndexReader) -> tantivy::Result<Self> {
+        let open_all = |clauses: &[Span]| {
+            clauses
+                .iter()
+                .map(|clause| Self::open(clause, inverted_index))
+                .collect::<tantivy::Result<Vec<_>>>()
+        };
+
+        Ok(match span {
+            Span::Term(term) => SegmentSpans::Term(
+                inverted_index.read_postings(term, IndexRecordOption::WithFreqsAndPositions)?,
+                vec![],
+            ),
+            Span::Near {
+                clauses,
+                slop,
+                in_order,
+            } => SegmentSpans::Near {
+                clauses: open_all(clauses)?,
+                slop: *slop,
+                in_order: *in_order,
+                doc: 0,
+            },
+            Span::Or(clauses) => SegmentSpans::Or {
+                clauses: open_all(clauses)?,
+                doc: 0,
+            },
+            Span::Not {
+                include,
+                exclude,
+                pre,
+                post,
+            } => SegmentSpans::Not {
+                include: Box::new(Self::open(include, inverted_index)?),
+                exclude: Box::new(Self::open(exclude, inverted_index)?),
+                pre: *pre,
+                post: *post,
+            },
+        })
+    }
+
+    fn doc(&self) -> DocId {
+        match self {
+            SegmentSpans::Term(None, _) => TERMINATED,
+            SegmentSpans::Term(Some(postings), _) => postings.doc(),
+            SegmentSpans::Near { doc, .. } | SegmentSpans::Or { doc, .. } => *doc,
+            SegmentSpans::Not { include, .. } => include.doc(),
+        }
+    }
+
+    /// Move to the first doc at or after `target` that has every term this span needs.  It may
+    /// still not have any spans.
+    fn seek(&mut self, target: DocId) -> DocId {
+        match self {
+            SegmentSpans::Term(None, _) => TERMINATED,
+            SegmentSpans::Term(Some(postings), _) => {
+                if postings.doc() >= target {
+                    postings.doc()
+                } else {
+                    postings.seek(target)
+                }
+            }
+            SegmentSpans::Near { clauses, doc, .. } => {
+                let mut target = target;
+                'leapfrog: loop {
+                    for clause in clauses.iter_mut() {
+                        let clause_doc = clause.seek(target);
+                        if clause_doc == TERMINATED {
+                            *doc = TERMINATED;
+                            return TERMINATED;
+                        }
+                        if clause_doc > target {
+                            target = clause_doc;
+                            continue 'leapfrog;
+                        }
+                    }
+                    *doc = target;
+                    return target;
+                }
+            }
+            SegmentSpans::Or { clauses, doc } => {
+                *doc = clauses
+                    .iter_mut()
+                    .map(|clause| clause.seek(target))
+                    .min()
+                    .unwrap_or(TERMINATED);
+                *doc
+            }
+            SegmentSpans::Not { include, .. } => include.seek(target),
+        }
+    }
+
+    fn size_hint(&self) -> u32 {
+        match self {
+            SegmentSpans::Term(None, _) => 0,
+            SegmentSpans::Term(Some(postings), _) => postings.size_hint(),
+            SegmentSpans::Near { clauses, .. } => clauses
+                .iter()
+                .map(SegmentSpans::size_hint)
+                .min()
+                .unwrap_or(0),
+            SegmentSpans::Or { clauses, .. } => clauses.iter().map(SegmentSpans::size_hint).sum(),
+            SegmentSpans::Not { include, .. } => include.size_hint(),
+        }
+    }
+
+    /// The spans in `doc`, which this must be positioned on, sorted and without duplicates
+    fn spans(&mut self, doc: DocId) -> Vec<(u32, u32)> {
+        let mut spans = match self {
+            SegmentSpans::Term(None, _) => vec![],
+            SegmentSpans::Term(Some(postings), positions) => {
+                positions.clear();
+                postings.positions(positions);
+                positions
+                    .iter()
+                    .map(|&position| (position, position + 1))
+                    .collect()
+            }
+            SegmentSpans::Near {
+                clauses,
+                slop,
+                in_order,
+                ..
+            } => {
+                let clause_spans = clauses
+                    .iter_mut()
+                    .map(|clause| clause.spans(doc))
+                    .collect::<Vec<_>>();
+
+                if *in_order {
+                    ordered_spans(&clause_spans, *slop)
+                } else {
+                    let mut spans = vec![];
+                    for order in permutations(clause_spans.len()) {
+                        let reordered = order
+                            .into_iter()
+                            .map(|idx| clause_spans[idx].clone())
+                            .collect::<Vec<_>>();
+                        spans.extend(ordered_spans(&reordered, *slop));
+                    }
+                    spans
+                }
+            }
+            SegmentSpans::Or { clauses, .. } => clauses
+                .iter_mut()
+                .filter(|clause| clause.doc() == doc)
+                .flat_map(|clause| clause.spans(doc))
+                .collect(),
+            SegmentSpans::Not {
+                include,
+                exclude,
+                pre,
+                post,
+            } => {
+                let spans = include.spans(doc);
+                if exclude.seek(doc) != doc {
+                    spans
+                } else {
+                    // an included span is ruled out by an excluded span that overlaps it, or
+                    // the `pre` positions before it, or the `post` positions after it
+                    let excluded = exclude.spans(doc);
+                    spans
+                        .into_iter()
+                        .filter(|&(start, end)| {
+                            !excluded.iter().any(|&(ex_start, ex_end)| {
+                                ex_start < end.saturating_add(*post)
+                                    && ex_end > start.saturating_sub(*pre)
+                            })
+                        })
+                        .collect()
+                }
+            }
+        };
+
+        spans.sort_unstable();
+        spans.dedup();
+        spans
+    }
+}
+
+/// Combine one span from each clause, in the clauses' order and without overlapping, into a span
+/// from the start of the first to the end of the last.  Only the combinations with at most `slop`
+/// positions between their spans in total are kept.
+fn ordered_spans(clauses: &[Vec<(u32, u32)>], slop: u32) -> Vec<(u32, u32)> {
+    let Some(first) = clauses.first() else {
+        return vec![];
+    };
+
+    // for each span of the current clause, the largest "start of the first span plus the length
+    // of every span" of any chain ending there, along with that start.  A chain ending at `end`
+    // leaves `end` minus that many positions between its spans
+    let mut best = first
+        .iter()
+        .map(|&(start, end)| Some((end, start)))
+        .collect::<Vec<_>>();
+
+    for pair in clauses.windows(2) {
+        let (previous, current) = (&pair[0], &pair[1]);
+        best = current
+            .iter()
+            .map(|&(start, end)| {
+                previous
+                    .iter()
+                    .zip(best.iter().copied())
+                    .filter_map(|(&(_, previous_end), chain)| {
+                        chain.filter(|_| previous_end <= start)
+                    })
+                    .max_by_key(|(covered, _)| *covered)
+                    .map(|(covered, chain_start)| (covered + end - start, chain_start))
+            })
+            .collect();
+    }
+
+    clauses
+        .last()
+        .unwrap()
+        .iter()
+        .zip(best)
+        .filter_map(|(&(_, end), chain)| {
+            let (covered, chain_start) = chain?;
+            (end - covered <= slop).then_some((chain_start, end))
+        })
+        .collect()
+}
+
+/// Every ordering of `0..n`
+fn permutations(n: usize) -> Vec<Vec<usize>> {
+    if n == 0 {
+        return vec![vec![]];
+    }
+    let mut all = vec![];
+    for rest in permutations(n - 1) {
+        for position in 0..=rest.len() {
+            let mut order = rest.clone();
+            order.insert(position, n - 1);
+            all.push(order);
+        }
+    }
+    all
+}
+
+struct SpanScorer {
+    spans: SegmentSpans,
+    doc: DocId,
+    freq: u32,
+    fieldnorms: FieldNormReader,
+    bm25: Option<Bm25Weight>,
+}
+
+impl SpanScorer {
+    fn advance_to(&mut self, mut target: DocId) -> DocId {
+        loop {
+            let doc = self.spans.seek(target);
+            if doc == TERMINATED {
+                self.doc = TERMINATED;
+                return TERMINATED;
+            }
+
+            let freq = self.spans.spans(doc).len() as u32;
+            if freq > 0 {
+                self.doc = doc;
+                self.freq = freq;
+                return doc;
+            }
+            target = doc + 1;
+        }
+    }
+}
+
+impl DocSet for SpanScorer {
+    fn advance(&mut self) -> DocId {
+        if self.doc == TERMINATED {
+            return TERMINATED;
+        }
+        self.advance_to(self.doc + 1)
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        if self.doc >= target {
+            return self.doc;
+        }
+        self.advance_to(target)
+    }
+
+    fn doc(&self) -> DocId {
+        self.doc
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.spans.size_hint()
+    }
+}
+
+impl Scorer for SpanScorer {
+    fn score(&mut self) -> Score {
+        match &self.bm25 {
+            Some(bm25) => bm25.score(self.fieldnorms.fieldnorm_id(self.doc), self.freq),
+            None => 1.0,
+        }
+    }
+}
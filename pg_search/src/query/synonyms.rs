@@ -0,0 +1,227 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Query-time synonym expansion.  A synonym set is the rows of `paradedb.synonyms` with the same
+//! `set_name`, where each row is a group of terms or phrases that mean the same thing, and text
+//! fields name the set they use in their tokenizer's `synonyms` option.  Sets are read whenever a
+//! query is built, so changing them never needs a reindex.
+
+use crate::query::{AsFieldType, QueryError};
+use pgrx::{IntoDatum, PgBuiltInOids, Spi};
+use std::collections::HashMap;
+use std::error::Error;
+use tantivy::query::{BooleanQuery, PhraseQuery, Query, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption};
+use tantivy::{Searcher, Term};
+
+/// The most alternatives a single term or phrase can expand to
+const MAX_EXPANSIONS: usize = 64;
+
+/// The groups of a synonym set, with each of their terms or phrases split into a field's tokens
+pub struct Synonyms {
+    groups: Vec<Vec<Vec<String>>>,
+}
+
+impl Synonyms {
+    /// Load the synonyms of the text field `field`, if its tokenizer names a synonym set
+    pub fn load(
+        field: Field,
+        field_lookup: &impl AsFieldType<String>,
+        searcher: &Searcher,
+    ) -> Result<Option<Self>, Box<dyn Error>> {
+        let Some(set_name) = field_lookup.synonym_set(field) else {
+            return Ok(None);
+        };
+
+        let rows = Spi::connect(|client| {
+            let mut rows = vec![];
+            for row in client.select(
+                "SELECT terms FROM paradedb.synonyms WHERE set_name = $1",
+                None,
+                Some(vec![(
+                    PgBuiltInOids::TEXTOID.oid(),
+                    set_name.clone().into_datum(),
+                )]),
+            )? {
+                if let Some(terms) = row.get::<Vec<Option<String>>>(1)? {
+                    rows.push(terms.into_iter().flatten().collect::<Vec<_>>());
+                }
+            }
+            Ok::<_, pgrx::spi::Error>(rows)
+        })
+        .map_err(|err| QueryError::SynonymSet(set_name, err))?;
+
+        // synonyms have to be tokenized like the field's text to match the terms in the index
+        let mut analyzer = searcher.index().tokenizer_for_field(field)?;
+        let mut groups = vec![];
+        for terms in rows {
+            let mut group: Vec<Vec<String>> = vec![];
+            for text in terms {
+                let mut tokens = vec![];
+                analyzer
+                    .token_stream(&text)
+                    .process(&mut |token| tokens.push(token.text.clone()));
+                if !tokens.is_empty() && !group.contains(&tokens) {
+                    group.push(tokens);
+                }
+            }
+            if group.len() > 1 {
+                groups.push(group);
+            }
+        }
+
+        Ok(Some(Self { groups }))
+    }
+
+    /// A query that matches `tokens` as a phrase in `field`, or any of the phrases made by
+    /// swapping the synonyms in it for each other
+    pub fn phrase_query(&self, field: Field, tokens: &[String], slop: u32) -> Box<dyn Query> {
+        let mut queries = self
+            .expand(tokens)
+            .into_iter()
+            .map(|tokens| phrase_or_term_query(field, &tokens, slop))
+            .collect::<Vec<_>>();
+        if queries.len() == 1 {
+            queries.pop().unwrap()
+        } else {
+            Box::new(BooleanQuery::union(queries))
+        }
+    }
+
+    /// Every way of writing `tokens` with synonyms, starting with `tokens` itself.  The longest
+    /// synonym at each position wins, so a multi-word synonym is swapped out as a whole.
+    fn expand(&self, tokens: &[String]) -> Vec<Vec<String>> {
+        let mut spans: Vec<Vec<&[String]>> = vec![];
+        let mut idx = 0;
+        while idx < tokens.len() {
+            let longest = self
+                .groups
+                .iter()
+                .flatten()
+                .filter(|synonym| tokens[idx..].starts_with(synonym))
+                .max_by_key(|synonym| synonym.len());
+
+            match longest {
+                Some(matched) => {
+                    let mut alternatives = vec![matched.as_slice()];
+                    for group in self.groups.iter().filter(|group| group.contains(matched)) {
+                        for synonym in group {
+                            if !alternatives.contains(&synonym.as_slice()) {
+                                alternatives.push(synonym);
+                            }
+                        }
+                    }
+                    spans.push(alternatives);
+                    idx += matched.len();
+                }
+                None => {
+                    spans.push(vec![&tokens[idx..idx + 1]]);
+                    idx += 1;
+                }
+            }
+        }
+
+        let mut expansions: Vec<Vec<String>> = vec![vec![]];
+        for alternatives in spans {
+            expansions = expansions
+                .iter()
+                .flat_map(|prefix| {
+                    alternatives.iter().map(move |alternative| {
+                        let mut expansion = prefix.clone();
+                        expansion.extend_from_slice(alternative);
+                        expansion
+                    })
+                })
+                .take(MAX_EXPANSIONS)
+                .collect();
+        }
+        expansions
+    }
+}
+
+fn phrase_or_term_query(field: Field, tokens: &[String], slop: u32) -> Box<dyn Query> {
+    let mut terms = tokens
+        .iter()
+        .map(|token| Term::from_field_text(field, token))
+        .collect::<Vec<_>>();
+    if terms.len() == 1 {
+        Box::new(TermQuery::new(
+            terms.pop().unwrap(),
+            IndexRecordOption::WithFreqs,
+        ))
+    } else {
+        let mut query = PhraseQuery::new(terms);
+        query.set_slop(slop);
+        Box::new(query)
+    }
+}
+
+/// Load the synonyms of every text field that has a synonym set
+pub fn load_all(
+    field_lookup: &impl AsFieldType<String>,
+    searcher: &Searcher,
+) -> Result<HashMap<Field, Synonyms>, Box<dyn Error>> {
+    let mut synonyms = HashMap::new();
+    for (_, _, field) in field_lookup.fields() {
+        if let Some(field_synonyms) = Synonyms::load(field, field_lookup, searcher)? {
+            synonyms.insert(field, field_synonyms);
+        }
+    }
+    Ok(synonyms)
+}
+
+/// Expand the terms and phrases of one of the query parser's leaf queries, in the fields that
+/// have synonyms
+pub fn expand_query(query: Box<dyn Query>, synonyms: &HashMap<Field, Synonyms>) -> Box<dyn Query> {
+    if let Some(boolean) = query.downcast_ref::<BooleanQuery>() {
+        return Box::new(BooleanQuery::new(
+            boolean
+                .clauses()
+                .iter()
+                .map(|(occur, query)| (*occur, expand_query(query.box_clone(), synonyms)))
+                .collect(),
+        ));
+    }
+
+    if let Some(term_query) = query.downcast_ref::<TermQuery>() {
+        let term = term_query.term();
+        if let (Some(field_synonyms), Some(text)) =
+            (synonyms.get(&term.field()), term.value().as_str())
+        {
+            return field_synonyms.phrase_query(term.field(), &[text.to_string()], 0);
+        }
+    }
+
+    if let Some(phrase_query) = query.downcast_ref::<PhraseQuery>() {
+        if let Some(field_synonyms) = synonyms.get(&phrase_query.field()) {
+            let tokens = phrase_query
+                .phrase_terms()
+                .iter()
+                .map(|term| term.value().as_str().map(String::from))
+                .collect::<Option<Vec<_>>>();
+            if let Some(tokens) = tokens {
+                return field_synonyms.phrase_query(
+                    phrase_query.field(),
+                    &tokens,
+                    phrase_query.slop(),
+                );
+            }
+        }
+    }
+
+    query
+}
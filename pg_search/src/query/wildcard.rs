@@ -0,0 +1,327 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Wildcard patterns, where `?` matches any single character, `*` matches any run of characters,
+//! and `\` makes the character after it match literally.
+
+use crate::query::{AsFieldType, QueryError};
+use std::ops::Range;
+use tantivy::json_utils::split_json_path;
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, EmptyQuery, Query, QueryParser, QueryParserError,
+    RegexQuery,
+};
+use tantivy::query_grammar::{self, Occur, UserInputAst, UserInputLeaf};
+use tantivy::schema::FieldType;
+use tantivy::Score;
+
+/// Stands in for a wildcard in a query string, followed by its number, while the rest of the query
+/// string is parsed.  It's a private use character, so it won't be in a real query string.
+const PLACEHOLDER: char = '\u{E000}';
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Glob {
+    Char(char),
+    One,
+    Many,
+}
+
+fn parse(pattern: &str) -> Vec<Glob> {
+    let mut globs = vec![];
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        globs.push(match ch {
+            '\\' => Glob::Char(chars.next().unwrap_or('\\')),
+            '?' => Glob::One,
+            '*' => Glob::Many,
+            ch => Glob::Char(ch),
+        });
+    }
+    globs
+}
+
+fn is_wildcard(pattern: &str) -> bool {
+    parse(pattern)
+        .iter()
+        .any(|glob| matches!(glob, Glob::One | Glob::Many))
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(ch) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+/// Translate a wildcard pattern into a regex that matches the same terms
+fn to_regex(pattern: &str, case_insensitive: bool) -> String {
+    let mut regex = String::new();
+    for glob in parse(pattern) {
+        match glob {
+            Glob::Char(ch) => regex.push_str(&escape(&ch.to_string())),
+            Glob::One => regex.push('.'),
+            Glob::Many => regex.push_str(".*"),
+        }
+    }
+    if case_insensitive {
+        format!("(?i:{regex})")
+    } else {
+        regex
+    }
+}
+
+/// Match the terms of `field`, which can be a path into a JSON field, that fit `pattern`.  The
+/// regex is compiled into an automaton that's run over the term dictionary.
+pub fn wildcard_query(
+    field: &str,
+    pattern: &str,
+    case_insensitive: bool,
+    field_lookup: &impl AsFieldType<String>,
+) -> Result<Box<dyn Query>, QueryError> {
+    let path = split_json_path(field);
+    let mut regex = String::new();
+    let tantivy_field = match field_lookup.as_field_type(&path[0]) {
+        Some((FieldType::Str(_), _, tantivy_field)) if path.len() == 1 => tantivy_field,
+        // the terms of a JSON field start with the path to their value, then the value's type
+        Some((FieldType::JsonObject(_), _, tantivy_field)) if path.len() > 1 => {
+            let segments = path[1..]
+                .iter()
+                .map(|segment| escape(segment))
+                .collect::<Vec<_>>();
+            regex.push_str(&segments.join("\\x01"));
+            regex.push_str("\\x00s");
+            tantivy_field
+        }
+        _ => return Err(QueryError::WrongFieldType(field.to_string())),
+    };
+    regex.push_str(&to_regex(pattern, case_insensitive));
+
+    Ok(Box::new(
+        RegexQuery::from_pattern(&regex, tantivy_field)
+            .map_err(|err| QueryError::RegexError(err, pattern.to_string()))?,
+    ))
+}
+
+/// Parse `query_string` like the query parser does, except that each `field:pattern` wildcard,
+/// which the query parser doesn't understand, matches the terms of `field` that fit `pattern`.
+/// If there's a `default_field`, the whole query string is parsed against it, and so are the
+/// wildcards without a field.  Like the rest of the query string, a pattern matches regardless of
+/// case if the field's tokenizer lowercases what it indexes.  With `conjunction`, the clauses
+/// without a `+` or `-` are required, as they are after `set_conjunction_by_default`.
+///
+/// Each of the query parser's leaf queries goes through `rewrite_leaf` before it's boosted or
+/// combined with the others, because there's no looking inside those afterwards.
+pub fn parse_query_string(
+    query_string: &str,
+    default_field: Option<&str>,
+    lenient: bool,
+    conjunction: bool,
+    parser: &QueryParser,
+    field_lookup: &impl AsFieldType<String>,
+    rewrite_leaf: impl Fn(Box<dyn Query>) -> Box<dyn Query>,
+) -> Result<Box<dyn Query>, QueryError> {
+    let mut wildcards = vec![];
+    let mut rewritten = String::with_capacity(query_string.len());
+    let mut copied_to = 0;
+
+    for word in top_level_words(query_string) {
+        let Some((occur, field, pattern)) =
+            split_clause(&query_string[word.clone()], default_field)
+        else {
+            continue;
+        };
+        let Some((_, _, tantivy_field)) = field_lookup.as_field_type(&split_json_path(field)[0])
+        else {
+            continue;
+        };
+        let case_insensitive = field_lookup.lowercases(tantivy_field);
+        let Ok(query) = wildcard_query(field, pattern, case_insensitive, field_lookup) else {
+            continue;
+        };
+
+        rewritten.push_str(&query_string[copied_to..word.start]);
+        rewritten.push_str(&format!(
+            "{occur}{field}:\"{PLACEHOLDER}{}\"",
+            wildcards.len()
+        ));
+        wildcards.push(query);
+        copied_to = word.end;
+    }
+    rewritten.push_str(&query_string[copied_to..]);
+
+    let query_string = match default_field {
+        Some(field) => format!("{field}:({rewritten})"),
+        None => rewritten,
+    };
+    let parse_error = |err| QueryError::ParseError(err, query_string.clone());
+
+    let ast = match lenient {
+        true => query_grammar::parse_query_lenient(&query_string).0,
+        false => query_grammar::parse_query(&query_string)
+            .map_err(|_| parse_error(QueryParserError::SyntaxError(query_string.clone())))?,
+    };
+    let builder = QueryBuilder {
+        parser,
+        wildcards,
+        lenient,
+        conjunction,
+        rewrite_leaf,
+    };
+    Ok(builder
+        .build(ast)
+        .map_err(parse_error)?
+        .unwrap_or_else(|| Box::new(EmptyQuery)))
+}
+
+/// Builds the boosts and clauses of a parsed query string, and the wildcards in it, and leaves
+/// the rest of its leaves to the query parser
+struct QueryBuilder<'a, F> {
+    parser: &'a QueryParser,
+    wildcards: Vec<Box<dyn Query>>,
+    lenient: bool,
+    conjunction: bool,
+    rewrite_leaf: F,
+}
+
+impl<F: Fn(Box<dyn Query>) -> Box<dyn Query>> QueryBuilder<'_, F> {
+    fn build(&self, ast: UserInputAst) -> Result<Option<Box<dyn Query>>, QueryParserError> {
+        match ast {
+            UserInputAst::Leaf(leaf) => {
+                if let Some(idx) = self.wildcard(&leaf) {
+                    return Ok(Some(self.wildcards[idx].box_clone()));
+                }
+                match self
+                    .parser
+                    .build_query_from_user_input_ast(UserInputAst::Leaf(leaf))
+                {
+                    Ok(query) => Ok(Some((self.rewrite_leaf)(query))),
+                    Err(_) if self.lenient => Ok(None),
+                    Err(err) => Err(err),
+                }
+            }
+            UserInputAst::Boost(ast, boost) => Ok(self
+                .build(*ast)?
+                .map(|query| Box::new(BoostQuery::new(query, boost as Score)) as Box<dyn Query>)),
+            UserInputAst::Clause(clauses) => {
+                let mut subqueries = vec![];
+                for (occur, ast) in clauses {
+                    if let Some(query) = self.build(ast)? {
+                        subqueries.push((occur.unwrap_or(self.default_occur()), query));
+                    }
+                }
+                if subqueries.is_empty() {
+                    return Ok(None);
+                }
+                // like the query parser, match everything but the excluded docs
+                if subqueries.iter().all(|(occur, _)| *occur == Occur::MustNot) {
+                    subqueries.push((Occur::Must, Box::new(AllQuery)));
+                }
+                Ok(Some(Box::new(BooleanQuery::new(subqueries))))
+            }
+        }
+    }
+
+    fn default_occur(&self) -> Occur {
+        match self.conjunction {
+            true => Occur::Must,
+            false => Occur::Should,
+        }
+    }
+
+    /// The number of the wildcard `leaf` stands in for, if it's a placeholder
+    fn wildcard(&self, leaf: &UserInputLeaf) -> Option<usize> {
+        let UserInputLeaf::Literal(literal) = leaf else {
+            return None;
+        };
+        let idx = literal
+            .phrase
+            .strip_prefix(PLACEHOLDER)?
+            .parse::<usize>()
+            .ok()?;
+        (idx < self.wildcards.len()).then_some(idx)
+    }
+}
+
+/// The byte ranges of the words in a query string that aren't part of a phrase, range or set
+fn top_level_words(query_string: &str) -> Vec<Range<usize>> {
+    let mut words = vec![];
+    let mut start = None;
+    let mut plain = true;
+    let mut in_quotes = false;
+    let mut depth = 0usize;
+    let mut escaped = false;
+
+    for (idx, ch) in query_string.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match ch {
+            '\\' => escaped = true,
+            '"' => {
+                in_quotes = !in_quotes;
+                plain = false;
+            }
+            '[' | '{' if !in_quotes => {
+                depth += 1;
+                plain = false;
+            }
+            ']' | '}' if !in_quotes => depth = depth.saturating_sub(1),
+            ch if (ch.is_whitespace() || ch == '(' || ch == ')') && !in_quotes && depth == 0 => {
+                if let Some(start) = start.take() {
+                    if plain {
+                        words.push(start..idx);
+                    }
+                }
+                plain = true;
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(idx);
+    }
+    if let Some(start) = start {
+        if plain {
+            words.push(start..query_string.len());
+        }
+    }
+    words
+}
+
+/// Split a `+field:pattern` query string clause into its occur prefix, field and pattern, if its
+/// pattern is a wildcard.  A lone `*` is left for the query parser, which treats it as "exists".
+fn split_clause<'a>(
+    word: &'a str,
+    default_field: Option<&'a str>,
+) -> Option<(&'a str, &'a str, &'a str)> {
+    let (occur, clause) = match word.strip_prefix(['+', '-']) {
+        Some(clause) => word.split_at(word.len() - clause.len()),
+        None => ("", word),
+    };
+    let (field, pattern) = match clause.split_once(':') {
+        Some((field, pattern)) => (field, pattern),
+        None => (default_field?, clause),
+    };
+    if field.is_empty() || pattern == "*" || pattern.contains(['^', ':']) || !is_wildcard(pattern) {
+        return None;
+    }
+    Some((occur, field, pattern))
+}
@@ -22,6 +22,8 @@ use tantivy::TantivyDocument;
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SearchDocument {
     pub doc: TantivyDocument,
+    /// The nested child documents of `doc`, which have to be indexed right before it
+    pub children: Vec<TantivyDocument>,
 }
 
 impl SearchDocument {
@@ -29,6 +31,11 @@ impl SearchDocument {
     pub fn insert(&mut self, SearchFieldId(key): SearchFieldId, value: OwnedValue) {
         self.doc.add_field_value(key, &value)
     }
+
+    /// The documents to index for this row, in order, with its nested children before it
+    pub fn into_documents(self) -> impl Iterator<Item = TantivyDocument> {
+        self.children.into_iter().chain(std::iter::once(self.doc))
+    }
 }
 
 impl From<SearchDocument> for TantivyDocument {
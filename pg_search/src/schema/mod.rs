@@ -18,6 +18,7 @@
 mod anyenum;
 mod document;
 pub mod range;
+mod similarity;
 
 use anyhow::{Context, Result};
 use derive_more::{AsRef, Display, From, Into};
@@ -28,14 +29,22 @@ use serde_json::json;
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use tantivy::schema::{
-    DateOptions, Field, JsonObjectOptions, NumericOptions, Schema, TextFieldIndexing, TextOptions,
-    FAST, INDEXED, STORED,
+    DateOptions, FacetOptions, Field, JsonObjectOptions, NumericOptions, Schema, TextFieldIndexing,
+    TextOptions, FAST, INDEXED, STORED, STRING,
 };
 use thiserror::Error;
 use tokenizers::{SearchNormalizer, SearchTokenizer};
 
 use crate::query::AsFieldType;
 pub use anyenum::AnyEnum;
+pub use similarity::{SearchSimilarity, TermStatistics};
+
+/// The hidden field holding the path of the JSON array that a nested child document came from
+pub const NESTED_PATH_FIELD: &str = "_nested_path";
+
+/// The hidden field holding the ctid of a nested child document's parent row.  Parent documents
+/// never have it, which is how they're told apart from their children.
+pub const NESTED_PARENT_FIELD: &str = "_nested_parent";
 
 /// The id of a field, stored in the index.
 #[derive(Debug, Clone, Display, From, AsRef, PartialEq, Eq, Serialize, Deserialize, Hash)]
@@ -61,6 +70,7 @@ pub enum SearchFieldType {
     Json,
     Date,
     Range,
+    Facet,
 }
 
 impl TryFrom<&PgOid> for SearchFieldType {
@@ -122,6 +132,8 @@ pub enum SearchFieldConfig {
         record: IndexRecordOption,
         #[serde(default)]
         normalizer: SearchNormalizer,
+        #[serde(default)]
+        similarity: SearchSimilarity,
     },
     Json {
         #[serde(default = "default_as_true")]
@@ -135,16 +147,24 @@ pub enum SearchFieldConfig {
         #[serde(default = "default_as_true")]
         expand_dots: bool,
         #[serde(default)]
+        nested: bool,
+        #[serde(default)]
         tokenizer: SearchTokenizer,
         #[serde(default = "default_as_freqs_and_positions")]
         record: IndexRecordOption,
         #[serde(default)]
         normalizer: SearchNormalizer,
+        #[serde(default)]
+        similarity: SearchSimilarity,
     },
     Range {
         #[serde(default = "default_as_true")]
         stored: bool,
     },
+    Facet {
+        #[serde(default = "default_as_true")]
+        stored: bool,
+    },
     Numeric {
         #[serde(default = "default_as_true")]
         indexed: bool,
@@ -221,6 +241,11 @@ impl SearchFieldConfig {
             None => Ok(SearchNormalizer::Raw),
         }?;
 
+        let similarity = match obj.get("similarity") {
+            Some(v) => SearchSimilarity::from_json_value(v),
+            None => Ok(SearchSimilarity::default()),
+        }?;
+
         Ok(SearchFieldConfig::Text {
             indexed,
             fast,
@@ -229,6 +254,7 @@ impl SearchFieldConfig {
             tokenizer,
             record,
             normalizer,
+            similarity,
         })
     }
 
@@ -265,6 +291,13 @@ impl SearchFieldConfig {
             None => Ok(true),
         }?;
 
+        let nested = match obj.get("nested") {
+            Some(v) => v
+                .as_bool()
+                .ok_or_else(|| anyhow::anyhow!("'nested' field should be a boolean")),
+            None => Ok(false),
+        }?;
+
         let tokenizer = match obj.get("tokenizer") {
             Some(v) => SearchTokenizer::from_json_value(v),
             None => Ok(SearchTokenizer::default()),
@@ -280,6 +313,11 @@ impl SearchFieldConfig {
             None => Ok(SearchNormalizer::Raw),
         }?;
 
+        let similarity = match obj.get("similarity") {
+            Some(v) => SearchSimilarity::from_json_value(v),
+            None => Ok(SearchSimilarity::default()),
+        }?;
+
         let fieldnorms = match obj.get("fieldnorms") {
             Some(v) => v
                 .as_bool()
@@ -293,9 +331,11 @@ impl SearchFieldConfig {
             stored,
             fieldnorms,
             expand_dots,
+            nested,
             tokenizer,
             record,
             normalizer,
+            similarity,
         })
     }
 
@@ -314,6 +354,21 @@ impl SearchFieldConfig {
         Ok(SearchFieldConfig::Range { stored })
     }
 
+    pub fn facet_from_json(value: serde_json::Value) -> Result<Self> {
+        let obj = value
+            .as_object()
+            .context("Expected a JSON object for Facet configuration")?;
+
+        let stored = match obj.get("stored") {
+            Some(v) => v
+                .as_bool()
+                .ok_or_else(|| anyhow::anyhow!("'stored' field should be a boolean")),
+            None => Ok(true),
+        }?;
+
+        Ok(SearchFieldConfig::Facet { stored })
+    }
+
     pub fn numeric_from_json(value: serde_json::Value) -> Result<Self> {
         let obj = value
             .as_object()
@@ -453,6 +508,7 @@ impl From<SearchFieldConfig> for TextOptions {
                 tokenizer,
                 record,
                 normalizer,
+                ..
             } => {
                 if stored {
                     text_options = text_options.set_stored();
@@ -519,6 +575,7 @@ impl From<SearchFieldConfig> for JsonObjectOptions {
                 tokenizer,
                 record,
                 normalizer,
+                ..
             } => {
                 if stored {
                     json_options = json_options.set_stored();
@@ -583,6 +640,24 @@ impl From<SearchFieldConfig> for DateOptions {
     }
 }
 
+impl From<SearchFieldConfig> for FacetOptions {
+    fn from(config: SearchFieldConfig) -> Self {
+        let mut facet_options = FacetOptions::default();
+        match config {
+            SearchFieldConfig::Facet { stored } => {
+                // facets are always indexed (and fast), as that's how they're counted and filtered
+                if stored {
+                    facet_options = facet_options.set_stored();
+                }
+            }
+            _ => {
+                panic!("attempted to convert non-facet search field config to tantivy facet config")
+            }
+        }
+        facet_options
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SearchField {
     /// The id of the field, stored in the index.
@@ -595,6 +670,13 @@ pub struct SearchField {
     pub type_: SearchFieldType,
 }
 
+impl SearchField {
+    /// Whether this is a JSON field whose arrays of objects are indexed as child documents
+    pub fn is_nested(&self) -> bool {
+        matches!(self.config, SearchFieldConfig::Json { nested: true, .. })
+    }
+}
+
 impl From<&SearchField> for Field {
     fn from(val: &SearchField) -> Self {
         val.id.0
@@ -647,6 +729,9 @@ impl SearchIndexSchema {
                     SearchFieldType::Json => builder.add_json_field(name.as_ref(), config.clone()),
                     SearchFieldType::Range => builder.add_json_field(name.as_ref(), config.clone()),
                     SearchFieldType::Date => builder.add_date_field(name.as_ref(), config.clone()),
+                    SearchFieldType::Facet => {
+                        builder.add_facet_field(name.as_ref(), config.clone())
+                    }
                 },
             }
             .into();
@@ -659,6 +744,13 @@ impl SearchIndexSchema {
             });
         }
 
+        // the arrays of nested JSON fields are indexed as child documents of their row, which
+        // need these to be found and joined back to it
+        if search_fields.iter().any(SearchField::is_nested) {
+            builder.add_text_field(NESTED_PATH_FIELD, STRING);
+            builder.add_u64_field(NESTED_PARENT_FIELD, INDEXED | FAST | STORED);
+        }
+
         let schema = builder.build();
 
         Ok(Self {
@@ -689,6 +781,17 @@ impl SearchIndexSchema {
             .clone()
     }
 
+    /// The field holding the path of nested child documents, if the index has any nested fields
+    pub fn nested_path_field(&self) -> Option<Field> {
+        self.schema.get_field(NESTED_PATH_FIELD).ok()
+    }
+
+    /// The field holding the parent ctid of nested child documents, if the index has any nested
+    /// fields
+    pub fn nested_parent_field(&self) -> Option<Field> {
+        self.schema.get_field(NESTED_PARENT_FIELD).ok()
+    }
+
     pub fn key_field(&self) -> SearchField {
         self.fields
             .get(self.key)
@@ -704,6 +807,7 @@ impl SearchIndexSchema {
     pub fn new_document(&self) -> SearchDocument {
         SearchDocument {
             doc: tantivy::TantivyDocument::new(),
+            children: vec![],
         }
     }
 
@@ -898,6 +1002,44 @@ impl AsFieldType<String> for (&PgRelation, &SearchIndexSchema) {
                 (field_type, self.typeoid(search_field), field)
             })
     }
+
+    fn synonym_set(&self, field: Field) -> Option<String> {
+        self.1
+            .fields
+            .iter()
+            .find(|search_field| search_field.id.0 == field)
+            .and_then(|search_field| match &search_field.config {
+                SearchFieldConfig::Text { tokenizer, .. } => {
+                    tokenizer.synonym_set().map(String::from)
+                }
+                _ => None,
+            })
+    }
+
+    fn similarity(&self, field: Field) -> SearchSimilarity {
+        self.1
+            .fields
+            .iter()
+            .find(|search_field| search_field.id.0 == field)
+            .map(|search_field| match &search_field.config {
+                SearchFieldConfig::Text { similarity, .. }
+                | SearchFieldConfig::Json { similarity, .. } => *similarity,
+                _ => SearchSimilarity::default(),
+            })
+            .unwrap_or_default()
+    }
+
+    fn lowercases(&self, field: Field) -> bool {
+        self.1
+            .fields
+            .iter()
+            .find(|search_field| search_field.id.0 == field)
+            .is_some_and(|search_field| match &search_field.config {
+                SearchFieldConfig::Text { tokenizer, .. }
+                | SearchFieldConfig::Json { tokenizer, .. } => tokenizer.lowercases(),
+                _ => false,
+            })
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,245 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! The models a text field's matches can be scored with.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tantivy::Score;
+
+/// Tantivy's own BM25 parameters, which every field used before they could be configured
+const DEFAULT_K1: f32 = 1.2;
+const DEFAULT_B: f32 = 0.75;
+
+fn default_k1() -> f32 {
+    DEFAULT_K1
+}
+
+fn default_b() -> f32 {
+    DEFAULT_B
+}
+
+fn default_delta() -> f32 {
+    1.0
+}
+
+fn default_c() -> f32 {
+    1.0
+}
+
+/// How the matches of a term in a text or JSON field are scored
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SearchSimilarity {
+    Bm25 {
+        #[serde(default = "default_k1")]
+        k1: f32,
+        #[serde(default = "default_b")]
+        b: f32,
+    },
+    /// BM25 with a floor of `delta` for any doc that contains the term, so that long docs aren't
+    /// scored as if they barely match
+    Bm25Plus {
+        #[serde(default = "default_k1")]
+        k1: f32,
+        #[serde(default = "default_b")]
+        b: f32,
+        #[serde(default = "default_delta")]
+        delta: f32,
+    },
+    TfIdf,
+    /// Every matching term scores 1, however often it appears
+    Boolean,
+    /// Divergence from randomness, made of a basic model, an after effect and a normalization
+    Dfr {
+        basic_model: DfrBasicModel,
+        after_effect: DfrAfterEffect,
+        normalization: DfrNormalization,
+        /// The `c` parameter of the `h2` normalization
+        #[serde(default = "default_c")]
+        c: f32,
+    },
+}
+
+// the parameters are checked to be finite, so they're never NaN
+impl Eq for SearchSimilarity {}
+
+impl Default for SearchSimilarity {
+    fn default() -> Self {
+        SearchSimilarity::Bm25 {
+            k1: DEFAULT_K1,
+            b: DEFAULT_B,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DfrBasicModel {
+    G,
+    If,
+    In,
+    Ine,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DfrAfterEffect {
+    L,
+    B,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DfrNormalization {
+    H1,
+    H2,
+}
+
+/// What's known about a term across the whole index when one of its matches is scored
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TermStatistics {
+    pub total_num_docs: u64,
+    pub doc_freq: u64,
+    /// How many times the term appears in total.  Only the DFR models that need it look it up.
+    pub total_term_freq: u64,
+    pub average_fieldnorm: Score,
+}
+
+impl SearchSimilarity {
+    pub fn from_json_value(value: &serde_json::Value) -> Result<Self> {
+        let similarity: SearchSimilarity = serde_json::from_value(value.clone())
+            .map_err(|err| anyhow::anyhow!("invalid 'similarity': {err}"))?;
+
+        let valid = match similarity {
+            SearchSimilarity::Bm25 { k1, b } => {
+                k1.is_finite() && k1 >= 0.0 && (0.0..=1.0).contains(&b)
+            }
+            SearchSimilarity::Bm25Plus { k1, b, delta } => {
+                k1.is_finite()
+                    && k1 >= 0.0
+                    && (0.0..=1.0).contains(&b)
+                    && delta.is_finite()
+                    && delta >= 0.0
+            }
+            SearchSimilarity::Dfr { c, .. } => c.is_finite() && c > 0.0,
+            SearchSimilarity::TfIdf | SearchSimilarity::Boolean => true,
+        };
+        if !valid {
+            anyhow::bail!(
+                "'similarity' needs a non-negative 'k1' and 'delta', a 'b' between 0 and 1, and a positive 'c', found: {value}"
+            );
+        }
+        Ok(similarity)
+    }
+
+    /// Does this score the same way Tantivy does on its own?
+    pub fn is_default(&self) -> bool {
+        *self == SearchSimilarity::default()
+    }
+
+    /// Does scoring need the term's `total_term_freq`, which is costly to find out?
+    pub fn needs_total_term_freq(&self) -> bool {
+        matches!(
+            self,
+            SearchSimilarity::Dfr {
+                basic_model: DfrBasicModel::G | DfrBasicModel::If | DfrBasicModel::Ine,
+                ..
+            } | SearchSimilarity::Dfr {
+                after_effect: DfrAfterEffect::B,
+                ..
+            }
+        )
+    }
+
+    /// The score of a doc that's `fieldnorm` tokens long and contains the term `term_freq` times
+    pub fn score(&self, stats: &TermStatistics, term_freq: u32, fieldnorm: Score) -> Score {
+        let num_docs = stats.total_num_docs.max(1) as Score;
+        let doc_freq = stats.doc_freq.max(1) as Score;
+        let term_freq = term_freq as Score;
+        let average_fieldnorm = stats.average_fieldnorm.max(1.0);
+        let fieldnorm = if fieldnorm > 0.0 {
+            fieldnorm
+        } else {
+            average_fieldnorm
+        };
+
+        match *self {
+            SearchSimilarity::Bm25 { k1, b } => {
+                bm25_idf(num_docs, doc_freq)
+                    * bm25_tf(term_freq, fieldnorm, average_fieldnorm, k1, b)
+            }
+            SearchSimilarity::Bm25Plus { k1, b, delta } => {
+                bm25_idf(num_docs, doc_freq)
+                    * (bm25_tf(term_freq, fieldnorm, average_fieldnorm, k1, b) + delta)
+            }
+            SearchSimilarity::TfIdf => {
+                let idf = 1.0 + ((num_docs + 1.0) / (doc_freq + 1.0)).ln();
+                term_freq.sqrt() * idf / fieldnorm.sqrt()
+            }
+            SearchSimilarity::Boolean => 1.0,
+            SearchSimilarity::Dfr {
+                basic_model,
+                after_effect,
+                normalization,
+                c,
+            } => {
+                let total_term_freq = (stats.total_term_freq as Score).max(term_freq);
+                let tfn = match normalization {
+                    DfrNormalization::H1 => term_freq * average_fieldnorm / fieldnorm,
+                    DfrNormalization::H2 => {
+                        term_freq * (1.0 + c * average_fieldnorm / fieldnorm).log2()
+                    }
+                };
+                let basic = match basic_model {
+                    DfrBasicModel::G => {
+                        let lambda = (total_term_freq + 1.0) / (num_docs + 1.0);
+                        (1.0 + lambda).log2() + tfn * ((1.0 + lambda) / lambda).log2()
+                    }
+                    DfrBasicModel::If => {
+                        tfn * (1.0 + (num_docs + 1.0) / (total_term_freq + 0.5)).log2()
+                    }
+                    DfrBasicModel::In => tfn * (1.0 + (num_docs + 1.0) / (doc_freq + 0.5)).log2(),
+                    DfrBasicModel::Ine => {
+                        let expected_doc_freq =
+                            num_docs * (1.0 - ((num_docs - 1.0) / num_docs).powf(total_term_freq));
+                        tfn * (1.0 + (num_docs + 1.0) / (expected_doc_freq + 0.5)).log2()
+                    }
+                };
+                let after_effect = match after_effect {
+                    DfrAfterEffect::L => 1.0 / (tfn + 1.0),
+                    DfrAfterEffect::B => (total_term_freq + 1.0) / (doc_freq * (tfn + 1.0)),
+                };
+                basic * after_effect
+            }
+        }
+    }
+}
+
+fn bm25_idf(num_docs: Score, doc_freq: Score) -> Score {
+    (1.0 + (num_docs - doc_freq + 0.5) / (doc_freq + 0.5)).ln()
+}
+
+fn bm25_tf(
+    term_freq: Score,
+    fieldnorm: Score,
+    average_fieldnorm: Score,
+    k1: Score,
+    b: Score,
+) -> Score {
+    term_freq * (k1 + 1.0) / (term_freq + k1 * (1.0 - b + b * fieldnorm / average_fieldnorm))
+}
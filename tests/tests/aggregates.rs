@@ -0,0 +1,175 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for aggregates computed by the index, both pushed down from SQL and via paradedb.aggregate()
+mod fixtures;
+
+use fixtures::utils::explain;
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use sqlx::PgConnection;
+
+#[rstest]
+fn aggregates_without_group_by(mut conn: PgConnection) {
+    AggItemsTable::setup().execute(&mut conn);
+
+    let sql = "SELECT COUNT(*), SUM(rating), AVG(rating)::float8, MIN(rating), MAX(rating) FROM agg_items WHERE description @@@ 'shoes OR keyboard'";
+    let plan = explain(sql, &mut conn);
+    assert!(plan.contains("ParadeDB Scan"), "{plan}");
+    assert!(
+        plan.contains("Aggregates: COUNT(*), SUM(rating), AVG(rating), MIN(rating), MAX(rating)"),
+        "{plan}"
+    );
+
+    let pushed_down = sql.fetch_one::<(i64, i64, f64, i32, i32)>(&mut conn);
+    "SET paradedb.enable_aggregate_custom_scan = off".execute(&mut conn);
+    let expected = sql.fetch_one::<(i64, i64, f64, i32, i32)>(&mut conn);
+    assert_eq!(pushed_down, expected);
+}
+
+#[rstest]
+fn aggregates_with_no_matches(mut conn: PgConnection) {
+    AggItemsTable::setup().execute(&mut conn);
+
+    let (count, sum, max) = "SELECT COUNT(*), SUM(rating), MAX(rating) FROM agg_items WHERE description @@@ 'does-not-exist'"
+        .fetch_one::<(i64, Option<i64>, Option<i32>)>(&mut conn);
+    assert_eq!((count, sum, max), (0, None, None));
+}
+
+#[rstest]
+fn aggregates_with_group_by(mut conn: PgConnection) {
+    AggItemsTable::setup().execute(&mut conn);
+
+    let sql = "SELECT category, COUNT(*), SUM(rating) + 1, MAX(rating) FROM agg_items WHERE description @@@ 'shoes OR keyboard OR plastic' GROUP BY category ORDER BY category";
+    let plan = explain(sql, &mut conn);
+    assert!(plan.contains("Group By: category"), "{plan}");
+
+    let pushed_down = sql.fetch::<(Option<String>, i64, i64, i32)>(&mut conn);
+    "SET paradedb.enable_aggregate_custom_scan = off".execute(&mut conn);
+    let expected = sql.fetch::<(Option<String>, i64, i64, i32)>(&mut conn);
+    assert_eq!(pushed_down, expected);
+}
+
+#[rstest]
+fn aggregates_respect_mvcc(mut conn: PgConnection) {
+    AggItemsTable::setup().execute(&mut conn);
+
+    // the old versions of these rows are still in the index, but must not be counted
+    "UPDATE agg_items SET rating = rating + 1 WHERE description @@@ 'shoes'".execute(&mut conn);
+    "DELETE FROM agg_items WHERE id = 1".execute(&mut conn);
+
+    let sql = "SELECT rating, COUNT(*) FROM agg_items WHERE description @@@ 'shoes OR keyboard' GROUP BY rating ORDER BY rating";
+    let pushed_down = sql.fetch::<(i32, i64)>(&mut conn);
+    "SET paradedb.enable_aggregate_custom_scan = off".execute(&mut conn);
+    let expected = sql.fetch::<(i32, i64)>(&mut conn);
+    assert_eq!(pushed_down, expected);
+}
+
+#[rstest]
+fn unsupported_aggregates_are_not_pushed_down(mut conn: PgConnection) {
+    AggItemsTable::setup().execute(&mut conn);
+
+    // `description` isn't a fast field, and neither DISTINCT nor HAVING can be pushed down
+    for sql in [
+        "SELECT description, COUNT(*) FROM agg_items WHERE description @@@ 'shoes' GROUP BY description",
+        "SELECT COUNT(DISTINCT rating) FROM agg_items WHERE description @@@ 'shoes'",
+        "SELECT rating, COUNT(*) FROM agg_items WHERE description @@@ 'shoes' GROUP BY rating HAVING COUNT(*) > 1",
+    ] {
+        let plan = explain(sql, &mut conn);
+        assert!(!plan.contains("Aggregates:"), "{plan}");
+    }
+}
+
+#[rstest]
+fn sums_are_exact(mut conn: PgConnection) {
+    r#"
+    CREATE TABLE big_values (id SERIAL PRIMARY KEY, description TEXT, big BIGINT, small INTEGER);
+    INSERT INTO big_values (description, big, small) VALUES
+        ('shoes', 9007199254740993, 2147483647),
+        ('shoes', 9007199254740993, 2147483647);
+    CREATE INDEX big_values_idx ON big_values
+        USING bm25 (id, description, big, small)
+        WITH (
+            key_field='id',
+            text_fields='{"description": {}}',
+            numeric_fields='{"big": {"fast": true}, "small": {"fast": true}}'
+        );
+    "#
+    .execute(&mut conn);
+
+    // Tantivy's f64 stats can't represent these int8 values, so they're summed by Postgres
+    let sql = "SELECT SUM(big) = 18014398509481986, AVG(big) = 9007199254740993 FROM big_values WHERE description @@@ 'shoes'";
+    let plan = explain(sql, &mut conn);
+    assert!(!plan.contains("Aggregates:"), "{plan}");
+    assert_eq!(sql.fetch_one::<(bool, bool)>(&mut conn), (true, true));
+
+    let sql =
+        "SELECT SUM(small), AVG(small) = 2147483647 FROM big_values WHERE description @@@ 'shoes'";
+    let plan = explain(sql, &mut conn);
+    assert!(
+        plan.contains("Aggregates: SUM(small), AVG(small)"),
+        "{plan}"
+    );
+    assert_eq!(sql.fetch_one::<(i64, bool)>(&mut conn), (4294967294, true));
+}
+
+#[rstest]
+fn aggregate_function(mut conn: PgConnection) {
+    use serde_json::Value;
+
+    AggItemsTable::setup().execute(&mut conn);
+    "DELETE FROM agg_items WHERE id IN (1, 2, 3)".execute(&mut conn);
+
+    let (result,) = r#"SELECT paradedb.aggregate('agg_items_idx', paradedb.all(), '{
+        "rating_count": {"value_count": {"field": "rating"}},
+        "rating_avg": {"avg": {"field": "rating"}},
+        "categories": {"terms": {"field": "category", "size": 100}}
+    }')"#
+        .fetch_one::<(Value,)>(&mut conn);
+
+    let (count, avg) = "SELECT COUNT(rating), AVG(rating)::float8 FROM agg_items"
+        .fetch_one::<(i64, f64)>(&mut conn);
+    assert_eq!(result["rating_count"]["value"].as_f64(), Some(count as f64));
+    assert!((result["rating_avg"]["value"].as_f64().unwrap() - avg).abs() < 1e-9);
+
+    let mut buckets = result["categories"]["buckets"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|bucket| {
+            (
+                bucket["key"].as_str().unwrap().to_string(),
+                bucket["doc_count"].as_i64().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    buckets.sort();
+    let mut expected = "SELECT category, COUNT(*) FROM agg_items GROUP BY category"
+        .fetch::<(String, i64)>(&mut conn);
+    expected.sort();
+    assert_eq!(buckets, expected);
+}
+
+#[rstest]
+fn aggregate_function_rejects_invalid_request(mut conn: PgConnection) {
+    AggItemsTable::setup().execute(&mut conn);
+
+    let result = r#"SELECT paradedb.aggregate('agg_items_idx', paradedb.all(), '{"bad": {"no_such_agg": {}}}')"#
+        .execute_result(&mut conn);
+    assert!(result.is_err());
+}
@@ -0,0 +1,141 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for named analyzers created with paradedb.create_analyzer
+mod fixtures;
+
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use sqlx::PgConnection;
+
+fn create_analyzer(conn: &mut PgConnection) {
+    r#"
+    SELECT paradedb.create_analyzer('english_words', '{
+        "tokenizer": {"type": "whitespace", "lowercase": false},
+        "filters": [
+            {"type": "pattern_replace", "pattern": "[^A-Za-z]", "replacement": ""},
+            {"type": "lowercase"},
+            {"type": "stemmer", "language": "English"}
+        ]
+    }')
+    "#
+    .execute(conn);
+}
+
+fn ids(query: &str, conn: &mut PgConnection) -> Vec<i32> {
+    format!("SELECT id FROM notes WHERE id @@@ {query} ORDER BY id")
+        .fetch::<(i32,)>(conn)
+        .into_iter()
+        .map(|(id,)| id)
+        .collect()
+}
+
+#[rstest]
+fn analyzer_tokenize(mut conn: PgConnection) {
+    create_analyzer(&mut conn);
+
+    let tokens: Vec<(String, i32)> = r#"
+    SELECT * FROM paradedb.tokenize(
+        paradedb.tokenizer('analyzer', analyzer => 'english_words'),
+        'Running, jumping!'
+    )
+    "#
+    .fetch(&mut conn);
+    assert_eq!(tokens, vec![("run".into(), 0), ("jump".into(), 1)]);
+
+    let result = "SELECT * FROM paradedb.tokenize(paradedb.tokenizer('analyzer', analyzer => 'missing'), 'text')"
+        .execute_result(&mut conn);
+    assert!(result.is_err());
+}
+
+#[rstest]
+fn invalid_analyzer(mut conn: PgConnection) {
+    let result = r#"
+    SELECT paradedb.create_analyzer('broken', '{
+        "tokenizer": {"type": "whitespace"},
+        "filters": [{"type": "edge_ngram", "min": 3, "max": 2}]
+    }')
+    "#
+    .execute_result(&mut conn);
+    assert!(result.is_err());
+
+    let count: (i64,) = "SELECT count(*) FROM paradedb.analyzers".fetch_one(&mut conn);
+    assert_eq!(count, (0,));
+}
+
+#[rstest]
+fn analyzer_index_keeps_its_version(mut conn: PgConnection) {
+    create_analyzer(&mut conn);
+    r#"
+        CREATE TABLE notes (id SERIAL PRIMARY KEY, body TEXT);
+        INSERT INTO notes (body) VALUES ('Running late!'), ('Sleeping in'), ('the runner');
+
+        CREATE INDEX notes_idx ON notes
+        USING bm25 (id, body)
+        WITH (
+            key_field = 'id',
+            text_fields = '{"body": {"tokenizer": {"type": "analyzer", "name": "english_words"}}}'
+        );
+    "#
+    .execute(&mut conn);
+
+    assert_eq!(ids("'body:runs'", &mut conn), vec![1]);
+    assert_eq!(ids("paradedb.term('body', 'sleep')", &mut conn), vec![2]);
+
+    // the index keeps the version it was built with until it's reindexed
+    r#"
+    SELECT paradedb.create_analyzer('english_words', '{
+        "tokenizer": {"type": "whitespace"}
+    }')
+    "#
+    .execute(&mut conn);
+    let version: (i64,) =
+        "SELECT version FROM paradedb.analyzers WHERE name = 'english_words'".fetch_one(&mut conn);
+    assert_eq!(version, (2,));
+
+    "INSERT INTO notes (body) VALUES ('Running errands')".execute(&mut conn);
+    assert_eq!(ids("paradedb.term('body', 'run')", &mut conn), vec![1, 4]);
+
+    "REINDEX INDEX notes_idx".execute(&mut conn);
+    assert_eq!(ids("paradedb.term('body', 'run')", &mut conn), vec![]);
+    assert_eq!(
+        ids("paradedb.term('body', 'running')", &mut conn),
+        vec![1, 4]
+    );
+    assert_eq!(ids("paradedb.term('body', 'late!')", &mut conn), vec![1]);
+}
+
+#[rstest]
+fn missing_analyzer(mut conn: PgConnection) {
+    r#"
+        CREATE TABLE notes (id SERIAL PRIMARY KEY, body TEXT);
+        INSERT INTO notes (body) VALUES ('text');
+    "#
+    .execute(&mut conn);
+
+    let result = r#"
+        CREATE INDEX notes_idx ON notes
+        USING bm25 (id, body)
+        WITH (
+            key_field = 'id',
+            text_fields = '{"body": {"tokenizer": {"type": "analyzer", "name": "missing"}}}'
+        )
+    "#
+    .execute_result(&mut conn);
+    assert!(result.is_err());
+}
@@ -0,0 +1,117 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for array columns, whose elements are indexed as the values of a multi-valued field
+mod fixtures;
+
+use fixtures::utils::{explain, ids};
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use sqlx::PgConnection;
+
+#[rstest]
+fn any_element_matches(mut conn: PgConnection) {
+    ArrayItemsTable::setup().execute(&mut conn);
+
+    let queries = [
+        ("paradedb.term('tags', 'red')", vec![1, 3]),
+        ("paradedb.term('tags', 'yellow')", vec![4]),
+        (
+            "paradedb.term_set(terms => ARRAY[paradedb.term('tags', 'green'), paradedb.term('tags', 'yellow')])",
+            vec![2, 3, 4],
+        ),
+        ("paradedb.term('scores', 40)", vec![3]),
+        ("paradedb.range('scores', '[10,20)'::int8range)", vec![1, 3]),
+        ("paradedb.range('scores', '[31,)'::int8range)", vec![3]),
+        (
+            "paradedb.term('seen', '2024-06-01 00:00:00+00'::timestamptz)",
+            vec![1, 5],
+        ),
+        (
+            "paradedb.range('seen', tstzrange('2024-02-01 00:00:00+00', '2024-04-01 00:00:00+00'))",
+            vec![2],
+        ),
+        ("paradedb.exists('scores')", vec![1, 2, 3]),
+    ];
+
+    for (query, expected) in queries {
+        let sql = format!("SELECT id FROM items WHERE id @@@ {query} ORDER BY id");
+        assert_eq!(ids(&sql, &mut conn), expected, "{query}");
+    }
+}
+
+#[rstest]
+fn array_predicates_are_pushed_down(mut conn: PgConnection) {
+    ArrayItemsTable::setup().execute(&mut conn);
+
+    let predicates = [
+        "'blue' = ANY(tags)",
+        "tags @> ARRAY['blue', 'red']",
+        "ARRAY['green'] <@ tags",
+        "tags && ARRAY['green', 'yellow']",
+        "15 = ANY(scores)",
+        "scores @> ARRAY[12, 7]::int8[]",
+        "scores && ARRAY[1, 30]::int8[]",
+    ];
+
+    for predicate in predicates {
+        let sql =
+            format!("SELECT id FROM items WHERE id @@@ paradedb.all() AND {predicate} ORDER BY id");
+        let plan = explain(&sql, &mut conn);
+        assert!(plan.contains("ParadeDB Scan"), "{predicate}: {plan}");
+
+        let pushed_down = ids(&sql, &mut conn);
+        "SET paradedb.enable_custom_scan TO off".execute(&mut conn);
+        let expected = ids(&sql, &mut conn);
+        "SET paradedb.enable_custom_scan TO on".execute(&mut conn);
+        assert!(!expected.is_empty(), "{predicate}");
+        assert_eq!(pushed_down, expected, "{predicate}");
+    }
+
+    // no array contains a NULL, and neither comparing whole arrays nor `ALL` is about any one
+    // element
+    let predicates = [
+        "tags @> ARRAY['yellow', NULL]",
+        "tags = ARRAY['green']",
+        "'red' = ALL(tags)",
+    ];
+
+    for predicate in predicates {
+        let sql =
+            format!("SELECT id FROM items WHERE id @@@ paradedb.all() AND {predicate} ORDER BY id");
+        let plan = explain(&sql, &mut conn);
+        assert!(!plan.contains("ParadeDB Scan"), "{predicate}: {plan}");
+    }
+}
+
+#[rstest]
+fn order_by_array_column(mut conn: PgConnection) {
+    ArrayItemsTable::setup().execute(&mut conn);
+
+    // Postgres compares arrays element by element, which a multi-valued fast field can't do
+    for column in ["tags", "scores", "seen"] {
+        let sql = format!(
+            "SELECT id FROM items WHERE id @@@ paradedb.all() ORDER BY {column}, id LIMIT 3"
+        );
+        let results = ids(&sql, &mut conn);
+        "SET paradedb.enable_custom_scan TO off".execute(&mut conn);
+        let expected = ids(&sql, &mut conn);
+        "SET paradedb.enable_custom_scan TO on".execute(&mut conn);
+        assert_eq!(results, expected, "{column}");
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for bitmap index scans over the bm25 index
+mod fixtures;
+
+use fixtures::utils::explain;
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use sqlx::PgConnection;
+
+fn without_bitmap_scans(sql: &str, conn: &mut PgConnection) -> Vec<(i32,)> {
+    "SET enable_bitmapscan TO off; SET enable_seqscan TO on".execute(conn);
+    let rows = sql.fetch::<(i32,)>(conn);
+    "SET enable_bitmapscan TO on; SET enable_seqscan TO off".execute(conn);
+    rows
+}
+
+#[rstest]
+fn bitmap_scan(mut conn: PgConnection) {
+    SimpleProductsTable::setup_bitmap_scans().execute(&mut conn);
+
+    let sql =
+        "SELECT id FROM paradedb.bm25_search WHERE description @@@ 'shoes OR keyboard' ORDER BY id";
+    let plan = explain(sql, &mut conn);
+    assert!(
+        plan.contains("Bitmap Index Scan on bm25_search_bm25_index"),
+        "{plan}"
+    );
+
+    let rows = sql.fetch::<(i32,)>(&mut conn);
+    assert_eq!(rows, without_bitmap_scans(sql, &mut conn));
+}
+
+#[rstest]
+fn bitmap_or_with_btree(mut conn: PgConnection) {
+    SimpleProductsTable::setup_bitmap_scans().execute(&mut conn);
+
+    let sql = "SELECT id FROM paradedb.bm25_search WHERE description @@@ 'shoes' OR rating = 5 ORDER BY id";
+    let plan = explain(sql, &mut conn);
+    assert!(plan.contains("BitmapOr"), "{plan}");
+
+    let rows = sql.fetch::<(i32,)>(&mut conn);
+    assert_eq!(rows, without_bitmap_scans(sql, &mut conn));
+}
+
+#[rstest]
+fn bitmap_and_with_btree(mut conn: PgConnection) {
+    SimpleProductsTable::setup_bitmap_scans().execute(&mut conn);
+
+    let sql = "SELECT id FROM paradedb.bm25_search WHERE description @@@ 'shoes OR keyboard OR plastic' AND rating = 4 ORDER BY id";
+    let plan = explain(sql, &mut conn);
+    assert!(plan.contains("BitmapAnd"), "{plan}");
+    assert!(
+        plan.contains("Bitmap Index Scan on bm25_search_bm25_index"),
+        "{plan}"
+    );
+    assert!(
+        plan.contains("Bitmap Index Scan on bm25_search_rating_idx"),
+        "{plan}"
+    );
+
+    let rows = sql.fetch::<(i32,)>(&mut conn);
+    assert_eq!(rows, without_bitmap_scans(sql, &mut conn));
+}
+
+#[rstest]
+fn parallel_bitmap_heap_scan(mut conn: PgConnection) {
+    SimpleProductsTable::setup_bitmap_scans().execute(&mut conn);
+
+    r#"
+        SET max_parallel_workers_per_gather TO 2;
+        SET parallel_setup_cost TO 0;
+        SET parallel_tuple_cost TO 0;
+        SET min_parallel_table_scan_size TO 0;
+        SET min_parallel_index_scan_size TO 0;
+    "#
+    .execute(&mut conn);
+
+    // the bitmap is built from the index, which still has the deleted row, but the heap pages
+    // the workers are handed must only give back the visible ones
+    "DELETE FROM paradedb.bm25_search WHERE id = 2".execute(&mut conn);
+
+    let sql = "SELECT id FROM paradedb.bm25_search WHERE description @@@ 'shoes OR keyboard OR plastic' ORDER BY id";
+    let plan = explain(sql, &mut conn);
+    assert!(plan.contains("Parallel Bitmap Heap Scan"), "{plan}");
+    assert!(plan.contains("Workers Planned"), "{plan}");
+    assert!(
+        plan.contains("Bitmap Index Scan on bm25_search_bm25_index"),
+        "{plan}"
+    );
+
+    let rows = sql.fetch::<(i32,)>(&mut conn);
+    assert!(!rows.contains(&(2,)));
+    assert_eq!(rows, without_bitmap_scans(sql, &mut conn));
+}
@@ -0,0 +1,188 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for bm25 indexes over expressions, and partial bm25 indexes
+mod fixtures;
+
+use fixtures::utils::{explain, ids};
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use sqlx::PgConnection;
+
+#[rstest]
+fn expression_fields(mut conn: PgConnection) {
+    r#"
+    CREATE TABLE docs (id SERIAL PRIMARY KEY, title TEXT, body TEXT);
+    INSERT INTO docs (title, body) VALUES
+        ('Running Shoes', 'comfortable and light'),
+        ('Keyboard', 'wireless and quiet');
+
+    CREATE INDEX docs_idx ON docs
+    USING bm25 (id, (lower(title) || ' ' || body), lower(title))
+    WITH (
+        key_field = 'id',
+        text_fields = '{"expr": {}, "lower": {"tokenizer": {"type": "raw", "lowercase": false}}}'
+    );
+    "#
+    .execute(&mut conn);
+
+    // an expression is a field named after the index's attribute for it
+    assert_eq!(
+        ids(
+            "SELECT id FROM docs WHERE id @@@ paradedb.term('expr', 'running') ORDER BY id",
+            &mut conn
+        ),
+        vec![1]
+    );
+    assert_eq!(
+        ids(
+            "SELECT id FROM docs WHERE id @@@ 'expr:wireless' ORDER BY id",
+            &mut conn
+        ),
+        vec![2]
+    );
+    assert_eq!(
+        ids(
+            "SELECT id FROM docs WHERE id @@@ paradedb.term('lower', 'running shoes') ORDER BY id",
+            &mut conn
+        ),
+        vec![1]
+    );
+
+    // and it's computed for the rows that are inserted and updated later
+    r#"
+    INSERT INTO docs (title, body) VALUES ('Wireless Mouse', 'small');
+    UPDATE docs SET title = 'Trail Shoes' WHERE id = 1;
+    "#
+    .execute(&mut conn);
+    assert_eq!(
+        ids(
+            "SELECT id FROM docs WHERE id @@@ 'expr:wireless' ORDER BY id",
+            &mut conn
+        ),
+        vec![2, 3]
+    );
+    assert_eq!(
+        ids(
+            "SELECT id FROM docs WHERE id @@@ paradedb.term('lower', 'trail shoes') ORDER BY id",
+            &mut conn
+        ),
+        vec![1]
+    );
+    assert_eq!(
+        ids(
+            "SELECT id FROM docs WHERE id @@@ paradedb.term('expr', 'running') ORDER BY id",
+            &mut conn
+        ),
+        Vec::<i32>::new()
+    );
+}
+
+#[rstest]
+fn partial_index(mut conn: PgConnection) {
+    r#"
+    CREATE TABLE docs (id SERIAL PRIMARY KEY, body TEXT, deleted_at TIMESTAMP);
+    INSERT INTO docs (body, deleted_at) VALUES
+        ('red shoes', NULL),
+        ('blue shoes', now()),
+        ('green shoes', NULL),
+        ('wireless keyboard', NULL);
+
+    CREATE INDEX docs_idx ON docs
+    USING bm25 (id, body)
+    WITH (key_field = 'id')
+    WHERE deleted_at IS NULL;
+    "#
+    .execute(&mut conn);
+
+    // only the rows that satisfy the predicate are indexed, but the table's statistics count them all
+    let (reltuples,) =
+        "SELECT reltuples FROM pg_class WHERE relname = 'docs'".fetch_one::<(f32,)>(&mut conn);
+    assert_eq!(reltuples, 4.0);
+
+    let sql = "SELECT id, paradedb.score(id) FROM docs WHERE body @@@ 'shoes' AND deleted_at IS NULL ORDER BY id";
+    assert_eq!(
+        sql.fetch::<(i32, f32)>(&mut conn)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>(),
+        vec![1, 3]
+    );
+    let plan = explain(sql, &mut conn);
+    assert!(plan.contains("ParadeDB Scan"), "{plan}");
+
+    // the predicate is checked as rows are inserted and updated
+    r#"
+    INSERT INTO docs (body, deleted_at) VALUES ('black shoes', NULL), ('white shoes', now());
+    UPDATE docs SET deleted_at = now() WHERE id = 1;
+    "#
+    .execute(&mut conn);
+    assert_eq!(
+        ids(
+            "SELECT id FROM docs WHERE body @@@ 'shoes' AND deleted_at IS NULL ORDER BY id",
+            &mut conn
+        ),
+        vec![3, 5]
+    );
+
+    // the same rows are found without the custom scan
+    "SET paradedb.enable_custom_scan = false".execute(&mut conn);
+    assert_eq!(
+        ids(
+            "SELECT id FROM docs WHERE body @@@ 'shoes' AND deleted_at IS NULL ORDER BY id",
+            &mut conn
+        ),
+        vec![3, 5]
+    );
+    "SET paradedb.enable_custom_scan = true".execute(&mut conn);
+
+    // without the predicate, the index doesn't have every row the query could match, with or
+    // without the custom scan
+    for enable_custom_scan in [true, false] {
+        format!("SET paradedb.enable_custom_scan = {enable_custom_scan}").execute(&mut conn);
+        for sql in [
+            "SELECT id FROM docs WHERE body @@@ 'shoes'",
+            "SELECT id FROM docs WHERE body @@@ 'shoes' AND deleted_at IS NOT NULL",
+        ] {
+            let result = sql.fetch_result::<(i32,)>(&mut conn);
+            assert!(result.is_err(), "{sql}");
+            let err = result.unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("must imply the predicate of its partial `USING bm25` index"),
+                "{sql}"
+            );
+            assert_eq!(
+                err.as_database_error()
+                    .and_then(|err| err.code())
+                    .as_deref(),
+                Some("0A000"),
+                "{sql}"
+            );
+        }
+    }
+
+    // the rows outside the predicate can still be found without searching
+    assert_eq!(
+        ids(
+            "SELECT id FROM docs WHERE body LIKE '%shoes%' ORDER BY id",
+            &mut conn
+        ),
+        vec![1, 2, 3, 5, 6]
+    );
+}
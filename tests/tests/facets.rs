@@ -0,0 +1,130 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+mod fixtures;
+
+use fixtures::utils::explain;
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use serde_json::{json, Value};
+use sqlx::PgConnection;
+
+#[rstest]
+fn facet_counts(mut conn: PgConnection) {
+    FacetItemsTable::setup().execute(&mut conn);
+
+    let counts: Vec<(String, i64)> = "SELECT * FROM paradedb.facet_counts('facet_items_idx', paradedb.all(), 'category') ORDER BY facet"
+        .fetch(&mut conn);
+    assert_eq!(
+        counts,
+        vec![("/electronics".into(), 3), ("/footwear".into(), 4)]
+    );
+
+    let counts: Vec<(String, i64)> = "SELECT * FROM paradedb.facet_counts('facet_items_idx', paradedb.parse('description:boots OR description:phone'), 'category', '/footwear') ORDER BY facet"
+        .fetch(&mut conn);
+    assert_eq!(counts, vec![("/footwear/boots".into(), 2)]);
+
+    // each element of an array column is its own facet
+    let counts: Vec<(String, i64)> = "SELECT * FROM paradedb.facet_counts('facet_items_idx', paradedb.all(), 'tags') ORDER BY facet"
+        .fetch(&mut conn);
+    assert_eq!(counts, vec![("/new".into(), 2), ("/sale".into(), 3)]);
+}
+
+#[rstest]
+fn facet_counts_respect_mvcc(mut conn: PgConnection) {
+    FacetItemsTable::setup().execute(&mut conn);
+
+    "UPDATE facet_items SET category = '/footwear/sneakers' WHERE description = 'Summer sandals'"
+        .execute(&mut conn);
+    "DELETE FROM facet_items WHERE category = '/footwear/boots'".execute(&mut conn);
+
+    let counts: Vec<(String, i64)> = "SELECT * FROM paradedb.facet_counts('facet_items_idx', paradedb.all(), 'category', '/footwear') ORDER BY facet"
+        .fetch(&mut conn);
+    assert_eq!(counts, vec![("/footwear/sneakers".into(), 2)]);
+}
+
+#[rstest]
+fn facets_alongside_top_n(mut conn: PgConnection) {
+    FacetItemsTable::setup().execute(&mut conn);
+
+    // the phone is still in the index, but it mustn't be counted
+    "DELETE FROM facet_items WHERE description = 'Budget phone'".execute(&mut conn);
+
+    let sql = "SELECT id, paradedb.facets(id, 'category'), paradedb.facets(id, 'category', '/footwear') FROM facet_items WHERE description @@@ 'boots OR phone OR laptop' ORDER BY paradedb.score(id) DESC LIMIT 2";
+    let plan = explain(sql, &mut conn);
+    assert!(plan.contains("TopNScanExecState"), "{plan}");
+
+    // every row gets the counts of all the matches, not just of the rows returned
+    let rows: Vec<(i32, Value, Value)> = sql.fetch(&mut conn);
+    assert_eq!(rows.len(), 2);
+    for (_, category, footwear) in rows {
+        assert_eq!(category, json!({"/electronics": 2, "/footwear": 2}));
+        assert_eq!(footwear, json!({"/footwear/boots": 2}));
+    }
+}
+
+#[rstest]
+fn facets_without_top_n(mut conn: PgConnection) {
+    FacetItemsTable::setup().execute(&mut conn);
+
+    "DELETE FROM facet_items WHERE description = 'Sleek running shoes'".execute(&mut conn);
+
+    let rows: Vec<(i32, Value)> = "SELECT id, paradedb.facets(id, 'tags') FROM facet_items WHERE id @@@ paradedb.all() ORDER BY id"
+        .fetch(&mut conn);
+    assert_eq!(rows.len(), 6);
+    for (_, tags) in rows {
+        assert_eq!(tags, json!({"/new": 2, "/sale": 2}));
+    }
+
+    let result = "SELECT id, paradedb.facets(id, 'description') FROM facet_items WHERE id @@@ paradedb.all() ORDER BY paradedb.score(id) DESC LIMIT 2"
+        .execute_result(&mut conn);
+    assert!(result.is_err());
+}
+
+#[rstest]
+fn facet_filter(mut conn: PgConnection) {
+    FacetItemsTable::setup().execute(&mut conn);
+
+    // a facet matches everything filed underneath it, too
+    let ids: Vec<(i32,)> =
+        "SELECT id FROM facet_items WHERE id @@@ paradedb.facet('category', '/electronics/phones') ORDER BY id"
+            .fetch(&mut conn);
+    assert_eq!(ids, vec![(5,), (6,)]);
+
+    let ids: Vec<(i32,)> = "SELECT id FROM facet_items WHERE id @@@ paradedb.boolean(must => ARRAY[paradedb.facet('tags', '/sale'), paradedb.facet('category', '/footwear')]) ORDER BY id"
+        .fetch(&mut conn);
+    assert_eq!(ids, vec![(1,), (2,)]);
+}
+
+#[rstest]
+fn invalid_facets(mut conn: PgConnection) {
+    FacetItemsTable::setup().execute(&mut conn);
+
+    let result = "INSERT INTO facet_items (description, category) VALUES ('Mystery item', 'no-leading-slash')"
+        .execute_result(&mut conn);
+    assert!(result.is_err());
+
+    let result =
+        "SELECT * FROM paradedb.facet_counts('facet_items_idx', paradedb.all(), 'description')"
+            .execute_result(&mut conn);
+    assert!(result.is_err());
+
+    let result = "CREATE INDEX facet_items_bad_idx ON facet_items USING bm25 (id, description) WITH (key_field='id', facet_fields='{\"id\": {}}')"
+        .execute_result(&mut conn);
+    assert!(result.is_err());
+}
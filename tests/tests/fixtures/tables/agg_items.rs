@@ -0,0 +1,49 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use chrono::{NaiveDate, NaiveDateTime};
+use sqlx::FromRow;
+
+#[derive(Debug, PartialEq, FromRow)]
+pub struct AggItemsTable {
+    pub id: i32,
+    pub description: String,
+    pub category: String,
+    pub rating: i32,
+    pub in_stock: bool,
+    pub metadata: serde_json::Value,
+    pub created_at: NaiveDateTime,
+    pub last_updated_date: NaiveDate,
+}
+
+impl AggItemsTable {
+    pub fn setup() -> String {
+        AGG_ITEMS_TABLE_SETUP.into()
+    }
+}
+
+static AGG_ITEMS_TABLE_SETUP: &str = r#"
+CALL paradedb.create_bm25_test_table(table_name => 'agg_items', schema_name => 'public');
+
+CREATE INDEX agg_items_idx ON agg_items
+    USING bm25 (id, description, category, rating)
+    WITH (
+        key_field='id',
+        text_fields='{"description": {}, "category": {"fast": true}}',
+        numeric_fields='{"rating": {"fast": true}}'
+    );
+"#;
@@ -0,0 +1,53 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+
+#[derive(Debug, PartialEq, FromRow)]
+pub struct ArrayItemsTable {
+    pub id: i32,
+    pub tags: Option<Vec<Option<String>>>,
+    pub scores: Option<Vec<i64>>,
+    pub seen: Option<Vec<DateTime<Utc>>>,
+}
+
+impl ArrayItemsTable {
+    pub fn setup() -> String {
+        ARRAY_ITEMS_TABLE_SETUP.into()
+    }
+}
+
+static ARRAY_ITEMS_TABLE_SETUP: &str = r#"
+SET enable_indexscan TO off;
+CREATE TABLE items (id SERIAL PRIMARY KEY, tags TEXT[], scores INT8[], seen TIMESTAMPTZ[]);
+INSERT INTO items (tags, scores, seen) VALUES
+    (ARRAY['red', 'blue'], ARRAY[1, 15], ARRAY['2024-01-01 00:00:00+00', '2024-06-01 00:00:00+00']::timestamptz[]),
+    (ARRAY['green'], ARRAY[30], ARRAY['2024-03-01 00:00:00+00']::timestamptz[]),
+    (ARRAY['blue', 'green', 'red'], ARRAY[12, 40, 7], ARRAY['2023-12-01 00:00:00+00']::timestamptz[]),
+    (ARRAY['yellow', NULL], ARRAY[]::int8[], NULL),
+    (NULL, NULL, ARRAY['2024-06-01 00:00:00+00']::timestamptz[]);
+
+CREATE INDEX items_idx ON items
+USING bm25 (id, tags, scores, seen)
+WITH (
+    key_field = 'id',
+    text_fields = '{"tags": {"tokenizer": {"type": "raw", "lowercase": false}, "fast": true}}',
+    numeric_fields = '{"scores": {"fast": true}}',
+    datetime_fields = '{"seen": {"fast": true}}'
+);
+"#;
@@ -0,0 +1,51 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use sqlx::FromRow;
+
+#[derive(Debug, PartialEq, FromRow)]
+pub struct FacetItemsTable {
+    pub id: i32,
+    pub description: String,
+    pub category: String,
+    pub tags: Option<Vec<String>>,
+}
+
+impl FacetItemsTable {
+    pub fn setup() -> String {
+        FACET_ITEMS_TABLE_SETUP.into()
+    }
+}
+
+static FACET_ITEMS_TABLE_SETUP: &str = r#"
+CREATE TABLE facet_items (id SERIAL PRIMARY KEY, description TEXT, category TEXT, tags TEXT[]);
+INSERT INTO facet_items (description, category, tags) VALUES
+    ('Sleek running shoes', '/footwear/sneakers', '{/sale}'),
+    ('Leather hiking boots', '/footwear/boots', '{/sale,/new}'),
+    ('Waterproof winter boots', '/footwear/boots', '{/new}'),
+    ('Summer sandals', '/footwear/sandals', NULL),
+    ('Wireless phone charger', '/electronics/phones/accessories', '{/sale}'),
+    ('Budget phone', '/electronics/phones', NULL),
+    ('Gaming laptop', '/electronics/laptops', NULL);
+CREATE INDEX facet_items_idx ON facet_items
+USING bm25 (id, description, category, tags)
+WITH (
+    key_field='id',
+    text_fields='{"description": {}}',
+    facet_fields='{"category": {}, "tags": {}}'
+);
+"#;
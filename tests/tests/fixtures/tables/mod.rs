@@ -15,22 +15,40 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+mod agg_items;
+mod array_items;
 mod deliveries;
 mod duckdb_types;
+mod facet_items;
 mod icu_amharic_posts;
 mod icu_arabic_posts;
 mod icu_czech_posts;
 mod icu_greek_posts;
+mod nested_products;
 mod nyc_trips;
+mod passages;
+mod regional_docs;
+mod sales;
+mod similarity_docs;
 mod simple_products;
+mod synonym_items;
 mod user_session_logs;
 
+pub use agg_items::*;
+pub use array_items::*;
 pub use deliveries::*;
 pub use duckdb_types::*;
+pub use facet_items::*;
 pub use icu_amharic_posts::*;
 pub use icu_arabic_posts::*;
 pub use icu_czech_posts::*;
 pub use icu_greek_posts::*;
+pub use nested_products::*;
 pub use nyc_trips::*;
+pub use passages::*;
+pub use regional_docs::*;
+pub use sales::*;
+pub use similarity_docs::*;
 pub use simple_products::*;
+pub use synonym_items::*;
 pub use user_session_logs::*;
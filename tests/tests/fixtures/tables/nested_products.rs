@@ -0,0 +1,51 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use sqlx::FromRow;
+
+#[derive(Debug, PartialEq, FromRow)]
+pub struct NestedProductsTable {
+    pub id: i32,
+    pub name: String,
+    pub metadata: serde_json::Value,
+    pub sizes: serde_json::Value,
+}
+
+impl NestedProductsTable {
+    pub fn setup() -> String {
+        NESTED_PRODUCTS_TABLE_SETUP.into()
+    }
+}
+
+static NESTED_PRODUCTS_TABLE_SETUP: &str = r#"
+CREATE TABLE products (id SERIAL PRIMARY KEY, name TEXT, metadata JSONB, sizes JSONB);
+INSERT INTO products (name, metadata, sizes) VALUES
+    ('shirt', '{"variants": [{"color": "red", "size": "S"}, {"color": "blue", "size": "L"}]}', '[{"size": "S"}]'),
+    ('hoodie', '{"variants": [{"color": "red", "size": "L"}]}', '[{"size": "L"}]'),
+    ('socks', '{"variants": [{"color": "red", "size": "M"}, {"color": "red", "size": "XL"}]}', '[{"size": "M"}, {"size": "XL"}]');
+
+CREATE INDEX products_idx ON products
+USING bm25 (id, name, metadata, sizes)
+WITH (
+    key_field = 'id',
+    text_fields = '{"name": {}}',
+    json_fields = '{
+        "metadata": {"nested": true},
+        "sizes": {"nested": true}
+    }'
+);
+"#;
@@ -0,0 +1,50 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use sqlx::FromRow;
+
+#[derive(Debug, PartialEq, FromRow)]
+pub struct PassagesTable {
+    pub id: i32,
+    pub body: String,
+    pub title: String,
+}
+
+impl PassagesTable {
+    pub fn setup() -> String {
+        PASSAGES_TABLE_SETUP.into()
+    }
+}
+
+static PASSAGES_TABLE_SETUP: &str = r#"
+CREATE TABLE passages (id SERIAL PRIMARY KEY, body TEXT, title TEXT);
+INSERT INTO passages (body, title) VALUES
+    ('the quick brown fox jumps over the lazy dog', 'fox'),
+    ('the lazy dog jumps over the quick brown fox', 'dog'),
+    ('quick thinking saved the brown dog', 'dog'),
+    ('a fox that is not brown', 'fox');
+
+CREATE INDEX passages_idx ON passages
+USING bm25 (id, body, title)
+WITH (
+    key_field = 'id',
+    text_fields = '{
+        "body": {},
+        "title": {"record": "basic"}
+    }'
+);
+"#;
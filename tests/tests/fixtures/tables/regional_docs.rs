@@ -0,0 +1,47 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use sqlx::FromRow;
+
+#[derive(Debug, PartialEq, FromRow)]
+pub struct RegionalDocsTable {
+    pub id: i32,
+    pub region: String,
+    pub body: String,
+}
+
+impl RegionalDocsTable {
+    pub fn setup() -> String {
+        REGIONAL_DOCS_TABLE_SETUP.into()
+    }
+}
+
+static REGIONAL_DOCS_TABLE_SETUP: &str = r#"
+CREATE TABLE docs (id INT, region TEXT, body TEXT) PARTITION BY LIST (region);
+CREATE TABLE docs_east PARTITION OF docs FOR VALUES IN ('east');
+CREATE TABLE docs_west PARTITION OF docs FOR VALUES IN ('west');
+
+INSERT INTO docs (id, region, body) VALUES (1, 'east', 'apple pie'), (2, 'west', 'apple pie');
+INSERT INTO docs (id, region, body)
+    SELECT n, 'east', CASE WHEN n % 2 = 0 THEN 'apple tart' ELSE 'cherry tart' END
+    FROM generate_series(3, 100) n;
+
+CREATE INDEX docs_east_idx ON docs_east USING bm25 (id, body) WITH (key_field = 'id', text_fields = '{"body": {}}');
+CREATE INDEX docs_west_idx ON docs_west USING bm25 (id, body) WITH (key_field = 'id', text_fields = '{"body": {}}');
+
+SET max_parallel_workers_per_gather = 0;
+"#;
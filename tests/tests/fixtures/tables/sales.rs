@@ -0,0 +1,55 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use sqlx::FromRow;
+
+#[derive(Debug, PartialEq, FromRow)]
+pub struct SalesTable {
+    pub id: i32,
+    pub region: String,
+    pub description: String,
+}
+
+impl SalesTable {
+    pub fn setup() -> String {
+        SALES_TABLE_SETUP.into()
+    }
+}
+
+static SALES_TABLE_SETUP: &str = r#"
+CREATE TABLE sales (id SERIAL, region TEXT, description TEXT) PARTITION BY LIST (region);
+CREATE TABLE sales_east PARTITION OF sales FOR VALUES IN ('east');
+CREATE TABLE sales_west PARTITION OF sales FOR VALUES IN ('west');
+
+INSERT INTO sales (region, description) VALUES
+    ('east', 'red running shoes'),
+    ('east', 'blue shoes'),
+    ('west', 'green shoes'),
+    ('west', 'wireless keyboard');
+
+CREATE INDEX sales_idx ON sales
+USING bm25 (id, region, description)
+WITH (
+    key_field = 'id',
+    text_fields = '{
+        "region": {"tokenizer": {"type": "raw", "lowercase": false}},
+        "description": {}
+    }'
+);
+
+SET max_parallel_workers_per_gather = 0;
+"#;
@@ -0,0 +1,52 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use sqlx::FromRow;
+
+#[derive(Debug, PartialEq, FromRow)]
+pub struct SimilarityDocsTable {
+    pub id: i32,
+    pub title: String,
+    pub body: String,
+}
+
+impl SimilarityDocsTable {
+    /// `similarity` is the JSON similarity config given to the `title` field.
+    pub fn setup(similarity: &str) -> String {
+        format!(
+            r#"
+            DROP TABLE IF EXISTS docs;
+            CREATE TABLE docs (id SERIAL PRIMARY KEY, title TEXT, body TEXT);
+            INSERT INTO docs (title, body) VALUES
+                ('apple', 'apple'),
+                ('apple banana cherry date elderberry fig', 'apple banana cherry date elderberry fig'),
+                ('apple apple', 'apple apple'),
+                ('grape', 'grape');
+
+            CREATE INDEX docs_idx ON docs
+            USING bm25 (id, title, body)
+            WITH (
+                key_field = 'id',
+                text_fields = '{{
+                    "title": {{"similarity": {similarity}}},
+                    "body": {{}}
+                }}'
+            );
+            "#
+        )
+    }
+}
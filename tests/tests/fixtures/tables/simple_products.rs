@@ -35,6 +35,22 @@ impl SimpleProductsTable {
     pub fn setup() -> String {
         SIMPLE_PRODUCTS_TABLE_SETUP.into()
     }
+
+    /// Leaves bitmap scans as the only way to use the bm25 and btree indexes.
+    pub fn setup_bitmap_scans() -> String {
+        format!("{SIMPLE_PRODUCTS_TABLE_SETUP}{BITMAP_SCANS_SETUP}")
+    }
+
+    /// Indexes `category` as a fast raw field so TopN scans can sort by it.
+    pub fn setup_fast_category() -> String {
+        FAST_CATEGORY_SETUP.into()
+    }
+
+    /// Indexes every column a pushed down predicate can filter on, leaving a few
+    /// ratings NULL.
+    pub fn setup_filter_fields() -> String {
+        FILTER_FIELDS_SETUP.into()
+    }
 }
 
 static SIMPLE_PRODUCTS_TABLE_SETUP: &str = r#"
@@ -61,3 +77,62 @@ BEGIN;
     );
 COMMIT;
 "#;
+
+static BITMAP_SCANS_SETUP: &str = r#"
+CREATE INDEX bm25_search_rating_idx ON paradedb.bm25_search (rating);
+ANALYZE paradedb.bm25_search;
+SET paradedb.enable_custom_scan TO off;
+SET enable_indexscan TO off;
+SET enable_indexonlyscan TO off;
+SET enable_seqscan TO off;
+"#;
+
+// ensure our custom scan wins against our small test table
+static FAST_CATEGORY_SETUP: &str = r#"
+SET enable_indexscan TO off;
+CALL paradedb.create_bm25_test_table(table_name => 'bm25_search', schema_name => 'paradedb');
+
+CREATE INDEX bm25_search_idx ON paradedb.bm25_search
+USING bm25 (id, description, category, rating)
+WITH (
+    key_field = 'id',
+    text_fields = '{
+        "description": {},
+        "category": {
+            "fast": true,
+            "normalizer": "raw"
+        }
+    }',
+    numeric_fields = '{
+        "rating": {}
+    }'
+);
+"#;
+
+// ensure our custom scan wins against our small test table
+static FILTER_FIELDS_SETUP: &str = r#"
+SET enable_indexscan TO off;
+CALL paradedb.create_bm25_test_table(table_name => 'bm25_search', schema_name => 'paradedb');
+UPDATE paradedb.bm25_search SET rating = NULL WHERE id IN (3, 5, 7);
+
+CREATE INDEX bm25_search_idx ON paradedb.bm25_search
+USING bm25 (id, description, category, rating, in_stock, last_updated_date)
+WITH (
+    key_field = 'id',
+    text_fields = '{
+        "description": {},
+        "category": {
+            "tokenizer": {"type": "raw", "lowercase": false}
+        }
+    }',
+    numeric_fields = '{
+        "rating": {}
+    }',
+    boolean_fields = '{
+        "in_stock": {}
+    }',
+    datetime_fields = '{
+        "last_updated_date": {}
+    }'
+);
+"#;
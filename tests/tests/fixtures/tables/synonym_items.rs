@@ -0,0 +1,55 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use sqlx::FromRow;
+
+#[derive(Debug, PartialEq, FromRow)]
+pub struct SynonymItemsTable {
+    pub id: i32,
+    pub description: String,
+    pub title: String,
+}
+
+impl SynonymItemsTable {
+    pub fn setup() -> String {
+        SYNONYM_ITEMS_TABLE_SETUP.into()
+    }
+}
+
+static SYNONYM_ITEMS_TABLE_SETUP: &str = r#"
+INSERT INTO paradedb.synonyms (set_name, terms) VALUES
+    ('electronics', ARRAY['tv', 'television']),
+    ('electronics', ARRAY['usa', 'United States']);
+
+CREATE TABLE items (id SERIAL PRIMARY KEY, description TEXT, title TEXT);
+INSERT INTO items (description, title) VALUES
+    ('Flat screen television', 'tv'),
+    ('Retro tv stand', 'stand'),
+    ('Speakers made in the United States', 'speakers'),
+    ('Headphones made in the usa', 'headphones'),
+    ('Portable radio', 'radio');
+
+CREATE INDEX items_idx ON items
+USING bm25 (id, description, title)
+WITH (
+    key_field = 'id',
+    text_fields = '{
+        "description": {"tokenizer": {"type": "default", "synonyms": "electronics"}},
+        "title": {}
+    }'
+);
+"#;
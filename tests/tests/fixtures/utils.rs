@@ -68,19 +68,30 @@ pub fn default_table_path(conn: &mut PgConnection, schema_name: &str, table_name
     default_schema_path(conn, schema_name).join(table_oid)
 }
 
-pub fn pg_search_index_directory_path(conn: &mut PgConnection, index_name: &str) -> PathBuf {
-    let (relfile_oid, index_oid) = format!(
-        "SELECT relfilenode::int4, oid::int4 FROM pg_class WHERE relname = '{}' AND relkind = 'i'",
-        index_name
-    )
-    .fetch_one::<(i32, i32)>(conn);
-    let (database_oid,) = "SELECT oid::int4 FROM pg_database WHERE datname = current_database();"
-        .fetch_one::<(i32,)>(conn);
+pub fn pg_search_index_relation_path(conn: &mut PgConnection, index_name: &str) -> PathBuf {
+    let (relation_path,) =
+        format!("SELECT pg_relation_filepath('{index_name}')").fetch_one::<(String,)>(conn);
     let data_directory = "SHOW data_directory;".fetch_one::<(String,)>(conn).0;
 
-    PathBuf::from(data_directory)
-        .join("pg_search")
-        .join(database_oid.to_string())
-        .join(index_oid.to_string())
-        .join(relfile_oid.to_string())
+    PathBuf::from(data_directory).join(relation_path)
+}
+
+pub fn explain(sql: &str, conn: &mut PgConnection) -> String {
+    format!("EXPLAIN {sql}")
+        .fetch::<(String,)>(conn)
+        .into_iter()
+        .map(|(line,)| line)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn ids(sql: &str, conn: &mut PgConnection) -> Vec<i32> {
+    sql.fetch::<(i32,)>(conn)
+        .into_iter()
+        .map(|(id,)| id)
+        .collect()
+}
+
+pub fn scores(sql: &str, conn: &mut PgConnection) -> Vec<(i32, f32)> {
+    sql.fetch::<(i32, f32)>(conn)
 }
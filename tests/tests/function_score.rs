@@ -0,0 +1,189 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for blending scores with functions of fast field values
+mod fixtures;
+
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use sqlx::PgConnection;
+
+fn scores(query: &str, conn: &mut PgConnection) -> Vec<(i32, f32)> {
+    format!(
+        "SELECT id, paradedb.score(id) FROM paradedb.bm25_search WHERE id @@@ {query} ORDER BY paradedb.score(id) DESC, id"
+    )
+    .fetch::<(i32, f32)>(conn)
+}
+
+fn assert_scores(actual: Vec<(i32, f32)>, expected: Vec<(i32, f32)>) {
+    assert_eq!(
+        actual.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+        expected.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+        "{actual:?}"
+    );
+    for ((_, actual), (_, expected)) in actual.iter().zip(&expected) {
+        assert!((actual - expected).abs() < 1e-4, "{actual} != {expected}");
+    }
+}
+
+#[rstest]
+fn field_value_factor(mut conn: PgConnection) {
+    SimpleProductsTable::setup().execute(&mut conn);
+
+    // the shoes are rated 5, 3 and 4
+    assert_scores(
+        scores(
+            "paradedb.function_score(paradedb.parse('description:shoes'), ARRAY[paradedb.field_value_factor('rating')], boost_mode => 'replace')",
+            &mut conn,
+        ),
+        vec![(3, 5.0), (5, 4.0), (4, 3.0)],
+    );
+
+    let bm25 = scores("paradedb.parse('description:shoes')", &mut conn);
+    let expected = bm25
+        .iter()
+        .map(|(id, score)| {
+            let rating = match id {
+                3 => 5.0f32,
+                4 => 3.0,
+                _ => 4.0,
+            };
+            (*id, score * (rating * 2.0 + 1.0).log10())
+        })
+        .collect::<Vec<_>>();
+    let mut actual = scores(
+        "paradedb.function_score(paradedb.parse('description:shoes'), ARRAY[paradedb.field_value_factor('rating', factor => 2, modifier => 'log1p')])",
+        &mut conn,
+    );
+    actual.sort_by_key(|(id, _)| bm25.iter().position(|(bm25_id, _)| bm25_id == id));
+    assert_scores(actual, expected);
+}
+
+#[rstest]
+fn decay_functions(mut conn: PgConnection) {
+    SimpleProductsTable::setup().execute(&mut conn);
+
+    assert_scores(
+        scores(
+            "paradedb.function_score(paradedb.parse('description:shoes'), ARRAY[paradedb.gauss_decay('rating', origin => '5', scale => '1')], boost_mode => 'replace')",
+            &mut conn,
+        ),
+        vec![(3, 1.0), (5, 0.5), (4, 0.0625)],
+    );
+    assert_scores(
+        scores(
+            "paradedb.function_score(paradedb.parse('description:shoes'), ARRAY[paradedb.linear_decay('rating', origin => '5', scale => '2', decay => 0.5)], boost_mode => 'replace')",
+            &mut conn,
+        ),
+        vec![(3, 1.0), (5, 0.75), (4, 0.5)],
+    );
+
+    // the shoes were last updated on 2023-04-29, 2023-04-22 and 2023-05-03
+    assert_scores(
+        scores(
+            "paradedb.function_score(paradedb.parse('description:shoes'), ARRAY[paradedb.exp_decay('last_updated_date', origin => '2023-05-03', scale => '1d')], boost_mode => 'replace')",
+            &mut conn,
+        ),
+        vec![(5, 1.0), (3, 0.0625), (4, 0.5f32.powi(11))],
+    );
+}
+
+#[rstest]
+fn score_modes(mut conn: PgConnection) {
+    SimpleProductsTable::setup().execute(&mut conn);
+
+    let functions = "ARRAY[paradedb.field_value_factor('rating'), paradedb.gauss_decay('rating', origin => '3', scale => '1')]";
+    assert_scores(
+        scores(
+            &format!("paradedb.function_score(paradedb.parse('description:shoes'), {functions}, score_mode => 'sum', boost_mode => 'replace')"),
+            &mut conn,
+        ),
+        vec![(3, 5.0625), (5, 4.5), (4, 4.0)],
+    );
+    assert_scores(
+        scores(
+            &format!("paradedb.function_score(paradedb.parse('description:shoes'), {functions}, score_mode => 'max', boost_mode => 'replace')"),
+            &mut conn,
+        ),
+        vec![(3, 5.0), (5, 4.0), (4, 3.0)],
+    );
+}
+
+#[rstest]
+fn function_scores_order_top_n(mut conn: PgConnection) {
+    SimpleProductsTable::setup().execute(&mut conn);
+
+    let rows: Vec<(i32,)> = r#"
+    SELECT id FROM paradedb.bm25_search
+    WHERE id @@@ paradedb.function_score(
+        paradedb.parse('description:shoes OR description:keyboard'),
+        ARRAY[paradedb.exp_decay('last_updated_date', origin => '2023-04-16', scale => '1d')],
+        boost_mode => 'replace'
+    )
+    ORDER BY paradedb.score(id) DESC
+    LIMIT 2
+    "#
+    .fetch(&mut conn);
+    assert_eq!(rows, vec![(2,), (4,)]);
+
+    let rows: Vec<(i32,)> = r#"
+    SELECT id FROM paradedb.bm25_search
+    WHERE id @@@
+    '{
+        "function_score": {
+            "query": {"parse": {"query_string": "description:shoes OR description:keyboard"}},
+            "functions": [{"field_value_factor": {"field": "rating"}}],
+            "boost_mode": "replace"
+        }
+    }'::jsonb
+    ORDER BY paradedb.score(id) DESC, id
+    LIMIT 1
+    "#
+    .fetch(&mut conn);
+    assert_eq!(rows, vec![(3,)]);
+}
+
+#[rstest]
+fn invalid_score_functions(mut conn: PgConnection) {
+    SimpleProductsTable::setup().execute(&mut conn);
+
+    let cases = [
+        (
+            "ARRAY[paradedb.field_value_factor('description')]",
+            "must be a numeric or date fast field",
+        ),
+        (
+            "ARRAY[paradedb.gauss_decay('rating', scale => '1')]",
+            "origin is required",
+        ),
+        (
+            "ARRAY[paradedb.exp_decay('last_updated_date', scale => 'soon')]",
+            "scale must be a positive number",
+        ),
+    ];
+
+    for (functions, message) in cases {
+        let sql = format!(
+            "SELECT id FROM paradedb.bm25_search WHERE id @@@ paradedb.function_score(paradedb.all(), {functions})"
+        );
+        match sql.fetch_result::<(i32,)>(&mut conn) {
+            Err(err) => assert!(err.to_string().contains(message), "{functions}: {err}"),
+            _ => panic!("{functions} should fail"),
+        }
+    }
+}
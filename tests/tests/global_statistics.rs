@@ -0,0 +1,89 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for scoring with the statistics of every index under an Append
+mod fixtures;
+
+use fixtures::*;
+use rstest::*;
+use sqlx::PgConnection;
+
+fn scores(conn: &mut PgConnection) -> Vec<(i32, f32)> {
+    "SELECT id, paradedb.score(id) FROM docs WHERE body @@@ 'apple' AND id IN (1, 2) ORDER BY id"
+        .fetch::<(i32, f32)>(conn)
+}
+
+#[rstest]
+fn partitions_score_alike(mut conn: PgConnection) {
+    RegionalDocsTable::setup().execute(&mut conn);
+
+    // each partition scores with its own statistics, so the same doc scores differently
+    let local = scores(&mut conn);
+    assert_eq!(local.len(), 2);
+    assert_ne!(local[0].1, local[1].1, "{local:?}");
+
+    "SET paradedb.enable_global_statistics = true".execute(&mut conn);
+    let global = scores(&mut conn);
+    assert_eq!(global.len(), 2);
+    assert_eq!(global[0].1, global[1].1, "{global:?}");
+
+    let (plan,) =
+        "EXPLAIN (FORMAT JSON) SELECT id, paradedb.score(id) FROM docs WHERE body @@@ 'apple'"
+            .fetch_one::<(serde_json::Value,)>(&mut conn);
+    assert!(
+        plan.to_string().contains("Global Statistics Indexes"),
+        "{plan:#?}"
+    );
+}
+
+#[rstest]
+fn single_table_unaffected(mut conn: PgConnection) {
+    r#"
+    CREATE TABLE docs (id SERIAL PRIMARY KEY, body TEXT);
+    INSERT INTO docs (body) VALUES ('apple pie'), ('apple tart'), ('cherry tart');
+    CREATE INDEX docs_idx ON docs USING bm25 (id, body) WITH (key_field = 'id', text_fields = '{"body": {}}');
+    "#
+    .execute(&mut conn);
+
+    let query = "SELECT id, paradedb.score(id) FROM docs WHERE body @@@ 'apple' ORDER BY id";
+    let local = query.fetch::<(i32, f32)>(&mut conn);
+    "SET paradedb.enable_global_statistics = true".execute(&mut conn);
+    assert_eq!(query.fetch::<(i32, f32)>(&mut conn), local);
+}
+
+#[rstest]
+fn similarity_with_global_statistics(mut conn: PgConnection) {
+    RegionalDocsTable::setup().execute(&mut conn);
+
+    // this similarity needs how often the term appears overall, not just how many docs have it
+    r#"
+    DROP INDEX docs_east_idx;
+    DROP INDEX docs_west_idx;
+    CREATE INDEX docs_east_idx ON docs_east USING bm25 (id, body) WITH (key_field = 'id', text_fields = '{"body": {"similarity": {"type": "dfr", "basic_model": "if", "after_effect": "b", "normalization": "h1"}}}');
+    CREATE INDEX docs_west_idx ON docs_west USING bm25 (id, body) WITH (key_field = 'id', text_fields = '{"body": {"similarity": {"type": "dfr", "basic_model": "if", "after_effect": "b", "normalization": "h1"}}}');
+    "#
+    .execute(&mut conn);
+
+    let local = scores(APPLE_SCORES, &mut conn);
+    assert_eq!(local.len(), 2);
+    assert_ne!(local[0].1, local[1].1, "{local:?}");
+
+    "SET paradedb.enable_global_statistics = true".execute(&mut conn);
+    let global = scores(APPLE_SCORES, &mut conn);
+    assert_eq!(global.len(), 2);
+    assert_eq!(global[0].1, global[1].1, "{global:?}");
+}
@@ -20,7 +20,7 @@ mod fixtures;
 
 use std::path::PathBuf;
 
-use fixtures::utils::pg_search_index_directory_path;
+use fixtures::utils::pg_search_index_relation_path;
 use fixtures::*;
 use pretty_assertions::assert_eq;
 use rstest::*;
@@ -554,19 +554,20 @@ fn delete_index_deletes_tantivy_files(mut conn: PgConnection) {
         USING bm25 (id, description) WITH (key_field='id')"#
         .execute(&mut conn);
 
-    let index_dir = pg_search_index_directory_path(&mut conn, "index_config_index");
+    let index_path = pg_search_index_relation_path(&mut conn, "index_config_index");
     assert!(
-        index_dir.exists(),
-        "expected index directory to exist at: {:?}",
-        index_dir
+        index_path.exists(),
+        "expected index relation to exist at: {:?}",
+        index_path
     );
 
     "DROP INDEX index_config_index CASCADE".execute(&mut conn);
 
+    // Postgres truncates the relation's main fork on commit and unlinks it at the next checkpoint
     assert!(
-        !index_dir.join("search-index.json").exists(),
-        "expected index directory to have been deleted at: {:?}",
-        index_dir
+        !index_path.exists() || index_path.metadata().unwrap().len() == 0,
+        "expected index relation to have been deleted at: {:?}",
+        index_path
     );
 }
 
@@ -579,11 +580,11 @@ fn delete_index_aborted_maintains_tantivy_files(mut conn: PgConnection) {
         USING bm25 (id, description) WITH (key_field='id')"#
         .execute(&mut conn);
 
-    let index_dir = pg_search_index_directory_path(&mut conn, "index_config_index");
+    let index_path = pg_search_index_relation_path(&mut conn, "index_config_index");
     assert!(
-        index_dir.exists(),
-        "expected index directory to exist at: {:?}",
-        index_dir
+        index_path.exists(),
+        "expected index relation to exist at: {:?}",
+        index_path
     );
 
     "DO $$ 
@@ -595,10 +596,15 @@ fn delete_index_aborted_maintains_tantivy_files(mut conn: PgConnection) {
         .ok();
 
     assert!(
-        index_dir.join("search-index.json").exists(),
-        "expected index directory to have been not been deleted at: {:?}",
-        index_dir
+        index_path.metadata().unwrap().len() > 0,
+        "expected index relation to have been not been deleted at: {:?}",
+        index_path
     );
+
+    let rows: Vec<(i32,)> =
+        "SELECT id FROM index_config WHERE index_config @@@ 'description:keyboard' ORDER BY id"
+            .fetch(&mut conn);
+    assert!(!rows.is_empty());
 }
 
 #[rstest]
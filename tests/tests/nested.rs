@@ -0,0 +1,156 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for nested queries over the objects in JSON arrays
+mod fixtures;
+
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use sqlx::PgConnection;
+
+fn ids(query: &str, conn: &mut PgConnection) -> Vec<i32> {
+    format!("SELECT id FROM products WHERE id @@@ {query} ORDER BY id")
+        .fetch::<(i32,)>(conn)
+        .into_iter()
+        .map(|(id,)| id)
+        .collect()
+}
+
+const RED_AND_LARGE: &str =
+    "paradedb.parse('metadata.variants.color:red AND metadata.variants.size:l')";
+
+#[rstest]
+fn nested_matches_within_one_object(mut conn: PgConnection) {
+    NestedProductsTable::setup().execute(&mut conn);
+
+    // the flattened arrays match the shirt, whose red variant isn't the large one
+    assert_eq!(ids(RED_AND_LARGE, &mut conn), vec![1, 2]);
+    assert_eq!(
+        ids(
+            &format!("paradedb.nested('metadata.variants', {RED_AND_LARGE})"),
+            &mut conn
+        ),
+        vec![2]
+    );
+
+    let rows: Vec<(i32,)> = r#"
+    SELECT id FROM products WHERE id @@@
+    '{
+        "nested": {
+            "path": "metadata.variants",
+            "query": {
+                "boolean": {
+                    "must": [
+                        {"term": {"field": "metadata.variants.color", "value": "blue"}},
+                        {"term": {"field": "metadata.variants.size", "value": "l"}}
+                    ]
+                }
+            }
+        }
+    }'::jsonb
+    ORDER BY id
+    "#
+    .fetch(&mut conn);
+    assert_eq!(rows, vec![(1,)]);
+
+    // a field whose value is an array is nested at the name of the field
+    assert_eq!(
+        ids(
+            "paradedb.nested('sizes', paradedb.term('sizes.size', 'xl'))",
+            &mut conn
+        ),
+        vec![3]
+    );
+}
+
+#[rstest]
+fn nested_children_are_not_rows(mut conn: PgConnection) {
+    NestedProductsTable::setup().execute(&mut conn);
+
+    assert_eq!(ids("paradedb.all()", &mut conn), vec![1, 2, 3]);
+    let (count,) = "SELECT count(*) FROM products WHERE id @@@ paradedb.term('metadata.variants.color', 'red')"
+        .fetch_one::<(i64,)>(&mut conn);
+    assert_eq!(count, 3);
+}
+
+#[rstest]
+fn nested_score_modes(mut conn: PgConnection) {
+    NestedProductsTable::setup().execute(&mut conn);
+
+    let scores = |score_mode: &str, conn: &mut PgConnection| {
+        format!(
+            "SELECT id, paradedb.score(id) FROM products WHERE id @@@ paradedb.nested('metadata.variants', paradedb.term('metadata.variants.color', 'red'), score_mode => '{score_mode}') ORDER BY id"
+        )
+        .fetch::<(i32, f32)>(conn)
+    };
+
+    let avg = scores("avg", &mut conn);
+    let sum = scores("sum", &mut conn);
+    let none = scores("none", &mut conn);
+    assert_eq!(
+        avg.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+
+    // the socks have two red variants, and the others have one
+    assert!((sum[2].1 - 2.0 * avg[2].1).abs() < 1e-4, "{sum:?} {avg:?}");
+    assert!((sum[0].1 - avg[0].1).abs() < 1e-4, "{sum:?} {avg:?}");
+    assert!(none.iter().all(|(_, score)| *score == 0.0), "{none:?}");
+}
+
+#[rstest]
+fn nested_follows_updates_and_deletes(mut conn: PgConnection) {
+    NestedProductsTable::setup().execute(&mut conn);
+
+    let query = format!("paradedb.nested('metadata.variants', {RED_AND_LARGE})");
+    r#"UPDATE products SET metadata = '{"variants": [{"color": "red", "size": "L"}]}' WHERE id = 1"#
+        .execute(&mut conn);
+    assert_eq!(ids(&query, &mut conn), vec![1, 2]);
+
+    "DELETE FROM products WHERE id = 2; VACUUM products".execute(&mut conn);
+    assert_eq!(ids(&query, &mut conn), vec![1]);
+    assert_eq!(ids("paradedb.all()", &mut conn), vec![1, 3]);
+}
+
+#[rstest]
+fn nested_requires_a_nested_field(mut conn: PgConnection) {
+    NestedProductsTable::setup().execute(&mut conn);
+
+    match "SELECT id FROM products WHERE id @@@ paradedb.nested('name', paradedb.all())"
+        .fetch_result::<(i32,)>(&mut conn)
+    {
+        Err(err) => assert!(
+            err.to_string()
+                .contains("is not the path of an array in a nested JSON field"),
+            "{err}"
+        ),
+        _ => panic!("nested queries should need a JSON field"),
+    }
+
+    SimpleProductsTable::setup().execute(&mut conn);
+    match "SELECT id FROM paradedb.bm25_search WHERE id @@@ paradedb.nested('metadata', paradedb.all())"
+        .fetch_result::<(i32,)>(&mut conn)
+    {
+        Err(err) => assert!(
+            err.to_string()
+                .contains("is not the path of an array in a nested JSON field"),
+            "{err}"
+        ),
+        _ => panic!("nested queries should need an index with nested fields"),
+    }
+}
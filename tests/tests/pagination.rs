@@ -0,0 +1,191 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for paging through sorted results, with OFFSET and with search_after cursors
+mod fixtures;
+
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use serde_json::Value;
+use sqlx::PgConnection;
+
+fn top_n_plan(sql: &str, conn: &mut PgConnection) -> serde_json::Map<String, Value> {
+    let (plan,) = format!("EXPLAIN (FORMAT JSON) {sql}").fetch_one::<(Value,)>(conn);
+    plan.pointer("/0/Plan/Plans/0")
+        .unwrap()
+        .as_object()
+        .unwrap()
+        .clone()
+}
+
+/// Page through `query` ordered by `order_by`, `page_size` rows at a time, using a search_after
+/// cursor built from `cursor_columns`, and return the ids in the order they were seen
+fn page_with_search_after(
+    conn: &mut PgConnection,
+    query: &str,
+    sort_keys: &[&str],
+    cursor_columns: &str,
+    order_by: &str,
+    page_size: usize,
+) -> Vec<i32> {
+    let sort_keys = sort_keys
+        .iter()
+        .map(|key| format!("'{key}'"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut ids = vec![];
+    let mut search = query.to_string();
+    loop {
+        let page = format!(
+            "SELECT id, jsonb_build_array({cursor_columns})::text, ctid::text FROM paradedb.bm25_search WHERE id @@@ {search} ORDER BY {order_by} LIMIT {page_size}"
+        )
+        .fetch::<(i32, String, String)>(conn);
+
+        ids.extend(page.iter().map(|(id, ..)| *id));
+        match page.last() {
+            Some((_, after, ctid)) if page.len() == page_size => {
+                search = format!(
+                    "paradedb.search_after({query}, ARRAY[{sort_keys}], '{after}', '{ctid}')"
+                );
+            }
+            _ => return ids,
+        }
+    }
+}
+
+#[rstest]
+fn offset_is_pushed_down(mut conn: PgConnection) {
+    SimpleProductsTable::setup_fast_category().execute(&mut conn);
+
+    let sql = "SELECT id, rating FROM paradedb.bm25_search WHERE id @@@ paradedb.all() ORDER BY rating DESC, id LIMIT 5 OFFSET 10";
+    let plan = top_n_plan(sql, &mut conn);
+    assert_eq!(plan.get("   Top N Limit"), Some(&Value::from(15)));
+    assert_eq!(plan.get("   Top N Offset"), Some(&Value::from(10)));
+
+    let pushed_down = sql.fetch::<(i32, i32)>(&mut conn);
+    "SET paradedb.enable_custom_scan TO off".execute(&mut conn);
+    let expected = sql.fetch::<(i32, i32)>(&mut conn);
+    assert_eq!(pushed_down, expected);
+}
+
+#[rstest]
+fn offset_rows_skip_snippets(mut conn: PgConnection) {
+    SimpleProductsTable::setup_fast_category().execute(&mut conn);
+
+    let sql = "SELECT id, paradedb.snippet(description) FROM paradedb.bm25_search WHERE description @@@ 'keyboard OR shoes' ORDER BY paradedb.score(id) DESC, id LIMIT 2 OFFSET 1";
+    let pushed_down = sql.fetch::<(i32, Option<String>)>(&mut conn);
+    assert_eq!(pushed_down.len(), 2);
+    assert!(
+        pushed_down.iter().all(|(_, snippet)| snippet.is_some()),
+        "{pushed_down:?}"
+    );
+
+    let all = "SELECT id FROM paradedb.bm25_search WHERE description @@@ 'keyboard OR shoes' ORDER BY paradedb.score(id) DESC, id LIMIT 3"
+        .fetch::<(i32,)>(&mut conn);
+    assert_eq!(
+        pushed_down.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+        all[1..].iter().map(|(id,)| *id).collect::<Vec<_>>()
+    );
+}
+
+#[rstest]
+fn top_n_refills_after_dead_rows(mut conn: PgConnection) {
+    SimpleProductsTable::setup_fast_category().execute(&mut conn);
+
+    // the deleted rows are still in the index, and are the first ones it'll find
+    "DELETE FROM paradedb.bm25_search WHERE id <= 20".execute(&mut conn);
+
+    let sql = "SELECT id FROM paradedb.bm25_search WHERE id @@@ paradedb.all() ORDER BY id LIMIT 5 OFFSET 5";
+    let pushed_down = sql.fetch::<(i32,)>(&mut conn);
+    assert_eq!(pushed_down, vec![(26,), (27,), (28,), (29,), (30,)]);
+}
+
+#[rstest]
+fn ctid_breaks_ties(mut conn: PgConnection) {
+    SimpleProductsTable::setup_fast_category().execute(&mut conn);
+
+    let sql = "SELECT id FROM paradedb.bm25_search WHERE id @@@ paradedb.all() ORDER BY rating DESC, ctid LIMIT 10";
+    let plan = top_n_plan(sql, &mut conn);
+    assert_eq!(
+        plan.get("   Sort Keys"),
+        Some(&Value::String(String::from("rating desc, ctid asc")))
+    );
+
+    let pushed_down = sql.fetch::<(i32,)>(&mut conn);
+    "SET paradedb.enable_custom_scan TO off".execute(&mut conn);
+    let expected = sql.fetch::<(i32,)>(&mut conn);
+    assert_eq!(pushed_down, expected);
+}
+
+#[rstest]
+fn search_after_by_field(mut conn: PgConnection) {
+    SimpleProductsTable::setup_fast_category().execute(&mut conn);
+
+    let paged = page_with_search_after(
+        &mut conn,
+        "paradedb.all()",
+        &["rating desc"],
+        "rating",
+        "rating DESC, ctid",
+        7,
+    );
+
+    "SET paradedb.enable_custom_scan TO off".execute(&mut conn);
+    let expected = "SELECT id FROM paradedb.bm25_search ORDER BY rating DESC, ctid"
+        .fetch::<(i32,)>(&mut conn)
+        .into_iter()
+        .map(|(id,)| id)
+        .collect::<Vec<_>>();
+    assert_eq!(paged, expected);
+}
+
+#[rstest]
+fn search_after_by_text_and_field(mut conn: PgConnection) {
+    SimpleProductsTable::setup_fast_category().execute(&mut conn);
+
+    let paged = page_with_search_after(
+        &mut conn,
+        "paradedb.parse('description:keyboard OR description:shoes OR category:electronics')",
+        &["category asc", "rating desc"],
+        "category, rating",
+        "category, rating DESC, ctid",
+        3,
+    );
+
+    "SET paradedb.enable_custom_scan TO off".execute(&mut conn);
+    let expected = "SELECT id FROM paradedb.bm25_search WHERE id @@@ paradedb.parse('description:keyboard OR description:shoes OR category:electronics') ORDER BY category, rating DESC, ctid"
+        .fetch::<(i32,)>(&mut conn)
+        .into_iter()
+        .map(|(id,)| id)
+        .collect::<Vec<_>>();
+    assert_eq!(paged, expected);
+}
+
+#[rstest]
+fn search_after_rejects_mismatched_cursor(mut conn: PgConnection) {
+    SimpleProductsTable::setup_fast_category().execute(&mut conn);
+
+    let result = "SELECT id FROM paradedb.bm25_search WHERE id @@@ paradedb.search_after(paradedb.all(), ARRAY['rating desc', 'id'], '[4]', '(0,1)')"
+        .execute_result(&mut conn);
+    assert!(result.is_err());
+
+    let result = "SELECT id FROM paradedb.bm25_search WHERE id @@@ paradedb.search_after(paradedb.all(), ARRAY['rating sideways'], '[4]', '(0,1)')"
+        .execute_result(&mut conn);
+    assert!(result.is_err());
+}
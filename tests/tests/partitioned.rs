@@ -0,0 +1,187 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for bm25 indexes on partitioned tables
+mod fixtures;
+
+use fixtures::utils::{explain, ids};
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use sqlx::PgConnection;
+
+fn num_indexes(table: &str, conn: &mut PgConnection) -> i64 {
+    let (count,) = format!("SELECT COUNT(*) FROM pg_indexes WHERE tablename = '{table}'")
+        .fetch_one::<(i64,)>(conn);
+    count
+}
+
+#[rstest]
+fn search_partitioned_table(mut conn: PgConnection) {
+    SalesTable::setup().execute(&mut conn);
+
+    let sql = "SELECT id FROM sales WHERE description @@@ 'shoes' ORDER BY id";
+    assert_eq!(ids(sql, &mut conn), vec![1, 2, 3]);
+    assert_eq!(
+        ids(
+            "SELECT id FROM sales WHERE id @@@ paradedb.term('description', 'keyboard')",
+            &mut conn
+        ),
+        vec![4]
+    );
+
+    // a partition created later gets an index of its own, and is searched along with the others
+    r#"
+    CREATE TABLE sales_north PARTITION OF sales FOR VALUES IN ('north');
+    INSERT INTO sales (region, description) VALUES ('north', 'brown shoes');
+    "#
+    .execute(&mut conn);
+    assert_eq!(num_indexes("sales_north", &mut conn), 1);
+    assert_eq!(ids(sql, &mut conn), vec![1, 2, 3, 5]);
+
+    // so is one that's attached
+    r#"
+    CREATE TABLE sales_south (id SERIAL, region TEXT, description TEXT);
+    INSERT INTO sales_south (id, region, description) VALUES (6, 'south', 'yellow shoes');
+    ALTER TABLE sales ATTACH PARTITION sales_south FOR VALUES IN ('south');
+    "#
+    .execute(&mut conn);
+    assert_eq!(num_indexes("sales_south", &mut conn), 1);
+    assert_eq!(ids(sql, &mut conn), vec![1, 2, 3, 5, 6]);
+
+    // and the same rows are found without the custom scan
+    "SET paradedb.enable_custom_scan = false".execute(&mut conn);
+    assert_eq!(ids(sql, &mut conn), vec![1, 2, 3, 5, 6]);
+}
+
+#[rstest]
+fn duplicate_keys_across_partitions(mut conn: PgConnection) {
+    SalesTable::setup().execute(&mut conn);
+
+    // keys are only unique within a partition, so give a west row the key of an east row
+    r#"
+    INSERT INTO sales (id, region, description) VALUES (1, 'west', 'green hat');
+    SET paradedb.enable_custom_scan = false;
+    "#
+    .execute(&mut conn);
+
+    // the east row matches, but the west row with the same key doesn't
+    let sql = "SELECT id, region FROM sales WHERE description @@@ 'shoes' ORDER BY id, region";
+    let rows: Vec<(i32, String)> = sql.fetch(&mut conn);
+    assert_eq!(
+        rows,
+        vec![(1, "east".into()), (2, "east".into()), (3, "west".into())]
+    );
+
+    // and the other way around
+    let sql = "SELECT id, region FROM sales WHERE description @@@ 'hat' ORDER BY id, region";
+    let rows: Vec<(i32, String)> = sql.fetch(&mut conn);
+    assert_eq!(rows, vec![(1, "west".into())]);
+}
+
+#[rstest]
+fn detach_partition_drops_its_index(mut conn: PgConnection) {
+    SalesTable::setup().execute(&mut conn);
+    assert_eq!(num_indexes("sales_west", &mut conn), 1);
+
+    "ALTER TABLE sales DETACH PARTITION sales_west".execute(&mut conn);
+    assert_eq!(num_indexes("sales_west", &mut conn), 0);
+    assert_eq!(num_indexes("sales_east", &mut conn), 1);
+    assert_eq!(
+        ids(
+            "SELECT id FROM sales WHERE description @@@ 'shoes' ORDER BY id",
+            &mut conn
+        ),
+        vec![1, 2]
+    );
+
+    // the detached table can be given a bm25 index of its own
+    r#"
+    CREATE INDEX sales_west_idx ON sales_west USING bm25 (id, description)
+    WITH (key_field = 'id', text_fields = '{"description": {}}')
+    "#
+    .execute(&mut conn);
+    assert_eq!(
+        ids(
+            "SELECT id FROM sales_west WHERE description @@@ 'shoes'",
+            &mut conn
+        ),
+        vec![3]
+    );
+}
+
+#[rstest]
+fn partition_pruning(mut conn: PgConnection) {
+    SalesTable::setup().execute(&mut conn);
+
+    // Postgres prunes partitions with ordinary predicates on the partition key
+    let sql = "SELECT id FROM sales WHERE region = 'east' AND description @@@ 'shoes' ORDER BY id";
+    assert_eq!(ids(sql, &mut conn), vec![1, 2]);
+    let plan = explain(sql, &mut conn);
+    assert!(!plan.contains("sales_west"), "{plan}");
+
+    // and the search prunes partitions with the terms it requires on the partition key
+    let sql = r#"
+        SELECT id FROM sales
+        WHERE id @@@ paradedb.boolean(must => ARRAY[
+            paradedb.term('region', 'east'),
+            paradedb.parse('description:shoes')
+        ])
+        ORDER BY id"#;
+    assert_eq!(ids(sql, &mut conn), vec![1, 2]);
+    let plan = explain(sql, &mut conn);
+    assert_eq!(
+        plan.matches(r#"Tantivy Query: "empty""#).count(),
+        1,
+        "{plan}"
+    );
+
+    // a term on a tokenized field doesn't say what the whole value is, so nothing is pruned
+    let sql = "SELECT id FROM sales WHERE id @@@ paradedb.term('description', 'west') ORDER BY id";
+    assert_eq!(ids(sql, &mut conn), Vec::<i32>::new());
+    let plan = explain(sql, &mut conn);
+    assert!(!plan.contains(r#""empty""#), "{plan}");
+}
+
+#[rstest]
+fn top_n_across_partitions(mut conn: PgConnection) {
+    SalesTable::setup().execute(&mut conn);
+    r#"
+    INSERT INTO sales (region, description)
+        SELECT CASE WHEN n % 2 = 0 THEN 'east' ELSE 'west' END, repeat('shoes ', n % 5 + 1) || 'for sale'
+        FROM generate_series(1, 50) n;
+    "#
+    .execute(&mut conn);
+
+    let all = "SELECT id, paradedb.score(id) FROM sales WHERE description @@@ 'shoes' ORDER BY paradedb.score(id) DESC, id"
+        .fetch::<(i32, f32)>(&mut conn);
+    for (limit, offset) in [(5, 0), (3, 4)] {
+        let sql = format!("SELECT id, paradedb.score(id) FROM sales WHERE description @@@ 'shoes' ORDER BY paradedb.score(id) DESC LIMIT {limit} OFFSET {offset}");
+        let top = sql.fetch::<(i32, f32)>(&mut conn);
+
+        let expected = all[offset..offset + limit]
+            .iter()
+            .map(|(_, score)| *score)
+            .collect::<Vec<_>>();
+        let scores = top.iter().map(|(_, score)| *score).collect::<Vec<_>>();
+        assert_eq!(scores, expected, "{sql}");
+
+        let plan = explain(&sql, &mut conn);
+        assert!(plan.contains("Top N Limit"), "{plan}");
+        assert!(!plan.contains("Top N Offset"), "{plan}");
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for pushing ordinary WHERE predicates on indexed fields down into the search query
+mod fixtures;
+
+use fixtures::utils::{explain, ids};
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use sqlx::PgConnection;
+
+#[rstest]
+fn predicates_are_pushed_down(mut conn: PgConnection) {
+    SimpleProductsTable::setup_filter_fields().execute(&mut conn);
+
+    let search = "description @@@ 'shoes OR keyboard OR plastic OR running'";
+    let predicates = [
+        "rating > 3",
+        "rating <= 3",
+        "3 < rating",
+        "rating = 4",
+        "rating BETWEEN 2 AND 4",
+        "rating IN (1, 5)",
+        "rating IS NULL",
+        "rating IS NOT NULL",
+        "category = 'Electronics'",
+        "category IN ('Electronics', 'Footwear')",
+        "in_stock = true",
+        "last_updated_date >= '2023-05-01'",
+        "(rating = 5 OR category = 'Footwear')",
+    ];
+
+    for predicate in predicates {
+        let sql = format!(
+            "SELECT id FROM paradedb.bm25_search WHERE {search} AND {predicate} ORDER BY id"
+        );
+        let plan = explain(&sql, &mut conn);
+        assert!(plan.contains("ParadeDB Scan"), "{predicate}: {plan}");
+
+        let pushed_down = sql.fetch::<(i32,)>(&mut conn);
+        "SET paradedb.enable_custom_scan TO off".execute(&mut conn);
+        let expected = sql.fetch::<(i32,)>(&mut conn);
+        "SET paradedb.enable_custom_scan TO on".execute(&mut conn);
+        assert_eq!(pushed_down, expected, "{predicate}");
+    }
+}
+
+#[rstest]
+fn unsupported_predicates_are_not_pushed_down(mut conn: PgConnection) {
+    SimpleProductsTable::setup_filter_fields().execute(&mut conn);
+
+    // `description` isn't indexed verbatim, `<>`, `LIKE` and `ILIKE` have no exact translation,
+    // and negating a predicate would also select the rows where it's NULL
+    let search = "description @@@ 'shoes OR keyboard OR plastic OR running'";
+    let predicates = [
+        "description = 'Sleek running shoes'",
+        "rating <> 3",
+        "category LIKE 'Elec%'",
+        "category ILIKE 'elec%'",
+        "NOT (description @@@ 'shoes' AND rating > 3)",
+    ];
+
+    for predicate in predicates {
+        let sql = format!(
+            "SELECT id FROM paradedb.bm25_search WHERE {search} AND {predicate} ORDER BY id"
+        );
+        let plan = explain(&sql, &mut conn);
+        assert!(!plan.contains("ParadeDB Scan"), "{predicate}: {plan}");
+    }
+}
+
+#[rstest]
+fn filtered_raw_text_is_not_pushed_down(mut conn: PgConnection) {
+    r#"
+    SET enable_indexscan TO off;
+    CALL paradedb.create_bm25_test_table(table_name => 'bm25_search', schema_name => 'paradedb');
+    INSERT INTO paradedb.synonyms (set_name, terms) VALUES ('categories', ARRAY['Electronics', 'Gadgets']);
+    "#
+    .execute(&mut conn);
+
+    // each of these filters changes, adds or drops tokens, so the field doesn't hold the values
+    // as they are in the column
+    let filters = [
+        r#""ascii_folding": true"#,
+        r#""stopwords": ["Electronics"]"#,
+        r#""synonyms": "categories""#,
+        r#""shingle": {"min": 2, "max": 2}"#,
+        r#""edge_ngram": {"min": 1, "max": 3}"#,
+        r#""length": {"min": 0, "max": 5}"#,
+        r#""pattern_replace": {"pattern": "o", "replacement": "0"}"#,
+    ];
+
+    for filter in filters {
+        format!(
+            r#"
+            DROP INDEX IF EXISTS paradedb.bm25_search_idx;
+            CREATE INDEX bm25_search_idx ON paradedb.bm25_search
+            USING bm25 (id, description, category)
+            WITH (
+                key_field = 'id',
+                text_fields = '{{
+                    "description": {{}},
+                    "category": {{"tokenizer": {{"type": "raw", "lowercase": false, {filter}}}}}
+                }}'
+            );
+            "#
+        )
+        .execute(&mut conn);
+
+        let sql = "SELECT id FROM paradedb.bm25_search WHERE description @@@ 'shoes OR keyboard OR plastic OR running' AND category = 'Electronics' ORDER BY id";
+        let plan = explain(sql, &mut conn);
+        assert!(!plan.contains("ParadeDB Scan"), "{filter}: {plan}");
+        assert_eq!(ids(sql, &mut conn), vec![1, 2], "{filter}");
+    }
+}
+
+#[rstest]
+fn float_zero_predicates(mut conn: PgConnection) {
+    r#"
+    SET enable_indexscan TO off;
+    CREATE TABLE readings (id SERIAL PRIMARY KEY, description TEXT, value FLOAT8);
+    INSERT INTO readings (description, value) VALUES
+        ('sensor', -1.5), ('sensor', '-0'), ('sensor', 0), ('sensor', 2.5);
+    CREATE INDEX readings_idx ON readings
+    USING bm25 (id, description, value)
+    WITH (key_field = 'id', text_fields = '{"description": {}}', numeric_fields = '{"value": {}}');
+    "#
+    .execute(&mut conn);
+
+    // Postgres considers -0.0 and 0.0 equal
+    let predicates = [
+        ("value = 0", vec![2, 3]),
+        ("value = '-0'", vec![2, 3]),
+        ("value IN (0, 2.5)", vec![2, 3, 4]),
+        ("value < 0", vec![1]),
+        ("value <= '-0'", vec![1, 2, 3]),
+        ("value > '-0'", vec![4]),
+        ("value >= 0", vec![2, 3, 4]),
+    ];
+
+    for (predicate, expected) in predicates {
+        let sql = format!(
+            "SELECT id FROM readings WHERE description @@@ 'sensor' AND {predicate} ORDER BY id"
+        );
+        assert_eq!(ids(&sql, &mut conn), expected, "{predicate}");
+    }
+}
+
+#[rstest]
+fn pushed_down_predicates_leave_scores_alone(mut conn: PgConnection) {
+    SimpleProductsTable::setup_filter_fields().execute(&mut conn);
+
+    let filtered = "SELECT id, paradedb.score(id) FROM paradedb.bm25_search WHERE description @@@ 'shoes' AND rating > 2 ORDER BY id"
+        .fetch::<(i32, f32)>(&mut conn);
+    let unfiltered = "SELECT id, paradedb.score(id) FROM paradedb.bm25_search WHERE description @@@ 'shoes' ORDER BY id"
+        .fetch::<(i32, f32)>(&mut conn);
+
+    assert!(!filtered.is_empty());
+    for row in &filtered {
+        assert!(unfiltered.contains(row), "{row:?} not in {unfiltered:?}");
+    }
+}
+
+#[rstest]
+fn aggregates_over_pushed_down_predicates(mut conn: PgConnection) {
+    SimpleProductsTable::setup_filter_fields().execute(&mut conn);
+
+    let sql = "SELECT COUNT(*), SUM(rating) FROM paradedb.bm25_search WHERE description @@@ 'shoes OR keyboard' AND rating >= 3";
+    let plan = explain(sql, &mut conn);
+    assert!(plan.contains("Aggregates:"), "{plan}");
+
+    let pushed_down = sql.fetch_one::<(i64, i64)>(&mut conn);
+    "SET paradedb.enable_aggregate_custom_scan = off".execute(&mut conn);
+    let expected = sql.fetch_one::<(i64, i64)>(&mut conn);
+    assert_eq!(pushed_down, expected);
+}
@@ -1082,3 +1082,205 @@ async fn direct_prepared_statement_replanning_custom_scan(mut conn: PgConnection
         assert_eq!((score, id), (3.2668595, 2))
     }
 }
+
+#[rstest]
+fn wildcard(mut conn: PgConnection) {
+    SimpleProductsTable::setup().execute(&mut conn);
+
+    let ids = |query: &str, conn: &mut PgConnection| {
+        format!("SELECT id FROM paradedb.bm25_search WHERE bm25_search @@@ {query} ORDER BY id")
+            .fetch::<(i32,)>(conn)
+            .into_iter()
+            .map(|(id,)| id)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        ids("paradedb.wildcard('description', 'keyb?ard')", &mut conn),
+        vec![1, 2]
+    );
+    assert_eq!(
+        ids("paradedb.wildcard('description', 'sho*')", &mut conn),
+        vec![3, 4, 5]
+    );
+    assert_eq!(
+        ids("paradedb.wildcard('description', 'w??l')", &mut conn),
+        vec![9, 29]
+    );
+    assert_eq!(
+        ids("paradedb.wildcard('description', 'KEYB*')", &mut conn),
+        Vec::<i32>::new()
+    );
+    assert_eq!(
+        ids(
+            "paradedb.wildcard('description', 'KEYB*', case_insensitive => true)",
+            &mut conn
+        ),
+        vec![1, 2]
+    );
+    assert_eq!(
+        ids(
+            r#"'{"wildcard": {"field": "description", "pattern": "keyb?ard"}}'::jsonb"#,
+            &mut conn
+        ),
+        vec![1, 2]
+    );
+
+    assert_eq!(
+        ids("paradedb.wildcard('metadata.color', 'wh*')", &mut conn),
+        vec![4, 15, 25]
+    );
+
+    // wildcards in query strings match regardless of case when the field is lowercased
+    assert_eq!(
+        ids(
+            "paradedb.parse('description:KEYB?ard OR description:sho*')",
+            &mut conn
+        ),
+        vec![1, 2, 3, 4, 5]
+    );
+    assert_eq!(
+        ids(
+            "paradedb.parse('+description:sho* -description:jog*')",
+            &mut conn
+        ),
+        vec![3, 5]
+    );
+    assert_eq!(
+        ids(
+            "paradedb.parse_with_field('description', 'w??l')",
+            &mut conn
+        ),
+        vec![9, 29]
+    );
+    assert_eq!(
+        ids("paradedb.parse('description:\"sho*\"')", &mut conn),
+        Vec::<i32>::new()
+    );
+    assert_eq!(
+        ids("paradedb.parse('metadata.color:WHI*')", &mut conn),
+        vec![4, 15, 25]
+    );
+    assert_eq!(
+        ids(
+            "paradedb.parse('description:sho* AND missing:shoes', lenient => true)",
+            &mut conn
+        ),
+        vec![3, 4, 5]
+    );
+}
+
+#[rstest]
+fn wildcard_query_string_terms(mut conn: PgConnection) {
+    r#"
+    CREATE TABLE labels (id SERIAL PRIMARY KEY, name TEXT);
+    INSERT INTO labels (name) VALUES ('Shoes'), ('shoes');
+    INSERT INTO labels (name) SELECT 'label' || n FROM generate_series(1, 2000) n;
+    CREATE INDEX labels_idx ON labels
+    USING bm25 (id, name)
+    WITH (key_field = 'id', text_fields = '{"name": {"tokenizer": {"type": "raw", "lowercase": false}}}');
+    "#
+    .execute(&mut conn);
+
+    // the field isn't lowercased, so neither is the pattern
+    let (count,) = "SELECT COUNT(*) FROM labels WHERE id @@@ paradedb.parse('name:Sho*')"
+        .fetch_one::<(i64,)>(&mut conn);
+    assert_eq!(count, 1);
+
+    // there's no limit on how many terms a wildcard matches
+    let (count,) = "SELECT COUNT(*) FROM labels WHERE id @@@ paradedb.parse('name:label*')"
+        .fetch_one::<(i64,)>(&mut conn);
+    assert_eq!(count, 2000);
+}
+
+#[rstest]
+fn minimum_should_match(mut conn: PgConnection) {
+    SimpleProductsTable::setup().execute(&mut conn);
+
+    let ids = |query: &str, conn: &mut PgConnection| {
+        format!("SELECT id FROM paradedb.bm25_search WHERE bm25_search @@@ {query} ORDER BY id")
+            .fetch::<(i32,)>(conn)
+            .into_iter()
+            .map(|(id,)| id)
+            .collect::<Vec<_>>()
+    };
+    let boolean = |minimum_should_match: &str| {
+        format!(
+            r#"paradedb.boolean(
+                should => ARRAY[
+                    paradedb.term('description', 'running'),
+                    paradedb.term('description', 'shoes'),
+                    paradedb.term('description', 'sleek'),
+                    paradedb.term('description', 'white')
+                ],
+                minimum_should_match => '{minimum_should_match}'
+            )"#
+        )
+    };
+
+    assert_eq!(ids(&boolean("1"), &mut conn), vec![3, 4, 5]);
+    assert_eq!(ids(&boolean("2"), &mut conn), vec![3, 4]);
+    assert_eq!(ids(&boolean("3"), &mut conn), vec![3]);
+    assert_eq!(ids(&boolean("5"), &mut conn), Vec::<i32>::new());
+    assert_eq!(ids(&boolean("50%"), &mut conn), vec![3, 4]);
+    assert_eq!(ids(&boolean("-1"), &mut conn), vec![3]);
+    assert_eq!(ids(&boolean("-75%"), &mut conn), vec![3, 4, 5]);
+
+    // must clauses don't count towards the should clauses that have to match
+    assert_eq!(
+        ids(
+            r#"paradedb.boolean(
+                must => ARRAY[paradedb.term('description', 'shoes')],
+                should => ARRAY[
+                    paradedb.term('description', 'running'),
+                    paradedb.term('description', 'white')
+                ],
+                minimum_should_match => '1'
+            )"#,
+            &mut conn
+        ),
+        vec![3, 4]
+    );
+
+    assert_eq!(
+        ids(
+            r#"'{
+                "boolean": {
+                    "should": [
+                        {"term": {"field": "description", "value": "running"}},
+                        {"term": {"field": "description", "value": "shoes"}},
+                        {"term": {"field": "description", "value": "white"}}
+                    ],
+                    "minimum_should_match": 2
+                }
+            }'::jsonb"#,
+            &mut conn
+        ),
+        vec![3, 4]
+    );
+
+    assert_eq!(
+        ids(
+            "paradedb.parse('description:running description:shoes description:sleek description:white', minimum_should_match => '2')",
+            &mut conn
+        ),
+        vec![3, 4]
+    );
+    assert_eq!(
+        ids(
+            "paradedb.parse_with_field('description', 'running shoes sleek white', minimum_should_match => '75%')",
+            &mut conn
+        ),
+        vec![3]
+    );
+
+    match format!(
+        "SELECT id FROM paradedb.bm25_search WHERE bm25_search @@@ {}",
+        boolean("most")
+    )
+    .fetch_result::<(i32,)>(&mut conn)
+    {
+        Err(err) => assert!(err.to_string().contains("invalid minimum_should_match")),
+        _ => panic!("minimum_should_match should be a count or a percentage"),
+    }
+}
@@ -17,7 +17,7 @@
 
 mod fixtures;
 
-use crate::fixtures::utils::pg_search_index_directory_path;
+use crate::fixtures::utils::pg_search_index_relation_path;
 use anyhow::Result;
 use fixtures::*;
 use pretty_assertions::assert_eq;
@@ -28,9 +28,9 @@ use sqlx::PgConnection;
 async fn basic_reindex(mut conn: PgConnection) -> Result<()> {
     SimpleProductsTable::setup().execute(&mut conn);
 
-    // Get initial index directory path and verify it exists
-    let index_dir = pg_search_index_directory_path(&mut conn, "bm25_search_bm25_index");
-    assert!(index_dir.exists());
+    // Get initial index relation path and verify it exists
+    let index_path = pg_search_index_relation_path(&mut conn, "paradedb.bm25_search_bm25_index");
+    assert!(index_path.exists());
 
     // Verify initial search works
     let columns: SimpleProductsTableVec =
@@ -41,8 +41,9 @@ async fn basic_reindex(mut conn: PgConnection) -> Result<()> {
     // Perform REINDEX
     "REINDEX INDEX paradedb.bm25_search_bm25_index".execute(&mut conn);
 
-    // Verify index directory still exists
-    assert!(index_dir.exists());
+    // Verify the rebuilt index relation exists
+    let index_path = pg_search_index_relation_path(&mut conn, "paradedb.bm25_search_bm25_index");
+    assert!(index_path.exists());
 
     // Verify search still works after reindex
     let columns: SimpleProductsTableVec =
@@ -57,9 +58,9 @@ async fn basic_reindex(mut conn: PgConnection) -> Result<()> {
 async fn concurrent_reindex(mut conn: PgConnection) -> Result<()> {
     SimpleProductsTable::setup().execute(&mut conn);
 
-    // Get initial index directory path
-    let index_dir = pg_search_index_directory_path(&mut conn, "bm25_search_bm25_index");
-    assert!(index_dir.exists());
+    // Get initial index relation path
+    let index_path = pg_search_index_relation_path(&mut conn, "paradedb.bm25_search_bm25_index");
+    assert!(index_path.exists());
 
     // Verify initial search
     let columns: SimpleProductsTableVec =
@@ -11,8 +11,6 @@ use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Once;
-use std::thread;
-use std::time::Duration;
 use tempfile::TempDir;
 
 // Static variables for initializing port assignment and ensuring one-time setup
@@ -161,6 +159,49 @@ impl EphemeralPostgres {
         Self::new_from_initialized(tempdir_path.as_path(), postgresql_conf, pg_hba_conf)
     }
 
+    /// Create a hot standby that streams WAL from `primary`, connecting as `replication_user`
+    fn new_standby(
+        primary: &EphemeralPostgres,
+        replication_user: &str,
+        postgresql_conf: Option<&str>,
+    ) -> Self {
+        let tempdir = TempDir::new().expect("Failed to create temp dir");
+        let tempdir_path = tempdir.into_path();
+
+        // Permissions for the --pgdata directory passed to pg_basebackup
+        // should be u=rwx (0700) or u=rwx,g=rx (0750)
+        std::fs::set_permissions(
+            tempdir_path.as_path(),
+            std::fs::Permissions::from_mode(0o700),
+        )
+        .expect("couldn't set permissions on standby tempdir path");
+
+        // `-R` writes the `primary_conninfo` and `standby.signal` the standby needs to start
+        // streaming from the primary
+        let pg_basebackup = Self::pg_basebackup_path();
+        let primary_port = primary.port;
+        run_cmd!($pg_basebackup -D $tempdir_path -Fp -Xs -R -h localhost -U $replication_user --port $primary_port &> /dev/null)
+            .expect("Failed to run pg_basebackup");
+
+        Self::new_from_initialized(tempdir_path.as_path(), postgresql_conf, None)
+    }
+
+    /// Block until this standby has replayed all of the WAL `primary_conn` has generated so far
+    fn wait_for_replay(primary_conn: &mut PgConnection, standby_conn: &mut PgConnection) {
+        let (lsn,) = "SELECT pg_current_wal_lsn()::text".fetch_one::<(String,)>(primary_conn);
+        format!("SELECT pg_last_wal_replay_lsn() >= '{lsn}'::pg_lsn").fetch_retry::<(bool,)>(
+            standby_conn,
+            RETRIES,
+            RETRY_DELAY,
+            |result| {
+                result
+                    .first()
+                    .map(|(caught_up,)| *caught_up)
+                    .unwrap_or(false)
+            },
+        );
+    }
+
     // Method to establish a connection to the PostgreSQL instance
     async fn connection(&self) -> Result<PgConnection> {
         Ok(PgConnection::connect(&format!(
@@ -512,37 +553,18 @@ async fn test_wal_streaming_replication() -> Result<()> {
     ";
     let source_postgres = EphemeralPostgres::new(Some(postgresql_conf), Some(pg_hba_conf));
     let mut source_conn = source_postgres.connection().await?;
-    let source_port = source_postgres.port;
-    let source_username = "replicator";
 
     "CREATE USER replicator WITH REPLICATION ENCRYPTED PASSWORD 'replicator_pass'"
         .execute(&mut source_conn);
 
     // Standby Postgres setup
     let postgresql_conf = "
+        hot_standby = on
         shared_preload_libraries = 'pg_search'
     ";
-    let target_tempdir = TempDir::new().expect("Failed to create temp dir");
-    let target_tempdir_path = target_tempdir.into_path();
-
-    // Permissions for the --pgdata directory passed to pg_basebackup
-    // should be u=rwx (0700) or u=rwx,g=rx (0750)
-    std::fs::set_permissions(
-        target_tempdir_path.as_path(),
-        std::fs::Permissions::from_mode(0o700),
-    )
-    .expect("couldn't set permissions on target_tempdir path");
-
-    // Run pg_basebackup
-    let pg_basebackup = EphemeralPostgres::pg_basebackup_path();
-    run_cmd!($pg_basebackup -D $target_tempdir_path -Fp -Xs -P -R -h localhost -U $source_username --port $source_port)
-        .expect("Failed to run pg_basebackup");
-
-    let target_postgres = EphemeralPostgres::new_from_initialized(
-        target_tempdir_path.as_path(),
-        Some(postgresql_conf),
-        None,
-    );
+    let target_postgres =
+        EphemeralPostgres::new_standby(&source_postgres, "replicator", Some(postgresql_conf));
+    let mut target_conn = target_postgres.connection().await?;
 
     // Create the mock_items table schema on the source
     let schema = "
@@ -553,10 +575,8 @@ async fn test_wal_streaming_replication() -> Result<()> {
         )
     ";
     schema.execute(&mut source_conn);
+    EphemeralPostgres::wait_for_replay(&mut source_conn, &mut target_conn);
 
-    thread::sleep(Duration::from_millis(1000));
-
-    let mut target_conn = target_postgres.connection().await?;
     let target_count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM mock_items")
         .fetch_one(&mut target_conn)
         .await?;
@@ -569,21 +589,86 @@ async fn test_wal_streaming_replication() -> Result<()> {
     WITH (key_field = 'id');
     "
     .execute(&mut source_conn);
+    EphemeralPostgres::wait_for_replay(&mut source_conn, &mut target_conn);
 
-    thread::sleep(Duration::from_millis(1000));
+    let query = "SELECT id FROM mock_items WHERE mock_items @@@ 'description:shoes' ORDER BY id";
+    let source_results: Vec<(i32,)> = query.fetch(&mut source_conn);
+    let target_results: Vec<(i32,)> = query.fetch(&mut target_conn);
+    assert_eq!(source_results, vec![(3,), (4,), (5,)]);
+    assert_eq!(source_results, target_results);
 
-    match "SELECT id FROM mock_items WHERE mock_items @@@ 'description:shoes' ORDER BY id"
-        .fetch_result::<(i32,)>(&mut target_conn)
+    Ok(())
+}
+
+#[rstest]
+async fn test_wal_streaming_replication_dml() -> Result<()> {
+    let postgresql_conf = "
+        listen_addresses = 'localhost'
+        wal_level = replica
+        max_wal_senders = 4
+        shared_preload_libraries = 'pg_search'
+    ";
+    let pg_hba_conf = "
+        host replication all 127.0.0.1/32 md5
+        host replication all ::1/128 md5
+    ";
+    let source_postgres = EphemeralPostgres::new(Some(postgresql_conf), Some(pg_hba_conf));
+    let mut source_conn = source_postgres.connection().await?;
+
+    "CREATE USER replicator WITH REPLICATION ENCRYPTED PASSWORD 'replicator_pass'"
+        .execute(&mut source_conn);
+
+    "
+    CREATE EXTENSION pg_search;
+    CALL paradedb.create_bm25_test_table(schema_name => 'public', table_name => 'mock_items');
+    CREATE INDEX search_idx ON mock_items
+    USING bm25 (id, description, category, rating)
+    WITH (key_field = 'id');
+    "
+    .execute(&mut source_conn);
+
+    // the standby is created after the index, so it starts from a base backup that contains it
+    let postgresql_conf = "
+        hot_standby = on
+        hot_standby_feedback = on
+        shared_preload_libraries = 'pg_search'
+    ";
+    let target_postgres =
+        EphemeralPostgres::new_standby(&source_postgres, "replicator", Some(postgresql_conf));
+    let mut target_conn = target_postgres.connection().await?;
+
+    let query = "SELECT id, description FROM mock_items WHERE mock_items @@@ 'description:shoes OR category:footwear' ORDER BY id";
+    let assert_same_results = |source_conn: &mut PgConnection, target_conn: &mut PgConnection| {
+        EphemeralPostgres::wait_for_replay(source_conn, target_conn);
+        let source_results: Vec<(i32, String)> = query.fetch(source_conn);
+        let target_results: Vec<(i32, String)> = query.fetch(target_conn);
+        assert_eq!(source_results, target_results);
+        source_results
+    };
+
+    let initial = assert_same_results(&mut source_conn, &mut target_conn);
+    assert!(!initial.is_empty());
+
+    "INSERT INTO mock_items (description, category, rating) VALUES ('Purple suede shoes', 'Footwear', 4)"
+        .execute(&mut source_conn);
+    let after_insert = assert_same_results(&mut source_conn, &mut target_conn);
+    assert_eq!(after_insert.len(), initial.len() + 1);
+
+    "UPDATE mock_items SET description = 'Worn out boots' WHERE description = 'Purple suede shoes'"
+        .execute(&mut source_conn);
+    assert_same_results(&mut source_conn, &mut target_conn);
+
+    "DELETE FROM mock_items WHERE category = 'Footwear'".execute(&mut source_conn);
+    "VACUUM mock_items".execute(&mut source_conn);
+    let after_delete = assert_same_results(&mut source_conn, &mut target_conn);
+    assert!(after_delete.len() < after_insert.len());
+
+    // writes are still rejected on the standby
+    match "INSERT INTO mock_items (description, category, rating) VALUES ('Blue shoes', 'Footwear', 3)"
+        .execute_result(&mut target_conn)
     {
-        Ok(_) => panic!("index WAL replication not yet implemented"),
-        Err(err) => {
-            if !err
-                .to_string()
-                .contains("should be able to open search index")
-            {
-                panic!("expected an error, but not this one: {err}");
-            }
-        }
+        Ok(_) => panic!("a hot standby should not accept writes"),
+        Err(err) => assert!(err.to_string().contains("read-only transaction")),
     }
 
     Ok(())
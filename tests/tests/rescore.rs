@@ -0,0 +1,183 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for rescoring the top matches of a query with a second query
+mod fixtures;
+
+use fixtures::utils::scores;
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use sqlx::PgConnection;
+
+fn score_of(id: i32, scores: &[(i32, f32)]) -> f32 {
+    scores
+        .iter()
+        .find(|(scored_id, _)| *scored_id == id)
+        .map(|(_, score)| *score)
+        .unwrap_or_else(|| panic!("{id} should be in {scores:?}"))
+}
+
+#[rstest]
+fn rescore_blends_scores(mut conn: PgConnection) {
+    SimpleProductsTable::setup().execute(&mut conn);
+
+    // "Generic shoes" is the shortest, so it has the best score, and only "Sleek running shoes"
+    // matches the rescore query
+    let bm25 = scores("SELECT id, paradedb.score(id) FROM paradedb.bm25_search WHERE id @@@ paradedb.parse('description:shoes') ORDER BY paradedb.score(id) DESC, id", &mut conn);
+    assert_eq!(bm25[0].0, 5);
+    let running = score_of(
+        3,
+        &scores("SELECT id, paradedb.score(id) FROM paradedb.bm25_search WHERE id @@@ paradedb.term('description', 'running') ORDER BY paradedb.score(id) DESC, id", &mut conn),
+    );
+
+    let rescored = scores("SELECT id, paradedb.score(id) FROM paradedb.bm25_search WHERE id @@@ paradedb.rescore(paradedb.parse('description:shoes'), paradedb.term('description', 'running'), window_size => 3, weights => ARRAY[0.5, 10.0]) ORDER BY paradedb.score(id) DESC, id LIMIT 10", &mut conn);
+    assert_eq!(rescored[0].0, 3, "{rescored:?}");
+    for (id, score) in &rescored {
+        let expected = match id {
+            3 => 0.5 * score_of(3, &bm25) + 10.0 * running,
+            id => 0.5 * score_of(*id, &bm25),
+        };
+        assert!(
+            (score - expected).abs() < 1e-4,
+            "{id}: {score} != {expected}"
+        );
+    }
+}
+
+#[rstest]
+fn rescore_only_the_window(mut conn: PgConnection) {
+    SimpleProductsTable::setup().execute(&mut conn);
+
+    // only "Generic shoes" is in the window, so "Sleek running shoes" isn't rescored
+    let bm25 = scores("SELECT id, paradedb.score(id) FROM paradedb.bm25_search WHERE id @@@ paradedb.parse('description:shoes') ORDER BY paradedb.score(id) DESC, id", &mut conn);
+    let rescored = scores("SELECT id, paradedb.score(id) FROM paradedb.bm25_search WHERE id @@@ paradedb.rescore(paradedb.parse('description:shoes'), paradedb.term('description', 'running'), window_size => 1, weights => ARRAY[1.0, 10.0]) ORDER BY paradedb.score(id) DESC, id LIMIT 10", &mut conn);
+    assert_eq!(rescored, bm25);
+
+    // every match of the query is still returned
+    let rows: Vec<(i32,)> = r#"
+    SELECT id FROM paradedb.bm25_search
+    WHERE id @@@ paradedb.rescore(paradedb.parse('description:shoes'), paradedb.term('description', 'running'), window_size => 0)
+    ORDER BY id
+    "#
+    .fetch(&mut conn);
+    assert_eq!(rows, vec![(3,), (4,), (5,)]);
+}
+
+#[rstest]
+fn rescore_without_top_n(mut conn: PgConnection) {
+    SimpleProductsTable::setup().execute(&mut conn);
+
+    // without a top N, only the first pass is scored
+    let bm25 = scores("SELECT id, paradedb.score(id) FROM paradedb.bm25_search WHERE id @@@ paradedb.parse('description:shoes') ORDER BY id", &mut conn);
+    let rescored = scores("SELECT id, paradedb.score(id) FROM paradedb.bm25_search WHERE id @@@ paradedb.rescore(paradedb.parse('description:shoes'), paradedb.term('description', 'running'), weights => ARRAY[2.0, 10.0]) ORDER BY id", &mut conn);
+    for ((id, score), (_, expected)) in rescored.iter().zip(&bm25) {
+        assert!(
+            (score - 2.0 * expected).abs() < 1e-4,
+            "{id}: {score} != {expected}"
+        );
+    }
+}
+
+#[rstest]
+fn rescore_window_skips_invisible_docs(mut conn: PgConnection) {
+    SimpleProductsTable::setup().execute(&mut conn);
+
+    let bm25 = scores("SELECT id, paradedb.score(id) FROM paradedb.bm25_search WHERE id @@@ paradedb.parse('description:shoes') ORDER BY paradedb.score(id) DESC, id", &mut conn);
+    "DELETE FROM paradedb.bm25_search WHERE id = 5".execute(&mut conn);
+
+    // the deleted top doc is still in the index, but the window is the top visible doc, which is
+    // the only one that matches the rescore query
+    let rescored = scores("SELECT id, paradedb.score(id) FROM paradedb.bm25_search WHERE id @@@ paradedb.rescore(paradedb.parse('description:shoes'), paradedb.all(), window_size => 1, weights => ARRAY[0.0, 1.0]) ORDER BY paradedb.score(id) DESC, id LIMIT 10", &mut conn);
+    assert_eq!(bm25[0].0, 5);
+    assert_eq!(rescored[0], (bm25[1].0, 1.0));
+    assert!(
+        rescored[1..].iter().all(|(_, score)| *score == 0.0),
+        "{rescored:?}"
+    );
+}
+
+#[rstest]
+fn rescore_top_n(mut conn: PgConnection) {
+    SimpleProductsTable::setup().execute(&mut conn);
+
+    let rows: Vec<(i32,)> = r#"
+    SELECT id FROM paradedb.bm25_search
+    WHERE id @@@ paradedb.rescore(
+        paradedb.parse('description:shoes OR description:keyboard'),
+        paradedb.phrase('description', ARRAY['running', 'shoes']),
+        window_size => 10,
+        weights => ARRAY[1.0, 5.0]
+    )
+    ORDER BY paradedb.score(id) DESC
+    LIMIT 1
+    "#
+    .fetch(&mut conn);
+    assert_eq!(rows, vec![(3,)]);
+
+    let rows: Vec<(i32,)> = r#"
+    SELECT id FROM paradedb.bm25_search
+    WHERE id @@@
+    '{
+        "rescore": {
+            "query": {"parse": {"query_string": "description:shoes OR description:keyboard"}},
+            "rescore_query": {"phrase": {"field": "description", "phrases": ["jogging", "shoes"]}},
+            "rescore_query_weight": 5.0
+        }
+    }'::jsonb
+    ORDER BY paradedb.score(id) DESC
+    LIMIT 1
+    "#
+    .fetch(&mut conn);
+    assert_eq!(rows, vec![(4,)]);
+}
+
+#[rstest]
+fn rescore_with_nested_fields(mut conn: PgConnection) {
+    NestedProductsTable::setup().execute(&mut conn);
+
+    // the first pass is wrapped to leave out the nested children, and is still rescored
+    let rescored = scores("SELECT id, paradedb.score(id) FROM products WHERE id @@@ paradedb.rescore(paradedb.all(), paradedb.term('name', 'socks'), window_size => 3, weights => ARRAY[1.0, 10.0]) ORDER BY paradedb.score(id) DESC, id LIMIT 3", &mut conn);
+    assert_eq!(rescored.len(), 3, "{rescored:?}");
+    assert_eq!(rescored[0].0, 3, "{rescored:?}");
+    assert!(rescored[0].1 > 1.0, "{rescored:?}");
+    assert_eq!(&rescored[1..], &[(1, 1.0), (2, 1.0)]);
+}
+
+#[rstest]
+fn invalid_rescore(mut conn: PgConnection) {
+    SimpleProductsTable::setup().execute(&mut conn);
+
+    let cases = [
+        ("weights => ARRAY[1.0]", "must be an array of two weights"),
+        (
+            "weights => ARRAY[-1.0, 1.0]",
+            "must be finite and not negative",
+        ),
+        ("window_size => -1", "window_size must not be negative"),
+    ];
+
+    for (args, message) in cases {
+        let sql = format!(
+            "SELECT id FROM paradedb.bm25_search WHERE id @@@ paradedb.rescore(paradedb.all(), paradedb.all(), {args})"
+        );
+        match sql.fetch_result::<(i32,)>(&mut conn) {
+            Err(err) => assert!(err.to_string().contains(message), "{args}: {err}"),
+            _ => panic!("{args} should fail"),
+        }
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for the per-field similarity that scores matches
+mod fixtures;
+
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use sqlx::PgConnection;
+
+fn scores(query: &str, conn: &mut PgConnection) -> Vec<(i32, f32)> {
+    format!("SELECT id, paradedb.score(id) FROM docs WHERE id @@@ {query} ORDER BY id")
+        .fetch::<(i32, f32)>(conn)
+}
+
+#[rstest]
+fn boolean_similarity(mut conn: PgConnection) {
+    SimilarityDocsTable::setup(r#"{"type": "boolean"}"#).execute(&mut conn);
+
+    for query in ["paradedb.term('title', 'apple')", "'title:apple'"] {
+        assert_eq!(
+            scores(query, &mut conn),
+            vec![(1, 1.0), (2, 1.0), (3, 1.0)],
+            "{query}"
+        );
+    }
+    // a phrase scores as the sum of its terms
+    assert_eq!(
+        scores(
+            "paradedb.phrase('title', ARRAY['apple', 'banana'])",
+            &mut conn
+        ),
+        vec![(2, 2.0)]
+    );
+    // and so does everything built out of them
+    for query in [
+        "paradedb.boost(2.0, paradedb.term('title', 'apple'))",
+        "'title:apple^2'",
+    ] {
+        assert_eq!(
+            scores(query, &mut conn),
+            vec![(1, 2.0), (2, 2.0), (3, 2.0)],
+            "{query}"
+        );
+    }
+    assert_eq!(
+        scores("paradedb.disjunction_max(ARRAY[paradedb.term('title', 'apple'), paradedb.term('title', 'banana')])", &mut conn),
+        vec![(1, 1.0), (2, 1.0), (3, 1.0)]
+    );
+    assert_eq!(
+        scores(
+            "paradedb.phrase_prefix('title', ARRAY['apple', 'ban'])",
+            &mut conn
+        ),
+        vec![(2, 1.0)]
+    );
+    // fields without a similarity still use BM25
+    let body = scores("paradedb.term('body', 'apple')", &mut conn);
+    assert!(body[0].1 > body[1].1, "{body:?}");
+}
+
+#[rstest]
+fn bm25_parameters(mut conn: PgConnection) {
+    SimilarityDocsTable::setup(r#"{"type": "bm25", "k1": 1.2, "b": 0.0}"#).execute(&mut conn);
+
+    // without length normalization, a long title scores the same as a short one
+    let title = scores("paradedb.term('title', 'apple')", &mut conn);
+    assert_eq!(title[0].1, title[1].1, "{title:?}");
+    assert!(title[2].1 > title[0].1, "{title:?}");
+
+    let body = scores("paradedb.term('body', 'apple')", &mut conn);
+    assert!(body[0].1 > body[1].1, "{body:?}");
+}
+
+#[rstest]
+fn similarity_prefers_short_fields(mut conn: PgConnection) {
+    for similarity in [
+        r#"{"type": "bm25_plus", "delta": 0.5}"#,
+        r#"{"type": "tf_idf"}"#,
+        r#"{"type": "dfr", "basic_model": "in", "after_effect": "l", "normalization": "h2"}"#,
+        r#"{"type": "dfr", "basic_model": "if", "after_effect": "b", "normalization": "h1"}"#,
+    ] {
+        SimilarityDocsTable::setup(similarity).execute(&mut conn);
+
+        let title = scores("'title:apple'", &mut conn);
+        assert_eq!(title.len(), 3, "{similarity}");
+        assert!(title[0].1 > title[1].1, "{similarity}: {title:?}");
+        assert!(
+            title.iter().all(|(_, score)| *score > 0.0),
+            "{similarity}: {title:?}"
+        );
+    }
+}
+
+#[rstest]
+fn invalid_similarity(mut conn: PgConnection) {
+    "CREATE TABLE docs (id SERIAL PRIMARY KEY, title TEXT)".execute(&mut conn);
+
+    for similarity in [
+        r#"{"type": "bm25", "b": 2.0}"#,
+        r#"{"type": "bm25", "k1": -1.0}"#,
+        r#"{"type": "lm_dirichlet"}"#,
+        r#"{"type": "dfr", "basic_model": "in"}"#,
+    ] {
+        let result = format!(
+            r#"CREATE INDEX docs_idx ON docs USING bm25 (id, title)
+               WITH (key_field = 'id', text_fields = '{{"title": {{"similarity": {similarity}}}}}')"#
+        )
+        .execute_result(&mut conn);
+        assert!(result.is_err(), "{similarity}");
+    }
+}
@@ -164,3 +164,114 @@ fn sort_by_row_return_scores(mut conn: PgConnection) {
     assert_eq!(plan.get("   Sort Field"), None);
     assert_eq!(plan.get("Scores"), Some(&Value::Bool(true)));
 }
+
+fn setup_multi_key(conn: &mut PgConnection) {
+    // ensure our custom scan wins against our small test table
+    r#"
+        SET enable_indexscan TO off;
+        CALL paradedb.create_bm25_test_table(table_name => 'bm25_search', schema_name => 'paradedb');
+
+        CREATE INDEX bm25_search_idx ON paradedb.bm25_search
+        USING bm25 (id, description, category, rating, created_at)
+        WITH (
+            key_field = 'id',
+            text_fields = '{
+                "description": {},
+                "category": {
+                    "fast": true,
+                    "normalizer": "raw"
+                }
+            }',
+            numeric_fields = '{
+                "rating": {}
+            }',
+            datetime_fields = '{
+                "created_at": {}
+            }'
+        );
+    "#
+    .execute(conn);
+}
+
+fn top_n_plan(sql: &str, conn: &mut PgConnection) -> serde_json::Map<String, Value> {
+    let (plan,) = format!("EXPLAIN (FORMAT JSON) {sql}").fetch_one::<(Value,)>(conn);
+    let plan = plan
+        .pointer("/0/Plan/Plans/0")
+        .unwrap()
+        .as_object()
+        .unwrap()
+        .clone();
+    eprintln!("{plan:#?}");
+    plan
+}
+
+#[rstest]
+fn sort_by_multiple_fields(mut conn: PgConnection) {
+    setup_multi_key(&mut conn);
+
+    let sql = "SELECT id, rating, category FROM paradedb.bm25_search WHERE description @@@ 'keyboard OR shoes OR plastic' ORDER BY rating DESC, category ASC, id DESC LIMIT 5";
+    let plan = top_n_plan(sql, &mut conn);
+    assert_eq!(
+        plan.get("   Sort Keys"),
+        Some(&Value::String(String::from(
+            "rating desc, category asc, id desc"
+        )))
+    );
+
+    let pushed_down = sql.fetch::<(i32, i32, String)>(&mut conn);
+    "SET paradedb.enable_custom_scan TO off".execute(&mut conn);
+    let expected = sql.fetch::<(i32, i32, String)>(&mut conn);
+    assert_eq!(pushed_down, expected);
+}
+
+#[rstest]
+fn sort_by_date_and_field(mut conn: PgConnection) {
+    setup_multi_key(&mut conn);
+
+    let sql = "SELECT id FROM paradedb.bm25_search WHERE description @@@ 'keyboard OR shoes OR plastic' ORDER BY created_at DESC, id LIMIT 3";
+    let plan = top_n_plan(sql, &mut conn);
+    assert_eq!(
+        plan.get("   Sort Keys"),
+        Some(&Value::String(String::from("created_at desc, id asc")))
+    );
+
+    let pushed_down = sql.fetch::<(i32,)>(&mut conn);
+    "SET paradedb.enable_custom_scan TO off".execute(&mut conn);
+    let expected = sql.fetch::<(i32,)>(&mut conn);
+    assert_eq!(pushed_down, expected);
+}
+
+#[rstest]
+fn sort_by_score_and_field(mut conn: PgConnection) {
+    setup_multi_key(&mut conn);
+
+    let sql = "SELECT id, paradedb.score(id) FROM paradedb.bm25_search WHERE description @@@ 'keyboard OR shoes' ORDER BY paradedb.score(id) DESC, id ASC LIMIT 5";
+    let plan = top_n_plan(sql, &mut conn);
+    assert_eq!(
+        plan.get("   Sort Keys"),
+        Some(&Value::String(String::from(
+            "paradedb.score() desc, id asc"
+        )))
+    );
+    assert_eq!(plan.get("Scores"), Some(&Value::Bool(true)));
+
+    let rows = sql.fetch::<(i32, f32)>(&mut conn);
+    assert_eq!(rows.len(), 5);
+    let mut sorted = rows.clone();
+    sorted.sort_by(|(a_id, a_score), (b_id, b_score)| {
+        b_score.partial_cmp(a_score).unwrap().then(a_id.cmp(b_id))
+    });
+    assert_eq!(rows, sorted);
+}
+
+#[rstest]
+fn sort_by_unsupported_tiebreaker(mut conn: PgConnection) {
+    setup_multi_key(&mut conn);
+
+    // `description` isn't a fast field, so the index can't break ties in `rating` by itself
+    let (plan,) = "EXPLAIN (FORMAT JSON) SELECT * FROM paradedb.bm25_search WHERE description @@@ 'keyboard OR shoes' ORDER BY rating DESC, description LIMIT 5"
+        .fetch_one::<(Value,)>(&mut conn);
+    let plan = plan.to_string();
+    assert!(!plan.contains("Sort Field"), "{plan}");
+    assert!(!plan.contains("Sort Keys"), "{plan}");
+}
@@ -0,0 +1,172 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for span and proximity queries
+mod fixtures;
+
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use sqlx::PgConnection;
+
+fn ids(query: &str, conn: &mut PgConnection) -> Vec<i32> {
+    format!("SELECT id FROM passages WHERE passages @@@ {query} ORDER BY id")
+        .fetch::<(i32,)>(conn)
+        .into_iter()
+        .map(|(id,)| id)
+        .collect()
+}
+
+#[rstest]
+fn near(mut conn: PgConnection) {
+    PassagesTable::setup().execute(&mut conn);
+
+    assert_eq!(
+        ids(
+            "paradedb.near('body', 'quick', 'fox', distance => 1)",
+            &mut conn
+        ),
+        vec![1, 2]
+    );
+    assert_eq!(
+        ids("paradedb.near('body', 'quick', 'fox')", &mut conn),
+        Vec::<i32>::new()
+    );
+    assert_eq!(
+        ids(
+            "paradedb.near('body', 'fox', 'quick', distance => 1)",
+            &mut conn
+        ),
+        vec![1, 2]
+    );
+    assert_eq!(
+        ids(
+            "paradedb.near('body', 'fox', 'quick', distance => 1, ordered => true)",
+            &mut conn
+        ),
+        Vec::<i32>::new()
+    );
+
+    // "dog" comes five tokens before "fox" in the second passage, and after it in the first
+    assert_eq!(
+        ids(
+            "paradedb.near('body', 'dog', 'fox', distance => 5, ordered => true)",
+            &mut conn
+        ),
+        vec![2]
+    );
+    assert_eq!(
+        ids(
+            "paradedb.near('body', 'dog', 'fox', distance => 4, ordered => true)",
+            &mut conn
+        ),
+        Vec::<i32>::new()
+    );
+}
+
+#[rstest]
+fn nested_spans(mut conn: PgConnection) {
+    PassagesTable::setup().execute(&mut conn);
+
+    let brown_fox_or_dog = r#"
+        paradedb.span_near(
+            ARRAY[
+                paradedb.span_term('body', 'brown'),
+                paradedb.span_or(ARRAY[
+                    paradedb.span_term('body', 'fox'),
+                    paradedb.span_term('body', 'dog')
+                ])
+            ],
+            in_order => true
+        )
+    "#;
+    assert_eq!(ids(brown_fox_or_dog, &mut conn), vec![1, 2, 3]);
+
+    let quick_within_two_of_brown_fox = format!(
+        "paradedb.span_near(ARRAY[paradedb.span_term('body', 'quick'), {brown_fox_or_dog}], slop => 2, in_order => true)"
+    );
+    assert_eq!(ids(&quick_within_two_of_brown_fox, &mut conn), vec![1, 2]);
+}
+
+#[rstest]
+fn span_not(mut conn: PgConnection) {
+    PassagesTable::setup().execute(&mut conn);
+
+    assert_eq!(
+        ids(
+            "paradedb.span_not(paradedb.span_term('body', 'brown'), paradedb.span_term('body', 'not'))",
+            &mut conn
+        ),
+        vec![1, 2, 3, 4]
+    );
+    assert_eq!(
+        ids(
+            "paradedb.span_not(paradedb.span_term('body', 'brown'), paradedb.span_term('body', 'not'), pre => 1)",
+            &mut conn
+        ),
+        vec![1, 2, 3]
+    );
+    assert_eq!(
+        ids(
+            "paradedb.span_not(paradedb.span_term('body', 'fox'), paradedb.span_term('body', 'brown'), pre => 1)",
+            &mut conn
+        ),
+        vec![4]
+    );
+}
+
+#[rstest]
+fn span_scores(mut conn: PgConnection) {
+    PassagesTable::setup().execute(&mut conn);
+
+    let rows = "SELECT id, paradedb.score(id) FROM passages WHERE passages @@@ paradedb.near('body', 'brown', 'dog', distance => 4) ORDER BY id"
+        .fetch::<(i32, f32)>(&mut conn);
+    assert_eq!(
+        rows.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+        vec![2, 3]
+    );
+    assert!(rows.iter().all(|(_, score)| *score > 0.0), "{rows:?}");
+}
+
+#[rstest]
+fn invalid_span_queries(mut conn: PgConnection) {
+    PassagesTable::setup().execute(&mut conn);
+
+    let cases = [
+        (
+            "paradedb.near('title', 'fox', 'dog')",
+            "must be indexed with positions",
+        ),
+        (
+            "paradedb.span_near(ARRAY[paradedb.term('body', 'fox')])",
+            "can only combine other span queries",
+        ),
+        (
+            "paradedb.span_or(ARRAY[]::searchqueryinput[])",
+            "need at least one clause",
+        ),
+    ];
+
+    for (query, message) in cases {
+        match format!("SELECT id FROM passages WHERE passages @@@ {query}")
+            .fetch_result::<(i32,)>(&mut conn)
+        {
+            Err(err) => assert!(err.to_string().contains(message), "{query}: {err}"),
+            _ => panic!("{query} should fail"),
+        }
+    }
+}
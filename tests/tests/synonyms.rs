@@ -0,0 +1,125 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for query-time synonym expansion from paradedb.synonyms
+mod fixtures;
+
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use sqlx::PgConnection;
+
+fn ids(query: &str, conn: &mut PgConnection) -> Vec<i32> {
+    format!("SELECT id FROM items WHERE id @@@ {query} ORDER BY id")
+        .fetch::<(i32,)>(conn)
+        .into_iter()
+        .map(|(id,)| id)
+        .collect()
+}
+
+#[rstest]
+fn synonym_term(mut conn: PgConnection) {
+    SynonymItemsTable::setup().execute(&mut conn);
+
+    assert_eq!(
+        ids("paradedb.term('description', 'tv')", &mut conn),
+        vec![1, 2]
+    );
+    assert_eq!(
+        ids("paradedb.term('description', 'television')", &mut conn),
+        vec![1, 2]
+    );
+    // a single word can expand to a multi-word synonym
+    assert_eq!(
+        ids("paradedb.term('description', 'usa')", &mut conn),
+        vec![3, 4]
+    );
+    // fields without a synonym set aren't expanded
+    assert_eq!(
+        ids("paradedb.term('title', 'television')", &mut conn),
+        vec![]
+    );
+}
+
+#[rstest]
+fn synonym_phrase(mut conn: PgConnection) {
+    SynonymItemsTable::setup().execute(&mut conn);
+
+    assert_eq!(
+        ids(
+            "paradedb.phrase('description', ARRAY['made', 'in', 'the', 'usa'])",
+            &mut conn
+        ),
+        vec![3, 4]
+    );
+    assert_eq!(
+        ids(
+            "paradedb.phrase('description', ARRAY['united', 'states'])",
+            &mut conn
+        ),
+        vec![3, 4]
+    );
+    assert_eq!(
+        ids(
+            "paradedb.phrase('description', ARRAY['flat', 'screen', 'tv'])",
+            &mut conn
+        ),
+        vec![1]
+    );
+}
+
+#[rstest]
+fn synonym_parse(mut conn: PgConnection) {
+    SynonymItemsTable::setup().execute(&mut conn);
+
+    assert_eq!(ids("'description:television'", &mut conn), vec![1, 2]);
+    assert_eq!(
+        ids("'description:\"united states\"'", &mut conn),
+        vec![3, 4]
+    );
+    assert_eq!(
+        ids(
+            "paradedb.parse_with_field('description', 'usa OR radio')",
+            &mut conn
+        ),
+        vec![3, 4, 5]
+    );
+    // boosted terms and phrases are expanded too
+    assert_eq!(ids("'description:television^2'", &mut conn), vec![1, 2]);
+    assert_eq!(
+        ids(
+            "'description:\"united states\"^2 OR description:radio'",
+            &mut conn
+        ),
+        vec![3, 4, 5]
+    );
+}
+
+#[rstest]
+fn synonyms_change_without_reindex(mut conn: PgConnection) {
+    SynonymItemsTable::setup().execute(&mut conn);
+
+    assert_eq!(ids("'description:radio'", &mut conn), vec![5]);
+
+    "INSERT INTO paradedb.synonyms (set_name, terms) VALUES ('electronics', ARRAY['radio', 'speakers', 'headphones'])"
+        .execute(&mut conn);
+    assert_eq!(ids("'description:radio'", &mut conn), vec![3, 4, 5]);
+
+    "DELETE FROM paradedb.synonyms WHERE set_name = 'electronics' AND 'radio' = ANY(terms)"
+        .execute(&mut conn);
+    assert_eq!(ids("'description:radio'", &mut conn), vec![5]);
+}
@@ -0,0 +1,155 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+// Tests for the token filters of the tokenizer configuration
+mod fixtures;
+
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use sqlx::PgConnection;
+
+fn tokens(tokenizer: &str, text: &str, conn: &mut PgConnection) -> Vec<String> {
+    format!("SELECT token FROM paradedb.tokenize({tokenizer}, '{text}') ORDER BY position")
+        .fetch::<(String,)>(conn)
+        .into_iter()
+        .map(|(token,)| token)
+        .collect()
+}
+
+#[rstest]
+fn stopwords_tokenize(mut conn: PgConnection) {
+    assert_eq!(
+        tokens(
+            "paradedb.tokenizer('default', stopwords_language => 'English')",
+            "The Lord of the Rings",
+            &mut conn
+        ),
+        vec!["lord", "rings"]
+    );
+    assert_eq!(
+        tokens(
+            "paradedb.tokenizer('default', stopwords => ARRAY['Lord', 'of'])",
+            "The Lord of the Rings",
+            &mut conn
+        ),
+        vec!["the", "the", "rings"]
+    );
+
+    let result = "SELECT * FROM paradedb.tokenize(paradedb.tokenizer('default', stopwords_language => 'Klingon'), 'text')"
+        .execute_result(&mut conn);
+    assert!(result.is_err());
+}
+
+#[rstest]
+fn stopwords_index_and_query(mut conn: PgConnection) {
+    r#"
+        CREATE TABLE books (id SERIAL PRIMARY KEY, title TEXT);
+        INSERT INTO books (title) VALUES
+            ('The Lord of the Rings'),
+            ('The Hobbit'),
+            ('Rings of Power');
+
+        CREATE INDEX books_idx ON books
+        USING bm25 (id, title)
+        WITH (
+            key_field = 'id',
+            text_fields = '{"title": {"tokenizer": {"type": "default", "stopwords": "English"}}}'
+        );
+    "#
+    .execute(&mut conn);
+
+    let ids = |query: &str, conn: &mut PgConnection| -> Vec<i32> {
+        format!("SELECT id FROM books WHERE id @@@ {query} ORDER BY id")
+            .fetch::<(i32,)>(conn)
+            .into_iter()
+            .map(|(id,)| id)
+            .collect()
+    };
+
+    // stopwords are never indexed
+    assert_eq!(ids("paradedb.term('title', 'the')", &mut conn), vec![]);
+    // and are removed from query strings too
+    assert_eq!(ids("'title:\"the rings\"'", &mut conn), vec![1, 3]);
+    assert_eq!(ids("'title:(the hobbit)'", &mut conn), vec![2]);
+    assert_eq!(ids("'title:\"lord of the rings\"'", &mut conn), vec![1]);
+}
+
+#[rstest]
+fn ascii_folding_and_pattern_replace(mut conn: PgConnection) {
+    assert_eq!(
+        tokens(
+            "paradedb.tokenizer('default', ascii_folding => true)",
+            "Crème brûlée",
+            &mut conn
+        ),
+        vec!["creme", "brulee"]
+    );
+    assert_eq!(
+        tokens(
+            r#"paradedb.tokenizer('whitespace', pattern_replace => '{"pattern": "[^a-z0-9]", "replacement": ""}')"#,
+            "e-mail (inbox)",
+            &mut conn
+        ),
+        vec!["email", "inbox"]
+    );
+    assert_eq!(
+        tokens(
+            r#"paradedb.tokenizer('default', length => '{"min": 3, "max": 5}')"#,
+            "a tiny keyboard",
+            &mut conn
+        ),
+        vec!["tiny"]
+    );
+}
+
+#[rstest]
+fn shingle_and_edge_ngram(mut conn: PgConnection) {
+    assert_eq!(
+        tokens(
+            r#"paradedb.tokenizer('default', shingle => '{"min": 2, "max": 2}')"#,
+            "new york city",
+            &mut conn
+        ),
+        vec!["new york", "york city"]
+    );
+
+    r#"
+        CREATE TABLE cities (id SERIAL PRIMARY KEY, name TEXT);
+        INSERT INTO cities (name) VALUES ('Amsterdam'), ('Zürich'), ('Berlin');
+
+        CREATE INDEX cities_idx ON cities
+        USING bm25 (id, name)
+        WITH (
+            key_field = 'id',
+            text_fields = '{
+                "name": {"tokenizer": {"type": "default", "ascii_folding": true, "edge_ngram": {"min": 1, "max": 10}}}
+            }'
+        );
+    "#
+    .execute(&mut conn);
+
+    // every prefix is indexed, so the start of a name is enough to find it
+    let rows: Vec<(String,)> =
+        "SELECT name FROM cities WHERE id @@@ paradedb.term('name', 'zur') ORDER BY id"
+            .fetch(&mut conn);
+    assert_eq!(rows, vec![("Zürich".to_string(),)]);
+    let rows: Vec<(String,)> =
+        "SELECT name FROM cities WHERE id @@@ paradedb.term('name', 'ber') ORDER BY id"
+            .fetch(&mut conn);
+    assert_eq!(rows, vec![("Berlin".to_string(),)]);
+}
@@ -0,0 +1,111 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+mod fixtures;
+
+use fixtures::*;
+use pretty_assertions::assert_eq;
+use rstest::*;
+use sqlx::PgConnection;
+
+#[rstest]
+fn verify_healthy_index(mut conn: PgConnection) {
+    "CALL paradedb.create_bm25_test_table(table_name => 'verify_items', schema_name => 'public')"
+        .execute(&mut conn);
+    "CREATE INDEX verify_items_idx ON verify_items USING bm25 (id, description) WITH (key_field='id')"
+        .execute(&mut conn);
+    "UPDATE verify_items SET description = 'Ergonomic keyboard' WHERE id = 1".execute(&mut conn);
+    "DELETE FROM verify_items WHERE id = 2".execute(&mut conn);
+
+    let problems: Vec<(String, String, bool)> =
+        "SELECT * FROM paradedb.verify_index('verify_items_idx')".fetch(&mut conn);
+    assert_eq!(problems, vec![]);
+
+    "VACUUM verify_items".execute(&mut conn);
+    let problems: Vec<(String, String, bool)> =
+        "SELECT * FROM paradedb.verify_index('verify_items_idx')".fetch(&mut conn);
+    assert_eq!(problems, vec![]);
+}
+
+#[rstest]
+fn verify_and_repair_dangling_ctids(mut conn: PgConnection) {
+    "CALL paradedb.create_bm25_test_table(table_name => 'verify_items', schema_name => 'public')"
+        .execute(&mut conn);
+    "ALTER TABLE verify_items SET (autovacuum_enabled = 'off')".execute(&mut conn);
+    "CREATE INDEX verify_items_idx ON verify_items USING bm25 (id, description) WITH (key_field='id')"
+        .execute(&mut conn);
+
+    // removing the heap tuples without also cleaning up the index leaves it pointing at
+    // line pointers that no longer hold a tuple
+    "DELETE FROM verify_items WHERE id IN (1, 2, 3)".execute(&mut conn);
+    "VACUUM (INDEX_CLEANUP off) verify_items".execute(&mut conn);
+
+    let problems: Vec<(String, bool)> =
+        "SELECT problem, repaired FROM paradedb.verify_index('verify_items_idx')".fetch(&mut conn);
+    assert_eq!(problems, vec![("dangling_ctid".into(), false); 3]);
+
+    let problems: Vec<(String, bool)> =
+        "SELECT problem, repaired FROM paradedb.verify_index('verify_items_idx', repair => true)"
+            .fetch(&mut conn);
+    assert_eq!(problems, vec![("dangling_ctid".into(), true); 3]);
+
+    let problems: Vec<(String, String, bool)> =
+        "SELECT * FROM paradedb.verify_index('verify_items_idx')".fetch(&mut conn);
+    assert_eq!(problems, vec![]);
+
+    let (num_docs,) = "SELECT sum(num_docs)::int8 FROM paradedb.index_info('verify_items_idx')"
+        .fetch_one::<(i64,)>(&mut conn);
+    let (count,) = "SELECT count(*) FROM verify_items".fetch_one::<(i64,)>(&mut conn);
+    assert_eq!(num_docs, count);
+}
+
+#[rstest]
+fn verify_and_repair_missing_ctids(mut conn: PgConnection) {
+    "CALL paradedb.create_bm25_test_table(table_name => 'verify_items', schema_name => 'public')"
+        .execute(&mut conn);
+    "CREATE INDEX verify_items_idx ON verify_items USING bm25 (id, description) WITH (key_field='id')"
+        .execute(&mut conn);
+
+    // rows inserted while the index isn't ready for inserts are left out of it
+    "UPDATE pg_index SET indisready = false WHERE indexrelid = 'verify_items_idx'::regclass"
+        .execute(&mut conn);
+    "INSERT INTO verify_items (description, rating, category) VALUES ('Cordless drill', 4, 'Tools'), ('Hammer drill', 5, 'Tools')"
+        .execute(&mut conn);
+    "UPDATE pg_index SET indisready = true WHERE indexrelid = 'verify_items_idx'::regclass"
+        .execute(&mut conn);
+
+    let problems: Vec<(String, bool)> =
+        "SELECT problem, repaired FROM paradedb.verify_index('verify_items_idx')".fetch(&mut conn);
+    assert_eq!(problems, vec![("missing_ctid".into(), false); 2]);
+
+    let problems: Vec<(String, bool)> =
+        "SELECT problem, repaired FROM paradedb.verify_index('verify_items_idx', repair => true)"
+            .fetch(&mut conn);
+    assert_eq!(problems, vec![("missing_ctid".into(), true); 2]);
+
+    let problems: Vec<(String, String, bool)> =
+        "SELECT * FROM paradedb.verify_index('verify_items_idx')".fetch(&mut conn);
+    assert_eq!(problems, vec![]);
+
+    let rows: Vec<(String,)> =
+        "SELECT description FROM verify_items WHERE description @@@ 'drill' ORDER BY id"
+            .fetch(&mut conn);
+    assert_eq!(
+        rows,
+        vec![("Cordless drill".into(),), ("Hammer drill".into(),)]
+    );
+}
@@ -0,0 +1,240 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Named analyzers, which run a base tokenizer and then any filters, in the order they're listed.
+
+use crate::filters::{EdgeNgramFilter, LengthFilter, PatternReplaceFilter, ShingleFilter};
+use crate::manager::{SearchStopwords, SearchTokenizer};
+use anyhow::Result;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tantivy::tokenizer::{
+    AsciiFoldingFilter, BoxTokenStream, Language, LowerCaser, RemoveLongFilter, Stemmer,
+    StopWordFilter, TextAnalyzer, Tokenizer,
+};
+
+/// One step of an analyzer's filter list
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SearchTokenFilter {
+    RemoveLong {
+        limit: usize,
+    },
+    Lowercase,
+    AsciiFolding,
+    Stopwords {
+        stopwords: SearchStopwords,
+    },
+    PatternReplace {
+        pattern: String,
+        #[serde(default)]
+        replacement: String,
+    },
+    Length {
+        min: usize,
+        max: usize,
+    },
+    Stemmer {
+        language: Language,
+    },
+    Shingle {
+        min: usize,
+        max: usize,
+    },
+    EdgeNgram {
+        min: usize,
+        max: usize,
+    },
+}
+
+impl SearchTokenFilter {
+    fn validate(&self) -> Result<()> {
+        match self {
+            SearchTokenFilter::Stopwords {
+                stopwords: SearchStopwords::Language(language),
+            } if StopWordFilter::new(*language).is_none() => {
+                anyhow::bail!("there is no built-in stopword list for {language:?}")
+            }
+            SearchTokenFilter::PatternReplace { pattern, .. } => {
+                Regex::new(pattern).map_err(|err| {
+                    anyhow::anyhow!("pattern_replace filter requires a valid 'pattern': {err}")
+                })?;
+            }
+            SearchTokenFilter::Length { min, max } if min > max => {
+                anyhow::bail!("the length filter needs a 'min' of no more than its 'max'")
+            }
+            SearchTokenFilter::Shingle { min, max } | SearchTokenFilter::EdgeNgram { min, max }
+                if *min == 0 || min > max =>
+            {
+                anyhow::bail!(
+                    "shingle and edge_ngram filters need a 'min' of at least 1 and no more than their 'max'"
+                )
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn apply(&self, analyzer: TextAnalyzer) -> TextAnalyzer {
+        let builder = TextAnalyzer::builder(AnalyzerTokenizer(analyzer));
+        match self {
+            SearchTokenFilter::RemoveLong { limit } => {
+                builder.filter(RemoveLongFilter::limit(*limit)).build()
+            }
+            SearchTokenFilter::Lowercase => builder.filter(LowerCaser).build(),
+            SearchTokenFilter::AsciiFolding => builder.filter(AsciiFoldingFilter).build(),
+            SearchTokenFilter::Stopwords {
+                stopwords: SearchStopwords::Language(language),
+            } => builder
+                .filter(StopWordFilter::new(*language).expect("stopwords should be validated"))
+                .build(),
+            SearchTokenFilter::Stopwords {
+                stopwords: SearchStopwords::List(words),
+            } => builder
+                .filter(StopWordFilter::remove(words.iter().cloned()))
+                .build(),
+            SearchTokenFilter::PatternReplace {
+                pattern,
+                replacement,
+            } => builder
+                .filter(PatternReplaceFilter::new(
+                    Regex::new(pattern).expect("pattern_replace pattern should be validated"),
+                    replacement,
+                ))
+                .build(),
+            SearchTokenFilter::Length { min, max } => {
+                builder.filter(LengthFilter::new(*min, *max)).build()
+            }
+            SearchTokenFilter::Stemmer { language } => {
+                builder.filter(Stemmer::new(*language)).build()
+            }
+            SearchTokenFilter::Shingle { min, max } => {
+                builder.filter(ShingleFilter::new(*min, *max)).build()
+            }
+            SearchTokenFilter::EdgeNgram { min, max } => {
+                builder.filter(EdgeNgramFilter::new(*min, *max)).build()
+            }
+        }
+    }
+}
+
+/// The definition of a named analyzer, as of the version an index was built with
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SearchAnalyzer {
+    pub version: i64,
+    pub tokenizer: Box<SearchTokenizer>,
+    pub filters: Vec<SearchTokenFilter>,
+}
+
+impl SearchAnalyzer {
+    /// Parse an analyzer's configuration, which looks like
+    /// `{"tokenizer": {"type": "whitespace"}, "filters": [{"type": "lowercase"}]}`
+    pub fn from_json_value(version: i64, value: &serde_json::Value) -> Result<Self> {
+        let tokenizer = value.get("tokenizer").ok_or_else(|| {
+            anyhow::anyhow!("an analyzer needs a 'tokenizer', not found in: {value:#?}")
+        })?;
+        let tokenizer = SearchTokenizer::from_json_value(tokenizer)?;
+        if matches!(tokenizer, SearchTokenizer::Analyzer { .. }) {
+            anyhow::bail!("the tokenizer of an analyzer can't be another analyzer");
+        }
+
+        let filters: Vec<SearchTokenFilter> = match value.get("filters") {
+            Some(filters) => serde_json::from_value(filters.clone())
+                .map_err(|err| anyhow::anyhow!("invalid analyzer 'filters': {err}"))?,
+            None => vec![],
+        };
+        for filter in &filters {
+            filter.validate()?;
+        }
+
+        Ok(Self {
+            version,
+            tokenizer: Box::new(tokenizer),
+            filters,
+        })
+    }
+
+    /// Does this analyzer lowercase the tokens it indexes?
+    pub fn lowercases(&self) -> bool {
+        self.tokenizer.lowercases() || self.filters.contains(&SearchTokenFilter::Lowercase)
+    }
+
+    pub fn to_tantivy_tokenizer(&self) -> Option<TextAnalyzer> {
+        let analyzer = self.tokenizer.to_tantivy_tokenizer()?;
+        Some(
+            self.filters
+                .iter()
+                .fold(analyzer, |analyzer, filter| filter.apply(analyzer)),
+        )
+    }
+}
+
+/// Lets a built analyzer be the tokenizer of another, so that filters can be added one at a time
+#[derive(Clone)]
+struct AnalyzerTokenizer(TextAnalyzer);
+
+impl Tokenizer for AnalyzerTokenizer {
+    type TokenStream<'a> = BoxTokenStream<'a>;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        self.0.token_stream(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    fn test_analyzer() {
+        let json = r#"{
+            "tokenizer": {"type": "whitespace", "lowercase": false},
+            "filters": [
+                {"type": "pattern_replace", "pattern": "^The$", "replacement": ""},
+                {"type": "lowercase"},
+                {"type": "stemmer", "language": "English"}
+            ]
+        }"#;
+        let analyzer =
+            SearchAnalyzer::from_json_value(1, &serde_json::from_str(json).unwrap()).unwrap();
+
+        let mut tokens = vec![];
+        analyzer
+            .to_tantivy_tokenizer()
+            .unwrap()
+            .token_stream("The Running the Dogs")
+            .process(&mut |token| tokens.push(token.text.clone()));
+        // the pattern is matched before lowercasing, so only the first "The" is removed
+        assert_eq!(tokens, vec!["run", "the", "dog"]);
+    }
+
+    #[rstest]
+    fn test_invalid_analyzer() {
+        for json in [
+            r#"{"filters": []}"#,
+            r#"{"tokenizer": {"type": "default"}, "filters": [{"type": "unknown"}]}"#,
+            r#"{"tokenizer": {"type": "default"}, "filters": [{"type": "shingle", "min": 0, "max": 2}]}"#,
+            r#"{"tokenizer": {"type": "analyzer", "name": "other"}}"#,
+        ] {
+            assert!(
+                SearchAnalyzer::from_json_value(1, &serde_json::from_str(json).unwrap()).is_err(),
+                "{json}"
+            );
+        }
+    }
+}
@@ -0,0 +1,431 @@
+// Copyright (c) 2023-2024 Retake, Inc.
+//
+// This file is part of ParadeDB - Postgres for Search and Analytics
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Token filters that tantivy doesn't come with.
+
+use regex::Regex;
+use tantivy::tokenizer::{Token, TokenFilter, TokenStream, Tokenizer};
+
+/// Removes the tokens with fewer than `min` or more than `max` characters
+#[derive(Clone)]
+pub struct LengthFilter {
+    min: usize,
+    max: usize,
+}
+
+impl LengthFilter {
+    pub fn new(min: usize, max: usize) -> Self {
+        Self { min, max }
+    }
+}
+
+impl TokenFilter for LengthFilter {
+    type Tokenizer<T: Tokenizer> = LengthFilterWrapper<T>;
+
+    fn transform<T: Tokenizer>(self, tokenizer: T) -> LengthFilterWrapper<T> {
+        LengthFilterWrapper {
+            filter: self,
+            inner: tokenizer,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LengthFilterWrapper<T> {
+    filter: LengthFilter,
+    inner: T,
+}
+
+impl<T: Tokenizer> Tokenizer for LengthFilterWrapper<T> {
+    type TokenStream<'a> = LengthFilterStream<T::TokenStream<'a>>;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        LengthFilterStream {
+            filter: self.filter.clone(),
+            tail: self.inner.token_stream(text),
+        }
+    }
+}
+
+pub struct LengthFilterStream<T> {
+    filter: LengthFilter,
+    tail: T,
+}
+
+impl<T: TokenStream> TokenStream for LengthFilterStream<T> {
+    fn advance(&mut self) -> bool {
+        while self.tail.advance() {
+            let length = self.tail.token().text.chars().count();
+            if (self.filter.min..=self.filter.max).contains(&length) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn token(&self) -> &Token {
+        self.tail.token()
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        self.tail.token_mut()
+    }
+}
+
+/// Replaces every match of a regex in each token, where the replacement can refer to the
+/// regex's capture groups as `$1`, `$name`, and so on.  Tokens that end up empty are removed.
+#[derive(Clone)]
+pub struct PatternReplaceFilter {
+    regex: Regex,
+    replacement: String,
+}
+
+impl PatternReplaceFilter {
+    pub fn new(regex: Regex, replacement: &str) -> Self {
+        Self {
+            regex,
+            replacement: replacement.to_string(),
+        }
+    }
+}
+
+impl TokenFilter for PatternReplaceFilter {
+    type Tokenizer<T: Tokenizer> = PatternReplaceFilterWrapper<T>;
+
+    fn transform<T: Tokenizer>(self, tokenizer: T) -> PatternReplaceFilterWrapper<T> {
+        PatternReplaceFilterWrapper {
+            filter: self,
+            inner: tokenizer,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PatternReplaceFilterWrapper<T> {
+    filter: PatternReplaceFilter,
+    inner: T,
+}
+
+impl<T: Tokenizer> Tokenizer for PatternReplaceFilterWrapper<T> {
+    type TokenStream<'a> = PatternReplaceFilterStream<'a, T::TokenStream<'a>>;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        PatternReplaceFilterStream {
+            filter: &self.filter,
+            tail: self.inner.token_stream(text),
+        }
+    }
+}
+
+pub struct PatternReplaceFilterStream<'a, T> {
+    filter: &'a PatternReplaceFilter,
+    tail: T,
+}
+
+impl<T: TokenStream> TokenStream for PatternReplaceFilterStream<'_, T> {
+    fn advance(&mut self) -> bool {
+        while self.tail.advance() {
+            let token = self.tail.token_mut();
+            let text = self
+                .filter
+                .regex
+                .replace_all(&token.text, self.filter.replacement.as_str())
+                .into_owned();
+            if !text.is_empty() {
+                token.text = text;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn token(&self) -> &Token {
+        self.tail.token()
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        self.tail.token_mut()
+    }
+}
+
+/// Replaces each token with its prefixes of `min` to `max` characters, all at the token's
+/// position.  Tokens shorter than `min` are removed.
+#[derive(Clone)]
+pub struct EdgeNgramFilter {
+    min: usize,
+    max: usize,
+}
+
+impl EdgeNgramFilter {
+    pub fn new(min: usize, max: usize) -> Self {
+        assert!(min > 0, "edge n-grams must have at least one character");
+        Self { min, max }
+    }
+}
+
+impl TokenFilter for EdgeNgramFilter {
+    type Tokenizer<T: Tokenizer> = EdgeNgramFilterWrapper<T>;
+
+    fn transform<T: Tokenizer>(self, tokenizer: T) -> EdgeNgramFilterWrapper<T> {
+        EdgeNgramFilterWrapper {
+            filter: self,
+            inner: tokenizer,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EdgeNgramFilterWrapper<T> {
+    filter: EdgeNgramFilter,
+    inner: T,
+}
+
+impl<T: Tokenizer> Tokenizer for EdgeNgramFilterWrapper<T> {
+    type TokenStream<'a> = EdgeNgramFilterStream<T::TokenStream<'a>>;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        EdgeNgramFilterStream {
+            filter: self.filter.clone(),
+            tail: self.inner.token_stream(text),
+            token: Token::default(),
+            ends: vec![],
+        }
+    }
+}
+
+pub struct EdgeNgramFilterStream<T> {
+    filter: EdgeNgramFilter,
+    tail: T,
+    token: Token,
+    /// The byte offsets where the prefixes of the current token that are left end, shortest
+    /// first, so that popping them and truncating the token gives each prefix in turn
+    ends: Vec<usize>,
+}
+
+impl<T: TokenStream> TokenStream for EdgeNgramFilterStream<T> {
+    fn advance(&mut self) -> bool {
+        loop {
+            if let Some(end) = self.ends.pop() {
+                self.token.text.truncate(end);
+                return true;
+            }
+            if !self.tail.advance() {
+                return false;
+            }
+
+            self.token = self.tail.token().clone();
+            self.ends = self
+                .token
+                .text
+                .char_indices()
+                .map(|(offset, ch)| offset + ch.len_utf8())
+                .take(self.filter.max)
+                .skip(self.filter.min - 1)
+                .collect();
+        }
+    }
+
+    fn token(&self) -> &Token {
+        &self.token
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.token
+    }
+}
+
+/// Replaces the tokens with shingles, which are the runs of `min` to `max` consecutive tokens
+/// joined by spaces.  A shingle is at the position of its first token.
+#[derive(Clone)]
+pub struct ShingleFilter {
+    min: usize,
+    max: usize,
+}
+
+impl ShingleFilter {
+    pub fn new(min: usize, max: usize) -> Self {
+        assert!(min > 0, "shingles must have at least one token");
+        Self { min, max }
+    }
+}
+
+impl TokenFilter for ShingleFilter {
+    type Tokenizer<T: Tokenizer> = ShingleFilterWrapper<T>;
+
+    fn transform<T: Tokenizer>(self, tokenizer: T) -> ShingleFilterWrapper<T> {
+        ShingleFilterWrapper {
+            filter: self,
+            inner: tokenizer,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ShingleFilterWrapper<T> {
+    filter: ShingleFilter,
+    inner: T,
+}
+
+impl<T: Tokenizer> Tokenizer for ShingleFilterWrapper<T> {
+    type TokenStream<'a> = ShingleFilterStream<T::TokenStream<'a>>;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        ShingleFilterStream {
+            size: self.filter.min - 1,
+            filter: self.filter.clone(),
+            tail: Some(self.inner.token_stream(text)),
+            tokens: vec![],
+            start: 0,
+            token: Token::default(),
+        }
+    }
+}
+
+pub struct ShingleFilterStream<T> {
+    filter: ShingleFilter,
+    /// The stream of tokens to shingle, until they have all been read into `tokens`
+    tail: Option<T>,
+    tokens: Vec<Token>,
+    /// The index of the first token and the size of the current shingle
+    start: usize,
+    size: usize,
+    token: Token,
+}
+
+impl<T: TokenStream> TokenStream for ShingleFilterStream<T> {
+    fn advance(&mut self) -> bool {
+        // shingles need the tokens after their first one, so read them all up front
+        if let Some(mut tail) = self.tail.take() {
+            while tail.advance() {
+                self.tokens.push(tail.token().clone());
+            }
+        }
+
+        while self.start < self.tokens.len() {
+            self.size += 1;
+            if self.size > self.filter.max || self.start + self.size > self.tokens.len() {
+                self.start += 1;
+                self.size = self.filter.min - 1;
+                continue;
+            }
+
+            let shingle = &self.tokens[self.start..self.start + self.size];
+            self.token = Token {
+                offset_from: shingle[0].offset_from,
+                offset_to: shingle[shingle.len() - 1].offset_to,
+                position: shingle[0].position,
+                text: shingle
+                    .iter()
+                    .map(|token| token.text.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                position_length: self.size,
+            };
+            return true;
+        }
+        false
+    }
+
+    fn token(&self) -> &Token {
+        &self.token
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.token
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+    use tantivy::tokenizer::{SimpleTokenizer, TextAnalyzer};
+
+    fn tokens(mut analyzer: TextAnalyzer, text: &str) -> Vec<(String, usize)> {
+        let mut tokens = vec![];
+        analyzer
+            .token_stream(text)
+            .process(&mut |token| tokens.push((token.text.clone(), token.position)));
+        tokens
+    }
+
+    fn strings(tokens: &[(&str, usize)]) -> Vec<(String, usize)> {
+        tokens
+            .iter()
+            .map(|(text, position)| (text.to_string(), *position))
+            .collect()
+    }
+
+    #[rstest]
+    fn test_length_filter() {
+        let analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(LengthFilter::new(2, 4))
+            .build();
+        assert_eq!(
+            tokens(analyzer, "a bb ccc dddd eeeee"),
+            strings(&[("bb", 1), ("ccc", 2), ("dddd", 3)])
+        );
+    }
+
+    #[rstest]
+    fn test_pattern_replace_filter() {
+        let analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(PatternReplaceFilter::new(
+                Regex::new("^(\\d+)x$").unwrap(),
+                "$1",
+            ))
+            .filter(PatternReplaceFilter::new(Regex::new("^the$").unwrap(), ""))
+            .build();
+        assert_eq!(
+            tokens(analyzer, "the 4x zoom"),
+            strings(&[("4", 1), ("zoom", 2)])
+        );
+    }
+
+    #[rstest]
+    fn test_edge_ngram_filter() {
+        let analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(EdgeNgramFilter::new(2, 3))
+            .build();
+        assert_eq!(
+            tokens(analyzer, "a café"),
+            strings(&[("caf", 1), ("ca", 1)])
+        );
+    }
+
+    #[rstest]
+    fn test_shingle_filter() {
+        let analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(ShingleFilter::new(1, 2))
+            .build();
+        assert_eq!(
+            tokens(analyzer, "new york city"),
+            strings(&[
+                ("new", 0),
+                ("new york", 0),
+                ("york", 1),
+                ("york city", 1),
+                ("city", 2)
+            ])
+        );
+
+        let analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(ShingleFilter::new(2, 2))
+            .build();
+        assert_eq!(tokens(analyzer, "new"), vec![]);
+    }
+}
@@ -15,8 +15,10 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+pub mod analyzer;
 pub mod cjk;
 pub mod code;
+pub mod filters;
 #[cfg(feature = "icu")]
 pub mod icu;
 pub mod lindera;
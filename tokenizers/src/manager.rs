@@ -20,25 +20,79 @@ use std::fmt::Write;
 #[cfg(feature = "icu")]
 use crate::icu::ICUTokenizer;
 use crate::{
+    analyzer::SearchAnalyzer,
     cjk::ChineseTokenizer,
     code::CodeTokenizer,
+    filters::{EdgeNgramFilter, LengthFilter, PatternReplaceFilter, ShingleFilter},
     lindera::{LinderaChineseTokenizer, LinderaJapaneseTokenizer, LinderaKoreanTokenizer},
     DEFAULT_REMOVE_TOKEN_LENGTH,
 };
 use anyhow::Result;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use strum::AsRefStr;
 use tantivy::tokenizer::{
     AsciiFoldingFilter, Language, LowerCaser, NgramTokenizer, RawTokenizer, RegexTokenizer,
-    RemoveLongFilter, SimpleTokenizer, Stemmer, TextAnalyzer, WhitespaceTokenizer,
+    RemoveLongFilter, SimpleTokenizer, Stemmer, StopWordFilter, TextAnalyzer, WhitespaceTokenizer,
 };
 
+/// The stopwords a tokenizer removes, either the built-in list of a language or a list of words
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum SearchStopwords {
+    Language(Language),
+    List(Vec<String>),
+}
+
+/// The smallest and largest sizes a filter keeps or makes, in tokens for shingles and in
+/// characters otherwise
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchSizeRange {
+    pub min: usize,
+    pub max: usize,
+}
+
+impl SearchSizeRange {
+    fn from_json_value(
+        name: &str,
+        value: &serde_json::Value,
+        least_min: usize,
+    ) -> Result<Self, anyhow::Error> {
+        let range: Self = serde_json::from_value(value.clone()).map_err(|_| {
+            anyhow::anyhow!(
+                "a '{name}' value passed to the pg_search tokenizer configuration \
+                 must be an object with integer 'min' and 'max' fields, found: {value:#?}"
+            )
+        })?;
+        if range.min < least_min || range.min > range.max {
+            anyhow::bail!(
+                "the '{name}' filter needs a 'min' of at least {least_min} and no more than its 'max', found: {value:#?}"
+            );
+        }
+        Ok(range)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SearchPatternReplace {
+    pub pattern: String,
+    #[serde(default)]
+    pub replacement: String,
+}
+
 #[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq, Eq)]
 pub struct SearchTokenizerFilters {
     remove_long: Option<usize>,
     lowercase: Option<bool>,
     stemmer: Option<Language>,
+    stopwords: Option<SearchStopwords>,
+    synonyms: Option<String>,
+    ascii_folding: Option<bool>,
+    shingle: Option<SearchSizeRange>,
+    edge_ngram: Option<SearchSizeRange>,
+    length: Option<SearchSizeRange>,
+    pattern_replace: Option<SearchPatternReplace>,
 }
 
 impl SearchTokenizerFilters {
@@ -66,6 +120,68 @@ impl SearchTokenizerFilters {
                 anyhow::anyhow!("stemmer tokenizer requires a valid 'stemmer' field")
             })?);
         }
+        if let Some(stopwords) = value.get("stopwords") {
+            let stopwords = serde_json::from_value(stopwords.clone()).map_err(|_| {
+                anyhow::anyhow!(
+                    "a 'stopwords' value passed to the pg_search tokenizer configuration \
+                     must be a language or an array of words, found: {stopwords:#?}"
+                )
+            })?;
+            if let SearchStopwords::Language(language) = stopwords {
+                if StopWordFilter::new(language).is_none() {
+                    anyhow::bail!("there is no built-in stopword list for {language:?}");
+                }
+            }
+            filters.stopwords = Some(stopwords);
+        }
+        if let Some(synonyms) = value.get("synonyms") {
+            filters.synonyms = Some(
+                synonyms
+                    .as_str()
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "a 'synonyms' value passed to the pg_search tokenizer configuration \
+                             must be the name of a synonym set, found: {synonyms:#?}"
+                        )
+                    })?
+                    .to_string(),
+            );
+        }
+
+        if let Some(ascii_folding) = value.get("ascii_folding") {
+            filters.ascii_folding = Some(ascii_folding.as_bool().ok_or_else(|| {
+                anyhow::anyhow!(
+                    "an 'ascii_folding' value passed to the pg_search tokenizer configuration \
+                     must be of type bool, found: {ascii_folding:#?}"
+                )
+            })?);
+        }
+        if let Some(shingle) = value.get("shingle") {
+            filters.shingle = Some(SearchSizeRange::from_json_value("shingle", shingle, 1)?);
+        }
+        if let Some(edge_ngram) = value.get("edge_ngram") {
+            filters.edge_ngram = Some(SearchSizeRange::from_json_value(
+                "edge_ngram",
+                edge_ngram,
+                1,
+            )?);
+        }
+        if let Some(length) = value.get("length") {
+            filters.length = Some(SearchSizeRange::from_json_value("length", length, 0)?);
+        }
+        if let Some(pattern_replace) = value.get("pattern_replace") {
+            let pattern_replace: SearchPatternReplace =
+                serde_json::from_value(pattern_replace.clone()).map_err(|_| {
+                    anyhow::anyhow!(
+                        "a 'pattern_replace' value passed to the pg_search tokenizer configuration \
+                         must be an object with a string 'pattern' field, found: {pattern_replace:#?}"
+                    )
+                })?;
+            Regex::new(&pattern_replace.pattern).map_err(|err| {
+                anyhow::anyhow!("pattern_replace filter requires a valid 'pattern': {err}")
+            })?;
+            filters.pattern_replace = Some(pattern_replace);
+        }
 
         Ok(filters)
     }
@@ -80,6 +196,30 @@ impl SearchTokenizerFilters {
             let v = serde_json::Value::Bool(value);
             enclosing.insert("lowercase".to_string(), v);
         }
+        if let Some(value) = &self.stopwords {
+            let v = serde_json::to_value(value).expect("stopwords should serialize to json");
+            enclosing.insert("stopwords".to_string(), v);
+        }
+        if let Some(value) = &self.synonyms {
+            let v = serde_json::Value::String(value.clone());
+            enclosing.insert("synonyms".to_string(), v);
+        }
+        if let Some(value) = self.ascii_folding {
+            let v = serde_json::Value::Bool(value);
+            enclosing.insert("ascii_folding".to_string(), v);
+        }
+        if let Some(value) = self.shingle {
+            enclosing.insert("shingle".to_string(), json!(value));
+        }
+        if let Some(value) = self.edge_ngram {
+            enclosing.insert("edge_ngram".to_string(), json!(value));
+        }
+        if let Some(value) = self.length {
+            enclosing.insert("length".to_string(), json!(value));
+        }
+        if let Some(value) = &self.pattern_replace {
+            enclosing.insert("pattern_replace".to_string(), json!(value));
+        }
     }
 
     fn name_suffix(&self) -> String {
@@ -108,6 +248,48 @@ impl SearchTokenizerFilters {
             write!(buffer, "{}stemmer={value:?}", sep(is_empty)).unwrap();
             is_empty = false;
         }
+        match &self.stopwords {
+            Some(SearchStopwords::Language(language)) => {
+                write!(buffer, "{}stopwords={language:?}", sep(is_empty)).unwrap();
+                is_empty = false;
+            }
+            Some(SearchStopwords::List(words)) => {
+                write!(buffer, "{}stopwords=[{}]", sep(is_empty), words.join("|")).unwrap();
+                is_empty = false;
+            }
+            None => {}
+        }
+        if let Some(value) = self.ascii_folding {
+            write!(buffer, "{}ascii_folding={value}", sep(is_empty)).unwrap();
+            is_empty = false;
+        }
+        if let Some(SearchSizeRange { min, max }) = self.shingle {
+            write!(buffer, "{}shingle={min}..{max}", sep(is_empty)).unwrap();
+            is_empty = false;
+        }
+        if let Some(SearchSizeRange { min, max }) = self.edge_ngram {
+            write!(buffer, "{}edge_ngram={min}..{max}", sep(is_empty)).unwrap();
+            is_empty = false;
+        }
+        if let Some(SearchSizeRange { min, max }) = self.length {
+            write!(buffer, "{}length={min}..{max}", sep(is_empty)).unwrap();
+            is_empty = false;
+        }
+        if let Some(SearchPatternReplace {
+            pattern,
+            replacement,
+        }) = &self.pattern_replace
+        {
+            write!(
+                buffer,
+                "{}pattern_replace={pattern:?}->{replacement:?}",
+                sep(is_empty)
+            )
+            .unwrap();
+            is_empty = false;
+        }
+        // synonyms are expanded at query time and don't change how text is tokenized, so they
+        // are left out of the name
 
         if is_empty {
             "".into()
@@ -128,9 +310,67 @@ impl SearchTokenizerFilters {
         }
     }
 
+    fn stop_word_filter(&self) -> Option<StopWordFilter> {
+        match self.stopwords.as_ref()? {
+            SearchStopwords::Language(language) => StopWordFilter::new(*language),
+            // the words have to be lowercased too to match lowercased tokens
+            SearchStopwords::List(words) => Some(StopWordFilter::remove(words.iter().map(
+                |word| match self.lower_caser() {
+                    Some(_) => word.to_lowercase(),
+                    None => word.clone(),
+                },
+            ))),
+        }
+    }
+
     fn stemmer(&self) -> Option<Stemmer> {
         self.stemmer.map(Stemmer::new)
     }
+
+    fn ascii_folding_filter(&self) -> Option<AsciiFoldingFilter> {
+        match self.ascii_folding {
+            Some(true) => Some(AsciiFoldingFilter),
+            _ => None,
+        }
+    }
+
+    fn pattern_replace_filter(&self) -> Option<PatternReplaceFilter> {
+        self.pattern_replace.as_ref().map(|pattern_replace| {
+            PatternReplaceFilter::new(
+                Regex::new(&pattern_replace.pattern)
+                    .expect("pattern_replace pattern should have been validated"),
+                &pattern_replace.replacement,
+            )
+        })
+    }
+
+    fn length_filter(&self) -> Option<LengthFilter> {
+        self.length
+            .map(|SearchSizeRange { min, max }| LengthFilter::new(min, max))
+    }
+
+    fn shingle_filter(&self) -> Option<ShingleFilter> {
+        self.shingle
+            .map(|SearchSizeRange { min, max }| ShingleFilter::new(min, max))
+    }
+
+    fn edge_ngram_filter(&self) -> Option<EdgeNgramFilter> {
+        self.edge_ngram
+            .map(|SearchSizeRange { min, max }| EdgeNgramFilter::new(min, max))
+    }
+
+    /// Does any filter, besides the one removing long tokens, change, add or drop tokens?
+    fn transforms_tokens(&self) -> bool {
+        self.lower_caser().is_some()
+            || self.stemmer.is_some()
+            || self.stopwords.is_some()
+            || self.synonyms.is_some()
+            || self.ascii_folding_filter().is_some()
+            || self.shingle.is_some()
+            || self.edge_ngram.is_some()
+            || self.length.is_some()
+            || self.pattern_replace.is_some()
+    }
 }
 
 // Serde will pick a SearchTokenizer variant based on the value of the
@@ -173,6 +413,13 @@ pub enum SearchTokenizer {
     #[cfg(feature = "icu")]
     #[strum(serialize = "icu")]
     ICUTokenizer(SearchTokenizerFilters),
+    /// An analyzer created with `paradedb.create_analyzer`, which is looked up by name when an
+    /// index is built and kept in the index's schema from then on
+    Analyzer {
+        name: String,
+        analyzer: Option<Box<SearchAnalyzer>>,
+        filters: SearchTokenizerFilters,
+    },
 }
 
 impl Default for SearchTokenizer {
@@ -217,6 +464,7 @@ impl SearchTokenizer {
             SearchTokenizer::KoreanLindera(_filters) => json!({ "type": "korean_lindera" }),
             #[cfg(feature = "icu")]
             SearchTokenizer::ICUTokenizer(_filters) => json!({ "type": "icu" }),
+            SearchTokenizer::Analyzer { name, .. } => json!({ "type": "analyzer", "name": name }),
         };
 
         // Serialize filters to the enclosing json object.
@@ -283,6 +531,26 @@ impl SearchTokenizer {
             "korean_lindera" => Ok(SearchTokenizer::KoreanLindera(filters)),
             #[cfg(feature = "icu")]
             "icu" => Ok(SearchTokenizer::ICUTokenizer(filters)),
+            "analyzer" => {
+                let name: String = serde_json::from_value(value["name"].clone()).map_err(|_| {
+                    anyhow::anyhow!("analyzer tokenizer requires a string 'name' field")
+                })?;
+                // analyzers have their own filters, but are still expanded with synonyms
+                let only_synonyms = SearchTokenizerFilters {
+                    synonyms: filters.synonyms.clone(),
+                    ..Default::default()
+                };
+                if filters != only_synonyms {
+                    anyhow::bail!(
+                        "the only filter that can be added to analyzer '{name}' is 'synonyms'"
+                    );
+                }
+                Ok(SearchTokenizer::Analyzer {
+                    name,
+                    analyzer: None,
+                    filters,
+                })
+            }
             _ => Err(anyhow::anyhow!(
                 "unknown tokenizer type: {}",
                 tokenizer_type
@@ -296,14 +564,26 @@ impl SearchTokenizer {
                 TextAnalyzer::builder(SimpleTokenizer::default())
                     .filter(filters.remove_long_filter())
                     .filter(filters.lower_caser())
+                    .filter(filters.ascii_folding_filter())
+                    .filter(filters.stop_word_filter())
+                    .filter(filters.pattern_replace_filter())
+                    .filter(filters.length_filter())
                     .filter(filters.stemmer())
+                    .filter(filters.shingle_filter())
+                    .filter(filters.edge_ngram_filter())
                     .build(),
             ),
             SearchTokenizer::Raw(filters) => Some(
                 TextAnalyzer::builder(RawTokenizer::default())
                     .filter(filters.remove_long_filter())
                     .filter(filters.lower_caser())
+                    .filter(filters.ascii_folding_filter())
+                    .filter(filters.stop_word_filter())
+                    .filter(filters.pattern_replace_filter())
+                    .filter(filters.length_filter())
                     .filter(filters.stemmer())
+                    .filter(filters.shingle_filter())
+                    .filter(filters.edge_ngram_filter())
                     .build(),
             ),
             // Deprecated, use `raw` with `lowercase` filter instead
@@ -311,21 +591,39 @@ impl SearchTokenizer {
                 TextAnalyzer::builder(RawTokenizer::default())
                     .filter(filters.remove_long_filter())
                     .filter(filters.lower_caser())
+                    .filter(filters.ascii_folding_filter())
+                    .filter(filters.stop_word_filter())
+                    .filter(filters.pattern_replace_filter())
+                    .filter(filters.length_filter())
                     .filter(filters.stemmer())
+                    .filter(filters.shingle_filter())
+                    .filter(filters.edge_ngram_filter())
                     .build(),
             ),
             SearchTokenizer::WhiteSpace(filters) => Some(
                 TextAnalyzer::builder(WhitespaceTokenizer::default())
                     .filter(filters.remove_long_filter())
                     .filter(filters.lower_caser())
+                    .filter(filters.ascii_folding_filter())
+                    .filter(filters.stop_word_filter())
+                    .filter(filters.pattern_replace_filter())
+                    .filter(filters.length_filter())
                     .filter(filters.stemmer())
+                    .filter(filters.shingle_filter())
+                    .filter(filters.edge_ngram_filter())
                     .build(),
             ),
             SearchTokenizer::RegexTokenizer { pattern, filters } => Some(
                 TextAnalyzer::builder(RegexTokenizer::new(pattern.as_str()).unwrap())
                     .filter(filters.remove_long_filter())
                     .filter(filters.lower_caser())
+                    .filter(filters.ascii_folding_filter())
+                    .filter(filters.stop_word_filter())
+                    .filter(filters.pattern_replace_filter())
+                    .filter(filters.length_filter())
                     .filter(filters.stemmer())
+                    .filter(filters.shingle_filter())
+                    .filter(filters.edge_ngram_filter())
                     .build(),
             ),
             SearchTokenizer::Ngram {
@@ -340,43 +638,79 @@ impl SearchTokenizer {
                 )
                 .filter(filters.remove_long_filter())
                 .filter(filters.lower_caser())
+                .filter(filters.ascii_folding_filter())
+                .filter(filters.stop_word_filter())
+                .filter(filters.pattern_replace_filter())
+                .filter(filters.length_filter())
                 .filter(filters.stemmer())
+                .filter(filters.shingle_filter())
+                .filter(filters.edge_ngram_filter())
                 .build(),
             ),
             SearchTokenizer::ChineseCompatible(filters) => Some(
                 TextAnalyzer::builder(ChineseTokenizer)
                     .filter(filters.remove_long_filter())
                     .filter(filters.lower_caser())
+                    .filter(filters.ascii_folding_filter())
+                    .filter(filters.stop_word_filter())
+                    .filter(filters.pattern_replace_filter())
+                    .filter(filters.length_filter())
                     .filter(filters.stemmer())
+                    .filter(filters.shingle_filter())
+                    .filter(filters.edge_ngram_filter())
                     .build(),
             ),
             SearchTokenizer::SourceCode(filters) => Some(
                 TextAnalyzer::builder(CodeTokenizer::default())
                     .filter(filters.remove_long_filter())
                     .filter(filters.lower_caser())
+                    .filter(filters.ascii_folding_filter())
+                    .filter(filters.stop_word_filter())
+                    .filter(filters.pattern_replace_filter())
+                    .filter(filters.length_filter())
                     .filter(AsciiFoldingFilter)
                     .filter(filters.stemmer())
+                    .filter(filters.shingle_filter())
+                    .filter(filters.edge_ngram_filter())
                     .build(),
             ),
             SearchTokenizer::ChineseLindera(filters) => Some(
                 TextAnalyzer::builder(LinderaChineseTokenizer::default())
                     .filter(filters.remove_long_filter())
                     .filter(filters.lower_caser())
+                    .filter(filters.ascii_folding_filter())
+                    .filter(filters.stop_word_filter())
+                    .filter(filters.pattern_replace_filter())
+                    .filter(filters.length_filter())
                     .filter(filters.stemmer())
+                    .filter(filters.shingle_filter())
+                    .filter(filters.edge_ngram_filter())
                     .build(),
             ),
             SearchTokenizer::JapaneseLindera(filters) => Some(
                 TextAnalyzer::builder(LinderaJapaneseTokenizer::default())
                     .filter(filters.remove_long_filter())
                     .filter(filters.lower_caser())
+                    .filter(filters.ascii_folding_filter())
+                    .filter(filters.stop_word_filter())
+                    .filter(filters.pattern_replace_filter())
+                    .filter(filters.length_filter())
                     .filter(filters.stemmer())
+                    .filter(filters.shingle_filter())
+                    .filter(filters.edge_ngram_filter())
                     .build(),
             ),
             SearchTokenizer::KoreanLindera(filters) => Some(
                 TextAnalyzer::builder(LinderaKoreanTokenizer::default())
                     .filter(filters.remove_long_filter())
                     .filter(filters.lower_caser())
+                    .filter(filters.ascii_folding_filter())
+                    .filter(filters.stop_word_filter())
+                    .filter(filters.pattern_replace_filter())
+                    .filter(filters.length_filter())
                     .filter(filters.stemmer())
+                    .filter(filters.shingle_filter())
+                    .filter(filters.edge_ngram_filter())
                     .build(),
             ),
             // Deprecated, use `stemmer` filter instead
@@ -384,7 +718,13 @@ impl SearchTokenizer {
                 TextAnalyzer::builder(SimpleTokenizer::default())
                     .filter(filters.remove_long_filter())
                     .filter(filters.lower_caser())
+                    .filter(filters.ascii_folding_filter())
+                    .filter(filters.stop_word_filter())
+                    .filter(filters.pattern_replace_filter())
+                    .filter(filters.length_filter())
                     .filter(Stemmer::new(Language::English))
+                    .filter(filters.shingle_filter())
+                    .filter(filters.edge_ngram_filter())
                     .build(),
             ),
             // Deprecated, use `stemmer` filter instead
@@ -392,15 +732,30 @@ impl SearchTokenizer {
                 TextAnalyzer::builder(SimpleTokenizer::default())
                     .filter(filters.remove_long_filter())
                     .filter(filters.lower_caser())
+                    .filter(filters.ascii_folding_filter())
+                    .filter(filters.stop_word_filter())
+                    .filter(filters.pattern_replace_filter())
+                    .filter(filters.length_filter())
                     .filter(Stemmer::new(*language))
+                    .filter(filters.shingle_filter())
+                    .filter(filters.edge_ngram_filter())
                     .build(),
             ),
+            SearchTokenizer::Analyzer { analyzer, .. } => analyzer
+                .as_ref()
+                .and_then(|analyzer| analyzer.to_tantivy_tokenizer()),
             #[cfg(feature = "icu")]
             SearchTokenizer::ICUTokenizer(filters) => Some(
                 TextAnalyzer::builder(ICUTokenizer)
                     .filter(filters.remove_long_filter())
                     .filter(filters.lower_caser())
+                    .filter(filters.ascii_folding_filter())
+                    .filter(filters.stop_word_filter())
+                    .filter(filters.pattern_replace_filter())
+                    .filter(filters.length_filter())
                     .filter(filters.stemmer())
+                    .filter(filters.shingle_filter())
+                    .filter(filters.edge_ngram_filter())
                     .build(),
             ),
         }
@@ -423,6 +778,7 @@ impl SearchTokenizer {
             SearchTokenizer::KoreanLindera(filters) => filters,
             #[cfg(feature = "icu")]
             SearchTokenizer::ICUTokenizer(filters) => filters,
+            SearchTokenizer::Analyzer { filters, .. } => filters,
         }
     }
 }
@@ -451,6 +807,33 @@ pub fn language_to_str(lang: &Language) -> &str {
 }
 
 impl SearchTokenizer {
+    /// The name of the synonym set that queries against fields with this tokenizer are expanded
+    /// with, if any
+    pub fn synonym_set(&self) -> Option<&str> {
+        self.filters().synonyms.as_deref()
+    }
+
+    /// Does this tokenizer lowercase the tokens it indexes?
+    pub fn lowercases(&self) -> bool {
+        match self {
+            SearchTokenizer::Analyzer { analyzer, .. } => analyzer
+                .as_ref()
+                .is_some_and(|analyzer| analyzer.lowercases()),
+            _ => self.filters().lower_caser().is_some(),
+        }
+    }
+
+    /// If this tokenizer indexes each value unchanged, as a single token, returns the length in
+    /// bytes a value must stay under to be indexed at all.
+    pub fn verbatim_token_limit(&self) -> Option<usize> {
+        match self {
+            SearchTokenizer::Raw(filters) if !filters.transforms_tokens() => {
+                Some(filters.remove_long.unwrap_or(DEFAULT_REMOVE_TOKEN_LENGTH))
+            }
+            _ => None,
+        }
+    }
+
     pub fn name(&self) -> String {
         let filters_suffix = self.filters().name_suffix();
         match self {
@@ -484,6 +867,10 @@ impl SearchTokenizer {
             SearchTokenizer::KoreanLindera(_filters) => format!("korean_lindera{filters_suffix}"),
             #[cfg(feature = "icu")]
             SearchTokenizer::ICUTokenizer(_filters) => format!("icu{filters_suffix}"),
+            SearchTokenizer::Analyzer { name, analyzer, .. } => match analyzer {
+                Some(analyzer) => format!("analyzer_{name}_v{}{filters_suffix}", analyzer.version),
+                None => format!("analyzer_{name}{filters_suffix}"),
+            },
         }
     }
 }
@@ -521,6 +908,7 @@ mod tests {
             remove_long: Some(999),
             lowercase: Some(true),
             stemmer: None,
+            ..Default::default()
         });
         assert_eq!(
             tokenizer.name(),
@@ -548,7 +936,8 @@ mod tests {
                 filters: SearchTokenizerFilters {
                     remove_long: Some(123),
                     lowercase: Some(false),
-                    stemmer: None
+                    stemmer: None,
+                    ..Default::default()
                 }
             }
         );
@@ -567,6 +956,7 @@ mod tests {
                 remove_long: Some(100),
                 lowercase: None,
                 stemmer: None,
+                ..Default::default()
             },
         };
 
@@ -576,6 +966,96 @@ mod tests {
         );
     }
 
+    #[rstest]
+    fn test_stopwords() {
+        let json = r#"{
+            "type": "default",
+            "stopwords": "English"
+        }"#;
+        let tokenizer =
+            SearchTokenizer::from_json_value(&serde_json::from_str(json).unwrap()).unwrap();
+        assert_eq!(tokenizer.name(), "default[stopwords=English]".to_string());
+
+        let json = r#"{
+            "type": "default",
+            "stopwords": ["The", "of"]
+        }"#;
+        let tokenizer =
+            SearchTokenizer::from_json_value(&serde_json::from_str(json).unwrap()).unwrap();
+        assert_eq!(tokenizer.name(), "default[stopwords=[The|of]]".to_string());
+
+        let mut analyzer = tokenizer.to_tantivy_tokenizer().unwrap();
+        let mut tokens = vec![];
+        analyzer
+            .token_stream("The Lord of the Rings")
+            .process(&mut |token| tokens.push(token.text.clone()));
+        assert_eq!(tokens, vec!["lord", "rings"]);
+
+        let json = r#"{
+            "type": "default",
+            "stopwords": 42
+        }"#;
+        assert!(SearchTokenizer::from_json_value(&serde_json::from_str(json).unwrap()).is_err());
+    }
+
+    #[rstest]
+    fn test_token_filters() {
+        let json = r#"{
+            "type": "whitespace",
+            "ascii_folding": true,
+            "pattern_replace": {"pattern": "-", "replacement": ""},
+            "length": {"min": 2, "max": 10},
+            "edge_ngram": {"min": 2, "max": 3}
+        }"#;
+        let tokenizer =
+            SearchTokenizer::from_json_value(&serde_json::from_str(json).unwrap()).unwrap();
+        assert_eq!(
+            tokenizer.name(),
+            r#"whitespace[ascii_folding=true,edge_ngram=2..3,length=2..10,pattern_replace="-"->""]"#
+                .to_string()
+        );
+        assert_eq!(
+            tokenizer,
+            SearchTokenizer::from_json_value(&tokenizer.to_json_value()).unwrap()
+        );
+
+        let mut analyzer = tokenizer.to_tantivy_tokenizer().unwrap();
+        let mut tokens = vec![];
+        analyzer
+            .token_stream("Crème e-Mail a")
+            .process(&mut |token| tokens.push(token.text.clone()));
+        assert_eq!(tokens, vec!["cre", "cr", "ema", "em"]);
+
+        let json = r#"{
+            "type": "default",
+            "shingle": {"min": 2, "max": 1}
+        }"#;
+        assert!(SearchTokenizer::from_json_value(&serde_json::from_str(json).unwrap()).is_err());
+
+        let json = r#"{
+            "type": "default",
+            "pattern_replace": {"pattern": "("}
+        }"#;
+        assert!(SearchTokenizer::from_json_value(&serde_json::from_str(json).unwrap()).is_err());
+    }
+
+    #[rstest]
+    fn test_synonyms() {
+        let json = r#"{
+            "type": "default",
+            "synonyms": "products"
+        }"#;
+        let tokenizer =
+            SearchTokenizer::from_json_value(&serde_json::from_str(json).unwrap()).unwrap();
+
+        assert_eq!(tokenizer.synonym_set(), Some("products"));
+        assert_eq!(tokenizer.name(), "default".to_string());
+        assert_eq!(
+            tokenizer,
+            SearchTokenizer::from_json_value(&tokenizer.to_json_value()).unwrap()
+        );
+    }
+
     #[rstest]
     fn test_search_normalizer() {
         assert_eq!(SearchNormalizer::Lowercase.name(), "lowercase");